@@ -7,6 +7,11 @@ cargo build --release --target aarch64-unknown-linux-gnu
 echo -e "Copying shaders to Raspberry Pi..."
 sshpass -p "ras" scp -rp ./res mattszymonski@192.168.33.17:/home/mattszymonski/programming/little-shader-display/
 
+if [ -f ./config.toml ]; then
+    echo -e "Copying config to Raspberry Pi..."
+    sshpass -p "ras" scp ./config.toml mattszymonski@192.168.33.17:/home/mattszymonski/programming/little-shader-display/
+fi
+
 echo -e "Copying binary to Raspberry Pi..."
 sshpass -p "ras" scp ./target/aarch64-unknown-linux-gnu/release/little-shader-display mattszymonski@192.168.33.17:/home/mattszymonski/programming/little-shader-display/
 
@@ -4,13 +4,17 @@
 layout(location = 0) in vec2 vertex_position;
 layout(location = 1) in vec2 vertex_texture_coordinates;
 
-// Uniforms
-layout(set = 0, binding = 0) uniform Uniforms {
+// Global uniforms, written every frame
+layout(set = 0, binding = 0) uniform GlobalUniforms {
     float time;
-    vec3 bluetooth_data;
     float screen_aspect_ratio;
 };
 
+// Dynamic uniforms, written only when their values change
+layout(set = 1, binding = 0) uniform DynamicUniforms {
+    vec3 bluetooth_data;
+};
+
 // Output fragment color
 layout(location = 0) out vec4 out_final_color;
 
@@ -0,0 +1,352 @@
+use rppal::i2c::I2c;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IDENTITY: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const SMOOTHING: f32 = 0.2; // Weight of a new sample, lower is smoother but follows rotation slower
+const LSB_PER_G: f32 = 16384.0; // All sensors at their ±2g range
+const MPU6050_LSB_PER_DPS: f32 = 131.0; // At its ±250°/s range
+const LSM6DS3_LSB_PER_DPS: f32 = 114.29; // At its ±245°/s range, 8.75 mdps per LSB
+const GYRO_CALIBRATION_SAMPLES: u32 = 50; // Averaged at start while the device is expected to be still
+const TILT_CORRECTION: f32 = 2.0; // Rate in rad/s at which gravity pulls the orientation back, higher trusts the gyro less
+const TILT_CORRECTION_MAX_ERROR_G: f32 = 0.3; // Samples further than this from 1g are shaking, not gravity, and are not used
+const ORIENTATION_MIN_G: f32 = 0.5; // Below this in the panel plane the device lies flat and the orientation is kept
+const ORIENTATION_MARGIN: f32 = 1.5; // The dominant axis must exceed the other one by this factor to change the orientation
+
+// Supported sensors with the I2C addresses they can be strapped to
+const SENSORS: [(Sensor, u16); 6] = [
+    (Sensor::Mpu6050, 0x68), (Sensor::Mpu6050, 0x69),
+    (Sensor::Lsm6ds3, 0x6A), (Sensor::Lsm6ds3, 0x6B),
+    (Sensor::Lis3dh, 0x18), (Sensor::Lis3dh, 0x19),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sensor {
+    Mpu6050, // Accelerometer and gyro
+    Lsm6ds3, // Accelerometer and gyro
+    Lis3dh, // Accelerometer only
+}
+
+// Values of one reading, acceleration in g and angular velocity in rad/s if the sensor has a gyro
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    acceleration: [f32; 3],
+    angular_velocity: Option<[f32; 3]>,
+}
+
+impl Sensor {
+    // Identification register and its expected value
+    fn who_am_i(self) -> (u8, u8) {
+        match self {
+            Sensor::Mpu6050 => (0x75, 0x68),
+            Sensor::Lsm6ds3 => (0x0F, 0x69),
+            Sensor::Lis3dh => (0x0F, 0x33),
+        }
+    }
+
+    // Starts the measurements, also needed after the sensor lost power
+    fn configure(self, i2c: &I2c) -> rppal::i2c::Result<()> {
+        match self {
+            Sensor::Mpu6050 => i2c.smbus_write_byte(0x6B, 0x00), // PWR_MGMT_1: leave sleep mode
+            Sensor::Lsm6ds3 => {
+                i2c.smbus_write_byte(0x10, 0x40)?; // CTRL1_XL: 104 Hz, ±2g
+                i2c.smbus_write_byte(0x11, 0x40) // CTRL2_G: 104 Hz, ±245°/s
+            }
+            Sensor::Lis3dh => {
+                i2c.smbus_write_byte(0x20, 0x57)?; // CTRL_REG1: 100 Hz, all axes enabled
+                i2c.smbus_write_byte(0x23, 0x08) // CTRL_REG4: ±2g, high resolution
+            }
+        }
+    }
+
+    fn read(self, i2c: &I2c) -> rppal::i2c::Result<Sample> {
+        let (register, length) = match self {
+            Sensor::Mpu6050 => (0x3B, 14), // ACCEL_XOUT_H, followed by the temperature and the gyro
+            Sensor::Lsm6ds3 => (0x22, 12), // OUTX_L_G, the gyro followed by the accelerometer
+            Sensor::Lis3dh => (0x28 | 0x80, 6), // OUT_X_L with register auto-increment
+        };
+        let mut bytes = [0u8; 14];
+        i2c.write_read(&[register], &mut bytes[..length])?;
+        Ok(self.decode(&bytes[..length]))
+    }
+
+    // Converts the X, Y and Z output registers to acceleration in g and angular velocity in rad/s
+    fn decode(self, bytes: &[u8]) -> Sample {
+        let axes = |offset: usize, big_endian: bool, lsb_per_unit: f32| {
+            [0, 2, 4].map(|index| {
+                let pair = [bytes[offset + index], bytes[offset + index + 1]];
+                let value = if big_endian { i16::from_be_bytes(pair) } else { i16::from_le_bytes(pair) };
+                value as f32 / lsb_per_unit
+            })
+        };
+        let dps_to_radians = |axes: [f32; 3]| axes.map(f32::to_radians);
+        match self {
+            Sensor::Mpu6050 => Sample { acceleration: axes(0, true, LSB_PER_G), angular_velocity: Some(dps_to_radians(axes(8, true, MPU6050_LSB_PER_DPS))) },
+            Sensor::Lsm6ds3 => Sample { acceleration: axes(6, false, LSB_PER_G), angular_velocity: Some(dps_to_radians(axes(0, false, LSM6DS3_LSB_PER_DPS))) },
+            Sensor::Lis3dh => Sample { acceleration: axes(0, false, LSB_PER_G), angular_velocity: None },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AccelerometerState {
+    gravity: [f32; 3],
+    quarter_turns: u32,
+    angular_velocity: [f32; 3],
+    orientation: [f32; 4],
+}
+
+impl Default for AccelerometerState {
+    fn default() -> Self {
+        Self { gravity: [0.0; 3], quarter_turns: 0, angular_velocity: [0.0; 3], orientation: IDENTITY }
+    }
+}
+
+// Reads an MPU6050 or LSM6DS3 IMU, or an LIS3DH accelerometer, on I2C bus 1 in the background.
+// The sensor axes are expected to be aligned with the panel: x to the right, y to the top and z out of the screen.
+pub struct Accelerometer {
+    state: Arc<Mutex<AccelerometerState>>,
+}
+
+impl Accelerometer {
+    // Finds a supported sensor and starts reading it, fails if there is none
+    pub fn start() -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::new()?;
+        let sensor = detect(&mut i2c)?.ok_or("No MPU6050, LSM6DS3 or LIS3DH found on I2C bus 1")?;
+        sensor.configure(&i2c)?;
+
+        let state = Arc::new(Mutex::new(AccelerometerState::default()));
+        let thread_state = state.clone();
+        thread::spawn(move || read_loop(i2c, sensor, thread_state));
+        Ok(Self { state })
+    }
+
+    // Smoothed gravity in g in the panel's frame, (0, -1, 0) when the panel stands upright
+    pub fn gravity(&self) -> [f32; 3] {
+        self.state.lock().unwrap().gravity
+    }
+
+    // Clockwise quarter turns the image needs to stay upright
+    pub fn quarter_turns(&self) -> u32 {
+        self.state.lock().unwrap().quarter_turns
+    }
+
+    // Latest angular velocity in rad/s around the panel's axes, zero without a gyro
+    pub fn angular_velocity(&self) -> [f32; 3] {
+        self.state.lock().unwrap().angular_velocity
+    }
+
+    // Orientation of the panel as a quaternion (x, y, z, w) rotating panel vectors into the world, where y is up.
+    // Identity when the panel stands upright facing its starting heading. Without a gyro only the tilt is known.
+    pub fn orientation(&self) -> [f32; 4] {
+        self.state.lock().unwrap().orientation
+    }
+}
+
+fn detect(i2c: &mut I2c) -> rppal::i2c::Result<Option<Sensor>> {
+    for (sensor, address) in SENSORS {
+        i2c.set_slave_address(address)?;
+        let (register, expected) = sensor.who_am_i();
+        if i2c.smbus_read_byte(register).ok() == Some(expected) {
+            println!("Found {:?} at I2C address {:#04x}", sensor, address);
+            return Ok(Some(sensor));
+        }
+    }
+    Ok(None)
+}
+
+// Keeps the last values while the sensor is disconnected and configures it again once it answers
+fn read_loop(i2c: I2c, sensor: Sensor, state: Arc<Mutex<AccelerometerState>>) {
+    let gyro_bias = calibrate_gyro(&i2c, sensor);
+    let mut connected = true;
+    let mut last_sample_time = Instant::now();
+    loop {
+        match sensor.read(&i2c) {
+            Ok(_) if !connected => {
+                println!("Accelerometer reconnected");
+                connected = true;
+                if let Err(err) = sensor.configure(&i2c) {
+                    println!("Warning: Failed to configure accelerometer: {}", err);
+                }
+            }
+            Ok(sample) => {
+                // The sensor measures the force holding it up, gravity points the other way
+                let gravity = sample.acceleration.map(|value| -value);
+                let angular_velocity = sample.angular_velocity.map_or([0.0; 3], |angular_velocity| sub(angular_velocity, gyro_bias));
+                let elapsed_time = last_sample_time.elapsed().as_secs_f32();
+                last_sample_time = Instant::now();
+
+                let mut state = state.lock().unwrap();
+                state.gravity = smooth(state.gravity, gravity);
+                state.quarter_turns = orientation(state.gravity, state.quarter_turns);
+                state.angular_velocity = angular_velocity;
+                state.orientation = fuse(state.orientation, angular_velocity, gravity, elapsed_time);
+            }
+            Err(err) => {
+                if connected {
+                    println!("Warning: Failed to read accelerometer ({}), retrying every {} seconds", err, RETRY_INTERVAL.as_secs());
+                    connected = false;
+                }
+                thread::sleep(RETRY_INTERVAL);
+                continue;
+            }
+        }
+        thread::sleep(SAMPLE_INTERVAL);
+    }
+}
+
+// Averages the gyro while the device is still, its offset would otherwise make the orientation drift
+fn calibrate_gyro(i2c: &I2c, sensor: Sensor) -> [f32; 3] {
+    if sensor == Sensor::Lis3dh {
+        return [0.0; 3];
+    }
+
+    println!("Calibrating gyro, keep the device still");
+    let mut sum = [0.0; 3];
+    let mut count = 0;
+    for _ in 0..GYRO_CALIBRATION_SAMPLES {
+        if let Ok(Sample { angular_velocity: Some(angular_velocity), .. }) = sensor.read(i2c) {
+            sum = [0, 1, 2].map(|axis| sum[axis] + angular_velocity[axis]);
+            count += 1;
+        }
+        thread::sleep(SAMPLE_INTERVAL);
+    }
+    if count == 0 {
+        return [0.0; 3];
+    }
+    sum.map(|value| value / count as f32)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|axis| a[axis] - b[axis])
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+// Rotates a vector by the conjugate of the quaternion, i.e. from the world into the panel's frame
+fn rotate_inverse(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let axis = [-q[0], -q[1], -q[2]];
+    let t = cross(axis, v).map(|value| value * 2.0);
+    let u = cross(axis, t);
+    [0, 1, 2].map(|i| v[i] + q[3] * t[i] + u[i])
+}
+
+// Advances the orientation by the angular velocity in the panel's frame (a complementary filter). The gyro is
+// integrated for fast and smooth changes, and the tilt is slowly pulled towards the measured gravity so it does not drift.
+// Heading drift can not be corrected without a magnetometer.
+fn fuse(q: [f32; 4], angular_velocity: [f32; 3], gravity: [f32; 3], elapsed_time: f32) -> [f32; 4] {
+    let mut rate = angular_velocity;
+    let gravity_length = length(gravity);
+    if (gravity_length - 1.0).abs() < TILT_CORRECTION_MAX_ERROR_G {
+        // Turning around the cross product of the measured and predicted gravity brings the prediction closer
+        let predicted = rotate_inverse(q, [0.0, -1.0, 0.0]);
+        let error = cross(gravity.map(|value| value / gravity_length), predicted);
+        rate = [0, 1, 2].map(|axis| rate[axis] + error[axis] * TILT_CORRECTION);
+    }
+
+    let [x, y, z] = rate.map(|value| value * elapsed_time * 0.5);
+    let [qx, qy, qz, qw] = q;
+    let q = [
+        qx + qw * x + qy * z - qz * y,
+        qy + qw * y + qz * x - qx * z,
+        qz + qw * z + qx * y - qy * x,
+        qw - qx * x - qy * y - qz * z,
+    ];
+    let norm = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    q.map(|value| value / norm)
+}
+
+fn smooth(previous: [f32; 3], sample: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|axis| previous[axis] + (sample[axis] - previous[axis]) * SMOOTHING)
+}
+
+// Returns the clockwise quarter turns which keep the image upright for the given gravity,
+// the current ones are kept while the device lies flat or is held close to a diagonal
+fn orientation(gravity: [f32; 3], current_quarter_turns: u32) -> u32 {
+    let [x, y, _] = gravity;
+    let (major, minor) = if x.abs() > y.abs() { (x, y) } else { (y, x) };
+    if major.abs() < ORIENTATION_MIN_G || major.abs() < minor.abs() * ORIENTATION_MARGIN {
+        return current_quarter_turns;
+    }
+
+    match (x.abs() > y.abs(), major > 0.0) {
+        (false, false) => 0, // Upright
+        (true, false) => 1, // Top of the panel points left
+        (false, true) => 2, // Upside down
+        (true, true) => 3, // Top of the panel points right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_sensor_registers() {
+        // 1g on X, -0.5g on Y, 0 on Z, and 0, 0, -1°/s on the gyro
+        let mpu6050 = Sensor::Mpu6050.decode(&[0x40, 0x00, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7D]);
+        assert_eq!(mpu6050.acceleration, [1.0, -0.5, 0.0]);
+        assert!((mpu6050.angular_velocity.unwrap()[2] + 1.0f32.to_radians()).abs() < 1e-6);
+        let lsm6ds3 = Sensor::Lsm6ds3.decode(&[0x00, 0x00, 0x00, 0x00, 0x8E, 0xFF, 0x00, 0x40, 0x00, 0xE0, 0x00, 0x00]);
+        assert_eq!(lsm6ds3.acceleration, [1.0, -0.5, 0.0]);
+        assert!((lsm6ds3.angular_velocity.unwrap()[2] + 1.0f32.to_radians()).abs() < 1e-4);
+        let lis3dh = Sensor::Lis3dh.decode(&[0x00, 0x40, 0x00, 0xE0, 0x00, 0x00]);
+        assert_eq!(lis3dh, Sample { acceleration: [1.0, -0.5, 0.0], angular_velocity: None });
+    }
+
+    #[test]
+    fn fusion_integrates_gyro() {
+        // A quarter turn around z within a second, while gravity is not usable
+        let mut q = IDENTITY;
+        for _ in 0..100 {
+            q = fuse(q, [0.0, 0.0, std::f32::consts::FRAC_PI_2], [0.0; 3], 0.01);
+        }
+        let half_angle = std::f32::consts::FRAC_PI_4;
+        assert!((q[2] - half_angle.sin()).abs() < 1e-3 && (q[3] - half_angle.cos()).abs() < 1e-3, "{:?}", q);
+    }
+
+    #[test]
+    fn fusion_converges_to_measured_tilt() {
+        // Lying flat with the screen up, gravity points into the screen
+        let mut q = IDENTITY;
+        for _ in 0..1000 {
+            q = fuse(q, [0.0; 3], [0.0, 0.0, -1.0], 0.02);
+        }
+        let predicted = rotate_inverse(q, [0.0, -1.0, 0.0]);
+        assert!(length(sub(predicted, [0.0, 0.0, -1.0])) < 1e-3, "{:?}", predicted);
+    }
+
+    #[test]
+    fn smoothing_converges_to_sample() {
+        let mut gravity = [0.0; 3];
+        for _ in 0..100 {
+            gravity = smooth(gravity, [0.0, -1.0, 0.0]);
+        }
+        assert!((gravity[1] + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn orientation_follows_dominant_axis() {
+        assert_eq!(orientation([0.0, -1.0, 0.0], 2), 0);
+        assert_eq!(orientation([-1.0, 0.0, 0.0], 0), 1);
+        assert_eq!(orientation([0.0, 1.0, 0.0], 0), 2);
+        assert_eq!(orientation([1.0, 0.0, 0.0], 0), 3);
+    }
+
+    #[test]
+    fn orientation_is_kept_when_flat_or_diagonal() {
+        assert_eq!(orientation([0.1, -0.2, -1.0], 3), 3);
+        assert_eq!(orientation([0.6, -0.7, 0.0], 3), 3);
+        assert_eq!(orientation([0.3, -0.9, 0.0], 3), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use rppal::i2c::I2c;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500); // The TSL2561 integrates for 402 ms
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const SMOOTHING: f32 = 0.2; // Weight of a new sample, so passing shadows do not make the display flicker
+const MIN_BRIGHTNESS: f32 = 0.1; // In the dark, so the display stays readable
+const FULL_BRIGHTNESS_LUX: f32 = 1000.0; // About an overcast day or a bright office
+
+// Supported sensors with the I2C addresses they can be strapped to
+const SENSORS: [(Sensor, u16); 5] = [(Sensor::Tsl2561, 0x39), (Sensor::Tsl2561, 0x29), (Sensor::Tsl2561, 0x49), (Sensor::Bh1750, 0x23), (Sensor::Bh1750, 0x5C)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sensor {
+    Tsl2561,
+    Bh1750,
+}
+
+impl Sensor {
+    // Whether the sensor answers at the current address, the BH1750 has no identification register
+    fn detect(self, i2c: &mut I2c) -> bool {
+        match self {
+            Sensor::Tsl2561 => i2c.smbus_read_byte(0x8A).is_ok_and(|id| id >> 4 == 0x5 || id >> 4 == 0x1), // ID: TSL2561 or TSL2560
+            Sensor::Bh1750 => i2c.write(&[0x01]).is_ok(), // Power on
+        }
+    }
+
+    // Starts the measurements, also needed after the sensor lost power
+    fn configure(self, i2c: &mut I2c) -> rppal::i2c::Result<()> {
+        match self {
+            Sensor::Tsl2561 => {
+                i2c.smbus_write_byte(0x80, 0x03)?; // CONTROL: power on
+                i2c.smbus_write_byte(0x81, 0x02) // TIMING: 1x gain, 402 ms
+            }
+            Sensor::Bh1750 => i2c.write(&[0x10]).map(|_| ()), // Continuous high resolution mode, 1 lx per count
+        }
+    }
+
+    fn read_lux(self, i2c: &mut I2c) -> rppal::i2c::Result<f32> {
+        match self {
+            Sensor::Tsl2561 => {
+                let visible_and_infrared = i2c.smbus_read_word(0xAC)?; // DATA0
+                let infrared = i2c.smbus_read_word(0xAE)?; // DATA1
+                Ok(tsl2561_lux(visible_and_infrared, infrared))
+            }
+            Sensor::Bh1750 => {
+                let mut bytes = [0u8; 2];
+                i2c.read(&mut bytes)?;
+                Ok(u16::from_be_bytes(bytes) as f32 / 1.2)
+            }
+        }
+    }
+}
+
+// Lux from the two channels of the TSL2561 (T package), from the datasheet's empirical formula.
+// The formula expects 16x gain, the counts at 1x gain are scaled up instead of saturating in daylight.
+fn tsl2561_lux(visible_and_infrared: u16, infrared: u16) -> f32 {
+    let channel_0 = visible_and_infrared as f32 * 16.0;
+    let channel_1 = infrared as f32 * 16.0;
+    if channel_0 == 0.0 {
+        return 0.0;
+    }
+    let ratio = channel_1 / channel_0;
+    let lux = match ratio {
+        _ if ratio <= 0.5 => 0.0304 * channel_0 - 0.062 * channel_0 * ratio.powf(1.4),
+        _ if ratio <= 0.61 => 0.0224 * channel_0 - 0.031 * channel_1,
+        _ if ratio <= 0.8 => 0.0128 * channel_0 - 0.0153 * channel_1,
+        _ if ratio <= 1.3 => 0.00146 * channel_0 - 0.00112 * channel_1,
+        _ => 0.0,
+    };
+    lux.max(0.0)
+}
+
+// Brightness for the given illuminance, following the eye's logarithmic response
+fn brightness_for_lux(lux: f32) -> f32 {
+    let level = (1.0 + lux.max(0.0)).log10() / (1.0 + FULL_BRIGHTNESS_LUX).log10();
+    MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * level.clamp(0.0, 1.0)
+}
+
+// Reads a TSL2561 or BH1750 ambient light sensor on I2C bus 1 in the background
+pub struct AmbientLight {
+    brightness: Arc<Mutex<f32>>,
+}
+
+impl AmbientLight {
+    // Finds a supported sensor and starts reading it, fails if there is none
+    pub fn start() -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::new()?;
+        let sensor = detect(&mut i2c)?.ok_or("No TSL2561 or BH1750 found on I2C bus 1")?;
+        sensor.configure(&mut i2c)?;
+
+        let brightness = Arc::new(Mutex::new(1.0));
+        let thread_brightness = brightness.clone();
+        thread::spawn(move || read_loop(i2c, sensor, thread_brightness));
+        Ok(Self { brightness })
+    }
+
+    // Smoothed brightness the display should have in the current light, from MIN_BRIGHTNESS to 1
+    pub fn brightness(&self) -> f32 {
+        *self.brightness.lock().unwrap()
+    }
+}
+
+fn detect(i2c: &mut I2c) -> rppal::i2c::Result<Option<Sensor>> {
+    for (sensor, address) in SENSORS {
+        i2c.set_slave_address(address)?;
+        if sensor.detect(i2c) {
+            println!("Found {:?} ambient light sensor at I2C address {:#04x}", sensor, address);
+            return Ok(Some(sensor));
+        }
+    }
+    Ok(None)
+}
+
+// Keeps the last brightness while the sensor is disconnected and configures it again once it answers
+fn read_loop(mut i2c: I2c, sensor: Sensor, brightness: Arc<Mutex<f32>>) {
+    let mut connected = true;
+    let mut first_sample = true;
+    loop {
+        match sensor.read_lux(&mut i2c) {
+            Ok(_) if !connected => {
+                println!("Ambient light sensor reconnected");
+                connected = true;
+                if let Err(err) = sensor.configure(&mut i2c) {
+                    println!("Warning: Failed to configure ambient light sensor: {}", err);
+                }
+            }
+            Ok(lux) => {
+                let target = brightness_for_lux(lux);
+                let mut brightness = brightness.lock().unwrap();
+                *brightness = if first_sample { target } else { *brightness + (target - *brightness) * SMOOTHING };
+                first_sample = false;
+            }
+            Err(err) => {
+                if connected {
+                    println!("Warning: Failed to read ambient light sensor ({}), retrying every {} seconds", err, RETRY_INTERVAL.as_secs());
+                    connected = false;
+                }
+                thread::sleep(RETRY_INTERVAL);
+                continue;
+            }
+        }
+        thread::sleep(SAMPLE_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tsl2561_lux_follows_datasheet() {
+        assert_eq!(tsl2561_lux(0, 0), 0.0);
+        // Mostly visible light: 0.0304 * 1600 - 0.062 * 1600 * 0.1^1.4
+        assert!((tsl2561_lux(100, 10) - 44.69).abs() < 0.01, "{}", tsl2561_lux(100, 10));
+        // Only infrared
+        assert_eq!(tsl2561_lux(100, 200), 0.0);
+    }
+
+    #[test]
+    fn brightness_is_logarithmic_between_dark_and_daylight() {
+        assert_eq!(brightness_for_lux(0.0), MIN_BRIGHTNESS);
+        assert_eq!(brightness_for_lux(FULL_BRIGHTNESS_LUX), 1.0);
+        assert_eq!(brightness_for_lux(100_000.0), 1.0);
+        let dim_room = brightness_for_lux(30.0);
+        assert!(dim_room > 0.5 && dim_room < 0.6, "{}", dim_room);
+    }
+}
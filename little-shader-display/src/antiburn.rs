@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+// Pixel shift for always-on displays, so a static shader does not burn into OLED (and some LCD) panels.
+// The output moves by whole pixels around a small square, one pixel per period. The path snakes through
+// the square and then back the same way, so every step is a single pixel and the movement is hard to notice.
+#[derive(Debug, Clone, Copy)]
+pub struct AntiBurn {
+    amplitude: u32, // Largest shift from the center in pixels, in each direction
+    period: Duration, // Time between steps
+}
+
+impl AntiBurn {
+    pub fn new(amplitude: u32, period: Duration) -> Self {
+        Self { amplitude, period }
+    }
+
+    // Shift in pixels (right, down) after the given time
+    pub fn offset(&self, elapsed: Duration) -> (i32, i32) {
+        let side = 2 * self.amplitude as u64 + 1;
+        let last_index = side * side - 1;
+        if last_index == 0 || self.period.is_zero() {
+            return (0, 0);
+        }
+
+        // Forward through the square, then backward to the start
+        let step = (elapsed.as_secs_f64() / self.period.as_secs_f64()) as u64 % (2 * last_index);
+        let index = if step <= last_index { step } else { 2 * last_index - step };
+
+        // Rows alternate direction, so consecutive positions are neighbours
+        let row = index / side;
+        let column = if row.is_multiple_of(2) { index % side } else { side - 1 - index % side };
+        (column as i32 - self.amplitude as i32, row as i32 - self.amplitude as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const PERIOD: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn steps_one_pixel_at_a_time_within_amplitude() {
+        let antiburn = AntiBurn::new(2, PERIOD);
+        let offsets = (0..100).map(|step| antiburn.offset(PERIOD * step)).collect::<Vec<_>>();
+
+        for pair in offsets.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            assert_eq!((x1 - x0).abs() + (y1 - y0).abs(), 1, "{:?}", pair);
+        }
+        assert!(offsets.iter().all(|&(x, y)| x.abs() <= 2 && y.abs() <= 2));
+        assert_eq!(offsets.iter().collect::<HashSet<_>>().len(), 25);
+    }
+
+    #[test]
+    fn holds_position_within_period() {
+        let antiburn = AntiBurn::new(3, PERIOD);
+        assert_eq!(antiburn.offset(Duration::ZERO), (-3, -3));
+        assert_eq!(antiburn.offset(PERIOD / 2), (-3, -3));
+        assert_eq!(antiburn.offset(PERIOD), (-2, -3));
+    }
+
+    #[test]
+    fn zero_amplitude_does_not_move() {
+        let antiburn = AntiBurn::new(0, PERIOD);
+        assert!((0..10).all(|step| antiburn.offset(PERIOD * step) == (0, 0)));
+    }
+}
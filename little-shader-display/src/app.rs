@@ -0,0 +1,824 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+    io::Read,
+    sync::Arc,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+use winit::window::WindowId;
+
+use crate::bluetooth_server::SnapshotRequest;
+use crate::file_watcher::FileWatcher;
+use crate::input_message::{self, Command, InputMessage};
+use crate::keyboard::{self, KeyStates};
+use crate::multipass;
+use crate::mouse::Mouse;
+use crate::metrics::RenderStats;
+use crate::http_api::ShaderList;
+use crate::playlist::Playlist;
+use crate::session::{SessionPlayer, SessionRecorder};
+use crate::renderer::{self, Renderer};
+use crate::shader_metadata;
+use crate::shader_dependencies::ShaderDependencies;
+use crate::stdin_shader::{self, StdinShader};
+use crate::{SHADERS_PATH, SHUTDOWN_REQUESTED, WINDOW_TITLE};
+use crate::watchdog::Watchdog;
+#[cfg(feature = "mqtt")]
+use crate::mqtt_control::{MqttState, MqttStatusPublisher};
+use crate::audio::AudioCapture;
+use crate::camera::CameraCapture;
+use crate::telemetry::Telemetry;
+use crate::websocket_server::CommandQueue;
+use crate::video_recorder::{self, VideoRecorder};
+#[cfg(all(target_os = "linux", feature = "accel"))]
+use crate::accelerometer::Accelerometer;
+#[cfg(target_os = "linux")]
+use crate::buttons::{ButtonAction, Buttons};
+#[cfg(target_os = "linux")]
+use crate::rotary_encoder::{EncoderEvent, RotaryEncoder};
+#[cfg(target_os = "linux")]
+use crate::ambient_light::AmbientLight;
+
+const RENDER_SCALE_STEP: f32 = 0.25;
+#[cfg(target_os = "linux")]
+const BRIGHTNESS_STEP: f32 = 0.1;
+
+// State of the running app, advanced once per main loop iteration by update() and drawn by render().
+// Window events are handled as they arrive, independently of the render rate.
+pub struct App {
+    renderer: Renderer,
+    playlist: Playlist,
+    file_watcher: FileWatcher,
+    shader_dependencies: ShaderDependencies,
+    bluetooth_server: Option<Arc<Mutex<Option<String>>>>,
+    bluetooth_data: [f32; 3],
+    clients_connected: Vec<Arc<AtomicU32>>, // Connected clients of the bluetooth and WebSocket servers
+    last_message: Option<Instant>, // When the last bluetooth or MQTT message arrived
+    snapshot_requests: Vec<SnapshotRequest>,
+    command_queues: Vec<CommandQueue>, // Commands received by remote control servers, e.g. over WebSocket
+    stdin: Option<File>, // Non-blocking stdin for key commands, not used when shaders are piped to stdin
+    stdin_shader: Option<StdinShader>,
+    stdin_shader_name: Option<&'static str>, // Set while a shader piped to stdin is shown instead of the playlist
+    shader_keys_all: bool,
+    mouse: Mouse,
+    key_states: KeyStates, // Keys held and toggled for the keyboard channel
+    refresh_interval: Option<f32>,
+    shader_shown_at: Instant, // When the current playlist shader was switched to, for the duration in its metadata
+    start_time: Instant,
+    paused_since: Option<Instant>,
+    paused_duration: Duration, // Total time spent paused before the current pause
+    last_refresh_step: Option<u64>,
+    frame: u32,
+    fps: u32,
+    last_fps_update: Instant,
+    watchdog: Option<Watchdog>,
+    metrics: Option<Arc<Mutex<RenderStats>>>,
+    shader_list: Option<Arc<Mutex<ShaderList>>>, // Served by the HTTP API
+    session_recorder: Option<SessionRecorder>,
+    session_player: Option<SessionPlayer>,
+    audio_capture: Option<AudioCapture>,
+    telemetry: Option<Telemetry>,
+    camera_capture: Option<CameraCapture>,
+    video_recorder: Option<VideoRecorder>,
+    video_path: Option<PathBuf>, // File videos are recorded to, a new timestamped file for each recording if not set
+    video_fps: f32,
+    video_exit_after: Option<f32>, // Seconds of video after which the recording stops and the app exits
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<MqttStatusPublisher>,
+    #[cfg(all(target_os = "linux", feature = "accel"))]
+    accelerometer: Option<(Accelerometer, bool)>, // With whether the ST7789 follows the device orientation
+    #[cfg(target_os = "linux")]
+    buttons: Option<Buttons>,
+    #[cfg(target_os = "linux")]
+    rotary_encoder: Option<(RotaryEncoder, Option<String>)>, // With the user param it changes, the selected one if none
+    #[cfg(target_os = "linux")]
+    ambient_light: Option<AmbientLight>,
+}
+
+impl App {
+    pub fn new(
+        renderer: Renderer,
+        playlist: Playlist,
+        file_watcher: FileWatcher,
+        bluetooth_server: Option<Arc<Mutex<Option<String>>>>,
+        stdin: Option<File>,
+        shader_keys_all: bool,
+        refresh_interval: Option<f32>,
+    ) -> Self {
+        Self {
+            renderer,
+            playlist,
+            file_watcher,
+            shader_dependencies: ShaderDependencies::scan(&SHADERS_PATH.join("uncompiled")),
+            bluetooth_server,
+            bluetooth_data: [0.0; 3],
+            clients_connected: Vec::new(),
+            last_message: None,
+            snapshot_requests: Vec::new(),
+            command_queues: Vec::new(),
+            stdin,
+            stdin_shader: None,
+            stdin_shader_name: None,
+            shader_keys_all,
+            mouse: Mouse::new(),
+            key_states: KeyStates::new(),
+            refresh_interval,
+            start_time: Instant::now(),
+            shader_shown_at: Instant::now(),
+            paused_since: None,
+            paused_duration: Duration::ZERO,
+            last_refresh_step: None,
+            frame: 0,
+            fps: 0,
+            last_fps_update: Instant::now(),
+            watchdog: None,
+            metrics: None,
+            shader_list: None,
+            session_recorder: None,
+            session_player: None,
+            audio_capture: None,
+            telemetry: None,
+            camera_capture: None,
+            video_recorder: None,
+            video_path: None,
+            video_fps: video_recorder::DEFAULT_FPS,
+            video_exit_after: None,
+            #[cfg(feature = "mqtt")]
+            mqtt: None,
+            #[cfg(all(target_os = "linux", feature = "accel"))]
+            accelerometer: None,
+            #[cfg(target_os = "linux")]
+            buttons: None,
+            #[cfg(target_os = "linux")]
+            rotary_encoder: None,
+            #[cfg(target_os = "linux")]
+            ambient_light: None,
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    pub fn set_mqtt(&mut self, mqtt: MqttStatusPublisher) {
+        self.mqtt = Some(mqtt);
+    }
+
+    // Feeds the audio channel with the spectrum and waveform of the captured audio every frame
+    pub fn set_audio_capture(&mut self, audio_capture: AudioCapture) {
+        self.audio_capture = Some(audio_capture);
+    }
+
+    // Feeds the CPU, memory and temperature uniforms with the latest system stats
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = Some(telemetry);
+    }
+
+    // Feeds the camera channel with the latest camera frame
+    pub fn set_camera_capture(&mut self, camera_capture: CameraCapture) {
+        self.camera_capture = Some(camera_capture);
+    }
+
+    // Records videos at the given frame rate, R starts and stops recording. With a path the recording starts right away,
+    // with a duration the app exits once the video is that long.
+    pub fn set_video_options(&mut self, path: Option<PathBuf>, fps: f32, duration: Option<f32>) {
+        self.video_path = path;
+        self.video_fps = fps;
+        self.video_exit_after = duration;
+        if self.video_path.is_some() {
+            self.start_video_recording();
+        }
+    }
+
+    // Switches shaders, pauses and changes the display brightness when GPIO buttons are pressed
+    #[cfg(target_os = "linux")]
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = Some(buttons);
+    }
+
+    // Turning the encoder steps the named user param (or the selected one), pushing it switches to the next shader
+    #[cfg(target_os = "linux")]
+    pub fn set_rotary_encoder(&mut self, rotary_encoder: RotaryEncoder, param_name: Option<String>) {
+        self.rotary_encoder = Some((rotary_encoder, param_name));
+    }
+
+    // Dims the display backlight and sets the ambient_brightness uniform for the light measured by the sensor
+    #[cfg(target_os = "linux")]
+    pub fn set_ambient_light(&mut self, ambient_light: AmbientLight) {
+        self.ambient_light = Some(ambient_light);
+    }
+
+    // Feeds the gravity uniform from the accelerometer, and with auto_rotate keeps the ST7789 image upright
+    #[cfg(all(target_os = "linux", feature = "accel"))]
+    pub fn set_accelerometer(&mut self, accelerometer: Accelerometer, auto_rotate: bool) {
+        self.accelerometer = Some((accelerometer, auto_rotate));
+    }
+
+    // Adds the number of clients connected to a server to the clients_connected uniform
+    pub fn add_clients_connected(&mut self, clients_connected: Arc<AtomicU32>) {
+        self.clients_connected.push(clients_connected);
+    }
+
+    // Lets the clients of a server request the current frame
+    pub fn add_snapshot_request(&mut self, snapshot_request: SnapshotRequest) {
+        self.snapshot_requests.push(snapshot_request);
+    }
+
+    // Handles the commands received by a server like MQTT ones
+    pub fn add_command_queue(&mut self, command_queue: CommandQueue) {
+        self.command_queues.push(command_queue);
+    }
+
+    pub fn set_metrics(&mut self, metrics: Arc<Mutex<RenderStats>>) {
+        self.metrics = Some(metrics);
+    }
+
+    // Keeps the shader list served by the HTTP API up to date
+    pub fn set_shader_list(&mut self, shader_list: Arc<Mutex<ShaderList>>) {
+        self.shader_list = Some(shader_list);
+    }
+
+    // Records the applied commands, starting with the current shader so a replay begins with it
+    pub fn set_session_recorder(&mut self, session_recorder: SessionRecorder) {
+        self.session_recorder = Some(session_recorder);
+        self.record(Command { shader: Some(self.playlist.current().to_string()), ..Default::default() });
+    }
+
+    // Replays a recorded session instead of waiting for input, the animation starts along with it
+    pub fn set_session_player(&mut self, session_player: SessionPlayer) {
+        self.session_player = Some(session_player);
+        self.restart_animation();
+    }
+
+    // Shows the shaders piped to stdin as they arrive, switching shaders returns to the playlist
+    pub fn set_stdin_shader(&mut self, stdin_shader: StdinShader) {
+        self.stdin_shader = Some(stdin_shader);
+    }
+
+    pub fn set_watchdog(&mut self, watchdog: Watchdog) {
+        self.watchdog = Some(watchdog);
+    }
+
+    // Handles a single event of one of the windows, returns false if a window was closed
+    pub fn handle_window_event(&mut self, window_id: WindowId, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CloseRequested => return false,
+            WindowEvent::Resized(size) => {
+                self.renderer.resize(window_id, size.width, size.height);
+            }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                self.renderer.resize(window_id, new_inner_size.width, new_inner_size.height);
+            }
+            WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(key), state, .. }, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                if self.key_states.key_changed(*key, pressed, self.shader_keys_all) {
+                    self.renderer.set_keyboard(&self.key_states.texels());
+                }
+                match keyboard::key_bit(*key, self.shader_keys_all) {
+                    Some(bit) => self.renderer.set_key_pressed(bit, pressed),
+                    None if pressed && keyboard::APP_CONTROL_KEYS.contains(key) => self.handle_app_control_key(*key),
+                    None => (),
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(window_size) = self.renderer.window_size(window_id) {
+                    self.mouse.cursor_moved(position.x, position.y, window_size);
+                }
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.mouse.button_changed(*state == ElementState::Pressed);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn handle_app_control_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Space => self.next_shader(),
+            VirtualKeyCode::B => self.previous_shader(),
+            VirtualKeyCode::H => {
+                self.renderer.toggle_hud();
+                self.last_refresh_step = None;
+            }
+            VirtualKeyCode::U => self.print_uniforms(),
+            VirtualKeyCode::P => self.select_next_user_param(),
+            VirtualKeyCode::R => self.toggle_video_recording(),
+            VirtualKeyCode::Minus => self.step_user_param(-1.0),
+            VirtualKeyCode::Equals => self.step_user_param(1.0),
+            VirtualKeyCode::LBracket => self.renderer.set_render_scale(self.renderer.render_scale() - RENDER_SCALE_STEP),
+            VirtualKeyCode::RBracket => self.renderer.set_render_scale(self.renderer.render_scale() + RENDER_SCALE_STEP),
+            _ => (),
+        }
+    }
+
+    // Handles a text command received from a remote control (e.g. MQTT or WebSocket), JSON commands are handled like bluetooth ones
+    fn handle_command(&mut self, command: &str) {
+        if let Some(path) = command.strip_prefix("loaddir ") {
+            self.load_directory(Path::new(path.trim()));
+            return;
+        }
+        match command {
+            "next" => self.next_shader(),
+            "previous" => self.previous_shader(),
+            "uniforms" => self.print_uniforms(),
+            _ if command.starts_with('{') => self.handle_input_message(command),
+            _ => println!("Warning: Unknown command: {}", command),
+        }
+    }
+
+    // Switches to another shaders directory (e.g. a downloaded shader pack) and starts its first shader.
+    // The directory needs the layout of res/shaders, with master.vert and at least one fragment shader in uncompiled.
+    fn load_directory(&mut self, path: &Path) {
+        let uncompiled_path = path.join("uncompiled");
+        if !uncompiled_path.join("master.vert").is_file() {
+            println!("Warning: Not loading {}, it has no uncompiled/master.vert", path.display());
+            return;
+        }
+        let shader_names = Playlist::scan(&uncompiled_path);
+        if shader_names.is_empty() {
+            println!("Warning: Not loading {}, it has no fragment shaders in uncompiled", path.display());
+            return;
+        }
+
+        println!("Loading shaders directory {} with {} shaders", path.display(), shader_names.len());
+        self.renderer.set_shaders_path(path.to_path_buf());
+        self.playlist = Playlist::new(shader_names);
+        self.file_watcher = FileWatcher::new(uncompiled_path.clone());
+        self.shader_dependencies = ShaderDependencies::scan(&uncompiled_path);
+        self.renderer.recompile_shaders(self.playlist.current(), true, false);
+        self.switch_to_current_shader();
+    }
+
+    // Applies a line received over bluetooth, either legacy values or a JSON command
+    fn handle_input_message(&mut self, text: &str) {
+        match input_message::parse(text) {
+            Some(InputMessage::Values(values)) => self.set_bluetooth_data(values),
+            Some(InputMessage::Command(command)) => self.apply_command(command),
+            None => (),
+        }
+    }
+
+    fn apply_command(&mut self, command: Command) {
+        if let Some(params) = command.params_uniform() {
+            self.set_bluetooth_data(params);
+        }
+        if let Some(shader_name) = &command.shader {
+            if shader_name != self.playlist.current() {
+                if self.playlist.select(shader_name) {
+                    self.switch_to_current_shader();
+                } else {
+                    println!("Warning: Unknown shader in command: {}", shader_name);
+                }
+            }
+        }
+        for (name, value) in command.user_params.iter().flatten() {
+            self.set_user_param(name, *value);
+        }
+        if let Some(pause) = command.pause {
+            self.set_paused(pause);
+        }
+    }
+
+    fn set_bluetooth_data(&mut self, values: [f32; 3]) {
+        if values != self.bluetooth_data {
+            self.bluetooth_data = values;
+            self.record(Command { params: Some(values.to_vec()), ..Default::default() });
+        }
+    }
+
+    fn set_user_param(&mut self, name: &str, value: f32) {
+        match self.renderer.set_user_param(name, value) {
+            Ok(value) => self.record_user_param(name, value),
+            Err(err) => println!("Warning: Ignoring user param: {}", err),
+        }
+    }
+
+    // Selects the next user param of the current shader for the - and = keys
+    fn select_next_user_param(&mut self) {
+        match self.renderer.select_next_user_param() {
+            Some(name) => println!("Selected param {}", name),
+            None => println!("The shader has no params"),
+        }
+    }
+
+    fn step_user_param(&mut self, steps: f32) {
+        if let Some((name, value)) = self.renderer.step_user_param(steps) {
+            println!("Param {} = {}", name, value);
+            self.record_user_param(&name, value);
+        }
+    }
+
+    fn record_user_param(&mut self, name: &str, value: f32) {
+        self.record(Command { user_params: Some(BTreeMap::from([(name.to_string(), value)])), ..Default::default() });
+    }
+
+    fn record(&mut self, command: Command) {
+        if let Some(session_recorder) = &mut self.session_recorder {
+            session_recorder.record(command);
+        }
+    }
+
+    // Starts the animation over from time 0 with no input, as when the app started
+    fn restart_animation(&mut self) {
+        self.start_time = Instant::now();
+        self.paused_since = None;
+        self.paused_duration = Duration::ZERO;
+        self.bluetooth_data = [0.0; 3];
+        self.last_refresh_step = None;
+    }
+
+    // Stops or resumes the animation time, real_time keeps running
+    fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_since) {
+            (true, None) => {
+                self.paused_since = Some(Instant::now());
+                self.record(Command { pause: Some(true), ..Default::default() });
+                println!("Animation paused");
+            }
+            (false, Some(paused_since)) => {
+                self.paused_duration += paused_since.elapsed();
+                self.shader_shown_at += paused_since.elapsed(); // The shader duration does not count the pause
+                self.paused_since = None;
+                self.record(Command { pause: Some(false), ..Default::default() });
+                println!("Animation resumed");
+            }
+            _ => (),
+        }
+    }
+
+    // Seconds since start without the time spent paused, while recording a video it advances by one frame per frame
+    fn animation_time(&self) -> f32 {
+        if let Some(video_recorder) = &self.video_recorder {
+            return video_recorder.time();
+        }
+        let current_pause = self.paused_since.map_or(Duration::ZERO, |paused_since| paused_since.elapsed());
+        (self.start_time.elapsed() - self.paused_duration - current_pause).as_secs_f32()
+    }
+
+    // Name of the shown fragment shader
+    fn current_shader(&self) -> &str {
+        self.stdin_shader_name.unwrap_or(self.playlist.current())
+    }
+
+    // Frame rate limit of the current shader, None to use --max-fps. Videos are rendered at their frame rate.
+    pub fn shader_target_fps(&self) -> Option<f32> {
+        if let Some(video_recorder) = &self.video_recorder {
+            return Some(video_recorder.fps());
+        }
+        self.renderer.shader_target_fps()
+    }
+
+    // Window title with the current shader, and the FPS unless in slow refresh mode
+    pub fn window_title(&self) -> String {
+        match self.refresh_interval {
+            Some(_) => format!("{} — {}", WINDOW_TITLE, self.current_shader()),
+            None => format!("{} — {} — {} FPS", WINDOW_TITLE, self.current_shader(), self.fps),
+        }
+    }
+
+    fn print_uniforms(&self) {
+        println!("Uniforms of {}: {}", self.current_shader(), self.renderer.uniforms_json());
+    }
+
+    fn next_shader(&mut self) {
+        self.playlist.next();
+        self.switch_to_current_shader();
+    }
+
+    fn previous_shader(&mut self) {
+        self.playlist.previous();
+        self.switch_to_current_shader();
+    }
+
+    fn switch_to_current_shader(&mut self) {
+        self.stdin_shader_name = None;
+        println!("Switched to shader index: {} ({})", self.playlist.current_index(), self.playlist.current());
+        self.renderer.recompile_shaders(self.playlist.current(), false, true);
+        self.shader_shown_at = Instant::now();
+        self.last_refresh_step = None;
+        self.record(Command { shader: Some(self.playlist.current().to_string()), ..Default::default() });
+    }
+
+    // Polls bluetooth, stdin and the file watcher and updates the uniforms, returns true if a frame should be rendered
+    pub fn update(&mut self) -> bool {
+        // 1. Tell the watchdog the loop is alive, reaching here means the previous frame completed
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.feed();
+        }
+
+        // 2. Check for data received by bluetooth server
+        let received_text = self.bluetooth_server.as_ref()
+            .and_then(|received_text| received_text.try_lock().ok()?.take());
+        if let Some(text) = received_text {
+            self.last_message = Some(Instant::now());
+            self.handle_input_message(&text);
+        }
+
+        // Answer snapshot requests with the current frame, an empty reply tells the client the capture failed
+        while let Some(snapshot_sender) = self.snapshot_requests.iter().find_map(|snapshot_request| snapshot_request.try_lock().ok()?.take()) {
+            let png_bytes = self.renderer.capture_png().unwrap_or_else(|err| {
+                println!("Warning: Failed to capture snapshot: {}", err);
+                Vec::new()
+            });
+            _ = snapshot_sender.send(png_bytes);
+        }
+
+        // 3. Handle user input to switch shaders or print uniforms
+        let mut buffer = [0u8; 1];
+        if self.stdin.as_ref().is_some_and(|mut stdin| stdin.read(&mut buffer).is_ok()) {
+            match buffer[0] {
+                b' ' => self.next_shader(),
+                b'b' => self.previous_shader(),
+                b'u' => self.print_uniforms(),
+                b'p' => self.select_next_user_param(),
+                b'r' => self.toggle_video_recording(),
+                b'-' => self.step_user_param(-1.0),
+                b'=' => self.step_user_param(1.0),
+                _ => (),
+            }
+        }
+
+        // Handle presses of the GPIO buttons
+        #[cfg(target_os = "linux")]
+        while let Some(action) = self.buttons.as_ref().and_then(Buttons::take_pressed) {
+            match action {
+                ButtonAction::Next => self.next_shader(),
+                ButtonAction::Previous => self.previous_shader(),
+                ButtonAction::Brighter => self.renderer.set_brightness(self.renderer.brightness() + BRIGHTNESS_STEP),
+                ButtonAction::Dimmer => self.renderer.set_brightness(self.renderer.brightness() - BRIGHTNESS_STEP),
+                ButtonAction::Pause => self.set_paused(self.paused_since.is_none()),
+            }
+        }
+
+        // Handle turns and presses of the rotary encoder
+        #[cfg(target_os = "linux")]
+        while let Some(event) = self.rotary_encoder.as_ref().and_then(|(encoder, _)| encoder.take_event()) {
+            match (event, self.rotary_encoder.as_ref().and_then(|(_, param_name)| param_name.clone())) {
+                (EncoderEvent::Turned(clicks), Some(param_name)) => match self.renderer.step_named_user_param(&param_name, clicks as f32) {
+                    Ok(value) => {
+                        println!("Param {} = {}", param_name, value);
+                        self.record_user_param(&param_name, value);
+                    }
+                    Err(err) => println!("Warning: Rotary encoder can not change {}: {}", param_name, err),
+                },
+                (EncoderEvent::Turned(clicks), None) => self.step_user_param(clicks as f32),
+                (EncoderEvent::Pressed, _) => self.next_shader(),
+            }
+        }
+
+        // Show the latest shader piped to stdin
+        if let Some(source) = self.stdin_shader.as_ref().and_then(StdinShader::take_latest) {
+            let shader_name = stdin_shader::shader_name(&source);
+            println!("Shader received on stdin ({} lines)", source.lines().count());
+            self.stdin_shader_name = Some(shader_name);
+            self.renderer.compile_fragment_source(shader_name, &source);
+            self.last_refresh_step = None;
+        }
+
+        // Handle commands received by the remote control servers
+        while let Some(command) = self.command_queues.iter().find_map(|command_queue| command_queue.try_lock().ok()?.pop_front()) {
+            self.last_message = Some(Instant::now());
+            self.handle_command(&command);
+        }
+
+        // Handle commands received over MQTT
+        #[cfg(feature = "mqtt")]
+        while let Some(command) = self.mqtt.as_ref().and_then(|mqtt| mqtt.take_command()) {
+            self.last_message = Some(Instant::now());
+            self.handle_command(&command);
+        }
+
+        // Replay the recorded session, the animation starts over whenever the session loops
+        if let Some(session_player) = &mut self.session_player {
+            let (restarted, commands) = session_player.poll();
+            if restarted {
+                println!("Session restarted");
+                self.restart_animation();
+            }
+            for command in commands {
+                self.apply_command(command);
+            }
+        }
+
+        // Advance the playlist once the current shader was shown for the duration in its metadata, unless paused
+        let duration_elapsed = self.renderer.shader_duration().is_some_and(|duration| self.shader_shown_at.elapsed().as_secs_f32() >= duration);
+        if duration_elapsed && self.stdin_shader_name.is_none() && self.paused_since.is_none() {
+            self.next_shader();
+        }
+
+        // 4. Calculate elapsed time, in slow refresh mode snap it to the start of the current refresh interval
+        // so the displayed frame is deterministic
+        let real_time = self.start_time.elapsed().as_secs_f32();
+        let mut elapsed_time = self.animation_time();
+        let mut refresh_due = true;
+        if let Some(interval) = self.refresh_interval {
+            let refresh_step = (elapsed_time / interval).floor() as u64;
+            refresh_due = self.last_refresh_step != Some(refresh_step);
+            self.last_refresh_step = Some(refresh_step);
+            elapsed_time = refresh_step as f32 * interval;
+        }
+
+        // Read the device orientation
+        #[cfg(all(target_os = "linux", feature = "accel"))]
+        if let Some((accelerometer, auto_rotate)) = &self.accelerometer {
+            self.renderer.set_gravity(accelerometer.gravity());
+            self.renderer.set_motion(accelerometer.angular_velocity(), accelerometer.orientation());
+            if *auto_rotate {
+                self.renderer.set_st7789_rotation(accelerometer.quarter_turns());
+            }
+        }
+
+        // Analyze the latest audio for the audio channel
+        if let Some(audio_capture) = &mut self.audio_capture {
+            let audio_frame = audio_capture.analyze();
+            self.renderer.set_audio(&audio_frame.spectrum, &audio_frame.waveform, audio_frame.loudness, audio_frame.beat);
+        }
+
+        // Follow the ambient light
+        #[cfg(target_os = "linux")]
+        if let Some(ambient_light) = &self.ambient_light {
+            self.renderer.set_ambient_brightness(ambient_light.brightness());
+        }
+
+        // Pass the latest system stats
+        if let Some(telemetry) = &self.telemetry {
+            self.renderer.set_system_stats(telemetry.stats());
+        }
+
+        // Upload the camera frame if a new one arrived
+        if let Some(camera_capture) = &self.camera_capture {
+            if let Some(frame) = camera_capture.take_frame() {
+                self.renderer.set_camera(&frame, camera_capture.size());
+            }
+        }
+
+        // 5. Update uniform buffer with the new values
+        let clients_connected = self.clients_connected.iter().map(|clients| clients.load(Ordering::Relaxed)).sum();
+        self.renderer.set_remote_input_state(clients_connected, self.last_message.map(|time| time.elapsed().as_secs_f32()));
+        self.renderer.update_uniforms(elapsed_time, real_time, self.bluetooth_data, self.mouse.next_frame_uniform());
+
+        // 6. FPS Calculation: Print FPS every second
+        if self.refresh_interval.is_none() && self.last_fps_update.elapsed() >= Duration::from_secs(1) {
+            println!("FPS: {}", self.frame);
+            self.fps = self.frame;
+            self.frame = 0; // Reset counter
+            self.last_fps_update = Instant::now(); // Reset timer
+        }
+
+        // Share the stats with the metrics server, skipped this frame if it is reading them
+        if let Some(mut stats) = self.metrics.as_ref().and_then(|metrics| metrics.try_lock().ok()) {
+            stats.fps = self.fps;
+            if stats.shader != self.current_shader() {
+                stats.shader = self.current_shader().to_string();
+            }
+            if stats.shader_error.as_deref() != self.renderer.shader_error() {
+                stats.shader_error = self.renderer.shader_error().map(str::to_string);
+            }
+            stats.st7789_timings = self.renderer.st7789_timings();
+        }
+
+        // Share the playlist with the HTTP API, skipped this frame if it is reading it
+        if let Some(mut shader_list) = self.shader_list.as_ref().and_then(|shader_list| shader_list.try_lock().ok()) {
+            if shader_list.shaders != self.playlist.shader_names() {
+                shader_list.shaders = self.playlist.shader_names().to_vec();
+            }
+            if shader_list.current != self.current_shader() {
+                shader_list.current = self.current_shader().to_string();
+            }
+        }
+
+        // Publish the state over MQTT, only sent when it changed
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &mut self.mqtt {
+            mqtt.publish_state(MqttState { shader: self.stdin_shader_name.unwrap_or(self.playlist.current()).to_string(), fps: self.fps });
+        }
+
+        // 7. Check for shader file changes, update the playlist, recompile shaders and recreate pipeline if necessary
+        if let Some(paths) = self.file_watcher.get_changes() {
+            for path in paths {
+                let file_name = path.file_name().unwrap();
+                let file_name_str = file_name.to_str().unwrap();
+                println!("Shader file change detected: {:?}. Name: {:?}", path, file_name);
+                self.shader_dependencies.update(&path);
+
+                // Check if the changed file is a vertex
+                if file_name_str.ends_with(".vert") {
+                    self.renderer.recompile_shaders(self.playlist.current(), true, false);
+                }
+
+                // Buffer pass shaders are not in the playlist, a change recompiles the current shader if it renders them
+                if multipass::is_buffer_shader(file_name_str) && self.stdin_shader_name.is_none() && self.renderer.uses_buffer_shader(file_name_str) {
+                    self.renderer.recompile_shaders(self.playlist.current(), false, true);
+                }
+
+                // Check if the changed file is a fragment shader, add new ones to the playlist and drop deleted ones
+                if renderer::is_fragment_shader(file_name_str) && !multipass::is_buffer_shader(file_name_str) {
+                    if !path.exists() {
+                        println!("Shader removed from playlist: {}", file_name_str);
+                        if self.playlist.remove(file_name_str) {
+                            self.switch_to_current_shader();
+                        }
+                        continue;
+                    }
+
+                    if self.playlist.add(file_name_str) {
+                        println!("Shader added to playlist: {}", file_name_str);
+                    }
+                    // The piped shader stays until another one is selected
+                    if self.stdin_shader_name.is_none() {
+                        self.renderer.recompile_shaders(self.playlist.current(), false, true);
+                    }
+                }
+
+                // Check if the changed file is the metadata of the current shader
+                if shader_metadata::is_sidecar(file_name_str) && file_name_str.trim_end_matches(".toml") == self.playlist.current() {
+                    self.renderer.reload_shader_metadata(self.playlist.current());
+                }
+
+                // Check if the changed file is included by the current shaders. Other shaders are compiled when switched to,
+                // so they pick up the change then.
+                let dependents = self.shader_dependencies.dependents(file_name_str);
+                if !dependents.is_empty() {
+                    println!("Shaders including {}: {:?}", file_name_str, dependents);
+                    let recompile_vertex_shader = dependents.iter().any(|dependent| dependent == "master.vert");
+                    let recompile_fragment_shader = self.stdin_shader_name.is_none()
+                        && dependents.iter().any(|dependent| dependent == self.playlist.current() || self.renderer.uses_buffer_shader(dependent));
+                    if recompile_vertex_shader || recompile_fragment_shader {
+                        self.renderer.recompile_shaders(self.playlist.current(), recompile_vertex_shader, recompile_fragment_shader);
+                    }
+                }
+            }
+            self.last_refresh_step = None;
+            refresh_due = true;
+        }
+
+        refresh_due
+    }
+
+    pub fn shutdown(&mut self) {
+        self.stop_video_recording();
+        if let Some(session_recorder) = &mut self.session_recorder {
+            session_recorder.save();
+        }
+        self.renderer.shutdown();
+    }
+
+    pub fn render(&mut self) {
+        let shader_label = self.stdin_shader_name.unwrap_or(self.playlist.current()).trim_end_matches(".frag").trim_end_matches(".wgsl");
+        match self.refresh_interval {
+            Some(_) => self.renderer.set_hud_text(shader_label),
+            None => self.renderer.set_hud_text(&format!("{} {} FPS", shader_label, self.fps)),
+        }
+        self.renderer.render();
+        self.frame += 1;
+        self.record_video_frame();
+    }
+
+    fn toggle_video_recording(&mut self) {
+        match self.video_recorder {
+            Some(_) => self.stop_video_recording(),
+            None => self.start_video_recording(),
+        }
+    }
+
+    fn start_video_recording(&mut self) {
+        let path = self.video_path.clone().unwrap_or_else(|| {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            PathBuf::from(format!("recording-{}.mp4", seconds))
+        });
+        match VideoRecorder::start(&path, self.renderer.capture_size(), self.video_fps, self.animation_time()) {
+            Ok(video_recorder) => {
+                println!("Recording video to {} at {} FPS", path.display(), self.video_fps);
+                self.video_recorder = Some(video_recorder);
+            }
+            Err(err) => println!("Warning: Failed to record video: {}", err),
+        }
+    }
+
+    fn stop_video_recording(&mut self) {
+        if let Some(video_recorder) = self.video_recorder.take() {
+            // Continue the animation from the recorded time, rendering is paced to the video so it is not ahead of the clock
+            let current_pause = self.paused_since.map_or(Duration::ZERO, |paused_since| paused_since.elapsed());
+            self.paused_duration = (self.start_time.elapsed() - current_pause).saturating_sub(Duration::from_secs_f32(video_recorder.time()));
+        }
+    }
+
+    // Appends the rendered frame to the video, which stops on errors and once it is as long as requested
+    fn record_video_frame(&mut self) {
+        let Some(video_recorder) = &mut self.video_recorder else {
+            return;
+        };
+        let (frame, width, height) = self.renderer.capture_frame();
+        if let Err(err) = video_recorder.write_frame(&frame, (width, height), self.paused_since.is_some()) {
+            println!("Warning: Stopping video recording: {}", err);
+            self.stop_video_recording();
+            return;
+        }
+        if self.video_exit_after.is_some_and(|duration| video_recorder.duration() >= duration) {
+            self.stop_video_recording();
+            SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Like Shadertoy's sound input: a 2048 sample FFT of which the lower 512 bins (up to about 11 kHz) are kept,
+// smoothed and mapped from -100..-30 dB to 0..255 like WebAudio's AnalyserNode, and the last 512 samples as waveform
+pub const BIN_COUNT: usize = 512;
+const FFT_SIZE: usize = 2048;
+const SAMPLE_RATE: u32 = 44100;
+const SMOOTHING: f32 = 0.8; // Weight of the previous frame's magnitudes
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+const LOUDNESS_WINDOW: usize = 1024; // About 23 ms, roughly one frame
+const BEAT_HISTORY: f32 = 1.0; // Seconds of loudness the current one is compared to
+const BEAT_THRESHOLD: f32 = 1.5; // Energy above the recent average which counts as an onset
+const BEAT_MIN_ENERGY: f32 = 1e-4; // Below this it is too quiet for beats, so noise does not trigger them
+const BEAT_MIN_INTERVAL: Duration = Duration::from_millis(250); // At most 240 beats per minute
+const BEAT_DECAY: f32 = 0.2; // Seconds the beat pulse takes to fade out
+pub const DEFAULT_SOURCE: &str = "@DEFAULT_MONITOR@"; // Monitor of the default output, so whatever is playing
+
+// Analysis of the latest samples for one frame
+pub struct AudioFrame {
+    pub spectrum: [u8; BIN_COUNT], // Bytes of the audio texture rows
+    pub waveform: [u8; BIN_COUNT],
+    pub loudness: f32, // RMS of the last LOUDNESS_WINDOW samples, 0..1
+    pub beat: f32, // 1 at an onset, fading to 0 over BEAT_DECAY
+}
+
+// Captures audio with parec in the background, which works with PulseAudio and PipeWire.
+// Samples are mono 32-bit floats, only the last FFT_SIZE samples are kept.
+pub struct AudioCapture {
+    child: Child,
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    magnitudes: Vec<f32>, // Smoothed spectrum of the previous frame
+    beat_detector: BeatDetector,
+}
+
+impl AudioCapture {
+    // Starts recording the given PulseAudio/PipeWire source, fails if parec can not be started
+    pub fn start(source: &str) -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new("parec")
+            .arg(format!("--device={}", source))
+            .args(["--format=float32le", "--channels=1", "--raw", "--latency-msec=20"])
+            .arg(format!("--rate={}", SAMPLE_RATE))
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to run parec: {}", err))?;
+        let stdout = child.stdout.take().ok_or("parec has no output")?;
+
+        let samples = Arc::new(Mutex::new(VecDeque::from(vec![0.0; FFT_SIZE])));
+        let thread_samples = samples.clone();
+        thread::spawn(move || read_loop(stdout, thread_samples));
+        Ok(Self { child, samples, magnitudes: vec![0.0; BIN_COUNT], beat_detector: BeatDetector::default() })
+    }
+
+    // Analyzes the latest samples, called once per frame
+    pub fn analyze(&mut self) -> AudioFrame {
+        let samples: Vec<f32> = self.samples.lock().unwrap().iter().copied().collect();
+        let loudness = loudness(&samples);
+        AudioFrame {
+            spectrum: spectrum(&samples, &mut self.magnitudes),
+            waveform: waveform(&samples),
+            loudness,
+            beat: self.beat_detector.update(loudness * loudness, Instant::now()),
+        }
+    }
+}
+
+// Detects onsets as jumps of the energy above its recent average
+#[derive(Default)]
+struct BeatDetector {
+    average_energy: f32,
+    last_update: Option<Instant>,
+    last_beat: Option<Instant>,
+}
+
+impl BeatDetector {
+    // Returns the beat pulse for the energy at the given time
+    fn update(&mut self, energy: f32, now: Instant) -> f32 {
+        // The average starts at the first energy, so audio which is already playing does not beat at start
+        let Some(last_update) = self.last_update.replace(now) else {
+            self.average_energy = energy;
+            return 0.0;
+        };
+        let elapsed_time = now.duration_since(last_update).as_secs_f32();
+
+        let can_beat = self.last_beat.is_none_or(|last_beat| now.duration_since(last_beat) >= BEAT_MIN_INTERVAL);
+        if can_beat && energy > BEAT_MIN_ENERGY && energy > self.average_energy * BEAT_THRESHOLD {
+            self.last_beat = Some(now);
+        }
+        // Averaged over time rather than frames, so the detection does not depend on the frame rate
+        self.average_energy += (energy - self.average_energy) * (1.0 - (-elapsed_time / BEAT_HISTORY).exp());
+
+        self.last_beat.map_or(0.0, |last_beat| (1.0 - now.duration_since(last_beat).as_secs_f32() / BEAT_DECAY).max(0.0))
+    }
+}
+
+// Root mean square of the last LOUDNESS_WINDOW samples
+fn loudness(samples: &[f32]) -> f32 {
+    let samples = &samples[samples.len() - LOUDNESS_WINDOW..];
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / LOUDNESS_WINDOW as f32).sqrt().min(1.0)
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+// Reads samples until parec exits, then leaves silence behind
+fn read_loop(mut stdout: impl Read, samples: Arc<Mutex<VecDeque<f32>>>) {
+    let mut buffer = [0u8; 4096];
+    let mut pending: Vec<u8> = Vec::new(); // Bytes of a sample split between reads
+    loop {
+        match stdout.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(count) => {
+                pending.extend_from_slice(&buffer[..count]);
+                let complete = pending.len() / 4 * 4;
+                let mut samples = samples.lock().unwrap();
+                for bytes in pending[..complete].chunks_exact(4) {
+                    samples.pop_front();
+                    samples.push_back(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+                }
+                pending.drain(..complete);
+            }
+        }
+    }
+    println!("Warning: Audio capture stopped, shaders get silence");
+    samples.lock().unwrap().iter_mut().for_each(|sample| *sample = 0.0);
+}
+
+// Blackman windowed FFT of the last FFT_SIZE samples, smoothed with the previous magnitudes and mapped to bytes
+fn spectrum(samples: &[f32], magnitudes: &mut [f32]) -> [u8; BIN_COUNT] {
+    let samples = &samples[samples.len() - FFT_SIZE..];
+    let mut real: Vec<f32> = samples.iter().enumerate().map(|(index, sample)| {
+        let phase = 2.0 * PI * index as f32 / FFT_SIZE as f32;
+        sample * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+    }).collect();
+    let mut imaginary = vec![0.0; FFT_SIZE];
+    fft(&mut real, &mut imaginary);
+
+    let mut bytes = [0u8; BIN_COUNT];
+    for bin in 0..BIN_COUNT {
+        let magnitude = (real[bin] * real[bin] + imaginary[bin] * imaginary[bin]).sqrt() / FFT_SIZE as f32;
+        magnitudes[bin] = SMOOTHING * magnitudes[bin] + (1.0 - SMOOTHING) * magnitude;
+        let decibels = 20.0 * magnitudes[bin].max(f32::MIN_POSITIVE).log10();
+        bytes[bin] = ((decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS) * 255.0).clamp(0.0, 255.0) as u8;
+    }
+    bytes
+}
+
+// The last BIN_COUNT samples mapped from -1..1 to 0..255
+fn waveform(samples: &[f32]) -> [u8; BIN_COUNT] {
+    let samples = &samples[samples.len() - BIN_COUNT..];
+    let mut bytes = [0u8; BIN_COUNT];
+    for (byte, sample) in bytes.iter_mut().zip(samples) {
+        *byte = ((sample.clamp(-1.0, 1.0) + 1.0) * 127.5).round() as u8;
+    }
+    bytes
+}
+
+// In-place iterative radix-2 FFT, the length must be a power of two
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let length = real.len();
+
+    // Reorder the samples by bit-reversed index
+    let mut target = 0;
+    for index in 0..length {
+        if index < target {
+            real.swap(index, target);
+            imaginary.swap(index, target);
+        }
+        let mut bit = length >> 1;
+        while target & bit != 0 {
+            target ^= bit;
+            bit >>= 1;
+        }
+        target |= bit;
+    }
+
+    // Combine the transforms of growing halves
+    let mut size = 2;
+    while size <= length {
+        let angle = -2.0 * PI / size as f32;
+        for start in (0..length).step_by(size) {
+            for offset in 0..size / 2 {
+                let (sin, cos) = (angle * offset as f32).sin_cos();
+                let even = start + offset;
+                let odd = even + size / 2;
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectrum_peaks_at_tone_frequency() {
+        // A quiet tone exactly at bin 64 (about 1378 Hz), loud ones fill the neighbouring bins up to 255 too
+        let samples: Vec<f32> = (0..FFT_SIZE).map(|index| 0.001 * (2.0 * PI * 64.0 * index as f32 / FFT_SIZE as f32).sin()).collect();
+        let mut magnitudes = vec![0.0; BIN_COUNT];
+        let mut bytes = [0u8; BIN_COUNT];
+        for _ in 0..50 {
+            bytes = spectrum(&samples, &mut magnitudes);
+        }
+        let peak = (0..BIN_COUNT).max_by_key(|&bin| bytes[bin]).unwrap();
+        assert_eq!(peak, 64);
+        assert_eq!(bytes[300], 0);
+    }
+
+    #[test]
+    fn silence_is_empty_spectrum_and_centered_waveform() {
+        let samples = vec![0.0; FFT_SIZE];
+        assert_eq!(spectrum(&samples, &mut [0.0; BIN_COUNT]), [0u8; BIN_COUNT]);
+        assert_eq!(waveform(&samples), [128u8; BIN_COUNT]);
+        assert_eq!(loudness(&samples), 0.0);
+    }
+
+    #[test]
+    fn loudness_is_rms() {
+        let samples: Vec<f32> = (0..FFT_SIZE).map(|index| if index % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        assert_eq!(loudness(&samples), 0.5);
+    }
+
+    #[test]
+    fn beats_are_detected_on_energy_jumps() {
+        let start = Instant::now();
+        let at = |milliseconds: u64| start + Duration::from_millis(milliseconds);
+        let mut beat_detector = BeatDetector::default();
+        // A steady hum does not beat
+        for frame in 0..60 {
+            beat_detector.update(0.01, at(frame * 20));
+        }
+        assert_eq!(beat_detector.update(0.01, at(1200)), 0.0);
+        // A kick does, and fades out
+        assert_eq!(beat_detector.update(0.1, at(1220)), 1.0);
+        assert!((beat_detector.update(0.01, at(1320)) - 0.5).abs() < 1e-3);
+        assert_eq!(beat_detector.update(0.01, at(1420)), 0.0);
+        // Silence does not
+        let mut beat_detector = BeatDetector::default();
+        assert_eq!(beat_detector.update(0.0, at(0)), 0.0);
+    }
+}
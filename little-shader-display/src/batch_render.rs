@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::renderer::Renderer;
+
+// Frames rendered offscreen by --once and saved as PNGs, e.g. golden images or thumbnails of every shader.
+// Each shader renders frame_count frames, starting at start_time and frame_rate frames per second apart.
+pub struct BatchRender {
+    pub shader_names: Vec<String>,
+    pub start_time: f32,
+    pub frame_count: u32,
+    pub frame_rate: f32,
+    pub output_path: PathBuf,
+}
+
+impl BatchRender {
+    // Renders and saves the frames of every shader, shaders which fail to compile are skipped.
+    // Returns the number of shaders which failed.
+    pub fn run(&self, renderer: &mut Renderer) -> usize {
+        if let Some(directory) = self.output_path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            _ = fs::create_dir_all(directory);
+        }
+
+        let mut failed_count = 0;
+        for shader_name in &self.shader_names {
+            renderer.recompile_shaders(shader_name, false, true);
+            if let Some(err) = renderer.shader_error() {
+                println!("Error: Shader compilation of {} failed, skipping it:\n{}", shader_name, err);
+                failed_count += 1;
+                continue;
+            }
+
+            // Frames are rendered in order, so shaders reading their previous frame evolve as when shown
+            for frame in 0..self.frame_count {
+                let time = self.start_time + frame as f32 / self.frame_rate;
+                let path = self.frame_path(shader_name, frame);
+                renderer.update_uniforms(time, time, [0.0; 3], [0.0; 4]);
+                if let Err(err) = renderer.render_to_file(&path.to_string_lossy()) {
+                    println!("Error: Failed to save {}: {}", path.display(), err);
+                    failed_count += 1;
+                    break;
+                }
+                println!("Saved frame of {} at time {} to {}", shader_name, time, path.display());
+            }
+        }
+        failed_count
+    }
+
+    // Path of a frame: the output path itself for a single frame of a single shader, otherwise the shader name
+    // and the frame number are added to its file name as needed, e.g. frames/frame_waves_0003.png
+    fn frame_path(&self, shader_name: &str, frame: u32) -> PathBuf {
+        let mut suffix = String::new();
+        if self.shader_names.len() > 1 {
+            suffix.push('_');
+            suffix.push_str(Path::new(shader_name).file_stem().and_then(|stem| stem.to_str()).unwrap_or(shader_name));
+        }
+        if self.frame_count > 1 {
+            suffix.push_str(&format!("_{:04}", frame));
+        }
+        if suffix.is_empty() {
+            return self.output_path.clone();
+        }
+
+        let stem = self.output_path.file_stem().map_or_else(|| "frame".into(), |stem| stem.to_string_lossy());
+        let extension = self.output_path.extension().map_or_else(|| "png".into(), |extension| extension.to_string_lossy());
+        self.output_path.with_file_name(format!("{}{}.{}", stem, suffix, extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch_render(shader_names: &[&str], frame_count: u32) -> BatchRender {
+        BatchRender {
+            shader_names: shader_names.iter().map(|name| name.to_string()).collect(),
+            start_time: 0.0,
+            frame_count,
+            frame_rate: 30.0,
+            output_path: PathBuf::from("frames/frame.png"),
+        }
+    }
+
+    #[test]
+    fn frame_paths_name_shader_and_frame_when_needed() {
+        assert_eq!(batch_render(&["waves.frag"], 1).frame_path("waves.frag", 0), Path::new("frames/frame.png"));
+        assert_eq!(batch_render(&["waves.frag"], 10).frame_path("waves.frag", 3), Path::new("frames/frame_0003.png"));
+        assert_eq!(batch_render(&["waves.frag", "fractal.wgsl"], 1).frame_path("fractal.wgsl", 0), Path::new("frames/frame_fractal.png"));
+        assert_eq!(batch_render(&["waves.frag", "fractal.wgsl"], 2).frame_path("waves.frag", 1), Path::new("frames/frame_waves_0001.png"));
+    }
+}
@@ -0,0 +1,38 @@
+// Copies a texture onto the whole render target with a single fullscreen triangle
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texture_coordinates: vec2<f32>,
+};
+
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // Vertices (-1,1), (3,1), (-1,-3) cover the screen, texture coordinates have v = 0 at the top
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.texture_coordinates = uv;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source_texture, source_sampler, input.texture_coordinates);
+}
+
+// Copies a texture of the same size replacing NaN with 0, infinities with 0 or 1 by their sign and clamping the rest to 0..1.
+// Texels are loaded without filtering, so a NaN does not spread to its neighbours. Checked on the bits since compilers
+// may assume floats are finite and drop comparisons like x != x.
+@fragment
+fn fs_sanitize(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(source_texture, vec2<i32>(input.position.xy), 0);
+    let bits = bitcast<vec4<u32>>(color);
+    let non_finite = (bits & vec4<u32>(0x7f800000u)) == vec4<u32>(0x7f800000u);
+    let nan = non_finite & ((bits & vec4<u32>(0x007fffffu)) != vec4<u32>(0u));
+    let positive = (bits & vec4<u32>(0x80000000u)) == vec4<u32>(0u);
+    let infinity = select(vec4<f32>(0.0), vec4<f32>(1.0), positive);
+    return select(clamp(color, vec4<f32>(0.0), vec4<f32>(1.0)), select(infinity, vec4<f32>(0.0), nan), non_finite);
+}
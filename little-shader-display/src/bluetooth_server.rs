@@ -1,22 +1,29 @@
 use bluer::rfcomm::{Listener, SocketAddr};
 use tokio::sync::Mutex;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::oneshot;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+const SNAPSHOT_COMMAND: &str = "snapshot";
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5); // How long to wait for the main loop to capture the frame
+
+// Pending request for the current frame, answered by the main loop with the frame encoded as PNG
+pub type SnapshotRequest = Arc<Mutex<Option<oneshot::Sender<Vec<u8>>>>>;
 
 pub struct BluetoothServer {
     pub received_text: Arc<Mutex<Option<String>>>,
+    pub clients_connected: Arc<AtomicU32>, // Clients currently connected, at most one since they are served one at a time
+    pub snapshot_request: SnapshotRequest,
+    _session: bluer::Session, // Kept alive for as long as the adapter is used
+    adapter: bluer::Adapter,
 }
 
 impl BluetoothServer {
+    // Connects to BlueZ and checks that a bluetooth adapter is available,
+    // so a missing adapter or D-Bus failure is reported before the server is started
     pub async fn new() -> bluer::Result<Self> {
-        Ok(BluetoothServer {
-            received_text: Arc::new(Mutex::new(None)),
-        })
-    }
-
-    pub async fn run(&self,) -> bluer::Result<()> {
-        println!("Starting Bluetooth server...");
-
         // Create a new Bluetooth session
         let session = bluer::Session::new().await?;
 
@@ -26,6 +33,20 @@ impl BluetoothServer {
         // Enable the Bluetooth adapter
         adapter.set_powered(true).await?;
 
+        Ok(BluetoothServer {
+            received_text: Arc::new(Mutex::new(None)),
+            clients_connected: Arc::new(AtomicU32::new(0)),
+            snapshot_request: Arc::new(Mutex::new(None)),
+            _session: session,
+            adapter,
+        })
+    }
+
+    pub async fn run(&self,) -> bluer::Result<()> {
+        println!("Starting Bluetooth server...");
+
+        let adapter = &self.adapter;
+
         // Make the device discoverable to others
         adapter.set_discoverable(true).await?;
 
@@ -67,7 +88,8 @@ impl BluetoothServer {
             }
 
             let mut read_buffer = vec![0; 1024];
-            let mut message_buffer = String::new();
+            let mut line_buffer = LineBuffer::default();
+            self.clients_connected.fetch_add(1, Ordering::Relaxed);
 
             loop {
                 match stream.read(&mut read_buffer).await {
@@ -76,23 +98,15 @@ impl BluetoothServer {
                         break;
                     }
                     Ok(n) => {
-                        // Decode and append to the message buffer
-                        if let Ok(text) = std::str::from_utf8(&read_buffer[..n]) {
-                            message_buffer.push_str(text);
-
-                            // Process each complete message line
-                            while let Some(idx) = message_buffer.find('\n') {
-                                // Get line without \n
-                                let line = message_buffer[..idx].trim();
-
-                                // Store the complete line text in the mutex so other code can access it
-                                *self.received_text.lock().await = Some(line.to_string());
-
-                                // Remove processed line from the buffer
-                                message_buffer = message_buffer[idx + 1..].to_string();
+                        // Store each complete line in the mutex so other code can access it, snapshots are answered right away
+                        for line in line_buffer.push(&read_buffer[..n]) {
+                            if line == SNAPSHOT_COMMAND {
+                                if let Err(err) = self.send_snapshot(&mut stream).await {
+                                    println!("Write failed: {}", err);
+                                }
+                            } else {
+                                *self.received_text.lock().await = Some(line);
                             }
-                        } else {
-                            message_buffer.clear();
                         }
                     }
                     Err(err) => {
@@ -101,6 +115,7 @@ impl BluetoothServer {
                     }
                 }
             }
+            self.clients_connected.fetch_sub(1, Ordering::Relaxed);
 
             // Close the stream after processing
             if let Err(err) = stream.shutdown().await {
@@ -109,4 +124,94 @@ impl BluetoothServer {
             println!("Connection closed.");
         }
     }
-}
\ No newline at end of file
+}
+
+impl BluetoothServer {
+    // Asks the main loop for the current frame and sends it as PNG prefixed with its length (4 bytes, big-endian).
+    // A length of 0 means the frame could not be captured.
+    async fn send_snapshot(&self, stream: &mut bluer::rfcomm::Stream) -> std::io::Result<()> {
+        let png_bytes = request_snapshot(&self.snapshot_request).await;
+        println!("Sending snapshot ({} bytes)", png_bytes.len());
+        stream.write_all(&snapshot_message(&png_bytes)).await
+    }
+}
+
+// Asks the main loop for the current frame and waits for it, returns the frame as PNG or no bytes if it was not captured
+pub async fn request_snapshot(snapshot_request: &SnapshotRequest) -> Vec<u8> {
+    let (sender, receiver) = oneshot::channel();
+    *snapshot_request.lock().await = Some(sender);
+    match tokio::time::timeout(SNAPSHOT_TIMEOUT, receiver).await {
+        Ok(Ok(png_bytes)) => png_bytes,
+        _ => {
+            println!("Warning: Snapshot was not captured within {} seconds", SNAPSHOT_TIMEOUT.as_secs());
+            snapshot_request.lock().await.take();
+            Vec::new()
+        }
+    }
+}
+
+// Snapshot reply as sent to the client, the PNG bytes prefixed with their length
+fn snapshot_message(png_bytes: &[u8]) -> Vec<u8> {
+    let mut message = (png_bytes.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(png_bytes);
+    message
+}
+
+// Collects received bytes and splits them into lines. Lines are only decoded once complete,
+// since a read can end in the middle of a line or of a multibyte UTF-8 character.
+#[derive(Default)]
+struct LineBuffer {
+    bytes: Vec<u8>,
+}
+
+impl LineBuffer {
+    // Appends the received bytes and returns the lines they completed, trimmed and without the newline
+    fn push(&mut self, received: &[u8]) -> Vec<String> {
+        self.bytes.extend_from_slice(received);
+
+        let mut lines = Vec::new();
+        while let Some(index) = self.bytes.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.bytes.drain(..=index).collect();
+            // Only the invalid line is dropped, the following ones are still decoded
+            match std::str::from_utf8(&line[..index]) {
+                Ok(text) => lines.push(text.trim().to_string()),
+                Err(_) => println!("Warning: Ignoring received line which is not UTF-8"),
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_characters_split_across_reads() {
+        let message = "{\"shader\":\"wavé.frag\"}\n".as_bytes();
+        let split = message.iter().position(|&byte| byte == 0xC3).unwrap() + 1; // Between the two bytes of é
+
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(&message[..split]).is_empty());
+        assert_eq!(buffer.push(&message[split..]), ["{\"shader\":\"wavé.frag\"}"]);
+    }
+
+    #[test]
+    fn splits_lines_and_keeps_incomplete_one() {
+        let mut buffer = LineBuffer::default();
+        assert_eq!(buffer.push(b"x: 1, y: 2, z: 3\r\nnext\npar"), ["x: 1, y: 2, z: 3", "next"]);
+        assert_eq!(buffer.push(b"tial\n"), ["partial"]);
+    }
+
+    #[test]
+    fn prefixes_snapshot_with_length() {
+        assert_eq!(snapshot_message(&[0x89, b'P', b'N', b'G']), [0, 0, 0, 4, 0x89, b'P', b'N', b'G']);
+        assert_eq!(snapshot_message(&[]), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn drops_only_invalid_lines() {
+        let mut buffer = LineBuffer::default();
+        assert_eq!(buffer.push(b"bad \xFF\ngood\n"), ["good"]);
+    }
+}
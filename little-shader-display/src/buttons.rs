@@ -0,0 +1,80 @@
+use rppal::gpio::{Gpio, InputPin, Trigger};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(30); // Edges closer together than this are contact bounce
+
+// What pressing a button does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonAction {
+    Next,
+    Previous,
+    Brighter,
+    Dimmer,
+    Pause, // Pauses or resumes the animation
+}
+
+impl ButtonAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "next" => Some(ButtonAction::Next),
+            "previous" => Some(ButtonAction::Previous),
+            "brighter" => Some(ButtonAction::Brighter),
+            "dimmer" => Some(ButtonAction::Dimmer),
+            "pause" => Some(ButtonAction::Pause),
+            _ => None,
+        }
+    }
+}
+
+// Parses a button given as <action>=<BCM GPIO number>, e.g. next=17
+pub fn parse_button(text: &str) -> Result<(ButtonAction, u8), String> {
+    let (name, pin) = text.split_once('=').ok_or_else(|| format!("{} must be <action>=<gpio>", text))?;
+    let action = ButtonAction::from_name(name).ok_or_else(|| format!("unknown button action {}, expected next, previous, brighter, dimmer or pause", name))?;
+    let pin = pin.parse().map_err(|_| format!("{} is not a GPIO number", pin))?;
+    Ok((action, pin))
+}
+
+// Push buttons wired between a GPIO and ground, read with the internal pull-ups.
+// Presses are detected by interrupts in the background and queued until the main loop takes them.
+pub struct Buttons {
+    _pins: Vec<InputPin>, // Kept so their interrupts stay active
+    pressed: Arc<Mutex<VecDeque<ButtonAction>>>,
+}
+
+impl Buttons {
+    // Starts watching the given buttons, fails if a GPIO can not be used
+    pub fn start(buttons: &[(ButtonAction, u8)]) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let pressed = Arc::new(Mutex::new(VecDeque::new()));
+        let mut pins = Vec::new();
+        for &(action, pin_number) in buttons {
+            let mut pin = gpio.get(pin_number).map_err(|err| format!("GPIO {}: {}", pin_number, err))?.into_input_pullup();
+            let pin_pressed = pressed.clone();
+            pin.set_async_interrupt(Trigger::FallingEdge, Some(DEBOUNCE), move |_| pin_pressed.lock().unwrap().push_back(action))?;
+            pins.push(pin);
+        }
+        Ok(Self { _pins: pins, pressed })
+    }
+
+    // Returns the oldest press which was not taken yet
+    pub fn take_pressed(&self) -> Option<ButtonAction> {
+        self.pressed.lock().unwrap().pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_action_and_pin() {
+        assert_eq!(parse_button("next=17"), Ok((ButtonAction::Next, 17)));
+        assert_eq!(parse_button("dimmer=5"), Ok((ButtonAction::Dimmer, 5)));
+        assert!(parse_button("next").is_err());
+        assert!(parse_button("jump=17").is_err());
+        assert!(parse_button("next=pin").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Size the camera feed is scaled to, small enough to upload every frame on a Raspberry Pi
+const FRAME_SIZE: (u32, u32) = (320, 240);
+
+// Captures a V4L2 camera with ffmpeg in the background as RGBA frames, keeping only the latest one
+pub struct CameraCapture {
+    child: Child,
+    size: (u32, u32),
+    latest_frame: Arc<Mutex<Option<Vec<u8>>>>, // Set when a new frame arrived, taken by the next rendered frame
+}
+
+impl CameraCapture {
+    // Starts capturing the given device (e.g. /dev/video0), scaled down to fit textures of the given size if limited.
+    // Fails if the device does not exist or ffmpeg can not be started.
+    pub fn start(device_path: &Path, max_texture_size: Option<u32>) -> Result<Self, Box<dyn Error>> {
+        if !device_path.exists() {
+            return Err(format!("{} does not exist", device_path.display()).into());
+        }
+
+        let (mut width, mut height) = FRAME_SIZE;
+        if let Some(max_size) = max_texture_size.filter(|&max_size| max_size < width) {
+            height = (height * max_size / width).max(1);
+            width = max_size;
+        }
+
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-nostdin", "-f", "v4l2", "-i"])
+            .arg(device_path)
+            .args(["-vf", &format!("scale={}:{}", width, height), "-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+        let stdout = child.stdout.take().ok_or("ffmpeg has no output")?;
+
+        let latest_frame = Arc::new(Mutex::new(None));
+        let thread_latest_frame = latest_frame.clone();
+        let frame_length = (width * height * 4) as usize;
+        thread::spawn(move || read_loop(stdout, frame_length, thread_latest_frame));
+        Ok(Self { child, size: (width, height), latest_frame })
+    }
+
+    // Returns the frame which arrived since the last call, RGBA8888 with the first row at the top
+    pub fn take_frame(&self) -> Option<Vec<u8>> {
+        self.latest_frame.lock().unwrap().take()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+}
+
+impl Drop for CameraCapture {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+// Reads whole frames until ffmpeg exits, the last frame stays on the channel
+fn read_loop(mut stdout: impl Read, frame_length: usize, latest_frame: Arc<Mutex<Option<Vec<u8>>>>) {
+    loop {
+        let mut frame = vec![0u8; frame_length];
+        if stdout.read_exact(&mut frame).is_err() {
+            break;
+        }
+        *latest_frame.lock().unwrap() = Some(frame);
+    }
+    println!("Warning: Camera capture stopped, the camera channel keeps the last frame");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_device_fails_to_start() {
+        assert!(CameraCapture::start(Path::new("/dev/missing-video-device"), None).is_err());
+    }
+}
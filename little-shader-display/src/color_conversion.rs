@@ -0,0 +1,186 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::ST7789_OUTPUT_SIZE;
+
+// Implementations of the RGBA8888 to RGB565 conversion of the ST7789 frames, the fastest one depends on the CPU.
+// Compare them on the device with --benchmark-convert and pick one with --convert.
+// NEON is the default where it is available.
+#[derive(Debug, Copy, Clone, Default, PartialEq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorConversion {
+    #[cfg_attr(not(target_arch = "aarch64"), default)]
+    Scalar, // Converts one pixel at a time, on every CPU
+    #[cfg(target_arch = "aarch64")]
+    #[default]
+    Neon, // Converts 16 pixels at a time with NEON instructions, on 64-bit ARM (e.g. a Pi running a 64-bit OS)
+}
+
+impl ColorConversion {
+    // Conversions available on the CPU the program is built for
+    #[cfg(target_arch = "aarch64")]
+    pub const ALL: &'static [ColorConversion] = &[Self::Scalar, Self::Neon];
+    #[cfg(not(target_arch = "aarch64"))]
+    pub const ALL: &'static [ColorConversion] = &[Self::Scalar];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Scalar => "scalar",
+            #[cfg(target_arch = "aarch64")]
+            Self::Neon => "neon",
+        }
+    }
+
+    // Converts RGBA8888 pixels (or BGRA8888 with flip_order) to RGB565 little-endian bytes
+    pub fn convert(self, input: &[u8], flip_order: bool) -> Vec<u8> {
+        match self {
+            Self::Scalar => rgba8888_to_rgb565_u8(input, flip_order),
+            #[cfg(target_arch = "aarch64")]
+            Self::Neon => rgba8888_to_rgb565_neon(input, flip_order),
+        }
+    }
+}
+
+// Converts RGBA8888 (4 bytes per pixel) to RGB565 (2 bytes per pixel, little-endian)
+// Skips the alpha channel entirely.
+pub fn rgba8888_to_rgb565_u8(input: &[u8], flip_order: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity((input.len() / 4) * 2); // 2 bytes per pixel (RGB565)
+    for chunk in input.chunks_exact(4) {
+
+        let r = if flip_order { chunk[2] } else { chunk[0] };
+        let g = chunk[1];
+        let b = if flip_order { chunk[0] } else { chunk[2] };
+
+        // Convert RGBA8888 to RGB565
+        let rgb565: u16 =
+            ((r as u16 & 0xF8) << 8) | // Red: upper 5 bits
+            ((g as u16 & 0xFC) << 3) | // Green: upper 6 bits
+            ((b as u16) >> 3);         // Blue: upper 5 bits
+
+        // Split color value into two consecutive bytes 
+        output.push((rgb565 & 0xFF) as u8);      // Low byte
+        output.push((rgb565 >> 8) as u8);        // High byte
+    }
+
+    output
+}
+
+// Converts 16 pixels per iteration, NEON is part of every 64-bit ARM CPU so no runtime detection is needed.
+// The bytes of both halves of each RGB565 pixel are computed separately and interleaved by the store.
+#[cfg(target_arch = "aarch64")]
+fn rgba8888_to_rgb565_neon(input: &[u8], flip_order: bool) -> Vec<u8> {
+    use std::arch::aarch64::*;
+
+    let mut output = vec![0u8; (input.len() / 4) * 2];
+    let pixels = input.chunks_exact(64);
+    let remainder = pixels.remainder();
+    let mut output_pixels = output.chunks_exact_mut(32);
+    for (pixels, output_pixels) in pixels.zip(&mut output_pixels) {
+        // SAFETY: NEON is always available on aarch64, the load reads 64 bytes and the store writes 32 bytes, the size of the chunks
+        unsafe {
+            let channels = vld4q_u8(pixels.as_ptr()); // Deinterleaved into one vector per channel
+            let (r, g, b) = if flip_order { (channels.2, channels.1, channels.0) } else { (channels.0, channels.1, channels.2) };
+            let low = vorrq_u8(vandq_u8(vshlq_n_u8::<3>(g), vdupq_n_u8(0xE0)), vshrq_n_u8::<3>(b)); // Lower 3 green bits, blue
+            let high = vorrq_u8(vandq_u8(r, vdupq_n_u8(0xF8)), vshrq_n_u8::<5>(g)); // Red, upper 3 green bits
+            vst2q_u8(output_pixels.as_mut_ptr(), uint8x16x2_t(low, high));
+        }
+    }
+
+    // The last pixels when the frame is not a multiple of 16 pixels
+    output_pixels.into_remainder().copy_from_slice(&rgba8888_to_rgb565_u8(remainder, flip_order));
+    output
+}
+
+// Expands an RGB565 pixel to XRGB8888, repeating the high bits in the low ones so white stays full white
+pub fn rgb565_to_xrgb8888(rgb565: u16) -> u32 {
+    let r = (rgb565 >> 11) as u32;
+    let g = ((rgb565 >> 5) & 0x3F) as u32;
+    let b = (rgb565 & 0x1F) as u32;
+    (((r << 3) | (r >> 2)) << 16) | (((g << 2) | (g >> 4)) << 8) | ((b << 3) | (b >> 2))
+}
+
+// Dims RGB565 little-endian pixels in place by a factor between 0 (black) and 1 (unchanged)
+pub fn scale_rgb565(bytes: &mut [u8], factor: f32) {
+    let factor = factor.clamp(0.0, 1.0);
+    for pixel in bytes.chunks_exact_mut(2) {
+        let rgb565 = u16::from_le_bytes([pixel[0], pixel[1]]);
+        let r = ((rgb565 >> 11) as f32 * factor) as u16;
+        let g = (((rgb565 >> 5) & 0x3F) as f32 * factor) as u16;
+        let b = ((rgb565 & 0x1F) as f32 * factor) as u16;
+        pixel.copy_from_slice(&((r << 11) | (g << 5) | b).to_le_bytes());
+    }
+}
+
+// Frame with every channel value, so all bit patterns go through the conversion
+fn synthetic_frame(size: u32) -> Vec<u8> {
+    (0..size * size * 4).map(|index| (index.wrapping_mul(31) % 251) as u8).collect()
+}
+
+// Runs every conversion over a synthetic ST7789 frame and prints its throughput
+pub fn benchmark(iterations: u32) {
+    let input = synthetic_frame(ST7789_OUTPUT_SIZE);
+    println!("Converting a {0}x{0} frame {1} times with each conversion:", ST7789_OUTPUT_SIZE, iterations);
+
+    for &conversion in ColorConversion::ALL {
+        // Warm up caches and the allocator before measuring
+        black_box(conversion.convert(black_box(&input), false));
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(conversion.convert(black_box(&input), false));
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let frame_ms = elapsed * 1000.0 / iterations as f64;
+        let megapixels_per_second = (input.len() / 4) as f64 * iterations as f64 / elapsed / 1_000_000.0;
+        println!("{:>8}: {:.3}ms per frame, {:.1} Mpixel/s", conversion.name(), frame_ms, megapixels_per_second);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_conversions_match_scalar() {
+        let frame = synthetic_frame(16);
+        // Also with a few pixels left over after the last full group of 16
+        for input in [&frame[..], &frame[..frame.len() - 12]] {
+            for flip_order in [false, true] {
+                let expected = rgba8888_to_rgb565_u8(input, flip_order);
+                for &conversion in ColorConversion::ALL {
+                    assert_eq!(conversion.convert(input, flip_order), expected, "{} with flip_order {}", conversion.name(), flip_order);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn converts_primary_colors() {
+        let input = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255];
+        for &conversion in ColorConversion::ALL {
+            assert_eq!(conversion.convert(&input, false), [0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00]);
+            assert_eq!(conversion.convert(&input, true), [0x1F, 0x00, 0xE0, 0x07, 0x00, 0xF8]);
+        }
+    }
+
+    #[test]
+    fn scales_rgb565_channels() {
+        let mut bytes = 0xFFFFu16.to_le_bytes().to_vec();
+        scale_rgb565(&mut bytes, 1.0);
+        assert_eq!(bytes, 0xFFFFu16.to_le_bytes());
+        scale_rgb565(&mut bytes, 0.5);
+        assert_eq!(u16::from_le_bytes([bytes[0], bytes[1]]), (15 << 11) | (31 << 5) | 15);
+        scale_rgb565(&mut bytes, 0.0);
+        assert_eq!(bytes, [0, 0]);
+    }
+
+    #[test]
+    fn expands_rgb565_to_xrgb8888() {
+        assert_eq!(rgb565_to_xrgb8888(0xFFFF), 0xFFFFFF);
+        assert_eq!(rgb565_to_xrgb8888(0xF800), 0xFF0000);
+        assert_eq!(rgb565_to_xrgb8888(0x07E0), 0x00FF00);
+        assert_eq!(rgb565_to_xrgb8888(0x001F), 0x0000FF);
+        assert_eq!(rgb565_to_xrgb8888(0x0000), 0);
+    }
+}
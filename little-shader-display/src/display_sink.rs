@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+// Display fed with frames rendered offscreen, e.g. the ST7789 panel.
+// Frames are RGB565 pixels packed as little-endian bytes, the first row is the top of the image.
+pub trait DisplaySink {
+    // Physical size of the display in pixels, exposed to shaders as panel_resolution
+    fn panel_size(&self) -> (u32, u32);
+
+    // Size the frames are rendered at when this is the first display, None for the default ST7789_OUTPUT_SIZE square
+    fn render_size(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    // Blocks until the display is ready for a tear-free frame, displays without such a signal return right away
+    fn wait_for_te(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>>;
+
+    // Switches the backlight, displays without a controllable one ignore it
+    fn set_backlight(&mut self, _on: bool) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    // Dims the backlight, from 0 (off) to 1 (full), displays without a dimmable one ignore it
+    fn set_backlight_level(&mut self, _level: f32) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+// How one rendered frame is shared between several displays
+#[derive(Debug, Copy, Clone, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisplayLayout {
+    #[default]
+    Mirror, // Every display shows the whole frame
+    Span, // The frame is cut into equally wide columns, left to right in the order the displays were added
+}
+
+// Settings of the outputs which are only built on Linux, defined here so the options can be read on every platform
+
+// How the grey levels of the SSD1306 are turned into lit and dark pixels
+#[derive(Debug, Copy, Clone, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dithering {
+    #[default]
+    Ordered, // Fixed crosshatch patterns, stable between frames so animations do not shimmer
+    FloydSteinberg, // Error diffusion, more detail in still images but noisy in motion
+}
+
+// How the HUB75 signals are wired to the GPIO pins
+#[derive(Debug, Copy, Clone, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hub75Mapping {
+    #[default]
+    Regular, // Passive adapter boards
+    AdafruitHat, // Adafruit RGB Matrix HAT and Bonnet
+}
+
+// Order the WS2812 color channels are sent in
+#[derive(Debug, Copy, Clone, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorOrder {
+    #[default]
+    Grb, // WS2812, WS2812B and most SK6812
+    Rgb,
+}
+
+// Cuts an RGB565 frame into the given number of columns of whole pixels, the last one takes the remainder.
+// Returns the bytes and the width of each column, all of them have the height of the frame.
+pub fn split_columns(rgb565_bytes: &[u8], width: u32, height: u32, count: u32) -> Vec<(Vec<u8>, u32)> {
+    let column_width = width / count;
+    (0..count)
+        .map(|index| {
+            let start = index * column_width;
+            let end = if index == count - 1 { width } else { start + column_width };
+            let bytes = rgb565_bytes
+                .chunks_exact((width * 2) as usize)
+                .take(height as usize)
+                .flat_map(|row| &row[(start * 2) as usize..(end * 2) as usize])
+                .copied()
+                .collect();
+            (bytes, end - start)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_renderer::{can_render, TestRenderer};
+    use crate::ST7789_OUTPUT_SIZE;
+
+    const SOLID_COLOR_SHADER_NAME: &str = "solid_color.frag";
+    const SOLID_COLOR_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vec4(1.0, 0.0, 0.0, 1.0);
+}
+";
+    const RED_RGB565: u16 = 0xF800;
+    const BLUE_RGB565: u16 = 0x001F;
+
+    #[test]
+    fn renders_solid_color_frames_to_sink() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).build();
+        test.render_frames(3);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 3);
+        for frame in frames.iter() {
+            assert_eq!((frame.width, frame.height), (ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE));
+            assert!(frame.pixels().iter().all(|&pixel| pixel == RED_RGB565));
+        }
+    }
+
+    #[test]
+    fn presents_to_every_display() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).displays(2).build();
+
+        // Red left half and blue right half
+        let rgba = [[255, 0, 0, 255], [255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 255, 255]].concat();
+        test.renderer.present_raw(&rgba, 4, 1);
+        test.renderer.set_display_layout(DisplayLayout::Span);
+        test.renderer.present_raw(&rgba, 4, 1);
+
+        for (display_index, span_pixel) in [(0, RED_RGB565), (1, BLUE_RGB565)] {
+            let frames = test.take_frames(display_index);
+            assert_eq!(frames.len(), 2);
+            assert_eq!((frames[0].width, frames[1].width), (4, 2));
+            assert_eq!(frames[1].pixels(), [span_pixel, span_pixel]);
+        }
+    }
+
+    #[test]
+    fn splits_frame_into_columns() {
+        // 5x2 frame with the column index as the pixel value
+        let rgb565_bytes: Vec<u8> = (0..2).flat_map(|_| (0u16..5).flat_map(|column| column.to_le_bytes())).collect();
+        let columns = split_columns(&rgb565_bytes, 5, 2, 2);
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns[0], (vec![0, 0, 1, 0, 0, 0, 1, 0], 2));
+        assert_eq!(columns[1], (vec![2, 0, 3, 0, 4, 0, 2, 0, 3, 0, 4, 0], 3));
+    }
+}
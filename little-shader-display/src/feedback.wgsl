@@ -0,0 +1,10 @@
+// Draws the frame a feedback shader rendered into its feedback buffer onto the output, through the output's vertices
+// so flips, rotation and pixel shift apply like to any other shader
+
+@group(2) @binding(0) var channel_sampler: sampler;
+@group(2) @binding(5) var frame: texture_2d<f32>;
+
+@fragment
+fn main(@location(0) vertex_position: vec2<f32>, @location(1) vertex_texture_coordinates: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(frame, channel_sampler, vertex_texture_coordinates);
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use crate::DEBUG_OVERHEADS;
+
+// Limits the main loop to a maximum frame rate so it does not spin a core at 100% when frames render faster,
+// e.g. on the ST7789-only path which has no vsync to wait for
+pub struct FramePacer {
+    frame_interval: Duration,
+    target_fps: f32,
+    frame_start: Instant,
+    frames: u32,
+    slept: Duration,
+    last_report: Instant,
+}
+
+impl FramePacer {
+    pub fn new(max_fps: f32) -> Self {
+        Self {
+            frame_interval: Duration::from_secs_f32(1.0 / max_fps),
+            target_fps: max_fps,
+            frame_start: Instant::now(),
+            frames: 0,
+            slept: Duration::ZERO,
+            last_report: Instant::now(),
+        }
+    }
+
+    // Changes the frame rate limit, e.g. when a shader with its own target_fps is shown
+    pub fn set_target_fps(&mut self, target_fps: f32) {
+        if target_fps != self.target_fps {
+            self.frame_interval = Duration::from_secs_f32(1.0 / target_fps);
+            self.target_fps = target_fps;
+        }
+    }
+
+    // Earliest time the next frame may start
+    pub fn next_frame_time(&self) -> Instant {
+        self.frame_start + self.frame_interval
+    }
+
+    pub fn frame_due(&self) -> bool {
+        Instant::now() >= self.next_frame_time()
+    }
+
+    // Marks the start of a frame, call when a frame is rendered
+    pub fn frame_started(&mut self) {
+        let now = Instant::now();
+        // Start from the planned time so the rate does not drift, unless the loop fell behind by more than a frame
+        self.frame_start = if now.duration_since(self.next_frame_time()) < self.frame_interval { self.next_frame_time() } else { now };
+        self.frames += 1;
+
+        if DEBUG_OVERHEADS && self.last_report.elapsed() >= Duration::from_secs(1) {
+            println!("Frame pacing: {} FPS (target {}), slept {:.2}ms", self.frames, self.target_fps, self.slept.as_secs_f64() * 1000.0);
+            self.frames = 0;
+            self.slept = Duration::ZERO;
+            self.last_report = Instant::now();
+        }
+    }
+
+    // Sleeps for the rest of the frame budget, then marks the start of the next frame
+    pub fn wait_for_next_frame(&mut self) {
+        let remaining = self.next_frame_time().saturating_duration_since(Instant::now());
+        if !remaining.is_zero() {
+            std::thread::sleep(remaining);
+            self.slept += remaining;
+        }
+        self.frame_started();
+    }
+}
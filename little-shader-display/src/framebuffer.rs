@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use crate::color_conversion;
+use crate::display_sink::DisplaySink;
+
+// Pixel layouts of the framebuffer, little-endian like the Raspberry Pi
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    Rgb565,
+    Xrgb8888,
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb565 => 2,
+            Self::Xrgb8888 => 4,
+        }
+    }
+}
+
+// Memory layout of a screen, shared with the DRM/KMS output
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenLayout {
+    pub width: u32,
+    pub height: u32,
+    pub stride: usize, // Bytes per line, can be more than the visible pixels
+    pub format: PixelFormat,
+}
+
+impl ScreenLayout {
+    // Draws an RGB565 frame into the screen memory, scaled to fit the screen keeping its aspect ratio (nearest neighbor)
+    // and centered. The borders are left as they are, black in freshly allocated memory.
+    pub fn draw(&self, screen: &mut [u8], rgb565_bytes: &[u8], width: u32, height: u32) {
+        let (x, y, fit_width, fit_height) = fit(width, height, self.width, self.height);
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        // Source column of every screen column
+        let source_columns: Vec<usize> = (0..fit_width).map(|column| (column * width / fit_width) as usize).collect();
+
+        for row in 0..fit_height {
+            let source_row = &rgb565_bytes[((row * height / fit_height) * width * 2) as usize..][..(width * 2) as usize];
+            let line_start = (y + row) as usize * self.stride + x as usize * bytes_per_pixel;
+            let line = &mut screen[line_start..line_start + fit_width as usize * bytes_per_pixel];
+            for (pixel, &source_column) in line.chunks_exact_mut(bytes_per_pixel).zip(&source_columns) {
+                let rgb565 = [source_row[source_column * 2], source_row[source_column * 2 + 1]];
+                match self.format {
+                    PixelFormat::Rgb565 => pixel.copy_from_slice(&rgb565),
+                    PixelFormat::Xrgb8888 => pixel.copy_from_slice(&color_conversion::rgb565_to_xrgb8888(u16::from_le_bytes(rgb565)).to_le_bytes()),
+                }
+            }
+        }
+    }
+
+    pub fn byte_count(&self) -> usize {
+        self.stride * self.height as usize
+    }
+}
+
+// Linux framebuffer device such as /dev/fb0, for HDMI or DSI screens without X or Wayland.
+// Frames are scaled to fit the screen keeping their aspect ratio, with black borders.
+pub struct Framebuffer {
+    file: File,
+    layout: ScreenLayout,
+    screen: Vec<u8>, // Contents of the whole framebuffer, written with a single write per frame
+}
+
+impl Framebuffer {
+    // Opens the device, its resolution and pixel format are read from sysfs
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or_else(|| format!("Invalid framebuffer path {}", path.display()))?;
+        let sysfs_path = Path::new("/sys/class/graphics").join(name);
+        let read_attribute = |attribute: &str| -> Result<String, String> {
+            let attribute_path = sysfs_path.join(attribute);
+            fs::read_to_string(&attribute_path).map(|value| value.trim().to_string()).map_err(|err| format!("Failed to read {}: {}", attribute_path.display(), err))
+        };
+
+        let (width, height) = parse_virtual_size(&read_attribute("virtual_size")?).ok_or("Invalid virtual_size of the framebuffer")?;
+        let format = match read_attribute("bits_per_pixel")?.as_str() {
+            "16" => PixelFormat::Rgb565,
+            "32" => PixelFormat::Xrgb8888,
+            bits => return Err(format!("Unsupported framebuffer depth of {} bits per pixel, expected 16 or 32", bits).into()),
+        };
+        let stride: usize = read_attribute("stride")?.parse().map_err(|_| "Invalid stride of the framebuffer")?;
+
+        let file = OpenOptions::new().write(true).open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        println!("Using framebuffer {}: {}x{} {:?}", path.display(), width, height, format);
+        let layout = ScreenLayout { width, height, stride, format };
+        Ok(Self { file, layout, screen: vec![0; layout.byte_count()] })
+    }
+}
+
+impl DisplaySink for Framebuffer {
+    fn panel_size(&self) -> (u32, u32) {
+        (self.layout.width, self.layout.height)
+    }
+
+    fn render_size(&self) -> Option<(u32, u32)> {
+        Some((self.layout.width, self.layout.height))
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        self.layout.draw(&mut self.screen, rgb565_bytes, width, height);
+        Ok(self.file.write_all_at(&self.screen, 0)?)
+    }
+}
+
+// Parses the virtual_size attribute, e.g. "1920,1080"
+fn parse_virtual_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once(',')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+// Largest rectangle with the frame's aspect ratio which fits on the screen, centered, as x, y, width and height
+fn fit(frame_width: u32, frame_height: u32, screen_width: u32, screen_height: u32) -> (u32, u32, u32, u32) {
+    let (width, height) = if frame_width as u64 * screen_height as u64 > screen_width as u64 * frame_height as u64 {
+        (screen_width, (frame_height as u64 * screen_width as u64 / frame_width as u64) as u32)
+    } else {
+        ((frame_width as u64 * screen_height as u64 / frame_height as u64) as u32, screen_height)
+    };
+    ((screen_width - width) / 2, (screen_height - height) / 2, width.max(1), height.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sysfs_attributes() {
+        assert_eq!(parse_virtual_size("1920,1080"), Some((1920, 1080)));
+        assert_eq!(parse_virtual_size("1920"), None);
+    }
+
+    #[test]
+    fn fits_frame_keeping_aspect_ratio() {
+        assert_eq!(fit(1920, 1080, 1920, 1080), (0, 0, 1920, 1080));
+        assert_eq!(fit(256, 256, 1920, 1080), (420, 0, 1080, 1080));
+        assert_eq!(fit(800, 200, 400, 400), (0, 150, 400, 100));
+    }
+
+    #[test]
+    fn draws_frame_centered_with_borders() {
+        // A 1x1 white frame on a 4x2 XRGB8888 screen with padded lines fills the middle 2x2 square
+        let layout = ScreenLayout { width: 4, height: 2, stride: 20, format: PixelFormat::Xrgb8888 };
+        let mut screen = vec![0u8; layout.byte_count()];
+        layout.draw(&mut screen, &0xFFFFu16.to_le_bytes(), 1, 1);
+        for line in screen.chunks_exact(20) {
+            assert_eq!(line[..4], [0; 4]);
+            assert_eq!(line[4..12], [0xFF, 0xFF, 0xFF, 0, 0xFF, 0xFF, 0xFF, 0]);
+            assert_eq!(line[12..], [0; 8]);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use crate::input_message::Command;
+use crate::websocket_server::CommandQueue;
+
+const MAX_BODY_LENGTH: usize = 65536;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10); // Clients which stall while sending a request are disconnected
+
+// Shaders of the playlist and the one shown, written by the main loop and served by GET /shaders
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ShaderList {
+    pub shaders: Vec<String>,
+    pub current: String,
+}
+
+// Small HTTP API for home automation, e.g. curl -H 'Content-Type: application/json' -d '{"shader":"waves.frag"}' http://<host>:<port>/shaders/current
+//   GET /shaders            returns {"shaders":[...],"current":"waves.frag"}
+//   POST /shaders/current   switches to the shader of a body like {"shader":"waves.frag"}
+//   POST /uniforms          sets bluetooth_data and user params, e.g. {"params":[0.5],"user_params":{"speed":2.0}}
+// Changes are queued as JSON commands for the main loop, like WebSocket ones, and answered with 202 Accepted.
+pub struct HttpApi {
+    listener: TcpListener,
+    state: ApiState,
+}
+
+// Shared with the request handlers
+#[derive(Clone)]
+struct ApiState {
+    shader_list: Arc<Mutex<ShaderList>>,
+    received_commands: CommandQueue,
+}
+
+impl HttpApi {
+    pub async fn bind(port: u16) -> std::io::Result<Self> {
+        Ok(HttpApi {
+            listener: TcpListener::bind(("0.0.0.0", port)).await?,
+            state: ApiState {
+                shader_list: Arc::new(Mutex::new(ShaderList::default())),
+                received_commands: Arc::new(Mutex::new(Default::default())),
+            },
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|address| address.port()).unwrap_or_default()
+    }
+
+    // Returns the shader list shared with the main loop
+    pub fn shader_list(&self) -> Arc<Mutex<ShaderList>> {
+        self.state.shader_list.clone()
+    }
+
+    pub fn received_commands(&self) -> CommandQueue {
+        self.state.received_commands.clone()
+    }
+
+    pub async fn run(self) {
+        let router = Router::new()
+            .route("/shaders", get(list_shaders))
+            .route("/shaders/current", post(select_shader))
+            .route("/uniforms", post(set_uniforms))
+            .fallback(|| async { error(StatusCode::NOT_FOUND, "unknown path") })
+            .layer(DefaultBodyLimit::max(MAX_BODY_LENGTH))
+            .layer(middleware::from_fn(timeout))
+            .with_state(self.state);
+
+        loop {
+            // Accept errors (e.g. out of file descriptors) only affect that connection, keep serving
+            let stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    println!("Warning: Failed to accept HTTP connection: {}", err);
+                    continue;
+                }
+            };
+
+            // The connection is closed if the request headers do not arrive within the timeout
+            let connection = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(REQUEST_TIMEOUT)
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(router.clone()));
+            tokio::spawn(async move {
+                if let Err(err) = connection.await {
+                    println!("Warning: HTTP connection failed: {}", err);
+                }
+            });
+        }
+    }
+}
+
+impl ApiState {
+    async fn queue(&self, command: Command) -> Response {
+        self.received_commands.lock().await.push_back(serde_json::to_string(&command).expect("Failed to serialize command"));
+        (StatusCode::ACCEPTED, Json(serde_json::json!({}))).into_response()
+    }
+}
+
+async fn list_shaders(State(state): State<ApiState>) -> Json<ShaderList> {
+    Json(state.shader_list.lock().await.clone())
+}
+
+async fn select_shader(State(state): State<ApiState>, body: Result<Json<Command>, JsonRejection>) -> Response {
+    let shader_name = match body {
+        Ok(Json(Command { shader: Some(shader_name), .. })) => shader_name,
+        Ok(_) => return error(StatusCode::BAD_REQUEST, "expected {\"shader\":\"<name>\"}"),
+        Err(rejection) => return error(rejection.status(), &rejection.body_text()),
+    };
+    if !state.shader_list.lock().await.shaders.contains(&shader_name) {
+        return error(StatusCode::NOT_FOUND, &format!("unknown shader {}", shader_name));
+    }
+    state.queue(Command { shader: Some(shader_name), ..Default::default() }).await
+}
+
+async fn set_uniforms(State(state): State<ApiState>, body: Result<Json<Command>, JsonRejection>) -> Response {
+    match body {
+        Ok(Json(command)) if command.shader.is_none() && command.pause.is_none() => state.queue(command).await,
+        Ok(_) => error(StatusCode::BAD_REQUEST, "only params and user_params can be set"),
+        Err(rejection) => error(rejection.status(), &rejection.body_text()),
+    }
+}
+
+// Answers 408 Request Timeout if the body does not arrive in time
+async fn timeout(request: Request, next: Next) -> Response {
+    tokio::time::timeout(REQUEST_TIMEOUT, next.run(request)).await
+        .unwrap_or_else(|_| error(StatusCode::REQUEST_TIMEOUT, "request timed out"))
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn request(port: u16, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(format!("{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn lists_and_selects_shaders_and_sets_uniforms() {
+        let api = HttpApi::bind(0).await.unwrap();
+        let port = api.port();
+        let received_commands = api.received_commands();
+        *api.shader_list().lock().await = ShaderList { shaders: vec!["grid.frag".to_string(), "waves.frag".to_string()], current: "grid.frag".to_string() };
+        tokio::spawn(api.run());
+
+        let response = request(port, "GET", "/shaders", "").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with(r#"{"shaders":["grid.frag","waves.frag"],"current":"grid.frag"}"#), "{}", response);
+
+        assert!(request(port, "POST", "/shaders/current", r#"{"shader":"waves.frag"}"#).await.starts_with("HTTP/1.1 202"));
+        assert!(request(port, "POST", "/shaders/current", r#"{"shader":"grid.frag"}"#).await.starts_with("HTTP/1.1 202"));
+        assert!(request(port, "POST", "/shaders/current", r#"{"shader":"missing.frag"}"#).await.starts_with("HTTP/1.1 404"));
+        assert!(request(port, "POST", "/shaders/current", "waves.frag").await.starts_with("HTTP/1.1 400"));
+        assert!(request(port, "POST", "/uniforms", r#"{"user_params":{"speed":2.0}}"#).await.starts_with("HTTP/1.1 202"));
+        assert!(request(port, "POST", "/uniforms", r#"{"pause":true}"#).await.starts_with("HTTP/1.1 400"));
+        assert!(request(port, "GET", "/uniforms", "").await.starts_with("HTTP/1.1 405"));
+        assert!(request(port, "GET", "/missing", "").await.starts_with("HTTP/1.1 404"));
+
+        let commands: Vec<String> = received_commands.lock().await.drain(..).collect();
+        assert_eq!(commands, [r#"{"shader":"waves.frag"}"#, r#"{"shader":"grid.frag"}"#, r#"{"user_params":{"speed":2.0}}"#]);
+    }
+}
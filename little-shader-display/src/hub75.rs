@@ -0,0 +1,171 @@
+use std::error::Error;
+
+use rpi_led_matrix::{LedCanvas, LedColor, LedMatrix, LedMatrixOptions, LedRuntimeOptions};
+
+use crate::display_sink::{DisplaySink, Hub75Mapping};
+
+pub const DEFAULT_ROWS: u32 = 32;
+pub const DEFAULT_COLS: u32 = 64;
+pub const DEFAULT_PWM_BITS: u8 = 8;
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+// Name of the hardware mapping in rpi-rgb-led-matrix
+fn mapping_name(mapping: Hub75Mapping) -> &'static str {
+    match mapping {
+        Hub75Mapping::Regular => "regular",
+        Hub75Mapping::AdafruitHat => "adafruit-hat",
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hub75Config {
+    pub rows: u32, // Of one panel, 16, 32 or 64
+    pub cols: u32, // Of one panel
+    pub chain: u32, // Panels daisy-chained on the same connector
+    pub mapping: Hub75Mapping,
+    pub pwm_bits: u8, // Levels per color channel as a power of two, fewer bits refresh faster
+    pub gamma: f32,
+}
+
+impl Default for Hub75Config {
+    fn default() -> Self {
+        Self { rows: DEFAULT_ROWS, cols: DEFAULT_COLS, chain: 1, mapping: Hub75Mapping::Regular, pwm_bits: DEFAULT_PWM_BITS, gamma: DEFAULT_GAMMA }
+    }
+}
+
+impl Hub75Config {
+    // Size of the image shown by all chained panels together
+    fn size(&self) -> (u32, u32) {
+        (self.cols * self.chain, self.rows)
+    }
+}
+
+// Drives 16 to 64 row HUB75 RGB LED matrix panels through rpi-rgb-led-matrix, which refreshes them from its own
+// realtime thread. Frames are corrected for the LED's brightness response and drawn into an offscreen canvas
+// which is swapped in on the next refresh.
+pub struct Hub75 {
+    config: Hub75Config,
+    matrix: LedMatrix,
+    canvas: Option<LedCanvas>, // Offscreen canvas, only taken while swapping
+    lit: bool,
+    brightness: f32,
+}
+
+impl Hub75 {
+    pub fn new(config: Hub75Config) -> Result<Self, Box<dyn Error>> {
+        let mut options = LedMatrixOptions::new();
+        options.set_rows(config.rows);
+        options.set_cols(config.cols);
+        options.set_chain_length(config.chain);
+        options.set_hardware_mapping(mapping_name(config.mapping));
+        options.set_pwm_bits(config.pwm_bits)?;
+        // Keep root, the other hardware and the shader files are opened after the matrix
+        let mut runtime_options = LedRuntimeOptions::new();
+        runtime_options.set_drop_privileges(false);
+
+        let matrix = LedMatrix::new(Some(options), Some(runtime_options)).map_err(|err| format!("Failed to open the LED matrix: {}", err))?;
+        let canvas = matrix.offscreen_canvas();
+
+        let (width, height) = config.size();
+        println!("Using HUB75 matrix of {}x{} pixels ({} panel(s) of {}x{})", width, height, config.chain, config.cols, config.rows);
+        Ok(Self { config, matrix, canvas: Some(canvas), lit: true, brightness: 1.0 })
+    }
+}
+
+impl DisplaySink for Hub75 {
+    fn panel_size(&self) -> (u32, u32) {
+        self.config.size()
+    }
+
+    fn render_size(&self) -> Option<(u32, u32)> {
+        Some(self.config.size())
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        let (panel_width, panel_height) = self.config.size();
+        let pixels = scale_to_panel(rgb565_bytes, width, height, panel_width, panel_height);
+        // Switching the LEDs off shows black frames, the matrix has no separate enable
+        let brightness = if self.lit { self.brightness } else { 0.0 };
+        let levels = gamma_table(self.config.gamma, brightness);
+
+        let mut canvas = self.canvas.take().expect("HUB75 canvas is always returned by swap");
+        for (index, &rgb565) in pixels.iter().enumerate() {
+            let (x, y) = (index as u32 % panel_width, index as u32 / panel_width);
+            let [red, green, blue] = rgb565_channels(rgb565).map(|value| levels[value as usize]);
+            canvas.set(x as i32, y as i32, &LedColor { red, green, blue });
+        }
+        self.canvas = Some(self.matrix.swap(canvas));
+        Ok(())
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.lit = on;
+        Ok(())
+    }
+
+    // There is no backlight, the LED levels are scaled from the next frame on
+    fn set_backlight_level(&mut self, level: f32) -> Result<(), Box<dyn Error>> {
+        self.brightness = level.clamp(0.0, 1.0);
+        Ok(())
+    }
+}
+
+// 8-bit red, green and blue of an RGB565 pixel
+fn rgb565_channels(rgb565: u16) -> [u8; 3] {
+    [((rgb565 >> 11) as u32 * 255 / 31) as u8, (((rgb565 >> 5) & 0x3F) as u32 * 255 / 63) as u8, ((rgb565 & 0x1F) as u32 * 255 / 31) as u8]
+}
+
+// LED level for every 8-bit channel value, LEDs look much brighter than their duty cycle at low levels
+fn gamma_table(gamma: f32, brightness: f32) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (value, level) in table.iter_mut().enumerate() {
+        *level = ((value as f32 / 255.0).powf(gamma) * brightness * 255.0).round() as u8;
+    }
+    table
+}
+
+// RGB565 pixels of the panel, the frame is cropped to the panel's aspect ratio around its center and sampled to its size
+fn scale_to_panel(rgb565_bytes: &[u8], width: u32, height: u32, panel_width: u32, panel_height: u32) -> Vec<u16> {
+    let (crop_width, crop_height) = if width * panel_height > height * panel_width { (height * panel_width / panel_height, height) } else { (width, width * panel_height / panel_width) };
+    let (crop_x, crop_y) = ((width - crop_width) / 2, (height - crop_height) / 2);
+    (0..panel_height)
+        .flat_map(|y| (0..panel_width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            // Center of the source pixels covered by the panel pixel
+            let source_x = crop_x + ((2 * x + 1) * crop_width / (2 * panel_width)).min(crop_width - 1);
+            let source_y = crop_y + ((2 * y + 1) * crop_height / (2 * panel_height)).min(crop_height - 1);
+            let index = ((source_y * width + source_x) * 2) as usize;
+            u16::from_le_bytes([rgb565_bytes[index], rgb565_bytes[index + 1]])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_table_spans_channel_levels() {
+        let table = gamma_table(2.2, 1.0);
+        assert_eq!((table[0], table[255]), (0, 255));
+        assert!(table[128] < 64, "{}", table[128]);
+        assert!(table.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(gamma_table(1.0, 0.5)[255], 128);
+    }
+
+    #[test]
+    fn scales_frame_to_chained_panels() {
+        // A 4x4 frame with a red top half on a 4x2 matrix, the middle rows are kept
+        let frame: Vec<u8> = (0..16).flat_map(|index| if index < 8 { 0xF800u16 } else { 0x001F }.to_le_bytes()).collect();
+        assert_eq!(scale_to_panel(&frame, 4, 4, 4, 2), vec![0xF800, 0xF800, 0xF800, 0xF800, 0x001F, 0x001F, 0x001F, 0x001F]);
+        assert_eq!(scale_to_panel(&frame, 4, 4, 2, 2), vec![0xF800, 0xF800, 0x001F, 0x001F]);
+    }
+
+    #[test]
+    fn expands_rgb565_channels() {
+        assert_eq!(rgb565_channels(0xFFFF), [255, 255, 255]);
+        assert_eq!(rgb565_channels(0xF800), [255, 0, 0]);
+        assert_eq!(rgb565_channels(0x07E0), [0, 255, 0]);
+        assert_eq!(rgb565_channels(0x001F), [0, 0, 255]);
+    }
+}
@@ -0,0 +1,361 @@
+use std::mem::size_of;
+use bytemuck::cast_slice;
+use bytemuck_derive::{Pod, Zeroable};
+
+// Text overlay drawn over the shader output in a second render pass, using a 5x7 bitmap font.
+// Lowercase letters are drawn as uppercase, characters missing from the font are drawn as '?'.
+// Lines are broken at '\n' and wrapped at the right edge of the target.
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1; // 1 pixel spacing between characters
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1; // 1 pixel spacing below characters
+const ATLAS_COLUMNS: u32 = 16; // Glyph cells per atlas row, keeps the atlas within small --max-texture-size limits
+const ATLAS_ROWS: u32 = (FONT.len() as u32).div_ceil(ATLAS_COLUMNS);
+const ATLAS_WIDTH: u32 = ATLAS_COLUMNS * CELL_WIDTH;
+const ATLAS_HEIGHT: u32 = ATLAS_ROWS * CELL_HEIGHT;
+const MARGIN: u32 = 4; // Distance of the text from the top-left corner in font pixels
+const MAX_CHARACTERS: usize = 512;
+const VERTICES_PER_CHARACTER: usize = 6;
+
+// Glyph rows from top to bottom, bit 4 is the leftmost pixel
+static FONT: [(char, [u8; 7]); 58] = [
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('"', [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000]),
+    (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('<', [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010]),
+    ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+    ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110]),
+    (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+];
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+struct HudVertex {
+    position: [f32; 2],
+    texture_coordinates: [f32; 2],
+}
+
+impl HudVertex {
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<HudVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // Position attribute: location 0
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                // Texture coordinate attribute: location 1
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: size_of::<[f32; 2]>() as u64,
+                    shader_location: 1,
+                },
+            ],
+        }
+    }
+}
+
+pub struct Hud {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    text: String,
+}
+
+impl Hud {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, output_format: wgpu::TextureFormat) -> Self {
+        // 1. Create the font atlas texture, glyph cells are placed in rows of ATLAS_COLUMNS in the order of the font table
+        let atlas_size = wgpu::Extent3d {
+            width: ATLAS_WIDTH,
+            height: ATLAS_HEIGHT,
+            depth_or_array_layers: 1,
+        };
+
+        let font_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HUD Font Texture"),
+            size: atlas_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &font_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &font_atlas_pixels(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(atlas_size.width),
+                rows_per_image: Some(atlas_size.height),
+            },
+            atlas_size,
+        );
+
+        let font_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("HUD Font Sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // 2. Bind the font texture and sampler
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hud_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&font_texture.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&font_sampler),
+                },
+            ],
+            label: Some("hud_bind_group"),
+        });
+
+        // 3. Create the overlay pipeline, the shader is built into the binary so it needs no compilation step
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hud_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("hud.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[HudVertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // 4. Allocate the vertex buffer for the longest supported text
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Vertex Buffer"),
+            size: (size_of::<HudVertex>() * VERTICES_PER_CHARACTER * MAX_CHARACTERS) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            vertex_buffer,
+            vertex_count: 0,
+            text: String::new(),
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().take(MAX_CHARACTERS).collect();
+    }
+
+    // Lays out the text for a target of the given size and uploads its quads.
+    // Must be called before encode_pass whenever the target or the text changes.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, target_width: u32, target_height: u32) {
+        let vertices = text_vertices(&self.text, target_width, target_height);
+        queue.write_buffer(&self.vertex_buffer, 0, cast_slice(&vertices));
+        self.vertex_count = vertices.len() as u32;
+    }
+
+    // Records the overlay render pass, drawing over what is already in the texture view
+    pub fn encode_pass(&self, encoder: &mut wgpu::CommandEncoder, texture_view: &wgpu::TextureView) {
+        if self.vertex_count == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("HUD Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+// Returns the index of the glyph drawn for the character in the font table
+fn glyph_index(character: char) -> usize {
+    let character = character.to_ascii_uppercase();
+    FONT.iter()
+        .position(|(glyph_character, _)| *glyph_character == character)
+        .unwrap_or(FONT.len() - 1)
+}
+
+// Builds R8 pixels of the font atlas, one CELL_WIDTH x CELL_HEIGHT cell per glyph
+fn font_atlas_pixels() -> Vec<u8> {
+    let mut pixels = vec![0u8; (ATLAS_WIDTH * ATLAS_HEIGHT) as usize];
+    for (glyph_index, (_, rows)) in FONT.iter().enumerate() {
+        let (cell_x, cell_y) = glyph_cell_origin(glyph_index);
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    pixels[(cell_y as usize + y) * ATLAS_WIDTH as usize + (cell_x + x) as usize] = 255;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// Top-left pixel of the glyph cell in the font atlas
+fn glyph_cell_origin(glyph_index: usize) -> (u32, u32) {
+    let glyph_index = glyph_index as u32;
+    ((glyph_index % ATLAS_COLUMNS) * CELL_WIDTH, (glyph_index / ATLAS_COLUMNS) * CELL_HEIGHT)
+}
+
+// Builds two triangles per character, scaled so the text stays readable on both the window and the small panel
+fn text_vertices(text: &str, target_width: u32, target_height: u32) -> Vec<HudVertex> {
+    let scale = (target_height / 240).max(1);
+
+    // Convert a position in target pixels to normalized device coordinates
+    let to_ndc = |x: u32, y: u32| [x as f32 / target_width as f32 * 2.0 - 1.0, 1.0 - y as f32 / target_height as f32 * 2.0];
+
+    let columns = ((target_width / scale).saturating_sub(2 * MARGIN) / CELL_WIDTH).max(1);
+    let (mut column, mut row) = (0, 0);
+
+    let mut vertices = Vec::with_capacity(text.len() * VERTICES_PER_CHARACTER);
+    for character in text.chars() {
+        if character == '\n' {
+            (column, row) = (0, row + 1);
+            continue;
+        }
+        if column == columns {
+            (column, row) = (0, row + 1);
+        }
+
+        let left = (MARGIN + column * CELL_WIDTH) * scale;
+        let top = (MARGIN + row * CELL_HEIGHT) * scale;
+        column += 1;
+        let right = left + CELL_WIDTH * scale;
+        let bottom = top + CELL_HEIGHT * scale;
+
+        let (cell_x, cell_y) = glyph_cell_origin(glyph_index(character));
+        let u0 = cell_x as f32 / ATLAS_WIDTH as f32;
+        let u1 = (cell_x + CELL_WIDTH) as f32 / ATLAS_WIDTH as f32;
+        let v0 = cell_y as f32 / ATLAS_HEIGHT as f32;
+        let v1 = (cell_y + CELL_HEIGHT) as f32 / ATLAS_HEIGHT as f32;
+
+        let top_left = HudVertex { position: to_ndc(left, top), texture_coordinates: [u0, v0] };
+        let bottom_left = HudVertex { position: to_ndc(left, bottom), texture_coordinates: [u0, v1] };
+        let top_right = HudVertex { position: to_ndc(right, top), texture_coordinates: [u1, v0] };
+        let bottom_right = HudVertex { position: to_ndc(right, bottom), texture_coordinates: [u1, v1] };
+        vertices.extend_from_slice(&[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+    }
+    vertices
+}
@@ -0,0 +1,23 @@
+// Text overlay shader, draws glyph cells from the font atlas over a translucent background
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texture_coordinates: vec2<f32>,
+};
+
+@group(0) @binding(0) var font_texture: texture_2d<f32>;
+@group(0) @binding(1) var font_sampler: sampler;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) texture_coordinates: vec2<f32>) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(position, 0.0, 1.0);
+    output.texture_coordinates = texture_coordinates;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let glyph = textureSample(font_texture, font_sampler, input.texture_coordinates).r;
+    return mix(vec4<f32>(0.0, 0.0, 0.0, 0.6), vec4<f32>(1.0, 1.0, 1.0, 1.0), glyph);
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+// Messages received over bluetooth, one per line, in one of two formats:
+// - legacy values "x: 1.0, y: -2.5, z: 9.8" (e.g. accelerometer readings), each clamped to -10..10 and normalized to -1..1
+// - a JSON object with any of the command fields, e.g. {"params":[0.1,0.2],"shader":"fractal.frag","user_params":{"speed":2.0},"pause":true}
+#[derive(Debug, PartialEq)]
+pub enum InputMessage {
+    Values([f32; 3]),
+    Command(Command),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Command {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<f32>>, // Up to 3 values written to bluetooth_data as they are, missing ones are 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader: Option<String>, // Name of the shader to switch to, e.g. fractal.frag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_params: Option<BTreeMap<String, f32>>, // Values of params declared in the metadata of the shader, by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause: Option<bool>, // Stops or resumes the animation time
+}
+
+impl Command {
+    // Values of the params as written to the uniform, extra values are ignored
+    pub fn params_uniform(&self) -> Option<[f32; 3]> {
+        let params = self.params.as_ref()?;
+        if params.len() > 3 {
+            println!("Warning: Only the first 3 of {} params are used", params.len());
+        }
+
+        let mut values = [0.0; 3];
+        for (value, param) in values.iter_mut().zip(params) {
+            *value = *param;
+        }
+        Some(values)
+    }
+}
+
+// Parses a received line, returns None and logs a warning if it is malformed
+pub fn parse(text: &str) -> Option<InputMessage> {
+    let text = text.trim();
+    if text.starts_with('{') {
+        return match serde_json::from_str(text) {
+            Ok(command) => Some(InputMessage::Command(command)),
+            Err(err) => {
+                println!("Warning: Ignoring malformed JSON command {:?}: {}", text, err);
+                None
+            }
+        };
+    }
+
+    parse_values(text).map(InputMessage::Values)
+}
+
+fn parse_values(text: &str) -> Option<[f32; 3]> {
+    if text.is_empty() {
+        return Some([0.0; 3]);
+    }
+
+    let values = text.split(',')
+        .map(|entry| entry.split(':').nth(1)?.trim().parse::<f32>().ok())
+        .map(|value| value.map(|value| value.clamp(-10.0, 10.0) / 10.0))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|values| <[f32; 3]>::try_from(values).ok());
+
+    if values.is_none() {
+        println!("Warning: Ignoring malformed values {:?}, expected \"x: <number>, y: <number>, z: <number>\"", text);
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(text: &str) -> Command {
+        match parse(text) {
+            Some(InputMessage::Command(command)) => command,
+            other => panic!("Expected a command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_full_json_command() {
+        let command = command(r#"{"params":[0.1,0.2],"shader":"fractal.frag","pause":true}"#);
+        assert_eq!(command.params, Some(vec![0.1, 0.2]));
+        assert_eq!(command.shader.as_deref(), Some("fractal.frag"));
+        assert_eq!(command.pause, Some(true));
+        assert_eq!(command.params_uniform(), Some([0.1, 0.2, 0.0]));
+    }
+
+    #[test]
+    fn parses_partial_json_commands() {
+        assert_eq!(command(r#"{"pause":false}"#), Command { pause: Some(false), ..Default::default() });
+        assert_eq!(command(r#" {"shader": "waves.frag"} "#), Command { shader: Some("waves.frag".to_string()), ..Default::default() });
+        assert_eq!(command("{}"), Command::default());
+        assert_eq!(command("{}").params_uniform(), None);
+        assert_eq!(command(r#"{"user_params":{"speed":2}}"#).user_params, Some(BTreeMap::from([("speed".to_string(), 2.0)])));
+    }
+
+    #[test]
+    fn uses_only_first_three_params() {
+        assert_eq!(command(r#"{"params":[1,2,3,4]}"#).params_uniform(), Some([1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn ignores_malformed_json() {
+        assert_eq!(parse(r#"{"shader":"waves.frag""#), None);
+        assert_eq!(parse(r#"{"pause":"yes"}"#), None);
+        assert_eq!(parse(r#"{"brightness":0.5}"#), None);
+    }
+
+    #[test]
+    fn parses_legacy_values() {
+        assert_eq!(parse("x: 5, y: -20, z: 0"), Some(InputMessage::Values([0.5, -1.0, 0.0])));
+        assert_eq!(parse(""), Some(InputMessage::Values([0.0; 3])));
+        assert_eq!(parse("x: 5, y: oops, z: 0"), None);
+        assert_eq!(parse("x: 5"), None);
+    }
+}
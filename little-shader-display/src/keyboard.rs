@@ -0,0 +1,147 @@
+use winit::event::VirtualKeyCode;
+
+// Keys reserved for controlling the app (Space - next shader, B - previous shader, H - toggle HUD, U - print uniforms,
+// [ and ] - decrease and increase render scale, P - select the next user param, - and = - decrease and increase it,
+// R - start and stop recording a video, S - reserved for future app controls).
+// They are not forwarded to shaders unless all keys are given to shaders with --shader-keys.
+pub const APP_CONTROL_KEYS: [VirtualKeyCode; 11] = [
+    VirtualKeyCode::Space,
+    VirtualKeyCode::B,
+    VirtualKeyCode::H,
+    VirtualKeyCode::U,
+    VirtualKeyCode::P,
+    VirtualKeyCode::S,
+    VirtualKeyCode::R,
+    VirtualKeyCode::LBracket,
+    VirtualKeyCode::RBracket,
+    VirtualKeyCode::Minus,
+    VirtualKeyCode::Equals,
+];
+
+// Stable key to bit mapping of the `keys` uniform, index in this table is the bit number.
+// Bit n is stored in keys[n / 32] at position n % 32. New keys must only be appended.
+static KEY_BITS: [VirtualKeyCode; 48] = [
+    // Bits 0-25: letters
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E,
+    VirtualKeyCode::F, VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J,
+    VirtualKeyCode::K, VirtualKeyCode::L, VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O,
+    VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R, VirtualKeyCode::S, VirtualKeyCode::T,
+    VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X, VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+    // Bits 26-35: digits
+    VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    // Bits 36-39: arrows
+    VirtualKeyCode::Left, VirtualKeyCode::Up, VirtualKeyCode::Right, VirtualKeyCode::Down,
+    // Bits 40-47: modifiers and special keys
+    VirtualKeyCode::LShift, VirtualKeyCode::LControl, VirtualKeyCode::LAlt, VirtualKeyCode::Tab,
+    VirtualKeyCode::Return, VirtualKeyCode::Escape, VirtualKeyCode::Back, VirtualKeyCode::Space,
+];
+
+// Returns the bit of the `keys` uniform for the given key, or None if the key is not exposed to shaders
+pub fn key_bit(key: VirtualKeyCode, shader_keys_all: bool) -> Option<u32> {
+    if !shader_keys_all && APP_CONTROL_KEYS.contains(&key) {
+        return None;
+    }
+
+    KEY_BITS.iter().position(|k| *k == key).map(|bit| bit as u32)
+}
+
+// Width of the keyboard channel, one texel per JavaScript key code like Shadertoy's keyboard texture
+pub const KEY_CODE_COUNT: usize = 256;
+
+// Returns the JavaScript key code of the given key, which is its texel in the keyboard channel
+pub fn key_code(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    let digits = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    let function_keys = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+    if let Some(index) = letters.iter().position(|k| *k == key) {
+        return Some(65 + index as u8);
+    }
+    if let Some(index) = digits.iter().position(|k| *k == key) {
+        return Some(48 + index as u8);
+    }
+    if let Some(index) = function_keys.iter().position(|k| *k == key) {
+        return Some(112 + index as u8);
+    }
+    match key {
+        Back => Some(8),
+        Tab => Some(9),
+        Return => Some(13),
+        LShift | RShift => Some(16),
+        LControl | RControl => Some(17),
+        LAlt | RAlt => Some(18),
+        Escape => Some(27),
+        Space => Some(32),
+        Left => Some(37),
+        Up => Some(38),
+        Right => Some(39),
+        Down => Some(40),
+        Delete => Some(46),
+        _ => None,
+    }
+}
+
+// Pressed and toggled state of every key code for the keyboard channel
+pub struct KeyStates {
+    pressed: [bool; KEY_CODE_COUNT],
+    toggled: [bool; KEY_CODE_COUNT], // Flipped on every press, like Caps Lock
+}
+
+impl KeyStates {
+    pub fn new() -> Self {
+        Self { pressed: [false; KEY_CODE_COUNT], toggled: [false; KEY_CODE_COUNT] }
+    }
+
+    // Returns true if the state of the key changed, key repeats while holding it down do not toggle it again
+    pub fn key_changed(&mut self, key: VirtualKeyCode, pressed: bool, shader_keys_all: bool) -> bool {
+        if !shader_keys_all && APP_CONTROL_KEYS.contains(&key) {
+            return false;
+        }
+        let Some(code) = key_code(key) else {
+            return false;
+        };
+        let code = code as usize;
+        if self.pressed[code] == pressed {
+            return false;
+        }
+        self.pressed[code] = pressed;
+        if pressed {
+            self.toggled[code] = !self.toggled[code];
+        }
+        true
+    }
+
+    // Texels of the keyboard channel, red is 1 while the key is held and green is 1 while it is toggled on
+    pub fn texels(&self) -> Vec<u8> {
+        (0..KEY_CODE_COUNT).flat_map(|code| [self.pressed[code] as u8 * 255, self.toggled[code] as u8 * 255]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_toggle_keys_once() {
+        let mut key_states = KeyStates::new();
+        assert!(key_states.key_changed(VirtualKeyCode::A, true, false));
+        assert!(!key_states.key_changed(VirtualKeyCode::A, true, false)); // Key repeat
+        assert_eq!(key_states.texels()[65 * 2..65 * 2 + 2], [255, 255]);
+
+        assert!(key_states.key_changed(VirtualKeyCode::A, false, false));
+        assert_eq!(key_states.texels()[65 * 2..65 * 2 + 2], [0, 255]);
+        key_states.key_changed(VirtualKeyCode::A, true, false);
+        key_states.key_changed(VirtualKeyCode::A, false, false);
+        assert_eq!(key_states.texels()[65 * 2..65 * 2 + 2], [0, 0]);
+    }
+
+    #[test]
+    fn app_control_keys_are_kept_from_shaders() {
+        let mut key_states = KeyStates::new();
+        assert!(!key_states.key_changed(VirtualKeyCode::Space, true, false));
+        assert!(key_states.key_changed(VirtualKeyCode::Space, true, true));
+        assert_eq!(key_code(VirtualKeyCode::Up), Some(38));
+    }
+}
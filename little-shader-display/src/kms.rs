@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::Path;
+
+use drm::buffer::{Buffer, DrmFourcc};
+use drm::control::{connector, crtc, dumbbuffer::DumbBuffer, framebuffer, Device as ControlDevice, Mode, ModeTypeFlags, PageFlipFlags};
+
+use crate::display_sink::DisplaySink;
+use crate::framebuffer::{PixelFormat, ScreenLayout};
+
+// The opened card, the drm crate talks to it through these traits
+struct Card(File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl drm::Device for Card {}
+impl ControlDevice for Card {}
+
+// A CPU writable buffer the CRTC can scan out
+struct ScanoutBuffer {
+    buffer: DumbBuffer,
+    fb: framebuffer::Handle,
+}
+
+impl ScanoutBuffer {
+    // Dumb buffers are zeroed, so the borders around frames stay black
+    fn new(card: &Card, width: u32, height: u32) -> io::Result<Self> {
+        let buffer = card.create_dumb_buffer((width, height), DrmFourcc::Xrgb8888, 32)?;
+        match card.add_framebuffer(&buffer, 24, 32) {
+            Ok(fb) => Ok(Self { buffer, fb }),
+            Err(err) => {
+                card.destroy_dumb_buffer(buffer).ok();
+                Err(err)
+            }
+        }
+    }
+
+    fn destroy(self, card: &Card) {
+        card.destroy_framebuffer(self.fb).ok();
+        card.destroy_dumb_buffer(self.buffer).ok();
+    }
+}
+
+// Shows frames on a screen through DRM/KMS without X, Wayland or a compositor, e.g. for kiosks.
+// Frames are drawn into one of two buffers which are flipped on vertical blank, so they do not tear.
+pub struct Kms {
+    card: Card,
+    crtc: crtc::Handle,
+    saved_crtc: crtc::Info, // Restored on exit, e.g. the console
+    connector: connector::Handle,
+    layout: ScreenLayout,
+    buffers: Vec<ScanoutBuffer>,
+    back_buffer: usize, // Index of the buffer which is not shown
+    flip_pending: bool,
+}
+
+impl Kms {
+    // Opens the card (e.g. /dev/dri/card0) and shows a black screen in the preferred mode of the first connected screen.
+    // Fails if another program such as a compositor controls the card.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().read(true).write(true).open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        let card = Card(file);
+
+        let resources = card.resource_handles().map_err(|err| format!("{} does not support mode setting: {}", path.display(), err))?;
+        let mut connected = None;
+        for &handle in resources.connectors() {
+            let connector = card.get_connector(handle, false)?;
+            if connector.state() == connector::State::Connected {
+                if let Some(mode) = preferred_mode(connector.modes()) {
+                    connected = Some((connector, mode));
+                    break;
+                }
+            }
+        }
+        let (connector, mode) = connected.ok_or("No connected screen found")?;
+
+        // The CRTC driving the connector's current encoder, or the first one an encoder of the connector can use
+        let current_crtc = match connector.current_encoder() {
+            Some(encoder) => card.get_encoder(encoder)?.crtc(),
+            None => None,
+        };
+        let crtc = match current_crtc {
+            Some(crtc) => crtc,
+            None => connector.encoders().iter()
+                .map(|&encoder| card.get_encoder(encoder))
+                .collect::<io::Result<Vec<_>>>()?
+                .iter()
+                .find_map(|encoder| resources.filter_crtcs(encoder.possible_crtcs()).first().copied())
+                .ok_or("No CRTC available for the screen")?,
+        };
+        let saved_crtc = card.get_crtc(crtc)?;
+
+        let (width, height) = (mode.size().0 as u32, mode.size().1 as u32);
+        // Buffers created so far are destroyed when the Kms is dropped, also if opening fails halfway
+        let mut kms = Self {
+            card,
+            crtc,
+            saved_crtc,
+            connector: connector.handle(),
+            layout: ScreenLayout { width, height, stride: 0, format: PixelFormat::Xrgb8888 },
+            buffers: Vec::new(),
+            back_buffer: 1,
+            flip_pending: false,
+        };
+        for _ in 0..2 {
+            let buffer = ScanoutBuffer::new(&kms.card, width, height).map_err(|err| format!("Failed to create a scanout buffer: {}", err))?;
+            kms.layout.stride = buffer.buffer.pitch() as usize;
+            kms.buffers.push(buffer);
+        }
+
+        kms.card.set_crtc(kms.crtc, Some(kms.buffers[0].fb), (0, 0), &[kms.connector], Some(mode))
+            .map_err(|err| format!("Failed to set the mode, is a compositor running? {}", err))?;
+        println!("Using DRM/KMS output {}: {} at {} Hz", path.display(), mode.name().to_string_lossy(), mode.vrefresh());
+        Ok(kms)
+    }
+
+    // Blocks until the flip requested with the previous frame happened, its buffer can then be drawn into again
+    fn wait_for_flip(&mut self) -> io::Result<()> {
+        if self.flip_pending {
+            // The page flip event, its content is not needed
+            self.card.receive_events()?.for_each(drop);
+            self.flip_pending = false;
+        }
+        Ok(())
+    }
+}
+
+impl DisplaySink for Kms {
+    fn panel_size(&self) -> (u32, u32) {
+        (self.layout.width, self.layout.height)
+    }
+
+    fn render_size(&self) -> Option<(u32, u32)> {
+        Some((self.layout.width, self.layout.height))
+    }
+
+    fn wait_for_te(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.wait_for_flip()?)
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        self.wait_for_flip()?;
+        let layout = self.layout;
+        let buffer = &mut self.buffers[self.back_buffer];
+        // Mapped only while drawing, the mapping borrows the buffer
+        let mut pixels = self.card.map_dumb_buffer(&mut buffer.buffer)?;
+        layout.draw(&mut pixels, rgb565_bytes, width, height);
+        drop(pixels);
+
+        self.card.page_flip(self.crtc, buffer.fb, PageFlipFlags::EVENT, None)?;
+        self.flip_pending = true;
+        self.back_buffer = 1 - self.back_buffer;
+        Ok(())
+    }
+}
+
+impl Drop for Kms {
+    fn drop(&mut self) {
+        self.wait_for_flip().ok();
+        if let Some(mode) = self.saved_crtc.mode() {
+            self.card.set_crtc(self.crtc, self.saved_crtc.framebuffer(), self.saved_crtc.position(), &[self.connector], Some(mode)).ok();
+        }
+        for buffer in self.buffers.drain(..) {
+            buffer.destroy(&self.card);
+        }
+    }
+}
+
+fn preferred_mode(modes: &[Mode]) -> Option<Mode> {
+    modes.iter().find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED)).or(modes.first()).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_preferred_mode() {
+        let mode = |hdisplay: u16, type_: u32| Mode::from(drm_ffi::drm_mode_modeinfo { hdisplay, type_, ..Default::default() });
+        let preferred = ModeTypeFlags::PREFERRED.bits();
+        assert_eq!(preferred_mode(&[mode(1280, 0), mode(1920, preferred)]).unwrap().size().0, 1920);
+        assert_eq!(preferred_mode(&[mode(1280, 0), mode(1920, 0)]).unwrap().size().0, 1280);
+        assert!(preferred_mode(&[]).is_none());
+    }
+}
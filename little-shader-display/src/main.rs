@@ -1,249 +1,985 @@
 // --- Module declarations and conditional compilation for platform-specific drivers ---
 mod file_watcher;
 mod bluetooth_server;
+mod keyboard;
+mod mouse;
+mod playlist;
+mod hud;
+mod blit;
+mod shader_metadata;
+mod shader_dependencies;
+mod shader_compiler;
+mod user_params;
+mod display_sink;
+mod multipass;
+mod watchdog;
+mod input_message;
+mod frame_pacer;
+mod session;
+mod metrics;
+mod color_conversion;
+mod antiburn;
+mod stdin_shader;
+mod spirv_dump;
+mod option_validation;
+mod options;
+#[cfg(feature = "mqtt")]
+mod mqtt_control;
+mod websocket_server;
+mod http_api;
 mod renderer;
+mod app;
 
 #[cfg(target_os = "linux")]
 mod st7789_driver;
+#[cfg(target_os = "linux")]
+mod spi_interface;
+#[cfg(target_os = "linux")]
+mod ssd1306;
+#[cfg(target_os = "linux")]
+mod framebuffer;
+#[cfg(target_os = "linux")]
+mod kms;
+#[cfg(target_os = "linux")]
+mod hub75;
+#[cfg(target_os = "linux")]
+mod ws2812;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))] // Only shown on the st7789, parsed on every platform
+mod test_pattern;
+#[cfg(target_os = "linux")]
+mod splash;
+#[cfg(all(target_os = "linux", feature = "accel"))]
+mod accelerometer;
+#[cfg(target_os = "linux")]
+mod buttons;
+#[cfg(target_os = "linux")]
+mod rotary_encoder;
+#[cfg(target_os = "linux")]
+mod ambient_light;
+mod audio;
+mod camera;
+mod telemetry;
+mod batch_render;
+mod video_recorder;
+#[cfg(test)]
+mod test_renderer;
 
 // --- Standard and external library imports ---
 use std::{
     env, 
     path::{PathBuf},
     sync::{Arc, LazyLock},
-    time::{Duration, Instant},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
-use renderer::Renderer;
+use renderer::{GpuOptions, Renderer, RendererOptions, ShaderCompileOptions};
+use app::App;
+use playlist::Playlist;
 use file_watcher::FileWatcher;
 use tokio::sync::Mutex;
 use winit::{
     dpi::LogicalSize,
-    event::{Event, WindowEvent},
-    event_loop::{EventLoop},
-    platform::run_return::EventLoopExtRunReturn,
-    window::{Window, WindowBuilder},
+    event::Event,
+    event_loop::EventLoop,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window, WindowBuilder},
 };
-use std::io::Read;
 use std::fs::File;
 use std::os::unix::io::AsRawFd;
 use libc::{fcntl, F_GETFL, F_SETFL, O_NONBLOCK};
 use bluetooth_server::BluetoothServer;
+use display_sink::DisplaySink;
+use watchdog::Watchdog;
+use frame_pacer::FramePacer;
+use session::{SessionPlayer, SessionRecorder};
+use metrics::MetricsServer;
+use websocket_server::WebSocketServer;
+use http_api::HttpApi;
+use antiburn::AntiBurn;
+use stdin_shader::StdinShader;
+use option_validation::OptionValidation;
+use options::{Cli, Command, Options, Power, PresentMode};
+use batch_render::BatchRender;
 
 static DEBUG_OVERHEADS: bool = false;
-static SHADER_NAMES: [&str; 6] = ["waves.frag", "mutation.frag", "fractal.frag", "grid.frag", "rings.frag", "tilt.frag"];
+static WINDOW_TITLE: &str = "Little Shader Display";
 static ST7789_OUTPUT_SIZE: u32 = 256;
-
+static DEFAULT_ST7789_MAX_FPS: f32 = 30.0; // ST7789 frame rate limit used when the window is also active
+static SLOW_REFRESH_POLL_INTERVAL: Duration = Duration::from_millis(50); // How often to check for work between frames in slow refresh mode
+static DEFAULT_ANTIBURN_AMPLITUDE: u32 = 3; // Largest pixel shift from the center with --antiburn
+static DEFAULT_ANTIBURN_PERIOD: f32 = 60.0; // Seconds between one pixel steps with --antiburn
+static FADE_OUT_DURATION: Duration = Duration::from_millis(500); // How long --fade-out takes to dim the ST7789 to black on shutdown
+static MAX_GPIO_PIN: u8 = 27; // Highest BCM GPIO number on the Raspberry Pi header
+static BENCHMARK_CONVERT_ITERATIONS: u32 = 200; // Frames converted by each conversion in --benchmark-convert
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false); // Set on Ctrl+C or SIGTERM so the main loop can shut down cleanly
+
+// Shaders directory used at startup, a different one can be loaded at runtime with the loaddir command
+#[cfg(not(test))]
 static SHADERS_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
     std::env::current_exe().unwrap().parent().unwrap().join("res").join("shaders")
 });
 
-static COMPILED_VERTEX_SHADER_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
-    SHADERS_PATH.join("compiled").join("master.vert.spv")
-});
-
-static COMPILED_FRAGMENT_SHADER_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
-    SHADERS_PATH.join("compiled").join("master.frag.spv")
+// Tests run without resources next to the executable, they render shaders copied to a temporary directory
+#[cfg(test)]
+static SHADERS_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    std::env::temp_dir().join(format!("little-shader-display-test-{}", std::process::id())).join("shaders")
 });
 
 
 #[tokio::main]
 async fn main() {
-    let mut use_window = false;
-    let mut use_st7789 = false;
-    let mut use_bluetooth = false;
+    // --- Read the config file and command-line arguments ---
+
+    // Invalid arguments print the error and exit with code 2, --help prints the usage
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = Cli::parse_args(&args).unwrap_or_else(|err| err.exit());
+    let (command, options) = Options::load(cli, std::env::current_exe().unwrap().parent().unwrap()).unwrap_or_else(|err| {
+        println!("Error: {}", err);
+        std::process::exit(2);
+    });
+
+    // --- Check the option values before anything is initialized ---
+
+    // The outputs and inputs are only checked when the run and record commands start them
+    let starts_outputs = matches!(command, Command::Run | Command::Record { .. })
+        && !options.once
+        && !options.benchmark_convert
+        && options.dump_spirv.is_none()
+        && !options.list_monitors;
+    check_options(&options, starts_outputs);
+
+    // --- Benchmark the RGB565 conversions and exit if requested ---
 
-    // --- Parse command-line arguments ---
+    if options.benchmark_convert {
+        color_conversion::benchmark(BENCHMARK_CONVERT_ITERATIONS);
+        return;
+    }
+
+    // --- Dump a compiled shader and exit if requested ---
 
-    let args: Vec<String> = env::args().collect();
-    for arg in &args {
-        match arg.as_str() {
-            "--window" => use_window = true,
-            "--st7789" => use_st7789 = true,
-            "--bluetooth" => use_bluetooth = true,
-            _ => {}
+    if let Some(shader_name) = &options.dump_spirv {
+        if let Err(err) = spirv_dump::dump(shader_name, compile_options(&options)) {
+            eprintln!("Error: Failed to dump {}: {}", shader_name, err);
+            std::process::exit(1);
         }
+        return;
+    }
+
+    // --- List the monitors and exit if requested ---
+
+    if options.list_monitors {
+        print_monitors(&EventLoop::new());
+        return;
+    }
+
+    // --- Run the chosen command, --once renders like the render command ---
+
+    match command {
+        Command::List => list_shaders(&options),
+        Command::Validate => validate_shaders(&options),
+        Command::Render => render_frames(&options),
+        Command::Run | Command::Record { .. } if options.once => render_frames(&options),
+        Command::Run | Command::Record { .. } => run(options).await,
+    }
+}
+
+// Shows the shaders on the window and displays until Ctrl+C, the window is closed or the app is asked to quit
+async fn run(options: Options) {
+    let gpu_options = gpu_options(&options);
+    let compile_options = compile_options(&options);
+    let color_conversion = options.convert;
+    let playlist = load_playlist(&options);
+    let Options {
+        window: use_window,
+        window_size,
+        monitors: monitor_indices,
+        all_monitors,
+        st7789: use_st7789,
+        st7789_rotation,
+        st7789_fps: mut st7789_max_fps,
+        st7789_te: st7789_te_pin,
+        st7789_panels,
+        st7789_layout,
+        ssd1306: use_ssd1306,
+        ssd1306_dither,
+        framebuffer: framebuffer_path,
+        drm: drm_path,
+        hub75: use_hub75,
+        hub75_rows,
+        hub75_cols,
+        hub75_chain,
+        hub75_mapping,
+        hub75_pwm_bits,
+        hub75_gamma,
+        ws2812: ws2812_layout,
+        ws2812_spi,
+        ws2812_order,
+        ws2812_brightness,
+        st7789_dc: st7789_dc_pin,
+        st7789_rst: st7789_rst_pin,
+        st7789_bl: st7789_bl_pin,
+        spi_clock,
+        verify_spi,
+        spi_chunk_size,
+        spi_retries,
+        test_pattern,
+        fade_out,
+        antiburn: use_antiburn,
+        antiburn_amplitude,
+        antiburn_period,
+        render_scale,
+        max_fps,
+        refresh_interval,
+        flip_uv,
+        sanitize,
+        hud: use_hud,
+        hud_st7789: hud_on_st7789,
+        error_overlay,
+        watchdog: watchdog_timeout,
+        record: video_path,
+        record_fps: video_fps,
+        duration: video_duration,
+        shader_keys: shader_keys_all,
+        stdin_shader: use_stdin_shader,
+        bluetooth: use_bluetooth,
+        #[cfg(feature = "mqtt")]
+        mqtt: mqtt_broker,
+        websocket_port,
+        websocket_public,
+        http_port,
+        metrics_port,
+        record_session: record_session_path,
+        play_session: play_session_path,
+        #[cfg(target_os = "linux")]
+        buttons: button_args,
+        #[cfg(target_os = "linux")]
+        encoder: encoder_arg,
+        #[cfg(target_os = "linux")]
+        encoder_param,
+        #[cfg(all(target_os = "linux", feature = "accel"))]
+        accel,
+        #[cfg(all(target_os = "linux", feature = "accel"))]
+        accel_rotate,
+        #[cfg(target_os = "linux")]
+        ambient_light: use_ambient_light,
+        telemetry: use_telemetry,
+        audio: use_audio,
+        audio_source,
+        camera: camera_path,
+        ..
+    } = options;
+
+    // Set if the accelerometer is used, true if the ST7789 follows its orientation
+    #[cfg(all(target_os = "linux", feature = "accel"))]
+    let accelerometer_rotate = (accel || accel_rotate).then_some(accel_rotate);
+
+    // Wiring and SPI settings of the st7789 panels
+    #[cfg(target_os = "linux")]
+    let st7789_config = {
+        let mut config = st7789_driver::ST7789Config { verify_spi, te_pin: st7789_te_pin, ..Default::default() };
+        if let Some(spi_retries) = spi_retries {
+            config.spi_retries = spi_retries;
+        }
+        if let Some(spi_chunk_size) = spi_chunk_size {
+            config.spi_chunk_size = spi_chunk_size;
+        }
+        if let Some(spi_clock) = spi_clock {
+            config.spi_clock_speed = (spi_clock * 1_000_000.0) as u32;
+        }
+        config.pins.dc = st7789_dc_pin.unwrap_or(config.pins.dc);
+        config.pins.rst = st7789_rst_pin.unwrap_or(config.pins.rst);
+        config.pins.bl = st7789_bl_pin.unwrap_or(config.pins.bl);
+        config
+    };
+
+    // --- Show a test pattern on the st7789 display and exit if requested ---
+
+    #[cfg(target_os = "linux")]
+    if let Some(pattern) = test_pattern {
+        show_test_pattern(pattern, st7789_config);
+        return;
     }
 
     println!("Using window display: {}", use_window);
     println!("Using st7789 display: {}", use_st7789);
+    if use_ssd1306 {
+        println!("Using SSD1306 display");
+    }
+    let use_framebuffer = framebuffer_path.is_some();
+    let use_drm = drm_path.is_some();
+    let use_ws2812 = ws2812_layout.is_some();
     println!("Using bluetooth: {}", use_bluetooth);
 
-    if use_st7789 && cfg!(target_os = "windows") {
-        panic!("st7789 display is not supported on Windows");
+    if let Some(interval) = refresh_interval {
+        println!("Using slow refresh every {} seconds", interval);
     }
 
-    if !use_window && cfg!(target_os = "windows") {
-        panic!("No display chosen for Windows");
+    if let Some(fps) = max_fps {
+        println!("Limiting main loop to {} FPS", fps);
     }
 
-    if !use_window && !use_st7789 && cfg!(target_os = "linux") {
-        panic!("No display chosen for Linux");
+    // With both displays the ST7789 is paced separately so its readback does not slow down the window
+    if use_window && (use_st7789 || use_ssd1306 || use_framebuffer || use_drm || use_hub75 || use_ws2812) {
+        let fps = *st7789_max_fps.get_or_insert(DEFAULT_ST7789_MAX_FPS);
+        println!("Rendering st7789 display at up to {} FPS", fps);
     }
 
     // --- Create st7789 driver, window, renderer, file watcher, and bluetooth server ---
 
     // Create and initialize st7789 driver if requested and on Linux 
     #[cfg(target_os = "linux")]
-    let st7789_driver: Option<st7789_driver::RaspberryST7789Driver> = if use_st7789 {
-        let mut driver = st7789_driver::RaspberryST7789Driver::new().unwrap();
-        driver.initialize().unwrap();
-        Some(driver)
+    let mut display_sinks: Vec<Box<dyn DisplaySink>> = if use_st7789 {
+        let config = st7789_config;
+        // One panel on SPI0 CE0 unless --st7789-panel lists them, only the first one waits for its TE signal
+        let spi_devices = if st7789_panels.is_empty() {
+            vec![st7789_driver::SpiDevice::default()]
+        } else {
+            st7789_panels.iter().map(|panel| st7789_driver::SpiDevice::parse(panel).expect("--st7789-panel is checked by check_options")).collect()
+        };
+        let configs: Vec<_> = spi_devices
+            .into_iter()
+            .enumerate()
+            .map(|(index, spi)| st7789_driver::ST7789Config { spi, te_pin: config.te_pin.filter(|_| index == 0), ..config })
+            .collect();
+        let drivers = st7789_driver::RaspberryST7789Driver::new_panels(&configs).unwrap_or_else(|err| panic!("Failed to create st7789 driver: {}", err));
+
+        // Show the splash while the shaders compile
+        let splash_rgba_bytes = splash::load(&SHADERS_PATH.parent().unwrap().join("splash.png"), ST7789_OUTPUT_SIZE);
+        let splash_rgb565_bytes = color_conversion::rgba8888_to_rgb565_u8(&splash_rgba_bytes, false);
+        drivers
+            .into_iter()
+            .map(|mut driver| {
+                driver.initialize().unwrap();
+                if let Err(err) = driver.draw(&splash_rgb565_bytes, ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE) {
+                    println!("Warning: Failed to show splash: {}", err);
+                }
+                Box::new(driver) as Box<dyn DisplaySink>
+            })
+            .collect()
     } else {
-        None
+        Vec::new()
     };
+    #[cfg(not(target_os = "linux"))]
+    let display_sinks: Vec<Box<dyn DisplaySink>> = Vec::new();
 
-    // Create window if requested
-    let mut event_loop = EventLoop::new(); 
-    let window: Option<Window> = if use_window {
-        let window = WindowBuilder::new()
-            .with_inner_size(LogicalSize::new(500, 500))
-            .with_title("Little Shader Display")
-            .with_visible(true) // Make visible directly
-            .build(&event_loop)
-            .expect("failed to create a window");
-        Some(window)
+    // The framebuffer, the DRM screen, the LEDs and the OLED get the same frames as the st7789 panels, or are the only displays
+    #[cfg(target_os = "linux")]
+    if let Some(path) = &framebuffer_path {
+        display_sinks.push(Box::new(framebuffer::Framebuffer::open(path).unwrap_or_else(|err| panic!("Failed to open framebuffer: {}", err))));
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(path) = &drm_path {
+        display_sinks.push(Box::new(kms::Kms::open(path).unwrap_or_else(|err| panic!("Failed to open DRM device: {}", err))));
+    }
+    #[cfg(target_os = "linux")]
+    if use_hub75 {
+        let defaults = hub75::Hub75Config::default();
+        let config = hub75::Hub75Config {
+            rows: hub75_rows.unwrap_or(defaults.rows),
+            cols: hub75_cols.unwrap_or(defaults.cols),
+            chain: hub75_chain.unwrap_or(defaults.chain),
+            mapping: hub75_mapping,
+            pwm_bits: hub75_pwm_bits.unwrap_or(defaults.pwm_bits),
+            gamma: hub75_gamma.unwrap_or(defaults.gamma),
+        };
+        display_sinks.push(Box::new(hub75::Hub75::new(config).unwrap_or_else(|err| panic!("Failed to create HUB75 driver: {}", err))));
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(layout) = &ws2812_layout {
+        let map = ws2812::LedMap::parse(layout).expect("--ws2812 is checked by check_options");
+        let spi_device = ws2812_spi.as_deref().map_or(Ok(st7789_driver::SpiDevice::default()), st7789_driver::SpiDevice::parse).expect("--ws2812-spi is checked by check_options");
+        let leds = ws2812::Ws2812::new(spi_device, map, ws2812_order, ws2812_brightness.unwrap_or(1.0));
+        display_sinks.push(Box::new(leds.unwrap_or_else(|err| panic!("Failed to create WS2812 driver: {}", err))));
+    }
+    #[cfg(target_os = "linux")]
+    if use_ssd1306 {
+        display_sinks.push(Box::new(ssd1306::Ssd1306::new(ssd1306_dither).unwrap_or_else(|err| panic!("Failed to create SSD1306 driver: {}", err))));
+    }
+
+    // Create event loop and window if requested
+    let event_loop: Option<EventLoop<()>> = if use_window { Some(EventLoop::new()) } else { None };
+    let windows: Vec<Window> = if let Some(event_loop) = &event_loop {
+        // On each chosen monitor a window is borderless fullscreen, otherwise a single window is placed by the system (usually on the primary monitor)
+        let mut monitors: Vec<Option<MonitorHandle>> = if all_monitors {
+            event_loop.available_monitors().map(Some).collect()
+        } else {
+            check_monitors(event_loop, &monitor_indices);
+            monitor_indices.iter().map(|&index| Some(select_monitor(event_loop, index))).collect()
+        };
+        if monitors.is_empty() {
+            monitors.push(None);
+        }
+        monitors
+            .into_iter()
+            .map(|monitor| {
+                WindowBuilder::new()
+                    .with_inner_size(LogicalSize::new(window_size[0], window_size[1]))
+                    .with_title(WINDOW_TITLE)
+                    .with_fullscreen(monitor.map(|monitor| Fullscreen::Borderless(Some(monitor))))
+                    .with_visible(true) // Make visible directly
+                    .build(event_loop)
+                    .expect("failed to create a window")
+            })
+            .collect()
     } else {
-        None
+        Vec::new()
     };
 
     // Create a file watcher to monitor shader files for changes
-    let mut file_watcher = FileWatcher::new(std::env::current_exe().unwrap().parent().unwrap().join(SHADERS_PATH.clone().join("uncompiled")));
+    let file_watcher = FileWatcher::new(std::env::current_exe().unwrap().parent().unwrap().join(SHADERS_PATH.clone().join("uncompiled")));
    
-    // Only on Linux: include all arguments
-    #[cfg(target_os = "linux")]
-    let mut renderer = Renderer::new(use_window, window.as_ref(), use_st7789, st7789_driver);
+    let mut renderer = Renderer::new(RendererOptions { window: windows.first(), display_sinks, gpu_options, compile_options, shader_name: playlist.current(), ..RendererOptions::default() });
+    for (index, window) in windows.iter().enumerate().skip(1) {
+        if let Err(err) = renderer.add_window(window) {
+            println!("Warning: Failed to show the shader in window {}: {}", index, err);
+        }
+    }
 
-    // On other platforms
-    #[cfg(not(target_os = "linux"))]
-    let mut renderer = Renderer::new(use_window, window.as_ref());
+    renderer.set_hud_visible(use_hud || hud_on_st7789);
+    renderer.set_hud_on_st7789(hud_on_st7789);
+    renderer.set_error_overlay(error_overlay);
+    renderer.set_st7789_rotation(st7789_rotation / 90);
+    renderer.set_display_layout(st7789_layout);
+    renderer.set_flip_uv(flip_uv);
+    renderer.set_color_conversion(color_conversion);
+    renderer.set_sanitize(sanitize);
+    renderer.set_st7789_max_fps(st7789_max_fps);
+    if fade_out {
+        renderer.set_fade_out(Some(FADE_OUT_DURATION));
+    }
+    if render_scale < 1.0 {
+        renderer.set_render_scale(render_scale);
+    }
+    if use_antiburn {
+        println!("Using antiburn pixel shift of up to {} pixels every {} seconds", antiburn_amplitude, antiburn_period);
+        renderer.set_antiburn(Some(AntiBurn::new(antiburn_amplitude, Duration::from_secs_f32(antiburn_period))));
+    }
 
+    // Create bluetooth server if requested, continue without it if bluetooth is unavailable
+    let mut bluetooth_clients = None;
+    let mut snapshot_request = None;
     let bluetooth_server: Option<Arc<Mutex<Option<String>>>> = if use_bluetooth {
-        let server = BluetoothServer::new().await.unwrap();
-        let received_text = server.received_text.clone();
-    
-        tokio::spawn(async move {
-            server.run().await.unwrap();
-        });
-    
-        Some(received_text)
+        match BluetoothServer::new().await {
+            Ok(server) => {
+                let received_text = server.received_text.clone();
+                bluetooth_clients = Some(server.clients_connected.clone());
+                snapshot_request = Some(server.snapshot_request.clone());
+
+                tokio::spawn(async move {
+                    if let Err(err) = server.run().await {
+                        println!("Warning: Bluetooth server stopped: {}", err);
+                    }
+                });
+
+                Some(received_text)
+            }
+            Err(err) => {
+                println!("Warning: Bluetooth is unavailable ({}), continuing without bluetooth", err);
+                None
+            }
+        }
     } else {
         None
     };
 
-    // --- Define main loop variables ---
+    // --- Define main loop state ---
 
-    let mut current_shader_index = 0;
-    let start_time = Instant::now();
-    let mut running = true;
-    let mut frame = 0;
 
-    
-    let mut last_fps_update = Instant::now();
-    
-    // Setup non-blocking stdin reading to detect user input 
-    let stdin = File::open("/dev/stdin").unwrap();
-    let fd: i32 = stdin.as_raw_fd();
-    let flags = unsafe { fcntl(fd, F_GETFL) };
-    unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) };    
+    // Setup non-blocking stdin reading to detect user input, unless stdin carries shaders
+    let stdin = if use_stdin_shader {
+        None
+    } else {
+        let stdin = File::open("/dev/stdin").unwrap();
+        let fd: i32 = stdin.as_raw_fd();
+        let flags = unsafe { fcntl(fd, F_GETFL) };
+        unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) };    
+        Some(stdin)
+    };
 
-    let mut bluetooth_data = String::new();
+    let mut app = App::new(renderer, playlist, file_watcher, bluetooth_server, stdin, shader_keys_all, refresh_interval);
+    if let Some(bluetooth_clients) = bluetooth_clients {
+        app.add_clients_connected(bluetooth_clients);
+    }
+    if let Some(snapshot_request) = snapshot_request {
+        app.add_snapshot_request(snapshot_request);
+    }
 
-    // --- Main loop ---
+    // Read shaders piped to stdin if requested
+    if use_stdin_shader {
+        println!("Reading shaders from stdin, end each shader with a line containing only {}", stdin_shader::DELIMITER);
+        app.set_stdin_shader(StdinShader::start());
+    }
 
-    println!("Initialization complete. Starting main loop...");
+    // Connect to the MQTT broker if requested, the client keeps reconnecting in the background if the broker is unreachable
+    #[cfg(feature = "mqtt")]
+    if let Some(broker) = &mqtt_broker {
+        println!("Using MQTT broker: {}", broker);
+        let mqtt_control = mqtt_control::MqttControl::new(broker);
+        app.set_mqtt(mqtt_control.status_publisher());
+        tokio::spawn(mqtt_control.run());
+    }
 
-    while running {
-        frame += 1;
+    // Serve render stats over TCP if requested, continue without them if the port can not be bound
+    if let Some(port) = metrics_port {
+        match MetricsServer::bind(port).await {
+            Ok(server) => {
+                println!("Serving metrics on TCP port {}", server.port());
+                app.set_metrics(server.stats());
+                tokio::spawn(server.run());
+            }
+            Err(err) => println!("Warning: Failed to serve metrics on port {} ({}), continuing without metrics", port, err),
+        }
+    }
 
-        // 1. Check for data received by bluetooth server
-        if use_bluetooth {
-            // Check if the Bluetooth server is running and print the latest received message
-            if let Some(received_text) = &bluetooth_server {
-                if let Ok(message) = received_text.try_lock() {
-                    if let Some(ref string) = *message {
-                        bluetooth_data = string.clone();
-                    }
-                } 
+    // Accept remote control commands over WebSocket if requested, continue without it if the port can not be bound
+    if let Some(port) = websocket_port {
+        match WebSocketServer::bind(port, websocket_public).await {
+            Ok(server) => {
+                let clients = if websocket_public { "any device" } else { "this device" };
+                println!("Accepting WebSocket commands from {} on TCP port {}", clients, server.port());
+                app.add_command_queue(server.received_commands());
+                app.add_clients_connected(server.clients_connected());
+                app.add_snapshot_request(server.snapshot_request());
+                tokio::spawn(server.run());
             }
+            Err(err) => println!("Warning: Failed to accept WebSocket connections on port {} ({}), continuing without them", port, err),
         }
+    }
 
-        // 2. Handle window events
-        if use_window {
-            running = handle_window_event(&mut event_loop, &mut renderer);
+    // Serve the HTTP API if requested, continue without it if the port can not be bound
+    if let Some(port) = http_port {
+        match HttpApi::bind(port).await {
+            Ok(api) => {
+                println!("Serving HTTP API on TCP port {}", api.port());
+                app.add_command_queue(api.received_commands());
+                app.set_shader_list(api.shader_list());
+                tokio::spawn(api.run());
+            }
+            Err(err) => println!("Warning: Failed to serve HTTP API on port {} ({}), continuing without it", port, err),
         }
+    }
 
-        // 3. Handle user input to switch shaders
-        let mut buffer = [0u8; 1];
-        if stdin.try_clone().unwrap().read(&mut buffer).is_ok() {
-            if buffer[0] == b' ' {
-                current_shader_index = (current_shader_index + 1) % SHADER_NAMES.len();
-                println!("Switched to shader index: {}", current_shader_index);
-                renderer.recompile_shaders(current_shader_index, false, true);
+    // Watch the GPIO buttons if requested, continue without them if the GPIOs can not be used
+    #[cfg(target_os = "linux")]
+    if !button_args.is_empty() {
+        let buttons: Vec<(buttons::ButtonAction, u8)> = button_args.iter()
+            .map(|arg| buttons::parse_button(arg).expect("--button is checked by check_options"))
+            .collect();
+        match buttons::Buttons::start(&buttons) {
+            Ok(buttons) => {
+                println!("Watching {} GPIO buttons", button_args.len());
+                app.set_buttons(buttons);
             }
+            Err(err) => println!("Warning: Buttons are unavailable ({}), continuing without them", err),
         }
+    }
 
-        // 4. Calculate elapsed time
-        let elapsed_time = start_time.elapsed().as_secs_f32();
-        
-        // 5. Update uniform buffer with the new values
-        renderer.update_uniforms(elapsed_time, bluetooth_data.clone());
-
-        // 6. FPS Calculation: Print FPS every second
-        if last_fps_update.elapsed() >= Duration::from_secs(1) {
-            println!("FPS: {}", frame);
-            frame = 0; // Reset counter
-            last_fps_update = Instant::now(); // Reset timer
-        }
-
-        // 7. Check for shader file changes, recompile them and recreate pipeline if necessary
-        if let Some(paths) = file_watcher.get_changes() {
-            for path in paths {
-                let file_name = path.file_name().unwrap();
-                println!("Shader file change detected: {:?}. Name: {:?}", path, file_name);
-    
-                // Check if the changed file is a vertex
-                if file_name.to_str().unwrap().ends_with(".vert") {
-                    renderer.recompile_shaders(current_shader_index, true, false);
-                }
-    
-                // Check if the changed file is a fragment shader
-                if file_name.to_str().unwrap().ends_with(".frag") {
-                    renderer.recompile_shaders(current_shader_index, false, true);
-                }
+    // Watch the rotary encoder if requested, continue without it if the GPIOs can not be used
+    #[cfg(target_os = "linux")]
+    if let Some(encoder_arg) = encoder_arg {
+        let pins = rotary_encoder::parse_pins(&encoder_arg).expect("--encoder is checked by check_options");
+        match rotary_encoder::RotaryEncoder::start(pins) {
+            Ok(encoder) => {
+                println!("Watching rotary encoder on GPIO {}", encoder_arg);
+                app.set_rotary_encoder(encoder, encoder_param);
             }
+            Err(err) => println!("Warning: Rotary encoder is unavailable ({}), continuing without it", err),
         }
+    }
 
-        // 8. Render
-        renderer.render();
+    // Read the accelerometer if requested, continue without it if no sensor is found
+    #[cfg(all(target_os = "linux", feature = "accel"))]
+    if let Some(auto_rotate) = accelerometer_rotate {
+        match accelerometer::Accelerometer::start() {
+            Ok(accelerometer) => app.set_accelerometer(accelerometer, auto_rotate),
+            Err(err) => println!("Warning: Accelerometer is unavailable ({}), continuing without it", err),
+        }
+    }
+
+    // Read the ambient light sensor if requested, continue at full brightness if no sensor is found
+    #[cfg(target_os = "linux")]
+    if use_ambient_light {
+        match ambient_light::AmbientLight::start() {
+            Ok(ambient_light) => app.set_ambient_light(ambient_light),
+            Err(err) => println!("Warning: Ambient light sensor is unavailable ({}), continuing without it", err),
+        }
+    }
+
+    // Sample the system stats for the telemetry uniforms if requested
+    if use_telemetry {
+        app.set_telemetry(telemetry::Telemetry::start());
+    }
+
+    // Capture audio for the audio channel if requested, shaders get silence without it
+    if use_audio {
+        match audio::AudioCapture::start(&audio_source) {
+            Ok(audio_capture) => {
+                println!("Capturing audio from {}", audio_source);
+                app.set_audio_capture(audio_capture);
+            }
+            Err(err) => println!("Warning: Audio capture is unavailable ({}), continuing without it", err),
+        }
+    }
+
+    // Capture the camera if requested, the camera channel stays black if it is missing
+    if let Some(path) = &camera_path {
+        match camera::CameraCapture::start(path, gpu_options.max_texture_size) {
+            Ok(camera_capture) => {
+                println!("Capturing camera {}", path.display());
+                app.set_camera_capture(camera_capture);
+            }
+            Err(err) => println!("Warning: Camera is unavailable ({}), continuing without it", err),
+        }
     }
-}
 
-fn handle_window_event(
-    event_loop: &mut EventLoop<()>,
-    renderer: &mut Renderer,
-) -> bool {
-    let mut running: bool = true;
+    // Record the session to a file on shutdown and/or replay a recorded one in a loop
+    if let Some(path) = &record_session_path {
+        println!("Recording session to {}", path.display());
+        app.set_session_recorder(SessionRecorder::new(path));
+    }
+    if let Some(path) = &play_session_path {
+        let session_player = SessionPlayer::load(path).unwrap_or_else(|err| panic!("Failed to load session {}: {}", path.display(), err));
+        println!("Playing session from {}", path.display());
+        app.set_session_player(session_player);
+    }
+
+    // Record a video of the output if requested, R starts and stops recordings too
+    app.set_video_options(video_path, video_fps, video_duration);
+
+    // Start the watchdog last so initialization does not count as a stall
+    if let Some(timeout) = watchdog_timeout {
+        println!("Using watchdog, exiting with code {} if no frame completes for {} seconds", watchdog::WATCHDOG_EXIT_CODE, timeout);
+        app.set_watchdog(Watchdog::start(Duration::from_secs(timeout)));
+    }
+
+    // --- Main loop ---
 
-    event_loop.run_return(|event, _, control_flow| {
-        control_flow.set_wait();
+    println!("Initialization complete. Starting main loop...");
 
-        match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => running = false,
-                WindowEvent::Resized(size) => {
-                    renderer.resize(size.width, size.height);
+    let mut frame_pacer = max_fps.map(FramePacer::new);
+
+    spawn_shutdown_signal_handler();
+
+    // With a window the event loop drives the app: events are handled as they arrive and a redraw is requested
+    // whenever a frame is due, in slow refresh mode the loop waits between frames instead of spinning.
+    // With --max-fps or a shader's target_fps the loop also waits until the next frame is due instead of polling.
+    // The first window requests the redraws, rendering draws all windows.
+    if let Some(event_loop) = event_loop {
+        let mut window_title = WINDOW_TITLE.to_string();
+        event_loop.run(move |event, _, control_flow| {
+            match event {
+                Event::WindowEvent { window_id, event } => {
+                    let running = app.handle_window_event(window_id, &event);
+                    if !running {
+                        control_flow.set_exit();
+                    }
                 }
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    renderer.resize(new_inner_size.width, new_inner_size.height);
+                Event::MainEventsCleared if SHUTDOWN_REQUESTED.load(Ordering::Relaxed) => control_flow.set_exit(),
+                Event::MainEventsCleared => {
+                    let refresh_due = app.update();
+                    update_frame_pacer(&mut frame_pacer, app.shader_target_fps().or(max_fps));
+
+                    // Show the current shader and FPS in the title, only set when it changes
+                    let title = app.window_title();
+                    if title != window_title {
+                        windows.iter().for_each(|window| window.set_title(&title));
+                        window_title = title;
+                    }
+
+                    if refresh_due {
+                        match &mut frame_pacer {
+                            Some(frame_pacer) => {
+                                if frame_pacer.frame_due() {
+                                    frame_pacer.frame_started();
+                                    windows[0].request_redraw();
+                                }
+                                control_flow.set_wait_until(frame_pacer.next_frame_time());
+                            }
+                            None => {
+                                control_flow.set_poll();
+                                windows[0].request_redraw();
+                            }
+                        }
+                    } else {
+                        control_flow.set_wait_timeout(SLOW_REFRESH_POLL_INTERVAL);
+                    }
                 }
+                Event::RedrawRequested(window_id) if window_id == windows[0].id() => app.render(),
+                Event::LoopDestroyed => app.shutdown(),
                 _ => (),
-            },
-            Event::MainEventsCleared => control_flow.set_exit(),
-            _ => (),
+            }
+        });
+    }
+
+    // Without a window there are no events to handle, only update and render
+    while !SHUTDOWN_REQUESTED.load(Ordering::Relaxed) {
+        let refresh_due = app.update();
+        update_frame_pacer(&mut frame_pacer, app.shader_target_fps().or(max_fps));
+        if refresh_due {
+            app.render();
+            if let Some(frame_pacer) = &mut frame_pacer {
+                frame_pacer.wait_for_next_frame();
+            }
+        } else {
+            std::thread::sleep(SLOW_REFRESH_POLL_INTERVAL);
+        }
+    }
+    app.shutdown();
+}
+
+// Paces the loop at the target_fps of the current shader if it has one, otherwise at --max-fps or not at all
+fn update_frame_pacer(frame_pacer: &mut Option<FramePacer>, target_fps: Option<f32>) {
+    match (frame_pacer.as_mut(), target_fps) {
+        (Some(frame_pacer), Some(target_fps)) => frame_pacer.set_target_fps(target_fps),
+        (None, Some(target_fps)) => *frame_pacer = Some(FramePacer::new(target_fps)),
+        (_, None) => *frame_pacer = None,
+    }
+}
+
+// Requests a clean shutdown of the main loop on Ctrl+C or SIGTERM (e.g. systemctl stop)
+fn spawn_shutdown_signal_handler() {
+    tokio::spawn(async {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
         }
+        println!("Shutdown requested, press Ctrl+C again to exit immediately");
+        SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+
+        // A stalled main loop never gets to shut down, so a second Ctrl+C exits right away
+        tokio::signal::ctrl_c().await.ok();
+        std::process::exit(130);
     });
+}
 
-    return running;
+fn print_monitors(event_loop: &EventLoop<()>) {
+    println!("Available monitors:");
+    for (index, monitor) in event_loop.available_monitors().enumerate() {
+        let size = monitor.size();
+        let position = monitor.position();
+        println!("{}: {} {}x{} at ({}, {})", index, monitor.name().unwrap_or_else(|| "Unknown".to_string()), size.width, size.height, position.x, position.y);
+    }
 }
 
+fn select_monitor(event_loop: &EventLoop<()>, index: usize) -> MonitorHandle {
+    let monitor = event_loop.available_monitors().nth(index).expect("--monitor is checked by check_monitors");
+    println!("Using monitor {}: {}", index, monitor.name().unwrap_or_else(|| "Unknown".to_string()));
+    monitor
+}
 
+// Exits with code 2 and lists the monitors if a --monitor does not exist, they are only known once the event loop exists
+fn check_monitors(event_loop: &EventLoop<()>, monitor_indices: &[usize]) {
+    let available: Vec<usize> = (0..event_loop.available_monitors().count()).collect();
+    let mut validation = OptionValidation::new();
+    for &index in monitor_indices {
+        validation.one_of("--monitor", Some(index), &available);
+    }
+    if let Err(errors) = validation.finish() {
+        print_monitors(event_loop);
+        exit_with_invalid_options(&errors);
+    }
+}
+
+// Exits with code 2 and lists every invalid value, e.g. out of range numbers or unknown names.
+// With starts_outputs the outputs and inputs are also checked to be available on this platform and build.
+fn check_options(options: &Options, starts_outputs: bool) {
+    let mut validation = OptionValidation::new();
+    validation.positive("--max-texture-size", options.max_texture_size);
+    validation.positive("--refresh-interval", options.refresh_interval);
+    validation.in_range("--render-scale", Some(options.render_scale), renderer::MIN_RENDER_SCALE..=1.0);
+    validation.positive("--antiburn-amplitude", Some(options.antiburn_amplitude));
+    validation.positive("--antiburn-period", Some(options.antiburn_period));
+    validation.positive("--watchdog", options.watchdog);
+    validation.positive("--max-fps", options.max_fps);
+    validation.positive("--st7789-fps", options.st7789_fps);
+    validation.in_range("--st7789-te", options.st7789_te, 0..=MAX_GPIO_PIN);
+    validation.in_range("--st7789-dc", options.st7789_dc, 0..=MAX_GPIO_PIN);
+    validation.in_range("--st7789-rst", options.st7789_rst, 0..=MAX_GPIO_PIN);
+    validation.in_range("--st7789-bl", options.st7789_bl, 0..=MAX_GPIO_PIN);
+    validation.positive("--spi-clock", options.spi_clock);
+    validation.at_least("--spi-chunk-size", options.spi_chunk_size, 2);
+    validation.positive("--metrics-port", options.metrics_port);
+    validation.positive("--websocket-port", options.websocket_port);
+    validation.positive("--http-port", options.http_port);
+    validation.positive("--record-fps", Some(options.record_fps));
+    validation.positive("--frames", Some(options.frames));
+    validation.positive("--frame-rate", Some(options.frame_rate));
+    validation.positive("--duration", options.duration);
+    validation.positive("--window-size", Some(options.window_size[0].min(options.window_size[1])));
+    validation.one_of("--st7789-rotation", Some(options.st7789_rotation), &[0, 90, 180, 270]);
+    validation.one_of("--hub75-rows", options.hub75_rows, &[16, 32, 64]);
+    validation.positive("--hub75-cols", options.hub75_cols);
+    validation.in_range("--hub75-chain", options.hub75_chain, 1..=8);
+    validation.in_range("--hub75-pwm-bits", options.hub75_pwm_bits, 1..=11);
+    validation.positive("--hub75-gamma", options.hub75_gamma);
+    // rpi-rgb-led-matrix takes over the GPIO pins, the regular mapping also uses the SPI0 pins
+    validation.not_both("--hub75", options.hub75, "--st7789", options.st7789, "the LED matrix library drives the GPIO and SPI0 pins");
+    validation.not_both("--hub75", options.hub75, "--ws2812", options.ws2812.is_some(), "the LED matrix library drives the GPIO and SPI0 pins");
+    validation.in_range("--ws2812-brightness", options.ws2812_brightness, 0.0..=1.0);
+    if let Some(shader_name) = &options.shader {
+        // Without any shaders load_playlist reports the empty directory instead
+        let available = Playlist::scan(&SHADERS_PATH.join("uncompiled"));
+        if !available.is_empty() {
+            validation.one_of("--shader", Some(shader_name), &available.iter().collect::<Vec<_>>());
+        }
+    }
+    if starts_outputs {
+        check_outputs(&mut validation, options);
+    }
+    if let Err(errors) = validation.finish() {
+        exit_with_invalid_options(&errors);
+    }
+}
+
+// Checks the outputs and inputs can be used on this platform and build and their settings can be parsed
+fn check_outputs(validation: &mut OptionValidation, options: &Options) {
+    const LINUX: bool = cfg!(target_os = "linux");
+    validation.requires("--mqtt", options.mqtt.is_some(), "building with --features mqtt", cfg!(feature = "mqtt"));
+    validation.requires("--accel", options.accel || options.accel_rotate, "Linux and building with --features accel", cfg!(all(target_os = "linux", feature = "accel")));
+    validation.requires("--camera", options.camera.is_some(), "Linux", LINUX);
+    validation.requires("--button", !options.buttons.is_empty(), "Linux", LINUX);
+    validation.requires("--ambient-light", options.ambient_light, "Linux", LINUX);
+    validation.requires("--encoder", options.encoder.is_some(), "Linux", LINUX);
+    validation.requires("--encoder-param", options.encoder_param.is_some(), "--encoder", options.encoder.is_some());
+    validation.requires("--test-pattern", options.test_pattern.is_some(), "Linux", LINUX);
+    validation.requires("--st7789", options.st7789, "Linux", LINUX);
+    validation.requires("--ssd1306", options.ssd1306, "Linux", LINUX);
+    validation.requires("--framebuffer", options.framebuffer.is_some(), "Linux", LINUX);
+    validation.requires("--drm", options.drm.is_some(), "Linux", LINUX);
+    validation.requires("--hub75", options.hub75, "Linux", LINUX);
+    validation.requires("--ws2812", options.ws2812.is_some(), "Linux", LINUX);
+    let displays = [options.st7789, options.ssd1306, options.framebuffer.is_some(), options.drm.is_some(), options.hub75, options.ws2812.is_some()];
+    if !options.window && !displays.contains(&true) && options.test_pattern.is_none() {
+        validation.error("No display chosen, give --window or one of the displays");
+    }
+
+    // The SPI devices and LED layouts are parsed again when the drivers are created
+    #[cfg(target_os = "linux")]
+    {
+        for panel in &options.st7789_panels {
+            validation.parse("--st7789-panel", panel, st7789_driver::SpiDevice::parse);
+        }
+        if let Some(layout) = &options.ws2812 {
+            validation.parse("--ws2812", layout, ws2812::LedMap::parse);
+        }
+        if let Some(spi_device) = &options.ws2812_spi {
+            validation.parse("--ws2812-spi", spi_device, st7789_driver::SpiDevice::parse);
+        }
+        for button in &options.buttons {
+            if let Some((_, pin)) = validation.parse("--button", button, buttons::parse_button) {
+                validation.in_range("--button", Some(pin), 0..=MAX_GPIO_PIN);
+            }
+        }
+        if let Some(encoder) = &options.encoder {
+            if let Some(pins) = validation.parse("--encoder", encoder, rotary_encoder::parse_pins) {
+                for pin in [pins.a, pins.b].into_iter().chain(pins.push) {
+                    validation.in_range("--encoder", Some(pin), 0..=MAX_GPIO_PIN);
+                }
+            }
+        }
+    }
+}
+
+// Prints the errors of check_options or check_monitors and exits with code 2 like invalid arguments
+fn exit_with_invalid_options(errors: &str) -> ! {
+    println!("Error: Invalid options:\n{}", errors);
+    std::process::exit(2);
+}
+
+fn gpu_options(options: &Options) -> GpuOptions {
+    let power_preference = match options.power {
+        Power::High => wgpu::PowerPreference::HighPerformance,
+        Power::Low => wgpu::PowerPreference::LowPower,
+    };
+    let present_mode = match options.present_mode {
+        PresentMode::Fifo => wgpu::PresentMode::Fifo,
+        PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        PresentMode::Immediate => wgpu::PresentMode::Immediate,
+    };
+    GpuOptions { max_texture_size: options.max_texture_size, power_preference, force_fallback_adapter: options.fallback_adapter, present_mode }
+}
+
+fn compile_options(options: &Options) -> ShaderCompileOptions {
+    ShaderCompileOptions { debug_info: options.shader_debug_info || ShaderCompileOptions::default().debug_info }
+}
+
+// The playlist is every fragment shader in the shaders directory or the configured ones,
+// files added later are picked up by the file watcher
+fn load_playlist(options: &Options) -> Playlist {
+    let playlist = Playlist::with_order(Playlist::scan(&SHADERS_PATH.join("uncompiled")), options.shaders.clone());
+    if playlist.is_empty() {
+        panic!("No fragment shaders found in {}", SHADERS_PATH.join("uncompiled").display());
+    }
+    playlist
+}
+
+fn list_shaders(options: &Options) {
+    load_playlist(options).shader_names().iter().for_each(|shader_name| println!("{}", shader_name));
+}
+
+// Renders frames of the given shaders at fixed times offscreen and saves them as PNGs, without a window or display.
+// Exits with code 1 if a shader fails to compile, e.g. to fail a CI job.
+fn render_frames(options: &Options) {
+    let playlist = load_playlist(options);
+    let shader_names = match &options.shader {
+        _ if options.all_shaders => playlist.shader_names().to_vec(),
+        Some(shader_name) => vec![shader_name.clone()],
+        None => vec![playlist.current().to_string()],
+    };
+    let batch_render = BatchRender { shader_names, start_time: options.time, frame_count: options.frames, frame_rate: options.frame_rate, output_path: PathBuf::from(&options.out) };
+
+    let gpu_options = gpu_options(options);
+    let mut renderer = Renderer::new(RendererOptions { offscreen: true, gpu_options, compile_options: compile_options(options), shader_name: &batch_render.shader_names[0], ..RendererOptions::default() });
+
+    renderer.set_flip_uv(options.flip_uv);
+    renderer.set_sanitize(options.sanitize);
+    let failed_count = batch_render.run(&mut renderer);
+
+    let output_size = ST7789_OUTPUT_SIZE.min(gpu_options.max_texture_size.unwrap_or(ST7789_OUTPUT_SIZE));
+    println!("Rendered {} frames of {} shaders ({}x{} PNG)", batch_render.frame_count, batch_render.shader_names.len(), output_size, output_size);
+    if failed_count > 0 {
+        println!("Error: {} of {} shaders failed", failed_count, batch_render.shader_names.len());
+        std::process::exit(1);
+    }
+}
+
+// Compiles every shader of the playlist and reports the ones which fail, e.g. to check a shader pack in CI.
+// Exits with code 1 if a shader fails to compile.
+fn validate_shaders(options: &Options) {
+    let playlist = load_playlist(options);
+    let shader_names = playlist.shader_names();
+    let mut renderer = Renderer::new(RendererOptions { offscreen: true, gpu_options: gpu_options(options), compile_options: compile_options(options), shader_name: playlist.current(), ..RendererOptions::default() });
+    let mut failed_count = 0;
+    for shader_name in shader_names {
+        renderer.recompile_shaders(shader_name, false, true);
+        match renderer.shader_error() {
+            Some(err) => {
+                println!("Error: {} failed to compile:\n{}", shader_name, err);
+                failed_count += 1;
+            }
+            None => println!("{} compiled", shader_name),
+        }
+    }
+
+    println!("Compiled {} of {} shaders", shader_names.len() - failed_count, shader_names.len());
+    if failed_count > 0 {
+        std::process::exit(1);
+    }
+}
+
+// Pushes a CPU generated test pattern straight to the st7789 display, bypassing the GPU pipeline, and waits for Enter
+#[cfg(target_os = "linux")]
+fn show_test_pattern(pattern: test_pattern::TestPattern, config: st7789_driver::ST7789Config) {
+    let config = st7789_driver::ST7789Config { te_pin: None, ..config };
+    let mut driver = st7789_driver::RaspberryST7789Driver::new(config).unwrap_or_else(|err| panic!("Failed to create st7789 driver: {}", err));
+    driver.initialize().unwrap();
+
+    let rgba_bytes = pattern.generate(ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE);
+    let rgb565_bytes = color_conversion::rgba8888_to_rgb565_u8(&rgba_bytes, false);
+    driver.draw(&rgb565_bytes, ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE).unwrap();
+
+    println!("Showing test pattern {:?}. Press Enter to exit", pattern);
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).unwrap();
+}
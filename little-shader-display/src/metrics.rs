@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+// Durations of the stages of the last frame sent to the ST7789, in milliseconds
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct FrameTimings {
+    pub render_ms: f64,
+    pub readback_ms: f64,
+    pub convert_ms: f64,
+    pub draw_ms: f64,
+}
+
+// Stats written by the main loop and read by the metrics server
+#[derive(Debug, Default, Serialize)]
+pub struct RenderStats {
+    pub fps: u32,
+    pub shader: String,
+    pub shader_error: Option<String>, // Compile error of the last shader change, the previous shader keeps running
+    pub st7789_timings: FrameTimings,
+}
+
+#[derive(Serialize)]
+struct MetricsReport<'a> {
+    #[serde(flatten)]
+    stats: &'a RenderStats,
+    uptime_secs: f64,
+}
+
+// Read-only TCP endpoint for monitoring, every connection gets the current stats as one line of JSON and is closed,
+// e.g. `nc <host> <port>`
+pub struct MetricsServer {
+    listener: TcpListener,
+    stats: Arc<Mutex<RenderStats>>,
+    start_time: Instant,
+}
+
+impl MetricsServer {
+    pub async fn bind(port: u16) -> std::io::Result<Self> {
+        Ok(MetricsServer {
+            listener: TcpListener::bind(("0.0.0.0", port)).await?,
+            stats: Arc::new(Mutex::new(RenderStats::default())),
+            start_time: Instant::now(),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|address| address.port()).unwrap_or_default()
+    }
+
+    // Returns the stats shared with the main loop
+    pub fn stats(&self) -> Arc<Mutex<RenderStats>> {
+        self.stats.clone()
+    }
+
+    pub async fn run(self) {
+        loop {
+            // Accept errors (e.g. out of file descriptors) only affect that connection, keep serving
+            let mut stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    println!("Warning: Failed to accept metrics connection: {}", err);
+                    continue;
+                }
+            };
+
+            let report = self.report().await;
+            tokio::spawn(async move {
+                if let Err(err) = stream.write_all(report.as_bytes()).await {
+                    println!("Warning: Failed to send metrics: {}", err);
+                }
+            });
+        }
+    }
+
+    async fn report(&self) -> String {
+        let stats = self.stats.lock().await;
+        let report = MetricsReport { stats: &stats, uptime_secs: self.start_time.elapsed().as_secs_f64() };
+        serde_json::to_string(&report).expect("Failed to serialize metrics") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn serves_current_stats_as_json() {
+        let server = MetricsServer::bind(0).await.unwrap();
+        let port = server.port();
+        {
+            let mut stats = server.stats.lock().await;
+            stats.fps = 30;
+            stats.shader = "waves.frag".to_string();
+            stats.st7789_timings.draw_ms = 12.5;
+        }
+        tokio::spawn(server.run());
+
+        let mut response = String::new();
+        TcpStream::connect(("127.0.0.1", port)).await.unwrap().read_to_string(&mut response).await.unwrap();
+
+        let report: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(report["fps"], 30);
+        assert_eq!(report["shader"], "waves.frag");
+        assert_eq!(report["st7789_timings"]["draw_ms"], 12.5);
+        assert!(report["uptime_secs"].as_f64().unwrap() >= 0.0);
+    }
+}
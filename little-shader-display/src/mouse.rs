@@ -0,0 +1,78 @@
+// Mouse state of the window, passed to shaders like Shadertoy's iMouse.
+// Positions are stored as fractions of the window with (0,0) at the bottom-left, the renderer scales them
+// to the pixels of each render pass, so they line up with the resolution uniform on every output.
+#[derive(Debug, Default)]
+pub struct Mouse {
+    last_cursor_position: [f32; 2],
+    position: [f32; 2], // Cursor position while the button is held, kept after it is released
+    click_position: [f32; 2], // Where the button was last pressed
+    pressed: bool,
+    clicked: bool, // Set when the button is pressed, cleared once the frame has seen it
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Cursor moved to the given physical position with (0,0) at the top-left of a window of the given size
+    pub fn cursor_moved(&mut self, x: f64, y: f64, (width, height): (u32, u32)) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let position = [(x / width as f64) as f32, 1.0 - (y / height as f64) as f32];
+        // Like on Shadertoy, the position only follows the cursor while dragging, otherwise the last cursor position
+        // is remembered for the next click
+        self.last_cursor_position = position;
+        if self.pressed {
+            self.position = position;
+        }
+    }
+
+    pub fn button_changed(&mut self, pressed: bool) {
+        if pressed && !self.pressed {
+            self.position = self.last_cursor_position;
+            self.click_position = self.last_cursor_position;
+            self.clicked = true;
+        }
+        self.pressed = pressed;
+    }
+
+    // Returns the uniform value for the next frame in fractions of the window: xy is the drag position, zw the click
+    // position, z is negative while the button is released and w is negative except on the frame of the click
+    pub fn next_frame_uniform(&mut self) -> [f32; 4] {
+        let [x, y] = self.position;
+        let [click_x, click_y] = self.click_position;
+        let uniform = [
+            x,
+            y,
+            if self.pressed { click_x } else { -click_x },
+            if self.clicked { click_y } else { -click_y },
+        ];
+        self.clicked = false;
+        uniform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_shadertoy_imouse_semantics() {
+        let mut mouse = Mouse::new();
+        mouse.cursor_moved(25.0, 75.0, (100, 100));
+        assert_eq!(mouse.next_frame_uniform(), [0.0, 0.0, -0.0, -0.0]);
+
+        // The click frame has positive zw, the following frames a negative w
+        mouse.button_changed(true);
+        assert_eq!(mouse.next_frame_uniform(), [0.25, 0.25, 0.25, 0.25]);
+        mouse.cursor_moved(50.0, 50.0, (100, 100));
+        assert_eq!(mouse.next_frame_uniform(), [0.5, 0.5, 0.25, -0.25]);
+
+        // After releasing, xy stays at the last drag position and z turns negative
+        mouse.button_changed(false);
+        mouse.cursor_moved(100.0, 0.0, (100, 100));
+        assert_eq!(mouse.next_frame_uniform(), [0.5, 0.5, -0.25, -0.25]);
+    }
+}
@@ -0,0 +1,111 @@
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const CLIENT_ID: &str = "little-shader-display";
+const COMMAND_TOPIC: &str = "little-shader-display/command";
+const STATUS_TOPIC: &str = "little-shader-display/status";
+const DEFAULT_PORT: u16 = 1883;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// State published to the status topic whenever it changes
+#[derive(Debug, Serialize, PartialEq)]
+pub struct MqttState {
+    pub shader: String,
+    pub fps: u32,
+}
+
+// Connects to an MQTT broker, receives commands sent to the command topic
+// and publishes the display state to the status topic (retained, so new subscribers get it right away)
+pub struct MqttControl {
+    received_commands: Arc<Mutex<VecDeque<String>>>,
+    client: AsyncClient,
+    event_loop: EventLoop,
+}
+
+impl MqttControl {
+    // Broker is given as host or host:port
+    pub fn new(broker: &str) -> Self {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().expect("--mqtt port must be a number")),
+            None => (broker, DEFAULT_PORT),
+        };
+
+        let mut options = MqttOptions::new(CLIENT_ID, host, port);
+        options.set_keep_alive(Duration::from_secs(10));
+        let (client, event_loop) = AsyncClient::new(options, 10);
+
+        MqttControl {
+            received_commands: Arc::new(Mutex::new(VecDeque::new())),
+            client,
+            event_loop,
+        }
+    }
+
+    // Returns the handle used by the main loop to take received commands and publish the state
+    pub fn status_publisher(&self) -> MqttStatusPublisher {
+        MqttStatusPublisher {
+            received_commands: self.received_commands.clone(),
+            client: self.client.clone(),
+            last_state: None,
+        }
+    }
+
+    pub async fn run(mut self) {
+        println!("Starting MQTT client...");
+
+        loop {
+            match self.event_loop.poll().await {
+                // Subscribe on every connection since the broker may have dropped the session
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    println!("Connected to MQTT broker, listening on {}", COMMAND_TOPIC);
+                    if let Err(err) = self.client.try_subscribe(COMMAND_TOPIC, QoS::AtLeastOnce) {
+                        println!("Warning: MQTT subscribe failed: {}", err);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    match std::str::from_utf8(&publish.payload) {
+                        Ok(command) => self.received_commands.lock().await.push_back(command.trim().to_string()),
+                        Err(_) => println!("Warning: Ignoring MQTT command which is not UTF-8"),
+                    }
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    // Polling again reconnects, wait first so an unreachable broker is not retried in a busy loop
+                    println!("Warning: MQTT connection failed: {}, reconnecting in {} seconds", err, RECONNECT_DELAY.as_secs());
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+pub struct MqttStatusPublisher {
+    received_commands: Arc<Mutex<VecDeque<String>>>,
+    client: AsyncClient,
+    last_state: Option<MqttState>,
+}
+
+impl MqttStatusPublisher {
+    // Returns the oldest received command, if any, without waiting for the client task
+    pub fn take_command(&self) -> Option<String> {
+        self.received_commands.try_lock().ok()?.pop_front()
+    }
+
+    // Publishes the state if it differs from the last published one
+    pub fn publish_state(&mut self, state: MqttState) {
+        if self.last_state.as_ref() == Some(&state) {
+            return;
+        }
+
+        // A failed publish is not retried until the state changes again, so it is not reported every frame
+        let payload = serde_json::to_string(&state).expect("Failed to serialize MQTT state");
+        if let Err(err) = self.client.try_publish(STATUS_TOPIC, QoS::AtLeastOnce, true, payload) {
+            println!("Warning: MQTT publish failed: {}", err);
+        }
+        self.last_state = Some(state);
+    }
+}
@@ -0,0 +1,570 @@
+use std::path::Path;
+
+use crate::audio;
+use crate::keyboard;
+use crate::shader_metadata::BufferMetadata;
+
+// Buffers are float textures, so intermediate results (e.g. a blur or a simulation state) keep more than 8 bits
+pub const BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Textures a pass can sample, bound at bindings 1 to 4 of group 2 after the sampler at binding 0
+pub const CHANNEL_COUNT: usize = 4;
+
+// The previous frame of a feedback shader is bound after the channels
+const PREVIOUS_FRAME_BINDING: u32 = CHANNEL_COUNT as u32 + 1;
+
+// Buffers are kept for a few output sizes, the windows and the offscreen target usually differ
+const MAX_TARGET_SETS: usize = 4;
+
+// Buffer pass shaders are named like x.buffer.frag, they are not shown on their own so the playlist skips them
+pub fn is_buffer_shader(file_name: &str) -> bool {
+    file_name.ends_with(".buffer.frag") || file_name.ends_with(".buffer.wgsl")
+}
+
+// Channel reading the spectrum (v < 0.5) and the waveform (v > 0.5) of the captured audio, silent without --audio
+pub const AUDIO_CHANNEL: &str = "audio";
+
+// Channel with a texel per JavaScript key code, red while the key is held and green while it is toggled on
+pub const KEYBOARD_CHANNEL: &str = "keyboard";
+
+// Channel with the latest camera frame, black without --camera
+pub const CAMERA_CHANNEL: &str = "camera";
+
+// Channels fed from inputs instead of buffers or images, their names can not be used for buffers
+pub fn is_input_channel(channel: &str) -> bool {
+    channel == AUDIO_CHANNEL || channel == KEYBOARD_CHANNEL || channel == CAMERA_CHANNEL
+}
+
+// Channels can also name images in the textures directory, which are loaded instead of rendered
+pub fn is_image_file(channel: &str) -> bool {
+    let channel = channel.to_lowercase();
+    channel.ends_with(".png") || channel.ends_with(".jpg") || channel.ends_with(".jpeg")
+}
+
+// A buffer pass of the current shader with the pipeline rendering it
+pub struct BufferPass {
+    pub metadata: BufferMetadata,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+// Bind groups of one frame, they differ between frames only in the previous frame of a feedback shader
+struct FrameBindGroups {
+    passes: Vec<wgpu::BindGroup>,
+    shader: wgpu::BindGroup, // Channels of the shader itself
+    present: Option<wgpu::BindGroup>, // Binds the feedback buffer rendered this frame, for drawing it to the output
+}
+
+// Buffer textures for one output size, with the channel bind groups of the passes reading them
+pub struct BufferTargets {
+    size: (u32, u32),
+    frame: usize, // Index of the current frame's bind groups and feedback buffer
+    views: Vec<wgpu::TextureView>, // Target of every buffer pass, in pass order
+    feedback_views: Vec<wgpu::TextureView>, // Ping-pong pair a feedback shader renders into, empty without feedback
+    bind_groups: Vec<FrameBindGroups>, // One per feedback buffer, or a single one without feedback
+}
+
+impl BufferTargets {
+    // Texture view the buffer pass renders into and the channels it reads
+    pub fn pass(&self, index: usize) -> (&wgpu::TextureView, &wgpu::BindGroup) {
+        (&self.views[index], &self.bind_groups[self.frame].passes[index])
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[self.frame].shader
+    }
+
+    // For feedback shaders, the buffer the shader renders into this frame and the bind group to draw it to the output with
+    pub fn feedback(&self) -> Option<(&wgpu::TextureView, &wgpu::BindGroup)> {
+        let frame_bind_groups = &self.bind_groups[self.frame];
+        Some((self.feedback_views.get(self.frame)?, frame_bind_groups.present.as_ref()?))
+    }
+}
+
+// Shadertoy style multi-pass rendering: buffer passes render shaders into named textures in a configured order,
+// each pass can sample the buffers of the passes before it, and the shader itself can sample all of them.
+// Shaders without buffer passes get a bind group with empty channels, so every pipeline shares one layout.
+// Feedback shaders render into one of two buffers and read the other one, which holds the previous frame.
+pub struct Multipass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    empty_channel: wgpu::TextureView, // Bound to unused channels, a single black pixel
+    passes: Vec<BufferPass>,
+    channels: Vec<String>, // Buffers or images bound to the channels of the shader itself
+    images: Vec<(String, wgpu::TextureView)>, // Images named by the channels, by file name
+    audio_texture: wgpu::Texture, // Spectrum and waveform rows of the audio channel
+    audio_channel: wgpu::TextureView,
+    keyboard_texture: wgpu::Texture,
+    keyboard_channel: wgpu::TextureView,
+    camera: Option<(wgpu::Texture, wgpu::TextureView)>, // Created with the first camera frame
+    feedback: bool,
+    targets: Vec<BufferTargets>,
+}
+
+impl Multipass {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        }];
+        entries.extend((1..=PREVIOUS_FRAME_BINDING).map(texture_entry));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("channel_bind_group_layout"),
+            entries: &entries,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Channel Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let empty_channel_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Empty Channel Texture"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            empty_channel_texture.as_image_copy(),
+            &[0, 0, 0, 255],
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4), rows_per_image: None },
+            empty_channel_texture.size(),
+        );
+        let empty_channel = empty_channel_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Devices limited to smaller textures (--max-texture-size) get fewer bins
+        let audio_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Audio Channel Texture"),
+            size: wgpu::Extent3d { width: (audio::BIN_COUNT as u32).min(device.limits().max_texture_dimension_2d), height: 2, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let audio_channel = audio_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Starts out zeroed, no key pressed or toggled. With textures limited below 256 texels (--max-texture-size)
+        // the highest key codes are left out, all mapped keys are below 128.
+        let keyboard_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Keyboard Channel Texture"),
+            size: wgpu::Extent3d { width: (keyboard::KEY_CODE_COUNT as u32).min(device.limits().max_texture_dimension_2d), height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let keyboard_channel = keyboard_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let multipass = Self {
+            bind_group_layout,
+            sampler,
+            empty_channel,
+            passes: Vec::new(),
+            channels: Vec::new(),
+            images: Vec::new(),
+            audio_texture,
+            audio_channel,
+            keyboard_texture,
+            keyboard_channel,
+            camera: None,
+            feedback: false,
+            targets: Vec::new(),
+        };
+        // Silence until audio is captured, an empty spectrum and a waveform at the middle
+        multipass.write_audio(queue, &[0; audio::BIN_COUNT], &[128; audio::BIN_COUNT]);
+        multipass
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    // Replaces the buffer passes, the channels of the shader and whether it reads its previous frame,
+    // the buffers are created again on the next frame
+    pub fn set_passes(&mut self, passes: Vec<BufferPass>, channels: Vec<String>, feedback: bool) {
+        self.passes = passes;
+        self.channels = channels;
+        self.feedback = feedback;
+        self.targets.clear();
+    }
+
+    pub fn passes(&self) -> &[BufferPass] {
+        &self.passes
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub fn feedback(&self) -> bool {
+        self.feedback
+    }
+
+    // Uploads the spectrum and the waveform of the audio channel, the spectrum is read at v < 0.5 like on Shadertoy
+    pub fn write_audio(&self, queue: &wgpu::Queue, spectrum: &[u8; audio::BIN_COUNT], waveform: &[u8; audio::BIN_COUNT]) {
+        let width = self.audio_texture.width() as usize;
+        let bytes: Vec<u8> = [spectrum, waveform].iter().flat_map(|row| (0..width).map(|x| row[x * audio::BIN_COUNT / width])).collect();
+        queue.write_texture(
+            self.audio_texture.as_image_copy(),
+            &bytes,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(width as u32), rows_per_image: None },
+            self.audio_texture.size(),
+        );
+    }
+
+    // Uploads the key states of the keyboard channel, two bytes per key code
+    pub fn write_keyboard(&self, queue: &wgpu::Queue, texels: &[u8]) {
+        let width = self.keyboard_texture.width();
+        queue.write_texture(
+            self.keyboard_texture.as_image_copy(),
+            &texels[..2 * width as usize],
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(2 * width), rows_per_image: None },
+            self.keyboard_texture.size(),
+        );
+    }
+
+    // Uploads a camera frame (RGBA8888, first row at the top) upright like images. The texture is created with the
+    // first frame, the bind groups are then created again to bind it.
+    pub fn write_camera(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, rgba: &[u8], (width, height): (u32, u32), srgb: bool) {
+        if self.camera.as_ref().is_none_or(|(texture, _)| texture.width() != width || texture.height() != height) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Camera Channel Texture"),
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: if srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm },
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.camera = Some((texture, view));
+            self.targets.clear();
+        }
+
+        let (texture, _) = self.camera.as_ref().unwrap();
+        let pixels: Vec<u8> = rgba.chunks_exact(4 * width as usize).rev().flatten().copied().collect();
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * width), rows_per_image: None },
+            texture.size(),
+        );
+    }
+
+    // Loads the images named by the channels of the passes and the shader from the textures directory. Images which are
+    // missing or can not be decoded are reported and read as black. With an sRGB output the images are decoded when
+    // sampled, so they are shown unchanged on sRGB and linear outputs alike.
+    pub fn load_images(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, textures_path: &Path, srgb: bool) {
+        let mut image_names: Vec<&String> = self.passes.iter().flat_map(|pass| &pass.metadata.channels).chain(&self.channels).filter(|channel| is_image_file(channel)).collect();
+        image_names.sort();
+        image_names.dedup();
+        self.images = image_names.into_iter().filter_map(|image_name| {
+            let path = textures_path.join(image_name);
+            match image::open(&path) {
+                Ok(image) => Some((image_name.clone(), create_image_view(device, queue, image, srgb, image_name))),
+                Err(err) => {
+                    println!("Warning: Failed to load channel image {:?}: {}, reading black instead", path, err);
+                    None
+                }
+            }
+        }).collect();
+        self.targets.clear();
+    }
+
+    // Returns true if a buffer pass of the current shader renders the given shader file
+    pub fn uses_shader(&self, shader_name: &str) -> bool {
+        self.passes.iter().any(|pass| pass.metadata.shader == shader_name)
+    }
+
+    // Creates the buffers for an output of the given size unless they exist, otherwise starts the next frame on them.
+    // Buffers start out black and keep their contents between frames.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if let Some(targets) = self.targets.iter_mut().find(|targets| targets.size == size) {
+            targets.frame = (targets.frame + 1) % targets.bind_groups.len();
+            return;
+        }
+        if self.targets.len() == MAX_TARGET_SETS {
+            self.targets.remove(0);
+        }
+
+        let create_view = |label: &str| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: BUFFER_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            }).create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let views: Vec<wgpu::TextureView> = self.passes.iter().map(|pass| create_view(&format!("Buffer {}", pass.metadata.name))).collect();
+        let feedback_views: Vec<wgpu::TextureView> = if self.feedback { (0..2).map(|index| create_view(&format!("Feedback Buffer {}", index))).collect() } else { Vec::new() };
+
+        // Every frame reads the feedback buffer written by the frame before. Metadata is validated when loaded,
+        // so channels only name buffers of earlier passes.
+        let bind_groups = if self.feedback {
+            (0..2).map(|frame| {
+                let previous_frame = &feedback_views[1 - frame];
+                FrameBindGroups {
+                    passes: self.passes.iter().map(|pass| self.create_bind_group(device, &pass.metadata.channels, &views, previous_frame)).collect(),
+                    shader: self.create_bind_group(device, &self.channels, &views, previous_frame),
+                    present: Some(self.create_bind_group(device, &[], &views, &feedback_views[frame])),
+                }
+            }).collect()
+        } else {
+            vec![FrameBindGroups {
+                passes: self.passes.iter().map(|pass| self.create_bind_group(device, &pass.metadata.channels, &views, &self.empty_channel)).collect(),
+                shader: self.create_bind_group(device, &self.channels, &views, &self.empty_channel),
+                present: None,
+            }]
+        };
+        self.targets.push(BufferTargets { size, frame: 0, views, feedback_views, bind_groups });
+    }
+
+    // Buffers of the given output size, prepare needs to be called for the size first
+    pub fn targets(&self, size: (u32, u32)) -> &BufferTargets {
+        self.targets.iter().find(|targets| targets.size == size).expect("Buffers are not prepared for the output size")
+    }
+
+    fn create_bind_group(&self, device: &wgpu::Device, channels: &[String], views: &[wgpu::TextureView], previous_frame: &wgpu::TextureView) -> wgpu::BindGroup {
+        let channel_view = |index: usize| {
+            let Some(channel) = channels.get(index) else {
+                return &self.empty_channel;
+            };
+            match channel.as_str() {
+                AUDIO_CHANNEL => return &self.audio_channel,
+                KEYBOARD_CHANNEL => return &self.keyboard_channel,
+                CAMERA_CHANNEL => return self.camera.as_ref().map_or(&self.empty_channel, |(_, view)| view),
+                _ => (),
+            }
+            match self.passes.iter().position(|pass| &pass.metadata.name == channel) {
+                Some(pass_index) => &views[pass_index],
+                None => self.images.iter().find(|(image_name, _)| image_name == channel).map_or(&self.empty_channel, |(_, view)| view),
+            }
+        };
+        let mut entries = vec![wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::Sampler(&self.sampler) }];
+        entries.extend((0..CHANNEL_COUNT).map(|index| wgpu::BindGroupEntry {
+            binding: index as u32 + 1,
+            resource: wgpu::BindingResource::TextureView(channel_view(index)),
+        }));
+        entries.push(wgpu::BindGroupEntry { binding: PREVIOUS_FRAME_BINDING, resource: wgpu::BindingResource::TextureView(previous_frame) });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("channel_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        })
+    }
+}
+
+// Uploads an image bottom row first, so it is upright with texture coordinate (0,0) at the bottom-left like Shadertoy's flipped textures
+fn create_image_view(device: &wgpu::Device, queue: &wgpu::Queue, mut image: image::DynamicImage, srgb: bool, label: &str) -> wgpu::TextureView {
+    // Images larger than the GPU supports are scaled down keeping their aspect ratio
+    let max_size = device.limits().max_texture_dimension_2d;
+    if image.width() > max_size || image.height() > max_size {
+        image = image.resize(max_size, max_size, image::imageops::FilterType::Triangle);
+    }
+    let pixels = image.flipv().to_rgba8();
+    let size = wgpu::Extent3d { width: pixels.width(), height: pixels.height(), depth_or_array_layers: 1 };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: if srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm },
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * size.width), rows_per_image: None },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_renderer::{can_render, TestRenderer};
+    use crate::ST7789_OUTPUT_SIZE;
+
+    // Cyan above and yellow below the middle, drawn directly to compare the other shaders with
+    const DIRECT_SHADER_NAME: &str = "direct.frag";
+    const DIRECT_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vertex_texture_coordinates.y < 0.5 ? vec4(1.0, 1.0, 0.0, 1.0) : vec4(0.0, 1.0, 1.0, 1.0);
+}
+";
+    const YELLOW_RGB565: u16 = 0xFFE0;
+    const CYAN_RGB565: u16 = 0x07FF;
+
+    // Two buffer passes: the first draws red below and blue above the middle, the second adds green to what the first drew,
+    // and the shader shows the second buffer. Should look like the direct shader.
+    const MULTIPASS_SHADER_NAME: &str = "multipass.frag";
+    const MULTIPASS_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+layout(set = 2, binding = 0) uniform sampler channel_sampler;
+layout(set = 2, binding = 1) uniform texture2D channel0;
+void main() {
+    out_final_color = texture(sampler2D(channel0, channel_sampler), vertex_texture_coordinates);
+}
+";
+    const MULTIPASS_METADATA: &str = "channels = [\"second\"]
+[[buffers]]
+name = \"first\"
+shader = \"first.buffer.frag\"
+[[buffers]]
+name = \"second\"
+shader = \"second.buffer.frag\"
+channels = [\"first\"]
+";
+    const FIRST_BUFFER_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vertex_texture_coordinates.y < 0.5 ? vec4(1.0, 0.0, 0.0, 1.0) : vec4(0.0, 0.0, 1.0, 1.0);
+}
+";
+    const SECOND_BUFFER_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+layout(set = 2, binding = 0) uniform sampler channel_sampler;
+layout(set = 2, binding = 1) uniform texture2D channel0;
+void main() {
+    out_final_color = texture(sampler2D(channel0, channel_sampler), vertex_texture_coordinates) + vec4(0.0, 1.0, 0.0, 0.0);
+}
+";
+
+    // Shows the image of channel0, a cyan top half and a yellow bottom half, which should look like the direct shader
+    const IMAGE_SHADER_NAME: &str = "image.frag";
+    const IMAGE_SHADER: &str = MULTIPASS_SHADER;
+    const IMAGE_METADATA: &str = "channels = [\"halves.png\"]\n";
+
+    // Adds red to the previous frame below the middle and draws blue above it, so red only builds up if the
+    // previous frame is read at the coordinates it was drawn at
+    const FEEDBACK_SHADER_NAME: &str = "feedback.frag";
+    const FEEDBACK_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+layout(set = 2, binding = 0) uniform sampler channel_sampler;
+layout(set = 2, binding = 5) uniform texture2D previous_frame;
+void main() {
+    vec4 previous = texture(sampler2D(previous_frame, channel_sampler), vertex_texture_coordinates);
+    out_final_color = vertex_texture_coordinates.y < 0.5 ? vec4(previous.r + 0.25, 0.0, 0.0, 1.0) : vec4(0.0, 0.0, 1.0, 1.0);
+}
+";
+    const FEEDBACK_METADATA: &str = "feedback = true\n";
+    // What the feedback shader shows on its third frame
+    const FEEDBACK_RESULT_SHADER_NAME: &str = "feedback_result.frag";
+    const FEEDBACK_RESULT_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vertex_texture_coordinates.y < 0.5 ? vec4(0.75, 0.0, 0.0, 1.0) : vec4(0.0, 0.0, 1.0, 1.0);
+}
+";
+
+    #[test]
+    fn renders_buffer_passes_in_order() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder()
+            .shader(DIRECT_SHADER_NAME, DIRECT_SHADER)
+            .shader(MULTIPASS_SHADER_NAME, MULTIPASS_SHADER)
+            .metadata(MULTIPASS_SHADER_NAME, MULTIPASS_METADATA)
+            .shader("first.buffer.frag", FIRST_BUFFER_SHADER)
+            .shader("second.buffer.frag", SECOND_BUFFER_SHADER)
+            .build();
+        test.render_frames(1);
+        test.renderer.recompile_shaders(MULTIPASS_SHADER_NAME, false, true);
+        test.render_frames(1);
+
+        // Each sampled buffer shows what was drawn at the same coordinates, so the image is not flipped on any backend
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 2);
+        let pixels = frames[1].pixels();
+        assert!(pixels.contains(&YELLOW_RGB565) && pixels.contains(&CYAN_RGB565));
+        assert!(frames[1].rgb565_bytes == frames[0].rgb565_bytes);
+    }
+
+    #[test]
+    fn binds_images_as_channels_upright() {
+        if !can_render() {
+            return;
+        }
+
+        let halves = image::RgbaImage::from_fn(ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE, |_, y| {
+            if y < ST7789_OUTPUT_SIZE / 2 { image::Rgba([0, 255, 255, 255]) } else { image::Rgba([255, 255, 0, 255]) }
+        });
+        let mut test = TestRenderer::builder()
+            .shader(DIRECT_SHADER_NAME, DIRECT_SHADER)
+            .shader(IMAGE_SHADER_NAME, IMAGE_SHADER)
+            .metadata(IMAGE_SHADER_NAME, IMAGE_METADATA)
+            .texture("halves.png", &halves)
+            .build();
+        test.render_frames(1);
+        test.renderer.recompile_shaders(IMAGE_SHADER_NAME, false, true);
+        test.render_frames(1);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 2);
+        assert!(frames[1].rgb565_bytes == frames[0].rgb565_bytes);
+    }
+
+    #[test]
+    fn feedback_shader_reads_its_previous_frame() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder()
+            .shader(FEEDBACK_SHADER_NAME, FEEDBACK_SHADER)
+            .metadata(FEEDBACK_SHADER_NAME, FEEDBACK_METADATA)
+            .shader(FEEDBACK_RESULT_SHADER_NAME, FEEDBACK_RESULT_SHADER)
+            .build();
+        test.render_frames(3);
+        test.renderer.recompile_shaders(FEEDBACK_RESULT_SHADER_NAME, false, true);
+        test.render_frames(1);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 4);
+        assert!(frames[1].rgb565_bytes != frames[2].rgb565_bytes);
+        assert!(frames[2].rgb565_bytes == frames[3].rgb565_bytes);
+    }
+}
@@ -0,0 +1,134 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+// Collects invalid values and unavailable options of the command line, checked right after parsing so a bad value is reported
+// by the name of its option before any GPU or hardware is initialized, instead of failing deep in wgpu or rppal
+#[derive(Default)]
+pub struct OptionValidation {
+    errors: Vec<String>,
+}
+
+impl OptionValidation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Checks the value is greater than zero, options which were not given are skipped
+    pub fn positive<T: PartialOrd + Default + Display>(&mut self, option: &str, value: Option<T>) {
+        if let Some(value) = value {
+            // NaN compares as None and fails too
+            if value.partial_cmp(&T::default()) != Some(Ordering::Greater) {
+                self.errors.push(format!("{} must be greater than 0, got {}", option, value));
+            }
+        }
+    }
+
+    // Checks the value is within the range, options which were not given are skipped
+    pub fn in_range<T: PartialOrd + Display>(&mut self, option: &str, value: Option<T>, range: RangeInclusive<T>) {
+        if let Some(value) = value {
+            if !range.contains(&value) {
+                self.errors.push(format!("{} must be between {} and {}, got {}", option, range.start(), range.end(), value));
+            }
+        }
+    }
+
+    // Checks the value is at least the minimum, options which were not given are skipped
+    pub fn at_least<T: PartialOrd + Display>(&mut self, option: &str, value: Option<T>, minimum: T) {
+        if let Some(value) = value {
+            if !matches!(value.partial_cmp(&minimum), Some(Ordering::Greater | Ordering::Equal)) {
+                self.errors.push(format!("{} must be at least {}, got {}", option, minimum, value));
+            }
+        }
+    }
+
+    // Checks the value is one of the allowed ones, options which were not given are skipped
+    pub fn one_of<T: PartialEq + Display>(&mut self, option: &str, value: Option<T>, allowed: &[T]) {
+        if let Some(value) = value {
+            if !allowed.contains(&value) {
+                let allowed: Vec<String> = allowed.iter().map(ToString::to_string).collect();
+                self.errors.push(format!("{} must be one of {}, got {}", option, allowed.join(", "), value));
+            }
+        }
+    }
+
+    // Checks two options which need the same hardware are not both given
+    pub fn not_both(&mut self, option: &str, given: bool, other: &str, other_given: bool, reason: &str) {
+        if given && other_given {
+            self.errors.push(format!("{} can not be combined with {}, {}", option, other, reason));
+        }
+    }
+
+    // Checks an option is only given where it can be used, e.g. on Linux or with a cargo feature
+    pub fn requires(&mut self, option: &str, given: bool, requirement: &str, available: bool) {
+        if given && !available {
+            self.errors.push(format!("{} requires {}", option, requirement));
+        }
+    }
+
+    // Parses the value of an option, returns None and keeps the error if it is invalid
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))] // The parsed options are only used on Linux
+    pub fn parse<T>(&mut self, option: &str, value: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+        parse(value).map_err(|err| self.errors.push(format!("{}: {}", option, err))).ok()
+    }
+
+    // Keeps an error which is not about the value of a single option
+    pub fn error(&mut self, message: &str) {
+        self.errors.push(message.to_string());
+    }
+
+    // Returns all errors as one message, one option per line
+    pub fn finish(self) -> Result<(), String> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_and_missing_values() {
+        let mut validation = OptionValidation::new();
+        validation.positive("--max-fps", Some(30.0));
+        validation.positive::<f32>("--st7789-fps", None);
+        validation.in_range("--render-scale", Some(1.0), 0.1..=1.0);
+        validation.at_least("--spi-chunk-size", Some(2), 2);
+        validation.one_of("--st7789-rotation", Some(90), &[0, 90, 180, 270]);
+        validation.not_both("--hub75", true, "--st7789", false, "both drive the SPI0 pins");
+        validation.requires("--encoder-param", false, "--encoder", false);
+        assert_eq!(validation.parse("--frames", "3", |text| text.parse::<u32>().map_err(|err| err.to_string())), Some(3));
+        assert_eq!(validation.finish(), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_invalid_option() {
+        let mut validation = OptionValidation::new();
+        validation.positive("--max-fps", Some(0.0));
+        validation.positive("--refresh-interval", Some(f32::NAN));
+        validation.in_range("--st7789-te", Some(30), 0..=27);
+        validation.at_least("--spi-chunk-size", Some(1), 2);
+        validation.one_of("--st7789-rotation", Some(45), &[0, 90, 180, 270]);
+        validation.not_both("--hub75", true, "--ws2812", true, "both drive the SPI0 pins");
+        validation.requires("--mqtt", true, "building with --features mqtt", false);
+        assert_eq!(validation.parse("--frames", "x", |text| text.parse::<u32>().map_err(|err| err.to_string())), None);
+        validation.error("No display chosen");
+        assert_eq!(
+            validation.finish(),
+            Err("--max-fps must be greater than 0, got 0\n\
+                 --refresh-interval must be greater than 0, got NaN\n\
+                 --st7789-te must be between 0 and 27, got 30\n\
+                 --spi-chunk-size must be at least 2, got 1\n\
+                 --st7789-rotation must be one of 0, 90, 180, 270, got 45\n\
+                 --hub75 can not be combined with --ws2812, both drive the SPI0 pins\n\
+                 --mqtt requires building with --features mqtt\n\
+                 --frames: invalid digit found in string\n\
+                 No display chosen"
+                .to_string())
+        );
+    }
+}
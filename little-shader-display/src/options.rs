@@ -0,0 +1,722 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::color_conversion::ColorConversion;
+use crate::display_sink::{ColorOrder, DisplayLayout, Dithering, Hub75Mapping};
+use crate::test_pattern::TestPattern;
+use crate::{audio, video_recorder};
+
+// Options repeated on the command line which add to the lists of the config file, other values replace its values
+const REPEATED_OPTIONS: [&str; 3] = ["monitors", "st7789_panels", "buttons"];
+
+// Command-line arguments, the options are accepted before and after the command
+#[derive(Debug, Parser)]
+#[command(
+    name = "little-shader-display",
+    about = "Shows shaders on a window and small displays",
+    after_help = "Options are described in README.md, they can also be set in config.toml next to the executable or in the file given with --config."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub options: CommandLine,
+}
+
+// What the program does, run if no command is given
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Show the shaders on the window and displays given by the options (default)
+    Run,
+    /// Render frames offscreen to PNG files and exit, like --once
+    Render,
+    /// Run and record the output to a video, like --record <path>
+    Record { path: PathBuf },
+    /// Compile every shader of the playlist, exit with code 1 if one fails
+    Validate,
+    /// Print the shaders of the playlist
+    List,
+}
+
+// GPU the renderer asks for, given with --power
+#[derive(Debug, Copy, Clone, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Power {
+    #[default]
+    High,
+    Low,
+}
+
+// How frames are presented to the window, given with --present-mode
+#[derive(Debug, Copy, Clone, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    #[default]
+    Fifo, // Waits for vsync
+    Mailbox, // Replaces the waiting frame, low latency without tearing where supported
+    Immediate, // Does not wait, frames may tear
+}
+
+// Options given on the command line, named like the fields of Options. Unset values keep the ones of the config file.
+// Flags are turned on without a value and off with =false, e.g. --bluetooth=false for bluetooth = true in the config.
+#[derive(Debug, Args, Serialize)]
+#[command(mut_args(|arg| arg.global(true)))]
+pub struct CommandLine {
+    /// Config file to read instead of config.toml next to the executable
+    #[arg(long, value_name = "PATH")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    // Outputs
+    /// Show the shaders in a window
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub window: Option<bool>,
+    /// Logical size of the window, e.g. 800x600
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub window_size: Option<[u32; 2]>,
+    /// Open a fullscreen window on this monitor, repeat for several monitors
+    #[arg(long = "monitor", value_name = "INDEX")]
+    pub monitors: Vec<usize>,
+    /// Open a fullscreen window on every monitor
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub all_monitors: Option<bool>,
+    /// Print the monitors with their index and exit
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub list_monitors: Option<bool>,
+    /// Show the shaders on the ST7789 panel
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub st7789: Option<bool>,
+    /// Turn the image on the ST7789 clockwise by 0, 90, 180 or 270 degrees
+    #[arg(long, value_name = "DEGREES")]
+    pub st7789_rotation: Option<u32>,
+    /// Frame rate limit of the ST7789 when the window is also shown
+    #[arg(long, value_name = "FPS")]
+    pub st7789_fps: Option<f32>,
+    /// GPIO of the tearing effect signal to send frames in sync with the panel
+    #[arg(long, value_name = "GPIO")]
+    pub st7789_te: Option<u8>,
+    /// SPI device of a panel, e.g. 0.1, repeat for several panels
+    #[arg(long = "st7789-panel", value_name = "BUS.CS")]
+    pub st7789_panels: Vec<String>,
+    /// Show the whole frame on every panel or a column of it on each
+    #[arg(long, value_name = "LAYOUT")]
+    pub st7789_layout: Option<DisplayLayout>,
+    /// Show the shaders on an SSD1306 OLED on I2C bus 1
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub ssd1306: Option<bool>,
+    /// How the SSD1306 turns grey levels into lit and dark pixels
+    #[arg(long, value_name = "DITHERING")]
+    pub ssd1306_dither: Option<Dithering>,
+    /// Show the shaders on a Linux framebuffer, e.g. /dev/fb0
+    #[arg(long, value_name = "PATH")]
+    pub framebuffer: Option<PathBuf>,
+    /// Show the shaders on a screen through DRM/KMS, e.g. /dev/dri/card0
+    #[arg(long, value_name = "PATH")]
+    pub drm: Option<PathBuf>,
+    /// Show the shaders on HUB75 LED matrix panels
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub hub75: Option<bool>,
+    /// Rows of one HUB75 panel, 16, 32 or 64
+    #[arg(long, value_name = "ROWS")]
+    pub hub75_rows: Option<u32>,
+    /// Columns of one HUB75 panel
+    #[arg(long, value_name = "COLS")]
+    pub hub75_cols: Option<u32>,
+    /// HUB75 panels daisy-chained on the connector
+    #[arg(long, value_name = "COUNT")]
+    pub hub75_chain: Option<u32>,
+    /// How the HUB75 signals are wired to the GPIO pins
+    #[arg(long, value_name = "MAPPING")]
+    pub hub75_mapping: Option<Hub75Mapping>,
+    /// Levels per HUB75 color channel as a power of two, fewer refresh faster
+    #[arg(long, value_name = "BITS")]
+    pub hub75_pwm_bits: Option<u8>,
+    /// Gamma correction of the HUB75 colors
+    #[arg(long, value_name = "GAMMA")]
+    pub hub75_gamma: Option<f32>,
+    /// Show the shaders on WS2812 LEDs: strip:<count>, grid:<width>x<height> or a mapping file
+    #[arg(long, value_name = "LAYOUT")]
+    pub ws2812: Option<String>,
+    /// SPI device the WS2812 data line is connected to
+    #[arg(long, value_name = "BUS.CS")]
+    pub ws2812_spi: Option<String>,
+    /// Order the WS2812 color channels are sent in
+    #[arg(long, value_name = "ORDER")]
+    pub ws2812_order: Option<ColorOrder>,
+    /// Brightness cap of the WS2812 LEDs
+    #[arg(long, value_name = "0-1")]
+    pub ws2812_brightness: Option<f32>,
+    /// GPIO of the ST7789 data/command pin
+    #[arg(long, value_name = "GPIO")]
+    pub st7789_dc: Option<u8>,
+    /// GPIO of the ST7789 reset pin
+    #[arg(long, value_name = "GPIO")]
+    pub st7789_rst: Option<u8>,
+    /// GPIO of the ST7789 backlight pin
+    #[arg(long, value_name = "GPIO")]
+    pub st7789_bl: Option<u8>,
+    /// SPI clock of the ST7789 in MHz
+    #[arg(long, value_name = "MHZ")]
+    pub spi_clock: Option<f32>,
+    /// Read back the ST7789 ID before initializing it, to check the SPI wiring
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub verify_spi: Option<bool>,
+    /// Largest SPI transfer, up to the spidev buffer size
+    #[arg(long, value_name = "BYTES")]
+    pub spi_chunk_size: Option<usize>,
+    /// Retries of a failed SPI write before the frame is skipped
+    #[arg(long, value_name = "COUNT")]
+    pub spi_retries: Option<u32>,
+    /// Implementation of the RGB565 conversion of the display frames
+    #[arg(long, value_name = "CONVERSION")]
+    pub convert: Option<ColorConversion>,
+    /// Time the RGB565 conversions and exit
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub benchmark_convert: Option<bool>,
+    /// Show a test pattern on the ST7789 instead of the shaders, e.g. to check the wiring
+    #[arg(long, value_name = "PATTERN")]
+    pub test_pattern: Option<TestPattern>,
+    /// Fade the displays to black on shutdown
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub fade_out: Option<bool>,
+    /// Shift the image by a few pixels over time against burn-in
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub antiburn: Option<bool>,
+    /// Largest pixel shift of --antiburn
+    #[arg(long, value_name = "PIXELS")]
+    pub antiburn_amplitude: Option<u32>,
+    /// Seconds between one pixel steps of --antiburn
+    #[arg(long, value_name = "SECONDS")]
+    pub antiburn_period: Option<f32>,
+
+    // Rendering
+    /// Comma separated shaders of the playlist in this order, all shaders if not given
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub shaders: Vec<String>,
+    /// Render at this fraction of the output size and upscale, e.g. 0.5 for slow GPUs
+    #[arg(long, value_name = "SCALE")]
+    pub render_scale: Option<f32>,
+    /// Frame rate limit of the main loop
+    #[arg(long, value_name = "FPS")]
+    pub max_fps: Option<f32>,
+    /// Render a frame only once per interval, e.g. for e-paper
+    #[arg(long, value_name = "SECONDS")]
+    pub refresh_interval: Option<f32>,
+    /// Cap the size of the window surface and the offscreen texture
+    #[arg(long, value_name = "PIXELS")]
+    pub max_texture_size: Option<u32>,
+    /// Prefer the fast or the power saving GPU
+    #[arg(long, value_name = "PREFERENCE")]
+    pub power: Option<Power>,
+    /// How frames are presented to the window
+    #[arg(long, value_name = "MODE")]
+    pub present_mode: Option<PresentMode>,
+    /// Render on the software adapter instead of the GPU
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub fallback_adapter: Option<bool>,
+    /// Put texture coordinate (0,0) at the top left
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub flip_uv: Option<bool>,
+    /// Replace NaN and infinite shader output with black
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub sanitize: Option<bool>,
+    /// Keep debug names in the compiled shaders
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub shader_debug_info: Option<bool>,
+    /// Show the shader name and FPS over the window
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub hud: Option<bool>,
+    /// Also show the HUD on the ST7789
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub hud_st7789: Option<bool>,
+    /// Show shader compile errors over the output, on by default
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub error_overlay: Option<bool>,
+    /// Do not show shader compile errors over the output
+    #[arg(long, conflicts_with = "error_overlay")]
+    #[serde(skip)]
+    pub no_error_overlay: bool,
+    /// Exit if no frame completes for this long, e.g. to be restarted by systemd
+    #[arg(long, value_name = "SECONDS")]
+    pub watchdog: Option<u64>,
+
+    // Rendering to files
+    /// Render frames offscreen to PNG files and exit, like the render command
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub once: Option<bool>,
+    /// Shader time of the first rendered frame
+    #[arg(long, value_name = "SECONDS")]
+    pub time: Option<f32>,
+    /// Shader to render, the first one of the playlist if not given
+    #[arg(long, value_name = "NAME")]
+    pub shader: Option<String>,
+    /// PNG file the frames are saved to
+    #[arg(long, value_name = "PATH")]
+    pub out: Option<String>,
+    /// Frames to render of each shader
+    #[arg(long, value_name = "COUNT")]
+    pub frames: Option<u32>,
+    /// Frames per second of shader time between the rendered frames
+    #[arg(long, value_name = "FPS")]
+    pub frame_rate: Option<f32>,
+    /// Render every shader of the playlist
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub all_shaders: Option<bool>,
+    /// Compile a shader, print its SPIR-V disassembly and exit
+    #[arg(long, value_name = "NAME")]
+    pub dump_spirv: Option<String>,
+    /// Record the output to a video, like the record command
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+    /// Frame rate of recorded videos
+    #[arg(long, value_name = "FPS")]
+    pub record_fps: Option<f32>,
+    /// Stop recording after this long
+    #[arg(long, value_name = "SECONDS")]
+    pub duration: Option<f32>,
+
+    // Inputs
+    /// Pass all keys to the shaders, also the ones controlling the app
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub shader_keys: Option<bool>,
+    /// Show shaders piped to stdin
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub stdin_shader: Option<bool>,
+    /// Accept input from a phone over Bluetooth
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub bluetooth: Option<bool>,
+    /// Accept commands from an MQTT broker, e.g. localhost:1883
+    #[arg(long, value_name = "BROKER")]
+    pub mqtt: Option<String>,
+    /// Accept commands over WebSocket on this port
+    #[arg(long, value_name = "PORT")]
+    pub websocket_port: Option<u16>,
+    /// Accept WebSocket connections from other devices, not only from this one
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub websocket_public: Option<bool>,
+    /// Serve the HTTP API on this port
+    #[arg(long, value_name = "PORT")]
+    pub http_port: Option<u16>,
+    /// Serve render stats on this TCP port
+    #[arg(long, value_name = "PORT")]
+    pub metrics_port: Option<u16>,
+    /// Record the shader switches and inputs, saved to this file on shutdown
+    #[arg(long, value_name = "PATH")]
+    pub record_session: Option<PathBuf>,
+    /// Replay the inputs of a recorded session in a loop
+    #[arg(long, value_name = "PATH")]
+    pub play_session: Option<PathBuf>,
+    /// GPIO button for an action, e.g. next=17, repeat for several buttons
+    #[arg(long = "button", value_name = "ACTION=GPIO")]
+    pub buttons: Vec<String>,
+    /// GPIOs of a rotary encoder switching shaders
+    #[arg(long, value_name = "A,B[,PUSH]")]
+    pub encoder: Option<String>,
+    /// User param the rotary encoder changes instead of the shader
+    #[arg(long, value_name = "NAME")]
+    pub encoder_param: Option<String>,
+    /// Read an accelerometer on I2C bus 1 into the gravity uniform
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub accel: Option<bool>,
+    /// Also turn the ST7789 image to stay upright, implies --accel
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub accel_rotate: Option<bool>,
+    /// Dim the displays with a light sensor on I2C bus 1
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub ambient_light: Option<bool>,
+    /// Pass CPU and memory usage and the temperature to the shaders
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub telemetry: Option<bool>,
+    /// Pass the loudness and beats of the audio to the shaders
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub audio: Option<bool>,
+    /// PulseAudio source to capture, the monitor of the default output if not given
+    #[arg(long, value_name = "SOURCE")]
+    pub audio_source: Option<String>,
+    /// Pass camera frames to the shaders, e.g. /dev/video0
+    #[arg(long, value_name = "PATH")]
+    pub camera: Option<PathBuf>,
+}
+
+impl Cli {
+    // Parses the arguments without the program name, a leading -- is left over from passing them through cargo run or scripts
+    pub fn parse_args(args: &[String]) -> Result<Self, clap::Error> {
+        let args = args.strip_prefix(&["--".to_string()]).unwrap_or(args);
+        Self::try_parse_from(std::iter::once("little-shader-display").chain(args.iter().map(String::as_str)))
+    }
+}
+
+// Options of the app, read from the config file and then overridden by command-line arguments.
+// Config keys are the names of the command-line options with underscores, e.g. st7789_fps = 30 for --st7789-fps 30,
+// except buttons, monitors and st7789_panels which are the lists of --button, --monitor and --st7789-panel values. See config.example.toml.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    // Outputs
+    pub window: bool,
+    pub window_size: [u32; 2], // Logical size of the window, ignored on a fullscreen monitor
+    pub monitors: Vec<usize>, // A fullscreen window on each, given with --monitor
+    pub all_monitors: bool,
+    pub list_monitors: bool,
+    pub st7789: bool,
+    pub st7789_rotation: u32, // Degrees the image is turned clockwise on the ST7789
+    pub st7789_fps: Option<f32>,
+    pub st7789_te: Option<u8>,
+    pub st7789_panels: Vec<String>, // SPI devices of the panels as <bus>.<chip select>, given with --st7789-panel
+    pub st7789_layout: DisplayLayout,
+    pub ssd1306: bool,
+    pub ssd1306_dither: Dithering,
+    pub framebuffer: Option<PathBuf>,
+    pub drm: Option<PathBuf>,
+    pub hub75: bool,
+    pub hub75_rows: Option<u32>,
+    pub hub75_cols: Option<u32>,
+    pub hub75_chain: Option<u32>,
+    pub hub75_mapping: Hub75Mapping,
+    pub hub75_pwm_bits: Option<u8>,
+    pub hub75_gamma: Option<f32>,
+    pub ws2812: Option<String>, // LED layout, strip:<count>, grid:<width>x<height> or a mapping file
+    pub ws2812_spi: Option<String>,
+    pub ws2812_order: ColorOrder,
+    pub ws2812_brightness: Option<f32>,
+    pub st7789_dc: Option<u8>,
+    pub st7789_rst: Option<u8>,
+    pub st7789_bl: Option<u8>,
+    pub spi_clock: Option<f32>, // MHz
+    pub verify_spi: bool,
+    pub spi_chunk_size: Option<usize>,
+    pub spi_retries: Option<u32>,
+    pub convert: ColorConversion,
+    pub benchmark_convert: bool,
+    pub test_pattern: Option<TestPattern>,
+    pub fade_out: bool,
+    pub antiburn: bool,
+    pub antiburn_amplitude: u32,
+    pub antiburn_period: f32,
+
+    // Rendering
+    pub shaders: Vec<String>, // Shaders of the playlist in this order, all shaders in the directory if empty
+    pub render_scale: f32,
+    pub max_fps: Option<f32>,
+    pub refresh_interval: Option<f32>,
+    pub max_texture_size: Option<u32>,
+    pub power: Power,
+    pub present_mode: PresentMode,
+    pub fallback_adapter: bool,
+    pub flip_uv: bool,
+    pub sanitize: bool,
+    pub shader_debug_info: bool,
+    pub hud: bool,
+    pub hud_st7789: bool,
+    pub error_overlay: bool,
+    pub watchdog: Option<u64>,
+
+    // Rendering to files
+    pub once: bool,
+    pub time: f32,
+    pub shader: Option<String>,
+    pub out: String,
+    pub frames: u32,
+    pub frame_rate: f32,
+    pub all_shaders: bool,
+    pub dump_spirv: Option<String>,
+    pub record: Option<PathBuf>,
+    pub record_fps: f32,
+    pub duration: Option<f32>,
+
+    // Inputs
+    pub shader_keys: bool,
+    pub stdin_shader: bool,
+    pub bluetooth: bool,
+    pub mqtt: Option<String>,
+    pub websocket_port: Option<u16>,
+    pub websocket_public: bool, // Binds all interfaces instead of localhost, anyone on the network can then send commands
+    pub http_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub record_session: Option<PathBuf>,
+    pub play_session: Option<PathBuf>,
+    pub buttons: Vec<String>,
+    pub encoder: Option<String>,
+    pub encoder_param: Option<String>,
+    pub accel: bool,
+    pub accel_rotate: bool,
+    pub ambient_light: bool,
+    pub telemetry: bool,
+    pub audio: bool,
+    pub audio_source: String,
+    pub camera: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            window: false,
+            window_size: [500, 500],
+            monitors: Vec::new(),
+            all_monitors: false,
+            list_monitors: false,
+            st7789: false,
+            st7789_rotation: 0,
+            st7789_fps: None,
+            st7789_te: None,
+            st7789_panels: Vec::new(),
+            st7789_layout: DisplayLayout::default(),
+            ssd1306: false,
+            ssd1306_dither: Dithering::default(),
+            framebuffer: None,
+            drm: None,
+            hub75: false,
+            hub75_rows: None,
+            hub75_cols: None,
+            hub75_chain: None,
+            hub75_mapping: Hub75Mapping::default(),
+            hub75_pwm_bits: None,
+            hub75_gamma: None,
+            ws2812: None,
+            ws2812_spi: None,
+            ws2812_order: ColorOrder::default(),
+            ws2812_brightness: None,
+            st7789_dc: None,
+            st7789_rst: None,
+            st7789_bl: None,
+            spi_clock: None,
+            verify_spi: false,
+            spi_chunk_size: None,
+            spi_retries: None,
+            convert: ColorConversion::default(),
+            benchmark_convert: false,
+            test_pattern: None,
+            fade_out: false,
+            antiburn: false,
+            antiburn_amplitude: crate::DEFAULT_ANTIBURN_AMPLITUDE,
+            antiburn_period: crate::DEFAULT_ANTIBURN_PERIOD,
+            shaders: Vec::new(),
+            render_scale: 1.0,
+            max_fps: None,
+            refresh_interval: None,
+            max_texture_size: None,
+            power: Power::default(),
+            present_mode: PresentMode::default(),
+            fallback_adapter: false,
+            flip_uv: false,
+            sanitize: false,
+            shader_debug_info: false,
+            hud: false,
+            hud_st7789: false,
+            error_overlay: true,
+            watchdog: None,
+            once: false,
+            time: 0.0,
+            shader: None,
+            out: "frame.png".to_string(),
+            frames: 1,
+            frame_rate: 30.0,
+            all_shaders: false,
+            dump_spirv: None,
+            record: None,
+            record_fps: video_recorder::DEFAULT_FPS,
+            duration: None,
+            shader_keys: false,
+            stdin_shader: false,
+            bluetooth: false,
+            mqtt: None,
+            websocket_port: None,
+            websocket_public: false,
+            http_port: None,
+            metrics_port: None,
+            record_session: None,
+            play_session: None,
+            buttons: Vec::new(),
+            encoder: None,
+            encoder_param: None,
+            accel: false,
+            accel_rotate: false,
+            ambient_light: false,
+            telemetry: false,
+            audio: false,
+            audio_source: audio::DEFAULT_SOURCE.to_string(),
+            camera: None,
+        }
+    }
+}
+
+impl Options {
+    // Reads the config file given with --config, or config.toml in the default directory if it exists,
+    // then applies the command-line options on top of it
+    pub fn load(cli: Cli, default_directory: &Path) -> Result<(Command, Self), String> {
+        let default_config_path = default_directory.join("config.toml");
+        let config = match &cli.options.config {
+            Some(path) => Self::from_file(path)?,
+            None if default_config_path.exists() => Self::from_file(&default_config_path)?,
+            None => Self::default(),
+        };
+        let mut options = config.overlay(&cli.options)?;
+        let command = cli.command.unwrap_or(Command::Run);
+        match &command {
+            Command::Render => options.once = true,
+            Command::Record { path } => options.record = Some(path.clone()),
+            _ => {}
+        }
+        Ok((command, options))
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let options = toml::from_str(&text).map_err(|err| format!("Invalid config {}: {}", path.display(), err))?;
+        println!("Using config {}", path.display());
+        Ok(options)
+    }
+
+    // Options with the values given on the command line, options which were not given keep their values
+    pub fn overlay(&self, command_line: &CommandLine) -> Result<Self, String> {
+        let mut table = to_table(self)?;
+        for (key, value) in to_table(command_line)? {
+            match value {
+                toml::Value::Array(values) if values.is_empty() => {}
+                toml::Value::Array(values) if REPEATED_OPTIONS.contains(&key.as_str()) => {
+                    if let Some(toml::Value::Array(config_values)) = table.get_mut(&key) {
+                        config_values.extend(values);
+                    }
+                }
+                value => _ = table.insert(key, value),
+            }
+        }
+        if command_line.no_error_overlay {
+            table.insert("error_overlay".to_string(), toml::Value::Boolean(false));
+        }
+        toml::Value::Table(table).try_into().map_err(|err| format!("Invalid options: {}", err))
+    }
+}
+
+fn to_table(value: &impl Serialize) -> Result<toml::Table, String> {
+    match toml::Value::try_from(value) {
+        Ok(toml::Value::Table(table)) => Ok(table),
+        Ok(_) => unreachable!("options serialize to a table"),
+        Err(err) => Err(format!("Invalid options: {}", err)),
+    }
+}
+
+// Parses a size given as <width>x<height>, e.g. 800x600
+fn parse_size(text: &str) -> Result<[u32; 2], String> {
+    let size = text.split_once('x').and_then(|(width, height)| Some([width.parse().ok()?, height.parse().ok()?]));
+    size.ok_or_else(|| format!("expected <width>x<height>, e.g. 800x600, got {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Cli, clap::Error> {
+        Cli::parse_args(&text.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    // Directory of its own for each test, so a config.toml left in the temporary directory is not read
+    fn config_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("little-shader-display-options-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&directory).ok();
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let options: Options = toml::from_str("st7789 = true\nst7789_fps = 20.0\nbuttons = [\"next=17\"]\nshaders = [\"waves.frag\"]\nhud = true\nbluetooth = true").unwrap();
+        let cli = parse("--window --st7789-fps 25 --button previous=27 --window-size 800x600 --no-error-overlay --bluetooth=false").unwrap();
+        let options = options.overlay(&cli.options).unwrap();
+        assert!(options.st7789 && options.window && options.hud && !options.bluetooth);
+        assert_eq!(options.st7789_fps, Some(25.0));
+        assert_eq!(options.buttons, ["next=17", "previous=27"]);
+        assert_eq!(options.shaders, ["waves.frag"]);
+        assert_eq!(options.window_size, [800, 600]);
+        assert_eq!(options.render_scale, 1.0);
+        assert!(!options.error_overlay);
+
+        let options = options.overlay(&parse("--shaders a.frag,b.wgsl").unwrap().options).unwrap();
+        assert_eq!(options.shaders, ["a.frag", "b.wgsl"]);
+        assert!(!options.error_overlay && options.window);
+        let options = options.overlay(&parse("--error-overlay --window=false").unwrap().options).unwrap();
+        assert!(options.error_overlay && !options.window);
+        assert!(parse("--error-overlay --no-error-overlay").is_err());
+    }
+
+    #[test]
+    fn parses_subcommands_and_rejects_unknown_options() {
+        let directory = config_directory("subcommands");
+        let load = |text: &str| Options::load(parse(text).unwrap(), &directory).unwrap();
+        assert_eq!(load("-- --window").0, Command::Run);
+        let (command, options) = load("render --frames 2");
+        assert_eq!(command, Command::Render);
+        assert!(options.once && options.frames == 2);
+        let (command, options) = load("--window record out.mp4 --duration 5");
+        assert_eq!(command, Command::Record { path: PathBuf::from("out.mp4") });
+        assert_eq!(options.record, Some(PathBuf::from("out.mp4")));
+        assert!(options.window && options.duration == Some(5.0));
+
+        assert_eq!(parse("list --help").unwrap_err().kind(), clap::error::ErrorKind::DisplayHelp);
+        assert!(parse("record --duration 5").is_err());
+        assert!(parse("draw").is_err());
+        assert!(parse("--st7789-fsp 30").is_err());
+        assert!(parse("--window extra").is_err());
+        assert!(parse("--frames two").is_err());
+        assert!(parse("--window-size 800").is_err());
+        assert!(parse("--st7789-te").is_err());
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn reads_config_from_default_directory_or_given_path() {
+        let directory = config_directory("config");
+        fs::write(directory.join("config.toml"), "st7789 = true\nmax_fps = 30.0").unwrap();
+        fs::write(directory.join("other.toml"), "window = true").unwrap();
+        let load = |text: &str| Options::load(parse(text).unwrap(), &directory);
+        let (_, options) = load("--max-fps 20").unwrap();
+        assert!(options.st7789 && options.max_fps == Some(20.0));
+        let (_, options) = load(&format!("--config {}", directory.join("other.toml").display())).unwrap();
+        assert!(options.window && !options.st7789);
+        assert!(load(&format!("--config {}", directory.join("missing.toml").display())).is_err());
+        fs::write(directory.join("config.toml"), "max_fps = \"fast\"").unwrap();
+        assert!(load("").is_err());
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    // Reads the field names of a struct from its Deserialize implementation
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("only the field names are read"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit unit_struct
+            newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    #[test]
+    fn every_option_has_a_command_line_argument() {
+        let mut option_names: &'static [&'static str] = &[];
+        _ = Options::deserialize(FieldNames(&mut option_names));
+        let mut option_names = option_names.to_vec();
+        let command = <Cli as clap::CommandFactory>::command();
+        let mut argument_names: Vec<&str> = command.get_arguments().map(|arg| arg.get_id().as_str()).filter(|name| !["config", "no_error_overlay"].contains(name)).collect();
+        option_names.sort();
+        argument_names.sort();
+        assert_eq!(option_names, argument_names);
+    }
+
+    #[test]
+    fn rejects_unknown_config_keys() {
+        assert!(toml::from_str::<Options>("st7789_fsp = 20.0").is_err());
+        assert_eq!(toml::from_str::<Options>("").unwrap(), Options::default());
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+
+use crate::multipass;
+use crate::renderer;
+
+// Runtime list of fragment shaders which can be cycled through, updated when shader files are added or deleted
+pub struct Playlist {
+    shader_names: Vec<String>,
+    current_index: usize,
+    order: Vec<String>, // Configured shaders, only these are added when files appear. All shaders if empty.
+}
+
+impl Playlist {
+    pub fn new(shader_names: Vec<String>) -> Self {
+        Self { shader_names, current_index: 0, order: Vec::new() }
+    }
+
+    // Playlist of the configured shaders in the configured order, the ones which are not available are skipped
+    // until their files appear. All available shaders are used if none are configured.
+    pub fn with_order(available_shader_names: Vec<String>, order: Vec<String>) -> Self {
+        if order.is_empty() {
+            return Self::new(available_shader_names);
+        }
+        let shader_names = order.iter().filter(|name| available_shader_names.contains(name)).cloned().collect();
+        Self { shader_names, current_index: 0, order }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shader_names.is_empty()
+    }
+
+    // Names of the fragment shaders in a directory without the buffer pass shaders, sorted so the order is the same on every device
+    pub fn scan(directory: &Path) -> Vec<String> {
+        let mut shader_names: Vec<String> = fs::read_dir(directory)
+            .map(|entries| entries.filter_map(Result::ok).filter_map(|entry| entry.file_name().into_string().ok()).collect())
+            .unwrap_or_default();
+        shader_names.retain(|name| renderer::is_fragment_shader(name) && !multipass::is_buffer_shader(name));
+        shader_names.sort();
+        shader_names
+    }
+
+    // Name of the currently selected shader
+    pub fn current(&self) -> &str {
+        &self.shader_names[self.current_index]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current_index
+    }
+
+    pub fn shader_names(&self) -> &[String] {
+        &self.shader_names
+    }
+
+    // Selects the next shader, wrapping around to the first one
+    pub fn next(&mut self) -> &str {
+        self.current_index = (self.current_index + 1) % self.shader_names.len();
+        self.current()
+    }
+
+    // Selects the previous shader, wrapping around to the last one
+    pub fn previous(&mut self) -> &str {
+        self.current_index = (self.current_index + self.shader_names.len() - 1) % self.shader_names.len();
+        self.current()
+    }
+
+    // Selects the shader with the given name, returns false if it is not in the list
+    pub fn select(&mut self, shader_name: &str) -> bool {
+        match self.shader_names.iter().position(|name| name == shader_name) {
+            Some(index) => {
+                self.current_index = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Appends a shader to the end of the list, returns false if it is already present
+    // Shaders which are not configured are not added.
+    pub fn add(&mut self, shader_name: &str) -> bool {
+        if self.shader_names.iter().any(|name| name == shader_name) {
+            return false;
+        }
+        if self.order.is_empty() {
+            self.shader_names.push(shader_name.to_string());
+            return true;
+        }
+        if !self.order.iter().any(|name| name == shader_name) {
+            return false;
+        }
+        // Keep the configured order
+        let order_index = |name: &str| self.order.iter().position(|ordered_name| ordered_name == name);
+        let index = self.shader_names.iter().position(|name| order_index(name) > order_index(shader_name)).unwrap_or(self.shader_names.len());
+        self.shader_names.insert(index, shader_name.to_string());
+        if index <= self.current_index && self.shader_names.len() > 1 {
+            self.current_index += 1;
+        }
+        true
+    }
+
+    // Removes a shader from the list, returns true if it was the selected one and the selection moved to another shader.
+    // The last remaining shader is never removed.
+    pub fn remove(&mut self, shader_name: &str) -> bool {
+        let Some(index) = self.shader_names.iter().position(|name| name == shader_name) else {
+            return false;
+        };
+
+        if self.shader_names.len() == 1 {
+            println!("Warning: Not removing {} from the playlist since it is the only shader", shader_name);
+            return false;
+        }
+
+        self.shader_names.remove(index);
+        if index < self.current_index {
+            self.current_index -= 1;
+            false
+        } else if index == self.current_index {
+            self.current_index %= self.shader_names.len();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> Playlist {
+        Playlist::new(vec!["a.frag".to_string(), "b.frag".to_string(), "c.frag".to_string()])
+    }
+
+    #[test]
+    fn previous_wraps_from_first_to_last() {
+        let mut playlist = playlist();
+        assert_eq!(playlist.previous(), "c.frag");
+        assert_eq!(playlist.current_index(), 2);
+        assert_eq!(playlist.previous(), "b.frag");
+    }
+
+    #[test]
+    fn previous_undoes_next() {
+        let mut playlist = playlist();
+        for _ in 0..4 {
+            playlist.next();
+        }
+        assert_eq!(playlist.current(), "b.frag");
+        for _ in 0..4 {
+            playlist.previous();
+        }
+        assert_eq!(playlist.current(), "a.frag");
+    }
+
+    #[test]
+    fn scan_finds_sorted_fragment_shaders() {
+        let directory = std::env::temp_dir().join(format!("little-shader-display-playlist-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for file_name in ["b.wgsl", "master.vert", "a.frag", "common.glsl", "a.buffer.frag"] {
+            fs::write(directory.join(file_name), "").unwrap();
+        }
+        assert_eq!(Playlist::scan(&directory), ["a.frag", "b.wgsl"]);
+        assert!(Playlist::scan(&directory.join("missing")).is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn configured_order_is_kept() {
+        let available = vec!["a.frag".to_string(), "b.frag".to_string(), "c.frag".to_string()];
+        let order = vec!["c.frag".to_string(), "d.frag".to_string(), "a.frag".to_string()];
+        let mut playlist = Playlist::with_order(available, order);
+        assert_eq!(playlist.shader_names(), ["c.frag", "a.frag"]);
+        playlist.next();
+        assert!(!playlist.add("b.frag"));
+        assert!(playlist.add("d.frag"));
+        assert_eq!(playlist.shader_names(), ["c.frag", "d.frag", "a.frag"]);
+        assert_eq!(playlist.current(), "a.frag");
+    }
+
+    #[test]
+    fn previous_with_single_shader_stays() {
+        let mut playlist = Playlist::new(vec!["a.frag".to_string()]);
+        assert_eq!(playlist.previous(), "a.frag");
+    }
+}
@@ -1,21 +1,34 @@
 use std::{fs, iter};
+use std::borrow::Cow;
 use std::iter::once;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use futures::executor::block_on;
 use wgpu::util::DeviceExt;
 use bytemuck_derive::{Pod, Zeroable};
-use std::{
-    mem::size_of,
-    sync::{LazyLock},
-};
+use std::mem::size_of;
 use bytemuck::{cast_slice};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use serde::Serialize;
 
-use crate::{DEBUG_OVERHEADS, SHADER_NAMES};
+use crate::DEBUG_OVERHEADS;
 use crate::ST7789_OUTPUT_SIZE;
 use crate::SHADERS_PATH;
-use crate::COMPILED_VERTEX_SHADER_PATH;
-use crate::COMPILED_FRAGMENT_SHADER_PATH;
+use crate::hud::Hud;
+use crate::antiburn::AntiBurn;
+use crate::blit::Blit;
+use crate::audio;
+use crate::shader_compiler;
+use crate::shader_metadata::{BufferMetadata, ShaderMetadata};
+use crate::telemetry::SystemStats;
+use crate::user_params::{UserParams, USER_PARAM_COUNT};
+use crate::multipass::{self, BufferPass, Multipass};
+use crate::display_sink::{self, DisplayLayout, DisplaySink};
+use crate::metrics::FrameTimings;
+use crate::color_conversion::{self, ColorConversion};
+
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+const ERROR_OVERLAY_LINES: usize = 8; // Lines of a shader error shown over the output
+const AMBIENT_BRIGHTNESS_MIN_CHANGE: f32 = 0.01;
 
 //use crate::file_watcher::FileWatcher;
 //use crate::Vertex;
@@ -23,28 +36,134 @@ use crate::COMPILED_FRAGMENT_SHADER_PATH;
 
 // --- Data Structures for Rendering ---
 
-// Uniform buffer struct that holds the current time to pass to the shader.
+// Uniforms are split into two bind groups:
+// - group 0 (GlobalUniforms) holds values which change every frame and is written every frame
+// - group 1 (DynamicUniforms) holds input values and is written only when they change
+// Entire struct sizes must be a multiple of 16 bytes to meet GLSL buffer layout rules
+
+// Global uniform buffer struct that holds the current time and screen properties to pass to the shader.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GlobalUniforms {
+    time: f32, // 4 at offset 0, animation time, snapped to the refresh interval in slow refresh mode
+    screen_aspect_ratio: f32, // 4 at offset 4
+    output_srgb: u32, // 4 at offset 8, 1 if the output format is sRGB and shader output gets gamma encoded by the GPU
+    _padding_0: f32, // 4 at offset 12
+    panel_resolution: [f32; 2], // 8 at offset 16, physical pixel size of the ST7789 panel, zero if no panel is used
+    real_time: f32, // 4 at offset 24, wall-clock seconds since startup, never snapped or paused
+    output_kind: u32, // 4 at offset 28, output the current pass renders for, OUTPUT_KIND_WINDOW or OUTPUT_KIND_ST7789
+    resolution: [f32; 2], // 8 at offset 32, pixel size of the target the current pass renders into (like Shadertoy's iResolution)
+    _padding_1: [f32; 2], // 8 at offset 40
+    mouse: [f32; 4], // 16 at offset 48, window mouse in pixels of the current pass, like Shadertoy's iMouse
+}
+
+// Values of the output_kind uniform, shaders can use them to adapt their detail to the output
+const OUTPUT_KIND_WINDOW: u32 = 0;
+const OUTPUT_KIND_ST7789: u32 = 1;
 
-// Entire struct size must be a multiple of
-// 16 bytes to meet GLSL buffer layout rules
-struct Uniforms { 
-    time: f32, // 4
-    _padding_0: [f32; 3], // 12
+impl GlobalUniforms {
+    fn new(output_format: wgpu::TextureFormat, panel_resolution: [f32; 2]) -> Self {
+        Self { time: 0.0, screen_aspect_ratio: 0.0, output_srgb: output_format.is_srgb() as u32, _padding_0: 0.0, panel_resolution, real_time: 0.0, output_kind: OUTPUT_KIND_WINDOW, resolution: [0.0; 2], _padding_1: [0.0; 2], mouse: [0.0; 4], }
+    }
+}
+
+// Dynamic uniform buffer struct that holds the input data to pass to the shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct DynamicUniforms {
     bluetooth_data: [f32; 3], // 12
-    screen_aspect_ratio: f32, // 4 
+    _padding_0: f32, // 4
+    keys: [u32; 4], // 16, bitmask of pressed keys, see keyboard.rs for the key to bit mapping
+    gravity: [f32; 3], // 12, smoothed gravity in g from the accelerometer, zero without one
+    _padding_1: f32, // 4
+    user_params: [f32; USER_PARAM_COUNT], // 64 at offset 48, params declared in the shader metadata, vec4[4] in shaders
+    angular_velocity: [f32; 3], // 12 at offset 112, rad/s around the panel axes from the gyro, zero without one
+    _padding_2: f32, // 4
+    orientation: [f32; 4], // 16 at offset 128, quaternion (x, y, z, w) from the gyro and accelerometer, identity without them
+    cpu_usage: f32, // 4 at offset 144, 0..1, -1 without --telemetry
+    memory_usage: f32, // 4 at offset 148, 0..1, -1 without --telemetry
+    soc_temperature: f32, // 4 at offset 152, °C, -1 without --telemetry
+    ambient_brightness: f32, // 4 at offset 156, 0..1 display brightness chosen for the ambient light, 1 without a sensor
+    loudness: f32, // 4 at offset 160, RMS of the captured audio, zero without --audio
+    beat: f32, // 4 at offset 164, 1 at a detected beat of the captured audio, fading to 0
+    clients_connected: u32, // 4 at offset 168, clients connected to the bluetooth and WebSocket servers, zero without them
+    seconds_since_last_message: f32, // 4 at offset 172, age of the last remote input message, -1 if none was received yet
 }
 
-impl Uniforms {
+impl DynamicUniforms {
     fn new() -> Self {
-        Self { time: 0.0, _padding_0: [0.0, 0.0, 0.0], bluetooth_data: [0.0, 0.0, 0.0], screen_aspect_ratio: 0.0, }
+        Self { bluetooth_data: [0.0, 0.0, 0.0], _padding_0: 0.0, keys: [0, 0, 0, 0], gravity: [0.0, 0.0, 0.0], _padding_1: 0.0, user_params: [0.0; USER_PARAM_COUNT], angular_velocity: [0.0; 3], _padding_2: 0.0, orientation: [0.0, 0.0, 0.0, 1.0], cpu_usage: -1.0, memory_usage: -1.0, soc_temperature: -1.0, ambient_brightness: 1.0, loudness: 0.0, beat: 0.0, clients_connected: 0, seconds_since_last_message: -1.0, }
+    }
+}
+
+// Serializable view of the values shaders currently receive from both uniform buffers, without padding
+#[derive(Debug, Serialize)]
+struct UniformsSnapshot {
+    time: f32,
+    screen_aspect_ratio: f32,
+    output_srgb: u32,
+    panel_resolution: [f32; 2],
+    real_time: f32,
+    output_kind: u32,
+    resolution: [f32; 2],
+    mouse: [f32; 4],
+    bluetooth_data: [f32; 3],
+    keys: [u32; 4],
+    gravity: [f32; 3],
+    user_params: [f32; USER_PARAM_COUNT],
+    angular_velocity: [f32; 3],
+    orientation: [f32; 4],
+    cpu_usage: f32,
+    memory_usage: f32,
+    soc_temperature: f32,
+    ambient_brightness: f32,
+    loudness: f32,
+    beat: f32,
+    clients_connected: u32,
+    seconds_since_last_message: f32,
+}
+
+// GPU selection and limits
+#[derive(Debug, Copy, Clone)]
+pub struct GpuOptions {
+    pub max_texture_size: Option<u32>, // Limits textures and the window surface, e.g. for low-memory devices
+    pub power_preference: wgpu::PowerPreference, // Picks the discrete or the integrated GPU where both are available
+    pub force_fallback_adapter: bool, // Renders on a software adapter (e.g. lavapipe), for machines without a usable GPU
+    pub present_mode: wgpu::PresentMode, // How window frames wait for vsync, falls back to one the surface supports
+}
+
+impl Default for GpuOptions {
+    fn default() -> Self {
+        Self { max_texture_size: None, power_preference: wgpu::PowerPreference::HighPerformance, force_fallback_adapter: false, present_mode: wgpu::PresentMode::Fifo }
     }
 }
 
+// Shader compiler settings, debug builds keep debug info in the SPIR-V
+#[derive(Debug, Copy, Clone)]
+pub struct ShaderCompileOptions {
+    pub debug_info: bool, // Debug names in the SPIR-V written by naga
+}
+
+impl Default for ShaderCompileOptions {
+    fn default() -> Self {
+        Self { debug_info: cfg!(debug_assertions) }
+    }
+}
+
+// Where the renderer draws and how it sets up the GPU
+#[derive(Default)]
+pub struct RendererOptions<'a> {
+    pub window: Option<&'a winit::window::Window>, // First window the shader is shown in, more are added with add_window
+    pub offscreen: bool, // Renders into the offscreen texture also without displays, e.g. for --once
+    pub display_sinks: Vec<Box<dyn DisplaySink>>, // Fed with the offscreen frames, the first one chooses their size
+    pub gpu_options: GpuOptions,
+    pub compile_options: ShaderCompileOptions,
+    pub shader_name: &'a str, // Fragment shader compiled first, the current one of the playlist
+}
+
 // Vertex struct representing a position and its corresponding texture coordinate.
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
 struct Vertex {
     position: [f32; 2],
     texture_coordinates: [f32; 2]
@@ -78,262 +197,1019 @@ impl Vertex {
     }
 }
 
-// Vertices of two screen filling triangles
-static VERTICES: LazyLock<[Vertex; 6]> = LazyLock::new(|| [
-    // First triangle (top-left to bottom-right)
-    Vertex::new(-1.0, 1.0, 0.0, 1.0),    // Top-left
-    Vertex::new(-1.0, -1.0, 0.0, 0.0),   // Bottom-left
-    Vertex::new(1.0, 1.0, 1.0, 1.0),     // Top-right
-    
-    // Second triangle (bottom-left to bottom-right)
-    Vertex::new(1.0, 1.0, 1.0, 1.0),     // Top-right (shared vertex)
-    Vertex::new(-1.0, -1.0, 0.0, 0.0),   // Bottom-left (shared vertex)
-    Vertex::new(1.0, -1.0, 1.0, 0.0),    // Bottom-right
-]);
+// Vertices of a single screen filling triangle.
+// Texture coordinates (0,0) are the bottom-left and (1,1) the top-right corner of the image as seen on every output,
+// and the first row of a readback is the top of the image, so the window and the panels agree without flipping anything.
+// flip_y mirrors the positions vertically for buffers, which are sampled first row at v = 0.
+// flip_uv mirrors the texture coordinates vertically instead, putting (0,0) at the top-left for shaders written with that convention.
+fn screen_vertices(flip_y: bool, flip_uv: bool) -> [Vertex; 3] {
+    let top = if flip_y { -1.0 } else { 1.0 };
+    let bottom = -top;
+    let v_top = if flip_uv { 0.0 } else { 1.0 };
+    let v_bottom = 1.0 - v_top;
+    // A single triangle twice the screen size in each direction covers the whole screen without a diagonal seam,
+    // the part outside the screen is clipped. Texture coordinates continue past the screen edges at the same rate,
+    // so the visible area still maps exactly to 0..1.
+    let v_beyond_top = v_bottom + 2.0 * (v_top - v_bottom);
+    [
+        Vertex::new(-1.0, bottom, 0.0, v_bottom),          // Bottom-left
+        Vertex::new(3.0, bottom, 2.0, v_bottom),           // Beyond bottom-right
+        Vertex::new(-1.0, 3.0 * top, 0.0, v_beyond_top),   // Beyond top-left
+    ]
+}
+
+// Rotates the image shown by the vertices clockwise by the given number of quarter turns,
+// by rotating the texture coordinates counterclockwise around the center of the image
+fn rotate_texture_coordinates(vertices: [Vertex; 3], quarter_turns: u32) -> [Vertex; 3] {
+    vertices.map(|vertex| {
+        let [mut u, mut v] = vertex.texture_coordinates.map(|coordinate| coordinate - 0.5);
+        for _ in 0..quarter_turns % 4 {
+            (u, v) = (-v, u);
+        }
+        Vertex::new(vertex.position[0], vertex.position[1], u + 0.5, v + 0.5)
+    })
+}
+
+// Float target the shader renders into before the sanitize pass, keeps NaN and infinities so they can be replaced
+const SANITIZE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Shader output rendered into a float texture and sanitized on the way to the offscreen target
+struct SanitizePass {
+    render_target: wgpu::Texture,
+    render_pipeline: wgpu::RenderPipeline, // Same shaders as the main pipeline, for the float format
+    feedback_present_pipeline: wgpu::RenderPipeline,
+    blit: Blit,
+    bind_group: wgpu::BindGroup,
+}
+
+// Moves the image shown by the vertices by whole pixels (right, down) on an output of the given size
+fn shift_vertices(vertices: [Vertex; 3], offset: (i32, i32), (width, height): (u32, u32)) -> [Vertex; 3] {
+    let dx = 2.0 * offset.0 as f32 / width as f32;
+    let dy = 2.0 * offset.1 as f32 / height as f32;
+    vertices.map(|vertex| {
+        let [u, v] = vertex.texture_coordinates;
+        Vertex::new(vertex.position[0] + dx, vertex.position[1] - dy, u, v)
+    })
+}
+
+// Vertex buffer which owns a copy of its vertices, so all geometry changes go through one place.
+// The vertex count is fixed when the buffer is allocated.
+struct VertexBuffer {
+    buffer: wgpu::Buffer,
+    vertices: Vec<Vertex>,
+}
+
+impl VertexBuffer {
+    // Allocates a buffer for the given number of vertices, vertices must be uploaded with update before drawing
+    fn new(device: &wgpu::Device, label: &str, vertex_count: usize) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (size_of::<Vertex>() * vertex_count) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self { buffer, vertices: vec![Vertex::default(); vertex_count] }
+    }
+
+    // Uploads new vertices to the GPU if they differ from the current ones
+    fn update(&mut self, queue: &wgpu::Queue, vertices: &[Vertex]) {
+        debug_assert_eq!(vertices.len(), self.vertices.len(), "Vertex count does not match the allocated vertex buffer size");
+        if vertices == self.vertices.as_slice() {
+            return;
+        }
+
+        queue.write_buffer(&self.buffer, 0, cast_slice(vertices));
+        self.vertices = vertices.to_vec();
+    }
 
+    fn vertex_count(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+}
+
+// A window the shader is shown in, every window has its own surface and is rendered at its own size
+struct WindowTarget {
+    id: winit::window::WindowId,
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    configured: bool, // False while the window has zero size, nothing is drawn to it until it is resized
+    render_target: Option<(wgpu::Texture, wgpu::BindGroup)>, // Keeps the previous frame for shaders which load instead of clear, or the downscaled frame
+}
+
+impl WindowTarget {
+    // Configures the surface of the window, with the given format or the best one it supports
+    fn new(device: &wgpu::Device, adapter: &wgpu::Adapter, surface: wgpu::Surface, window: &winit::window::Window, gpu_options: GpuOptions, format: Option<wgpu::TextureFormat>) -> Result<Self, String> {
+        // Get the physical size of the window
+        let physical_size = window.inner_size();
+
+        let swapchain_capabilities = surface.get_capabilities(adapter);
+        if swapchain_capabilities.formats.is_empty() {
+            return Err("The surface is not supported by the GPU".to_string());
+        }
+        let swapchain_format = match format {
+            Some(format) if swapchain_capabilities.formats.contains(&format) => format,
+            Some(format) => return Err(format!("The surface does not support the swapchain format {:?} (supported: {:?})", format, swapchain_capabilities.formats)),
+            None => {
+                let format = select_swapchain_format(&swapchain_capabilities.formats);
+                println!("Using swapchain format: {:?} (supported: {:?})", format, swapchain_capabilities.formats);
+                format
+            }
+        };
+        let present_mode = select_present_mode(gpu_options.present_mode, &swapchain_capabilities.present_modes);
+        if present_mode != gpu_options.present_mode {
+            println!("Warning: Present mode {:?} is not supported (supported: {:?}), using {:?}", gpu_options.present_mode, swapchain_capabilities.present_modes, present_mode);
+        }
+
+        // Create a surface configuration with the selected format and window size
+        let surface_size = clamp_texture_size((physical_size.width, physical_size.height), gpu_options.max_texture_size);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: swapchain_format,
+            width: surface_size.0,
+            height: surface_size.1,
+            present_mode,
+            alpha_mode: swapchain_capabilities.alpha_modes[0],
+            view_formats: Vec::new(),
+        };
+
+        // Apply the surface configuration to the surface, unless the window has no size yet
+        let configured = config.width > 0 && config.height > 0;
+        if configured {
+            surface.configure(device, &config);
+        } else {
+            println!("Window has zero size, deferring surface configuration until it is resized");
+        }
+
+        Ok(Self { id: window.id(), surface, config, configured, render_target: None })
+    }
+
+    fn aspect_ratio(&self) -> f32 {
+        self.config.width as f32 / self.config.height as f32
+    }
+}
 
 pub struct Renderer {
     use_window: bool,
-    use_st7789: bool,
 
-    surface: Option<wgpu::Surface>,
-    surface_config: Option<wgpu::SurfaceConfiguration>,
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    windows: Vec<WindowTarget>, // The first window is the one given to new, more are added with add_window
+    gpu_options: GpuOptions,
+    max_texture_size: Option<u32>,
 
-    #[cfg(target_os = "linux")]
-    st7789_driver: Option<crate::st7789_driver::RaspberryST7789Driver>,
-    st7789_render_target: Option<wgpu::Texture>,
-    st7789_render_buffer: Option<wgpu::Buffer>,
+    display_sinks: Vec<Box<dyn DisplaySink>>, // The ST7789 panels or other displays fed with frames rendered offscreen
+    display_layout: DisplayLayout,
+    offscreen_size: (u32, u32),
+    offscreen_render_target: Option<wgpu::Texture>,
+    offscreen_render_buffer: Option<wgpu::Buffer>,
 
     device: wgpu::Device,
     queue: wgpu::Queue,
-    uniforms: Uniforms,
+    global_uniforms: GlobalUniforms,
+    dynamic_uniforms: DynamicUniforms,
+    dynamic_uniforms_changed: bool,
+    mouse: [f32; 4], // Last mouse uniform in fractions of the window, scaled per pass into global_uniforms
     vertex_shader: wgpu::ShaderModule,
     fragment_shader: wgpu::ShaderModule,
     pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
-    uniform_buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
-    vertex_buffer: wgpu::Buffer,
+    feedback_shader: wgpu::ShaderModule,
+    feedback_render_pipeline: Option<wgpu::RenderPipeline>, // The shader for the feedback buffer format, set while it uses feedback
+    feedback_present_pipeline: wgpu::RenderPipeline, // Draws the frame a feedback shader rendered into its feedback buffer to the output
+    sanitize_pass: Option<SanitizePass>, // Set by --sanitize, used for the offscreen output
+    global_uniform_buffer: wgpu::Buffer,
+    global_bind_group: wgpu::BindGroup,
+    dynamic_uniform_buffer: wgpu::Buffer,
+    dynamic_bind_group: wgpu::BindGroup,
+    vertex_buffer: VertexBuffer,
+    st7789_vertex_buffer: VertexBuffer,
+    buffer_vertex_buffer: VertexBuffer, // Draws the buffer passes, never flipped or rotated
+    multipass: Multipass,
     output_format: wgpu::TextureFormat,
+    load_op: wgpu::LoadOp<wgpu::Color>,
+    flip_uv: bool, // Set by --flip-uv
+    shader_flip_uv: Option<bool>, // Set by the metadata of the current shader, overrides flip_uv
+    shader_target_fps: Option<f32>, // Set by the metadata of the current shader, overrides --max-fps
+    shader_duration: Option<f32>, // Set by the metadata of the current shader
+    shader_quarter_turns: u32, // Set by the metadata of the current shader, added to st7789_quarter_turns
+    user_params: UserParams, // Declared by the metadata of the current shader
+    blit: Blit,
+    raw_frame_texture: Option<(wgpu::Texture, wgpu::BindGroup)>, // Frame passed to present_raw, drawn to the window with the blit
+    render_scale: f32, // Fraction of the window size the shader is rendered at before being upscaled to the window
+    antiburn: Option<(AntiBurn, Instant)>, // Pixel shift and the time it started
+    antiburn_offset: (i32, i32), // Current pixel shift of both outputs
+    hud: Hud,
+    hud_visible: bool,
+    hud_on_st7789: bool,
+    shader_error: Option<String>,
+    error_overlay: bool,
+    st7789_frame_interval: Option<Duration>, // Minimum time between ST7789 frames while the window is also rendered
+    last_st7789_render: Option<Instant>,
+    color_conversion: ColorConversion,
+    st7789_timings: FrameTimings, // Collected on every ST7789 frame for the metrics, printed with DEBUG_OVERHEADS
+    st7789_brightness: f32, // Factor the ST7789 frames are dimmed by, lowered during the fade-out
+    brightness: f32, // Factor the ST7789 frames are dimmed by as chosen by the user, e.g. with buttons
+    fade_out: Option<Duration>,
+    st7789_quarter_turns: u32,
+    compile_options: ShaderCompileOptions,
+    shaders_path: PathBuf, // Directory with the uncompiled and compiled shaders, can be switched at runtime
 }
 
 impl Renderer {
-    pub fn new(
-        use_window: bool,
-        window: Option<&winit::window::Window>,
-        #[cfg(target_os = "linux")]
-        use_st7789: bool,
-        #[cfg(target_os = "linux")]
-        st7789_driver: Option<crate::st7789_driver::RaspberryST7789Driver>,
-    ) -> Self {
+    pub fn new(options: RendererOptions) -> Self {
+        let RendererOptions { window, offscreen: use_offscreen, display_sinks, gpu_options, compile_options, shader_name: first_shader_name } = options;
+        let use_window = window.is_some();
+
         // --- Create GPU resources for rendering ---
 
         // 1. Initialize wgpu  
-        let (device, queue, surface, surface_config, output_format) = match window {
-            Some(window) => initialize_wgpu_with_window(window),
-            None => initialize_wgpu_without_window(),
+        let max_texture_size = gpu_options.max_texture_size;
+        let (instance, adapter, device, queue, window, output_format) = match window {
+            Some(window) => initialize_wgpu_with_window(window, gpu_options),
+            None => initialize_wgpu_without_window(gpu_options),
         };
 
-        // 2. Create uniform buffer
-        let uniforms = Uniforms::new();
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[uniforms]),
+        // 2. Create uniform buffers (panel_resolution is the size of the first display)
+        let panel_resolution = display_sinks.first().map_or([0.0, 0.0], |sink| {
+            let (width, height) = sink.panel_size();
+            [width as f32, height as f32]
+        });
+        let global_uniforms = GlobalUniforms::new(output_format, panel_resolution);
+        let global_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Global Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[global_uniforms]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        // 3. Create a bind group layout for uniforms
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("uniform_bind_group_layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT, // or VERTEX if it's in the vertex shader
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
+        let dynamic_uniforms = DynamicUniforms::new();
+        let dynamic_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Dynamic Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[dynamic_uniforms]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        // 4. Create a bind group from the layout and uniform buffer
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: Some("uniform_bind_group"),
-        });
+        // 3. Create bind group layouts and bind groups for both uniform buffers
+        let (global_bind_group_layout, global_bind_group) = create_uniform_bind_group(&device, &global_uniform_buffer, "global_uniform");
+        let (dynamic_bind_group_layout, dynamic_bind_group) = create_uniform_bind_group(&device, &dynamic_uniform_buffer, "dynamic_uniform");
 
-        // 5. Define pipeline layout with uniform bindings
+        // 4. Define pipeline layout with uniform bindings (group 0 is global, group 1 is dynamic) and the channels (group 2)
+        let multipass = Multipass::new(&device, &queue);
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&global_bind_group_layout, &dynamic_bind_group_layout, multipass.bind_group_layout()],
             push_constant_ranges: &[],
         });
 
-        // 6. Compile and create shaders
-        compile_shader(SHADERS_PATH.clone().join("uncompiled").join("master.vert").clone(), COMPILED_VERTEX_SHADER_PATH.clone());
-        let vertex_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("master_vertex_shader"),
-            source: wgpu::util::make_spirv(&std::fs::read(COMPILED_VERTEX_SHADER_PATH.clone()).expect("Failed to read shader file")),
-        });
-
-        compile_shader(SHADERS_PATH.clone().join("uncompiled").join(SHADER_NAMES[0]).clone(), COMPILED_FRAGMENT_SHADER_PATH.clone());
-        let fragment_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("master_fragment_shader"),
-            source: wgpu::util::make_spirv(&std::fs::read(COMPILED_FRAGMENT_SHADER_PATH.clone()).expect("Failed to read shader file")),
-        });
+        // 5. Compile and create shaders, starting with the given one.
+        // There is no previous shader to fall back to yet so errors are fatal.
+        let vertex_shader = create_vertex_shader(&device, &SHADERS_PATH, compile_options).unwrap_or_else(|err| panic!("Shader compilation failed: {}", err));
+        let fragment_shader = create_fragment_shader(&device, &SHADERS_PATH, first_shader_name, None, compile_options).unwrap_or_else(|err| panic!("Shader compilation failed: {}", err));
 
-        // 7. Create a render pipeline using the shaders
+        // 6. Create a render pipeline using the shaders
         let render_pipeline = create_render_pipeline(&device, &pipeline_layout, &output_format, &vertex_shader, &fragment_shader);
-
-        // 8. Upload vertex buffer data
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: size_of::<Vertex>() as u64 * 6,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+        let feedback_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("feedback_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("feedback.wgsl").into()),
         });
-        queue.write_buffer(&vertex_buffer, 0, cast_slice(&*VERTICES));
-
-        // 9. Create offscreen texture for rendering (used by ST7789 to read pixels)
-        #[cfg(target_os = "linux")]
-        let (st7789_render_target, st7789_render_buffer) = if use_st7789 {
-                let output_image_size = wgpu::Extent3d {
-                    width: ST7789_OUTPUT_SIZE,
-                    height: ST7789_OUTPUT_SIZE,
-                    depth_or_array_layers: 1,
-                };
-
-                let output_image_texture = device.create_texture(&wgpu::TextureDescriptor {
-                    label: Some("Render Texture"),
-                    size: output_image_size,
-                    mip_level_count: 1,
-                    sample_count: 1,
-                    dimension: wgpu::TextureDimension::D2,
-                    format: output_format,
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-                    view_formats: &[],
-                });
-        
-            let data_size = (ST7789_OUTPUT_SIZE * ST7789_OUTPUT_SIZE * 4) as u64; // 4 bytes per pixel (RGBA)
-
-                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("Read Buffer"),
-                    size: data_size,
-                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-                    mapped_at_creation: false,
-                });
-        
-            (Some(output_image_texture), Some(buffer))
+        let feedback_present_pipeline = create_render_pipeline(&device, &pipeline_layout, &output_format, &vertex_shader, &feedback_shader);
+
+        // 7. Allocate vertex buffers (ST7789 gets its own buffer since it may need to be flipped),
+        // vertices are uploaded once the shader metadata is read
+        let vertex_buffer = VertexBuffer::new(&device, "Vertex Buffer", 3);
+
+        let st7789_vertex_buffer = VertexBuffer::new(&device, "ST7789 Vertex Buffer", 3);
+
+        // Buffers are drawn upside down, textures are sampled first row at v = 0 while outputs show the first row at the top,
+        // so sampling a buffer at a texture coordinate reads what was drawn at the same coordinate
+        let mut buffer_vertex_buffer = VertexBuffer::new(&device, "Buffer Vertex Buffer", 3);
+        buffer_vertex_buffer.update(&queue, &screen_vertices(true, false));
+
+        // 8. Create offscreen texture for rendering (used by ST7789 and single frame mode to read pixels)
+        // The first display can ask for another size than the default square, e.g. a framebuffer at the screen resolution
+        let use_offscreen = use_offscreen || !display_sinks.is_empty();
+        let offscreen_size = display_sinks.first().and_then(|sink| sink.render_size()).unwrap_or((ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE));
+        let offscreen_size = clamp_texture_size(offscreen_size, max_texture_size);
+        let (offscreen_render_target, offscreen_render_buffer) = if use_offscreen {
+            let (texture, buffer) = create_offscreen_render_target(&device, output_format, offscreen_size);
+            (Some(texture), Some(buffer))
         } else {
             (None, None)
         };
 
-        Self {
+        // 9. Create the HUD overlay, hidden until enabled
+        let hud = Hud::new(&device, &queue, output_format);
+
+        let blit = Blit::new(&device, output_format);
+
+        let mut renderer = Self {
             use_window,
-            use_st7789,
-            surface,
-            surface_config,
-            st7789_driver,
-            st7789_render_target,
-            st7789_render_buffer,
+            instance,
+            adapter,
+            windows: window.into_iter().collect(),
+            gpu_options,
+            max_texture_size,
+            display_sinks,
+            display_layout: DisplayLayout::Mirror,
+            offscreen_size,
+            offscreen_render_target,
+            offscreen_render_buffer,
             device,
             queue,
-            uniforms,
+            global_uniforms,
+            dynamic_uniforms,
+            dynamic_uniforms_changed: false,
+            mouse: [0.0; 4],
             vertex_shader,
             fragment_shader,
             pipeline_layout,
             render_pipeline,
-            uniform_buffer,
-            bind_group,
+            feedback_shader,
+            feedback_render_pipeline: None,
+            feedback_present_pipeline,
+            sanitize_pass: None,
+            global_uniform_buffer,
+            global_bind_group,
+            dynamic_uniform_buffer,
+            dynamic_bind_group,
             vertex_buffer,
+            st7789_vertex_buffer,
+            buffer_vertex_buffer,
+            multipass,
             output_format,
+            load_op: ShaderMetadata::default().load_op(),
+            flip_uv: false,
+            shader_flip_uv: None,
+            shader_target_fps: None,
+            shader_duration: None,
+            shader_quarter_turns: 0,
+            user_params: UserParams::default(),
+            blit,
+            raw_frame_texture: None,
+            render_scale: 1.0,
+            antiburn: None,
+            antiburn_offset: (0, 0),
+            hud,
+            hud_visible: false,
+            hud_on_st7789: false,
+            shader_error: None,
+            error_overlay: true,
+            st7789_frame_interval: None,
+            last_st7789_render: None,
+            color_conversion: ColorConversion::default(),
+            st7789_timings: FrameTimings::default(),
+            st7789_brightness: 1.0,
+            brightness: 1.0,
+            fade_out: None,
+            st7789_quarter_turns: 0,
+            compile_options,
+            shaders_path: SHADERS_PATH.clone(),
+        };
+
+        // 10. Apply the metadata of the first shader and upload the vertices
+        renderer.reload_shader_metadata(first_shader_name);
+        renderer
+    }
+
+    // The mouse is given in fractions of the window (see mouse.rs) and scaled to the pixels of every render pass
+    pub fn update_uniforms(&mut self, elapsed_time: f32, real_time: f32, bluetooth_data: [f32; 3], mouse: [f32; 4]) {
+        self.global_uniforms.time = elapsed_time;
+        self.global_uniforms.real_time = real_time;
+        self.mouse = mouse;
+        self.global_uniforms.mouse = mouse_in_pixels(mouse, self.global_uniforms.resolution);
+        if bluetooth_data != self.dynamic_uniforms.bluetooth_data {
+            self.dynamic_uniforms.bluetooth_data = bluetooth_data;
+            self.dynamic_uniforms_changed = true;
+        }
+        // Assign screen aspect ratio, calculate it from the first window if rendering to windows
+        self.global_uniforms.screen_aspect_ratio = self.first_window_aspect_ratio();
+
+        // Write updated global uniforms to their uniform buffer
+        self.queue.write_buffer(&self.global_uniform_buffer, 0, bytemuck::cast_slice(&[self.global_uniforms]));
+
+        // Write dynamic uniforms only if they changed
+        if self.dynamic_uniforms_changed {
+            self.dynamic_uniforms_changed = false;
+            self.queue.write_buffer(&self.dynamic_uniform_buffer, 0, bytemuck::cast_slice(&[self.dynamic_uniforms]));
         }
     }
 
-    pub fn update_uniforms(&mut self, elapsed_time: f32, bluetooth_data: String) {
-        self.uniforms.time = elapsed_time;
-        // Parse and assign bluetooth data into a 3-element array
-        self.uniforms.bluetooth_data = if bluetooth_data.trim().is_empty() {
-            [0.0, 0.0, 0.0]
-        } else {
-            bluetooth_data.split(',').map(|s| {
-                    let v: f32 = s.split(':').nth(1).unwrap().trim().parse().unwrap();
-                    (v.clamp(-10.0, 10.0)) / 10.0
-                }).collect::<Vec<_>>().try_into().unwrap()
-        };
-        // Assign screen aspect ratio, calculate it if rendering to window
-        self.uniforms.screen_aspect_ratio = if self.use_window {
-            self.surface_config.as_ref().unwrap().width as f32 / self.surface_config.as_ref().unwrap().height as f32
-        } else {
-            1.0
+    // Connection state of the remote inputs, uploaded with the next update_uniforms if it changed
+    pub fn set_remote_input_state(&mut self, clients_connected: u32, seconds_since_last_message: Option<f32>) {
+        let seconds_since_last_message = seconds_since_last_message.unwrap_or(-1.0);
+        if (clients_connected, seconds_since_last_message) != (self.dynamic_uniforms.clients_connected, self.dynamic_uniforms.seconds_since_last_message) {
+            self.dynamic_uniforms.clients_connected = clients_connected;
+            self.dynamic_uniforms.seconds_since_last_message = seconds_since_last_message;
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    // Returns the current uniform values as pretty printed JSON
+    pub fn uniforms_json(&self) -> String {
+        let snapshot = UniformsSnapshot {
+            time: self.global_uniforms.time,
+            screen_aspect_ratio: self.global_uniforms.screen_aspect_ratio,
+            output_srgb: self.global_uniforms.output_srgb,
+            panel_resolution: self.global_uniforms.panel_resolution,
+            real_time: self.global_uniforms.real_time,
+            output_kind: self.global_uniforms.output_kind,
+            resolution: self.global_uniforms.resolution,
+            mouse: self.global_uniforms.mouse,
+            bluetooth_data: self.dynamic_uniforms.bluetooth_data,
+            keys: self.dynamic_uniforms.keys,
+            gravity: self.dynamic_uniforms.gravity,
+            user_params: self.dynamic_uniforms.user_params,
+            angular_velocity: self.dynamic_uniforms.angular_velocity,
+            orientation: self.dynamic_uniforms.orientation,
+            cpu_usage: self.dynamic_uniforms.cpu_usage,
+            memory_usage: self.dynamic_uniforms.memory_usage,
+            soc_temperature: self.dynamic_uniforms.soc_temperature,
+            ambient_brightness: self.dynamic_uniforms.ambient_brightness,
+            loudness: self.dynamic_uniforms.loudness,
+            beat: self.dynamic_uniforms.beat,
+            clients_connected: self.dynamic_uniforms.clients_connected,
+            seconds_since_last_message: self.dynamic_uniforms.seconds_since_last_message,
         };
+        serde_json::to_string_pretty(&snapshot).expect("Failed to serialize uniforms")
+    }
 
-        // Write updated uniforms to the uniform buffer
-        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+    // Sets or clears the given bit of the keys uniform
+    pub fn set_key_pressed(&mut self, bit: u32, pressed: bool) {
+        let mask = 1 << (bit % 32);
+        let keys = &mut self.dynamic_uniforms.keys[(bit / 32) as usize];
+        let new_keys = if pressed { *keys | mask } else { *keys & !mask };
+        if new_keys != *keys {
+            *keys = new_keys;
+            self.dynamic_uniforms_changed = true;
+        }
     }
 
     pub fn recompile_shaders(
         &mut self,
-        shader_index: usize,
+        shader_name: &str,
         recompile_vertex_shader: bool,
         recompile_fragment_shader: bool,
     ) {
-        if recompile_vertex_shader {
-            compile_shader(
-                SHADERS_PATH.join("uncompiled").join("master.vert").clone(),
-                COMPILED_VERTEX_SHADER_PATH.clone(),
-            );
-            self.vertex_shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("vertex_shader"),
-                source: wgpu::util::make_spirv(&fs::read(COMPILED_VERTEX_SHADER_PATH.clone()).expect("Failed to read vertex shader")),
-            });
-        }
+        let result = self.try_recompile_shaders(shader_name, recompile_vertex_shader, recompile_fragment_shader, None);
+        self.apply_compile_result(result);
+    }
 
-        if recompile_fragment_shader {
-            compile_shader(
-                SHADERS_PATH.join("uncompiled").join(SHADER_NAMES[shader_index]).clone(),
-                COMPILED_FRAGMENT_SHADER_PATH.clone(),
-            );
-            self.fragment_shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("fragment_shader"),
-                source: wgpu::util::make_spirv(&fs::read(COMPILED_FRAGMENT_SHADER_PATH.clone()).expect("Failed to read fragment shader")),
-            });
+    // Compiles a fragment shader from source instead of its file, e.g. piped to stdin. The name tells GLSL from WGSL,
+    // includes are looked up in the shaders directory as for shader files.
+    pub fn compile_fragment_source(&mut self, shader_name: &str, source: &str) {
+        let result = self.try_recompile_shaders(shader_name, false, true, Some(source));
+        self.apply_compile_result(result);
+    }
+
+    fn apply_compile_result(&mut self, result: Result<(), String>) {
+        // A failed compile keeps the previous shaders running, so a typo while live coding does not stop the display
+        match result {
+            Ok(()) => {
+                if self.shader_error.take().is_some() {
+                    println!("Shader error fixed");
+                }
+            }
+            Err(err) => {
+                println!("Warning: Shader compilation failed, keeping the previous shaders:\n{}", err);
+                self.shader_error = Some(err);
+            }
         }
+    }
+
+    // Replaces the shaders and the pipelines only if all of them are created successfully
+    fn try_recompile_shaders(&mut self, shader_name: &str, recompile_vertex_shader: bool, recompile_fragment_shader: bool, fragment_source: Option<&str>) -> Result<(), String> {
+        let vertex_shader = if recompile_vertex_shader { Some(create_vertex_shader(&self.device, &self.shaders_path, self.compile_options)?) } else { None };
+        let fragment_shader = if recompile_fragment_shader { Some(create_fragment_shader(&self.device, &self.shaders_path, shader_name, fragment_source, self.compile_options)?) } else { None };
+
+        // The buffer passes are defined by the metadata of a new fragment shader, otherwise the current ones are rebuilt
+        let metadata = fragment_shader.as_ref().map(|_| ShaderMetadata::load(&self.shaders_path.join("uncompiled").join(shader_name)));
+        let buffers = match &metadata {
+            Some(metadata) => metadata.buffers.clone(),
+            None => self.multipass.passes().iter().map(|pass| pass.metadata.clone()).collect(),
+        };
+        let buffer_passes = self.create_buffer_passes(&buffers, vertex_shader.as_ref().unwrap_or(&self.vertex_shader))?;
+        let feedback = metadata.as_ref().map_or(self.multipass.feedback(), |metadata| metadata.feedback);
 
-        self.render_pipeline = create_render_pipeline(
+        // Catch pipeline errors such as uniform blocks which do not match the bind group layouts
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipeline = create_render_pipeline(
             &self.device,
             &self.pipeline_layout,
             &self.output_format,
-            &self.vertex_shader,
-            &self.fragment_shader,
+            vertex_shader.as_ref().unwrap_or(&self.vertex_shader),
+            fragment_shader.as_ref().unwrap_or(&self.fragment_shader),
         );
-    }   
+        let sanitize_pipeline = self.sanitize_pass.as_ref().map(|_| create_render_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &SANITIZE_FORMAT,
+            vertex_shader.as_ref().unwrap_or(&self.vertex_shader),
+            fragment_shader.as_ref().unwrap_or(&self.fragment_shader),
+        ));
+        let feedback_render_pipeline = feedback.then(|| create_render_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &multipass::BUFFER_FORMAT,
+            vertex_shader.as_ref().unwrap_or(&self.vertex_shader),
+            fragment_shader.as_ref().unwrap_or(&self.fragment_shader),
+        ));
+        if let Some(err) = block_on(self.device.pop_error_scope()) {
+            return Err(err.to_string());
+        }
+
+        if let (Some(sanitize_pass), Some(sanitize_pipeline)) = (&mut self.sanitize_pass, sanitize_pipeline) {
+            sanitize_pass.render_pipeline = sanitize_pipeline;
+        }
+        if let Some(vertex_shader) = vertex_shader {
+            self.feedback_present_pipeline = create_render_pipeline(&self.device, &self.pipeline_layout, &self.output_format, &vertex_shader, &self.feedback_shader);
+            if let Some(sanitize_pass) = &mut self.sanitize_pass {
+                sanitize_pass.feedback_present_pipeline = create_render_pipeline(&self.device, &self.pipeline_layout, &SANITIZE_FORMAT, &vertex_shader, &self.feedback_shader);
+            }
+            self.vertex_shader = vertex_shader;
+        }
+        if let Some(fragment_shader) = fragment_shader {
+            self.fragment_shader = fragment_shader;
+        }
+        self.render_pipeline = render_pipeline;
+        self.feedback_render_pipeline = feedback_render_pipeline;
+        let channels = metadata.as_ref().map_or_else(|| self.multipass.channels().to_vec(), |metadata| metadata.channels.clone());
+        self.multipass.set_passes(buffer_passes, channels, feedback);
+        self.multipass.load_images(&self.device, &self.queue, &self.textures_path(), self.output_format.is_srgb());
+        if let Some(metadata) = metadata {
+            self.apply_shader_metadata(&metadata);
+        }
+        Ok(())
+    }
+
+    // Compiles the shaders of the buffer passes and creates their pipelines, which render into float buffers
+    fn create_buffer_passes(&self, buffers: &[BufferMetadata], vertex_shader: &wgpu::ShaderModule) -> Result<Vec<BufferPass>, String> {
+        buffers.iter().map(|buffer| {
+            let fragment_shader = create_fragment_shader(&self.device, &self.shaders_path, &buffer.shader, None, self.compile_options)?;
+            self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let render_pipeline = create_render_pipeline(&self.device, &self.pipeline_layout, &multipass::BUFFER_FORMAT, vertex_shader, &fragment_shader);
+            if let Some(err) = block_on(self.device.pop_error_scope()) {
+                return Err(format!("{}: {}", buffer.shader, err));
+            }
+            Ok(BufferPass { metadata: buffer.clone(), render_pipeline })
+        }).collect()
+    }
+
+    // Frame rate limit from the metadata of the current shader
+    pub fn shader_target_fps(&self) -> Option<f32> {
+        self.shader_target_fps
+    }
+
+    // Seconds the current shader is shown before the playlist advances, from its metadata
+    pub fn shader_duration(&self) -> Option<f32> {
+        self.shader_duration
+    }
+
+    // Compile error of the last shader change, cleared once shaders compile again
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    // Shows the compile error over the output, on by default
+    pub fn set_error_overlay(&mut self, error_overlay: bool) {
+        self.error_overlay = error_overlay;
+    }
+
+    fn error_overlay_visible(&self) -> bool {
+        self.error_overlay && self.shader_error.is_some()
+    }
+
+    // Switches to another shaders directory with the same layout (uncompiled and compiled subdirectories).
+    // The current shaders keep running until the caller recompiles them from the new directory.
+    pub fn set_shaders_path(&mut self, shaders_path: PathBuf) {
+        if let Err(err) = fs::create_dir_all(shaders_path.join("compiled")) {
+            println!("Warning: Failed to create the compiled shaders directory: {}", err);
+        }
+        self.shaders_path = shaders_path;
+    }
+
+    // Feeds the audio channel with the latest analysis of the captured audio
+    pub fn set_audio(&mut self, spectrum: &[u8; audio::BIN_COUNT], waveform: &[u8; audio::BIN_COUNT], loudness: f32, beat: f32) {
+        self.multipass.write_audio(&self.queue, spectrum, waveform);
+        if (loudness, beat) != (self.dynamic_uniforms.loudness, self.dynamic_uniforms.beat) {
+            self.dynamic_uniforms.loudness = loudness;
+            self.dynamic_uniforms.beat = beat;
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    // Shows a camera frame (RGBA8888, first row at the top) on the camera channel
+    pub fn set_camera(&mut self, rgba: &[u8], size: (u32, u32)) {
+        self.multipass.write_camera(&self.device, &self.queue, rgba, size, self.output_format.is_srgb());
+    }
+
+    // Updates the keyboard channel with the texels of the key states
+    pub fn set_keyboard(&mut self, texels: &[u8]) {
+        self.multipass.write_keyboard(&self.queue, texels);
+    }
+
+    // Images for the channels are read from the textures directory next to the shaders directory (res/textures)
+    fn textures_path(&self) -> PathBuf {
+        self.shaders_path.parent().map_or_else(|| PathBuf::from("textures"), |path| path.join("textures"))
+    }
+
+    // Reads the sidecar metadata of the shader and applies it to the following frames. If the shaders of its buffer passes
+    // fail to compile, the previous buffer passes are kept like the previous shaders are on a failed compile.
+    pub fn reload_shader_metadata(&mut self, shader_name: &str) {
+        let metadata = ShaderMetadata::load(&self.shaders_path.join("uncompiled").join(shader_name));
+        match self.create_buffer_passes(&metadata.buffers, &self.vertex_shader) {
+            Ok(buffer_passes) => {
+                self.feedback_render_pipeline = metadata.feedback.then(|| create_render_pipeline(
+                    &self.device,
+                    &self.pipeline_layout,
+                    &multipass::BUFFER_FORMAT,
+                    &self.vertex_shader,
+                    &self.fragment_shader,
+                ));
+                self.multipass.set_passes(buffer_passes, metadata.channels.clone(), metadata.feedback);
+                self.multipass.load_images(&self.device, &self.queue, &self.textures_path(), self.output_format.is_srgb());
+            }
+            Err(err) => {
+                println!("Warning: Buffer shader compilation failed, keeping the previous buffer passes:\n{}", err);
+                self.shader_error = Some(err);
+            }
+        }
+        self.apply_shader_metadata(&metadata);
+    }
+
+    fn apply_shader_metadata(&mut self, metadata: &ShaderMetadata) {
+        self.load_op = metadata.load_op();
+        self.shader_flip_uv = metadata.flip_uv;
+        self.shader_target_fps = metadata.target_fps;
+        self.shader_duration = metadata.duration;
+        self.shader_quarter_turns = metadata.quarter_turns();
+        self.user_params.declare(&metadata.params);
+        self.update_user_params();
+        self.windows.iter_mut().for_each(|window| window.render_target = None);
+        self.update_vertex_buffers();
+    }
+
+    // Returns true if the current shader renders the given shader file in one of its buffer passes
+    pub fn uses_buffer_shader(&self, shader_name: &str) -> bool {
+        self.multipass.uses_shader(shader_name)
+    }
+
+    // Sets the named param of the current shader, clamped to its range, returns the value it was set to
+    pub fn set_user_param(&mut self, name: &str, value: f32) -> Result<f32, String> {
+        let value = self.user_params.set(name, value)?;
+        self.update_user_params();
+        Ok(value)
+    }
+
+    // Selects the next param of the current shader to be changed by step_user_param, returns its name
+    pub fn select_next_user_param(&mut self) -> Option<String> {
+        self.user_params.select_next().map(str::to_string)
+    }
+
+    // Changes the selected param of the current shader by the given number of steps, returns its name and value
+    pub fn step_user_param(&mut self, steps: f32) -> Option<(String, f32)> {
+        let (name, value) = self.user_params.step_selected(steps).map(|(name, value)| (name.to_string(), value))?;
+        self.update_user_params();
+        Some((name, value))
+    }
+
+    // Changes the named param of the current shader by the given number of steps, returns its new value
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn step_named_user_param(&mut self, name: &str, steps: f32) -> Result<f32, String> {
+        let value = self.user_params.step_named(name, steps)?;
+        self.update_user_params();
+        Ok(value)
+    }
+
+    fn update_user_params(&mut self) {
+        if self.user_params.values() != self.dynamic_uniforms.user_params {
+            self.dynamic_uniforms.user_params = self.user_params.values();
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    #[cfg_attr(not(all(target_os = "linux", feature = "accel")), allow(dead_code))]
+    pub fn set_gravity(&mut self, gravity: [f32; 3]) {
+        if gravity != self.dynamic_uniforms.gravity {
+            self.dynamic_uniforms.gravity = gravity;
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    #[cfg_attr(not(all(target_os = "linux", feature = "accel")), allow(dead_code))]
+    pub fn set_motion(&mut self, angular_velocity: [f32; 3], orientation: [f32; 4]) {
+        if angular_velocity != self.dynamic_uniforms.angular_velocity || orientation != self.dynamic_uniforms.orientation {
+            self.dynamic_uniforms.angular_velocity = angular_velocity;
+            self.dynamic_uniforms.orientation = orientation;
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    pub fn set_system_stats(&mut self, stats: SystemStats) {
+        let uniforms = &mut self.dynamic_uniforms;
+        if [stats.cpu_usage, stats.memory_usage, stats.soc_temperature] != [uniforms.cpu_usage, uniforms.memory_usage, uniforms.soc_temperature] {
+            uniforms.cpu_usage = stats.cpu_usage;
+            uniforms.memory_usage = stats.memory_usage;
+            uniforms.soc_temperature = stats.soc_temperature;
+            self.dynamic_uniforms_changed = true;
+        }
+    }
+
+    // Rotates the image on the ST7789 clockwise by the given number of quarter turns, e.g. to follow the device orientation
+    #[cfg_attr(not(all(target_os = "linux", feature = "accel")), allow(dead_code))]
+    pub fn set_st7789_rotation(&mut self, quarter_turns: u32) {
+        if quarter_turns % 4 != self.st7789_quarter_turns {
+            self.st7789_quarter_turns = quarter_turns % 4;
+            self.update_vertex_buffers();
+        }
+    }
+
+    // Fades the ST7789 to black over the given time on shutdown, then turns off its backlight
+    pub fn set_fade_out(&mut self, fade_out: Option<Duration>) {
+        self.fade_out = fade_out;
+    }
+
+    // Chooses whether several displays all show the whole frame or each show a part of it
+    pub fn set_display_layout(&mut self, display_layout: DisplayLayout) {
+        self.display_layout = display_layout;
+    }
+
+    // Puts (0,0) of the texture coordinates at the top-left instead of the bottom-left for shaders without their own flip_uv metadata
+    pub fn set_flip_uv(&mut self, flip_uv: bool) {
+        self.flip_uv = flip_uv;
+        self.update_vertex_buffers();
+    }
+
+    // Replaces NaN and infinite shader output with valid colors before frames are read back for the ST7789 or saved,
+    // so buggy shaders can not send undefined pixels to the panel. Costs an extra pass per frame.
+    pub fn set_sanitize(&mut self, sanitize: bool) {
+        self.sanitize_pass = None;
+        let Some(offscreen_render_target) = self.offscreen_render_target.as_ref().filter(|_| sanitize) else {
+            return;
+        };
+
+        let render_target = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sanitize Texture"),
+            size: offscreen_render_target.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SANITIZE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let render_pipeline = create_render_pipeline(&self.device, &self.pipeline_layout, &SANITIZE_FORMAT, &self.vertex_shader, &self.fragment_shader);
+        let feedback_present_pipeline = create_render_pipeline(&self.device, &self.pipeline_layout, &SANITIZE_FORMAT, &self.vertex_shader, &self.feedback_shader);
+        let blit = Blit::sanitizing(&self.device, self.output_format);
+        let bind_group = blit.create_bind_group(&self.device, &render_target);
+        self.sanitize_pass = Some(SanitizePass { render_target, render_pipeline, feedback_present_pipeline, blit, bind_group });
+    }
+
+    // Slowly moves the output around by a few pixels so always-on panels do not burn in
+    pub fn set_antiburn(&mut self, antiburn: Option<AntiBurn>) {
+        self.antiburn = antiburn.map(|antiburn| (antiburn, Instant::now()));
+        self.antiburn_offset = (0, 0);
+        self.update_vertex_buffers();
+    }
+
+    fn update_antiburn_offset(&mut self) {
+        if let Some((antiburn, start)) = &self.antiburn {
+            let offset = antiburn.offset(start.elapsed());
+            if offset != self.antiburn_offset {
+                self.antiburn_offset = offset;
+                self.update_vertex_buffers();
+            }
+        }
+    }
+
+    fn update_vertex_buffers(&mut self) {
+        let flip_uv = self.shader_flip_uv.unwrap_or(self.flip_uv);
+        let mut vertices = screen_vertices(false, flip_uv);
+        // The shift is in pixels of the first window, other windows shift by the same fraction of their size
+        if let Some(window) = self.windows.first() {
+            vertices = shift_vertices(vertices, self.antiburn_offset, (window.config.width, window.config.height));
+        }
+        self.vertex_buffer.update(&self.queue, &vertices);
+
+        let mut st7789_vertices = rotate_texture_coordinates(screen_vertices(false, flip_uv), self.st7789_quarter_turns + self.shader_quarter_turns);
+        if let Some(render_target) = &self.offscreen_render_target {
+            st7789_vertices = shift_vertices(st7789_vertices, self.antiburn_offset, (render_target.width(), render_target.height()));
+        }
+        self.st7789_vertex_buffer.update(&self.queue, &st7789_vertices);
+    }
+
+    // Renders the window at a fraction of its size and upscales it, trading sharpness for framerate on weak GPUs
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(MIN_RENDER_SCALE, 1.0);
+        self.windows.iter_mut().for_each(|window| window.render_target = None);
+        println!("Render scale: {}", self.render_scale);
+    }
+
+    // Shows the shader in one more window too, e.g. on another monitor. The window must outlive the renderer.
+    // Fails if the GPU can not present to the window in the format the shaders are built for.
+    pub fn add_window(&mut self, window: &winit::window::Window) -> Result<(), String> {
+        let surface = unsafe { self.instance.create_surface(window) }.map_err(|err| err.to_string())?;
+        let window_target = WindowTarget::new(&self.device, &self.adapter, surface, window, self.gpu_options, Some(self.output_format))?;
+        self.windows.push(window_target);
+        Ok(())
+    }
+
+    // Physical size of the window's surface, None if it is not one of the renderer's windows
+    pub fn window_size(&self, window_id: winit::window::WindowId) -> Option<(u32, u32)> {
+        self.windows.iter().find(|window| window.id == window_id).map(|window| (window.config.width, window.config.height))
+    }
+
+    fn first_window_aspect_ratio(&self) -> f32 {
+        match self.windows.first() {
+            Some(window) if self.use_window && window.configured => window.aspect_ratio(),
+            _ => 1.0,
+        }
+    }
+
+    // Sets screen_aspect_ratio for the next render pass, only uploading the uniforms when it changes
+    fn set_screen_aspect_ratio(&mut self, screen_aspect_ratio: f32) {
+        if self.global_uniforms.screen_aspect_ratio != screen_aspect_ratio {
+            self.global_uniforms.screen_aspect_ratio = screen_aspect_ratio;
+            self.queue.write_buffer(&self.global_uniform_buffer, 0, bytemuck::cast_slice(&[self.global_uniforms]));
+        }
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // Dims the frames sent to the displays, from 0 (black) to 1 (as rendered). The window is not affected.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness.clamp(0.0, 1.0);
+        println!("Display brightness: {:.0}%", self.brightness * 100.0);
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    // Dims the backlight of the displays for the ambient light and passes the level to shaders as ambient_brightness.
+    // Small changes are skipped so the backlight PWM is not restarted every frame.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn set_ambient_brightness(&mut self, level: f32) {
+        if (level - self.dynamic_uniforms.ambient_brightness).abs() < AMBIENT_BRIGHTNESS_MIN_CHANGE {
+            return;
+        }
+        self.dynamic_uniforms.ambient_brightness = level;
+        self.dynamic_uniforms_changed = true;
+        for display_sink in self.display_sinks.iter_mut() {
+            if let Err(err) = display_sink.set_backlight_level(level) {
+                println!("Warning: Failed to dim backlight: {}", err);
+            }
+        }
+    }
+
+    pub fn set_color_conversion(&mut self, color_conversion: ColorConversion) {
+        self.color_conversion = color_conversion;
+    }
+
+    pub fn set_hud_visible(&mut self, visible: bool) {
+        self.hud_visible = visible;
+    }
+
+    // Draws the HUD also on the ST7789 display, otherwise it is only shown in the window
+    pub fn set_hud_on_st7789(&mut self, on_st7789: bool) {
+        self.hud_on_st7789 = on_st7789;
+    }
+
+    pub fn toggle_hud(&mut self) {
+        self.hud_visible = !self.hud_visible;
+        println!("HUD {}", if self.hud_visible { "shown" } else { "hidden" });
+    }
+
+    pub fn set_hud_text(&mut self, text: &str) {
+        self.hud.set_text(text);
+    }
+
+    // Limits how often the ST7789 is rendered while the window is also rendered, so the slow readback
+    // and transfer do not hold back the window. Without a window the ST7789 is rendered every frame.
+    pub fn set_st7789_max_fps(&mut self, max_fps: Option<f32>) {
+        self.st7789_frame_interval = max_fps.map(|fps| Duration::from_secs_f32(1.0 / fps));
+    }
 
     pub fn render(
         &mut self
     ) {
+        self.update_antiburn_offset();
+
+        // The shader error replaces the HUD text until it is fixed
+        if let Some(shader_error) = self.shader_error.as_deref().filter(|_| self.error_overlay) {
+            self.hud.set_text(&error_overlay_text(shader_error));
+        }
+
         if self.use_window {
             // Render to the window if enabled
             self.render_to_window();
         }
 
-        #[cfg(target_os = "linux")]
-        if self.use_st7789 {
-            // Render to the ST7789 display if enabled
+        if !self.display_sinks.is_empty() && self.st7789_render_due() {
+            // Render to the ST7789 displays if enabled
+            self.last_st7789_render = Some(Instant::now());
             self.render_to_st7789();
         }
     }
 
+    // Shows a frame drawn by the caller instead of the shader output, for programs which render on the CPU (e.g. a clock).
+    // The RGBA8888 pixels (first row at the top) are stretched over the window and sent to the ST7789 as they are,
+    // so they should match the panel size. The HUD and the shader are skipped.
+    #[allow(dead_code)] // Not used by the player itself
+    pub fn present_raw(&mut self, rgba: &[u8], width: u32, height: u32) {
+        assert_eq!(rgba.len(), (width * height * 4) as usize, "present_raw expects width * height RGBA8888 pixels");
+
+        if self.use_window {
+            self.present_raw_to_window(rgba, width, height);
+        }
+
+        if !self.display_sinks.is_empty() && self.st7789_render_due() {
+            self.last_st7789_render = Some(Instant::now());
+            self.present_raw_to_st7789(rgba, width, height);
+        }
+    }
+
+    // Waits for in-flight GPU work and clears the display, so the GPU and the panel are left in a clean state for the next run.
+    // The readback buffer needs no unmapping since it is unmapped right after every read.
+    pub fn shutdown(&mut self) {
+        println!("Shutting down renderer");
+        self.device.poll(wgpu::Maintain::Wait);
+
+        // Keep rendering the last frame darker and darker until it is black
+        if let Some(fade_out) = self.fade_out.filter(|_| !self.display_sinks.is_empty()) {
+            println!("Fading out the display");
+            let fade_start = Instant::now();
+            while fade_start.elapsed() < fade_out {
+                self.st7789_brightness = 1.0 - fade_start.elapsed().as_secs_f32() / fade_out.as_secs_f32();
+                self.render_to_st7789();
+            }
+        }
+
+        if let Some(render_target) = &self.offscreen_render_target {
+            let size = render_target.size();
+            let black_rgb565_bytes = vec![0u8; (size.width * size.height * 2) as usize];
+            for display_sink in self.display_sinks.iter_mut() {
+                if let Err(err) = display_sink.present(&black_rgb565_bytes, size.width, size.height) {
+                    println!("Warning: Failed to clear display: {}", err);
+                }
+                if self.fade_out.is_some() {
+                    if let Err(err) = display_sink.set_backlight(false) {
+                        println!("Warning: Failed to turn off backlight: {}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    // Both outputs share the uniform buffer, so the output kind is written before each output's pass is submitted.
+    // The buffer passes render at the size of the output, their buffers are created for it on first use.
+    fn prepare_output(&mut self, output_kind: u32, (width, height): (u32, u32)) {
+        self.multipass.prepare(&self.device, (width, height));
+        let resolution = [width as f32, height as f32];
+        if self.global_uniforms.output_kind != output_kind || self.global_uniforms.resolution != resolution {
+            self.global_uniforms.output_kind = output_kind;
+            self.global_uniforms.resolution = resolution;
+            self.global_uniforms.mouse = mouse_in_pixels(self.mouse, resolution);
+            self.queue.write_buffer(&self.global_uniform_buffer, 0, bytemuck::cast_slice(&[self.global_uniforms]));
+        }
+    }
+
+    // Stage durations of the last frame sent to the ST7789. Without DEBUG_OVERHEADS the GPU is not waited for
+    // after submitting, so the GPU time is counted in the readback time instead of the render time.
+    pub fn st7789_timings(&self) -> FrameTimings {
+        self.st7789_timings
+    }
+
+    fn st7789_render_due(&self) -> bool {
+        match (self.use_window, self.st7789_frame_interval, self.last_st7789_render) {
+            (true, Some(interval), Some(last_render)) => last_render.elapsed() >= interval,
+            _ => true,
+        }
+    }
+
+    // Renders every window at its own size, each sees its own aspect ratio while the other outputs keep the first window's
     fn render_to_window(
-        &self,
+        &mut self,
     ) {
+        for index in 0..self.windows.len() {
+            if self.windows[index].configured {
+                self.set_screen_aspect_ratio(self.windows[index].aspect_ratio());
+                self.render_to_window_target(index);
+            }
+        }
+        self.set_screen_aspect_ratio(self.first_window_aspect_ratio());
+    }
+
+    fn render_to_window_target(&mut self, index: usize) {
+        let window_size = (self.windows[index].config.width, self.windows[index].config.height);
+        let render_size = self.window_render_size(window_size);
+        self.prepare_output(OUTPUT_KIND_WINDOW, render_size);
+
         // Get the next texture from the swapchain
-        let frame = self.surface.as_ref().unwrap().get_current_texture().expect("Failed to get next swapchain texture");
+        let frame = self.windows[index].surface.get_current_texture().expect("Failed to get next swapchain texture");
 
         // Create a texture view for the frame
         let texture_view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -341,25 +1217,27 @@ impl Renderer {
         // Create a command encoder to record the rendering commands
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Window Render Encoder") });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+        // Swapchain textures rotate between frames, so shaders which keep the previous frame
+        // render into a persistent texture which is then drawn to the window.
+        // The same texture is used to render at a reduced scale, it is upscaled when drawn to the window.
+        if self.load_op == wgpu::LoadOp::Load || self.render_scale < 1.0 {
+            if self.windows[index].render_target.is_none() {
+                let texture = self.create_window_render_target(render_size);
+                let bind_group = self.blit.create_bind_group(&self.device, &texture);
+                self.windows[index].render_target = Some((texture, bind_group));
+            }
+            let (render_target, bind_group) = self.windows[index].render_target.as_ref().unwrap();
+            let render_target_view = render_target.create_view(&wgpu::TextureViewDescriptor::default());
+            self.encode_shader_pass(&mut encoder, &render_target_view, &self.vertex_buffer, render_size);
+            self.blit.encode_pass(&mut encoder, bind_group, &texture_view);
+        } else {
+            self.encode_shader_pass(&mut encoder, &texture_view, &self.vertex_buffer, render_size);
+        }
 
-           // Set the render pipeline and bind group, then draw the vertices
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
+        // Draw the HUD over the shader output
+        if self.hud_visible || self.error_overlay_visible() {
+            self.hud.prepare(&self.queue, window_size.0, window_size.1);
+            self.hud.encode_pass(&mut encoder, &texture_view);
         }
 
         // Submit the command encoder to the queue
@@ -369,36 +1247,162 @@ impl Renderer {
         frame.present();
     }
 
+    fn present_raw_to_window(&mut self, rgba: &[u8], width: u32, height: u32) {
+        if !self.windows.iter().any(|window| window.configured) {
+            return;
+        }
+
+        // The texture is kept between frames and only recreated when the frame size changes
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        if self.raw_frame_texture.as_ref().is_none_or(|(texture, _)| texture.size() != size) {
+            // Pixels are stored as sRGB like in images, so they are written back unchanged to sRGB and linear surfaces alike
+            let format = if self.output_format.is_srgb() { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Raw Frame Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let bind_group = self.blit.create_bind_group(&self.device, &texture);
+            self.raw_frame_texture = Some((texture, bind_group));
+        }
+        let (texture, bind_group) = self.raw_frame_texture.as_ref().unwrap();
+
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        for window in self.windows.iter().filter(|window| window.configured) {
+            let frame = window.surface.get_current_texture().expect("Failed to get next swapchain texture");
+            let texture_view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Raw Frame Encoder") });
+            self.blit.encode_pass(&mut encoder, bind_group, &texture_view);
+            self.queue.submit(once(encoder.finish()));
+            frame.present();
+        }
+    }
+
+    fn present_raw_to_st7789(&mut self, rgba: &[u8], width: u32, height: u32) {
+        let rgb565_bytes = self.color_conversion.convert(rgba, false);
+
+        self.present_to_display_sinks(&rgb565_bytes, width, height);
+    }
+
+    // Sends the frame to every display, whole or cut into columns depending on the layout.
+    // A failed frame is skipped only on the display which failed, so a glitch does not stop an always-on display.
+    fn present_to_display_sinks(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) {
+        let mut rgb565_bytes = rgb565_bytes.to_vec();
+        let brightness = self.brightness * self.st7789_brightness;
+        if brightness < 1.0 {
+            color_conversion::scale_rgb565(&mut rgb565_bytes, brightness);
+        }
+
+        let frames = match self.display_layout {
+            DisplayLayout::Span if self.display_sinks.len() > 1 => display_sink::split_columns(&rgb565_bytes, width, height, self.display_sinks.len() as u32),
+            _ => vec![(rgb565_bytes, width); self.display_sinks.len()],
+        };
+
+        for (index, (sink, (bytes, width))) in self.display_sinks.iter_mut().zip(frames).enumerate() {
+            // Wait for the vertical blank of the panel if it reports it, so the frame is pushed without tearing
+            sink.wait_for_te().expect("Failed to wait for tearing effect signal");
+            if let Err(err) = sink.present(&bytes, width, height) {
+                println!("Warning: Skipping frame on display {}, failed to present it: {}", index, err);
+            }
+        }
+    }
+
+    // Renders a single frame into the offscreen texture and saves it as PNG.
+    // The image is ST7789_OUTPUT_SIZE pixels square (or less if limited by --max-texture-size) unless the first display
+    // asks for another size, its first row is the top of the frame as shown in the window.
+    pub fn render_to_file(&mut self, path: &str) -> Result<(), image::ImageError> {
+        let (texture_data, width, height) = self.render_offscreen();
+        save_as_png(texture_data, width, height, path)
+    }
+
+    // Renders the current frame like render_to_file and returns it encoded as PNG, e.g. to send it to a remote client
+    pub fn capture_png(&mut self) -> Result<Vec<u8>, image::ImageError> {
+        let (texture_data, width, height) = self.capture_frame();
+        let image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>> = image::ImageBuffer::from_raw(width, height, texture_data).unwrap();
+        let mut png_bytes = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut png_bytes), image::ImageFormat::Png)?;
+        Ok(png_bytes)
+    }
+
+    // Renders the current frame like render_to_file and returns its RGBA8888 pixels and size, e.g. for a video.
+    // Works with the window only too, the offscreen texture is created on the first capture then.
+    pub fn capture_frame(&mut self) -> (Vec<u8>, u32, u32) {
+        if self.offscreen_render_target.is_none() {
+            let (texture, buffer) = create_offscreen_render_target(&self.device, self.output_format, self.offscreen_size);
+            self.offscreen_render_target = Some(texture);
+            self.offscreen_render_buffer = Some(buffer);
+        }
+        self.render_offscreen()
+    }
+
+    // Size of the frames returned by capture_frame
+    pub fn capture_size(&self) -> (u32, u32) {
+        self.offscreen_size
+    }
+
+    // Renders the shader with the window's vertices into the offscreen texture and returns its RGBA8888 pixels and size
+    fn render_offscreen(&mut self) -> (Vec<u8>, u32, u32) {
+        // Rendered with the window's vertices, so shaders see it as the window output
+        let texture_size = self.offscreen_render_target.as_ref().expect("offscreen_render_target is None").size();
+        self.prepare_output(OUTPUT_KIND_WINDOW, (texture_size.width, texture_size.height));
+
+        let texture_view = self.offscreen_render_target.as_ref().expect("offscreen_render_target is None").create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Offscreen Render Encoder") });
+        self.encode_offscreen_shader_pass(&mut encoder, &texture_view, &self.vertex_buffer);
+        self.queue.submit(once(encoder.finish()));
+
+        let texture_data = self.read_texture(
+            self.offscreen_render_target.as_ref().expect("offscreen_render_target is None"),
+            self.offscreen_render_buffer.as_ref().expect("offscreen_render_buffer is None"),
+        );
+
+        let mut texture_data = texture_data;
+        if is_bgra(self.output_format) {
+            texture_data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        (texture_data, texture_size.width, texture_size.height)
+    }
+
     fn render_to_st7789(
         &mut self,
     ) {
         let render_start = Instant::now();
+        let texture_size = self.offscreen_render_target.as_ref().unwrap().size();
+        self.prepare_output(OUTPUT_KIND_ST7789, (texture_size.width, texture_size.height));
 
         // Create a texture view for the frame
-        let texture_view = self.st7789_render_target.as_mut().unwrap().create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_view = self.offscreen_render_target.as_mut().unwrap().create_view(&wgpu::TextureViewDescriptor::default());
 
         // Create a command encoder to record the rendering commands
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Window Render Encoder") });
+        self.encode_offscreen_shader_pass(&mut encoder, &texture_view, &self.st7789_vertex_buffer);
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-           // Set the render pipeline and bind group, then draw the vertices
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
+        // Draw the HUD over the shader output
+        if (self.hud_visible && self.hud_on_st7789) || self.error_overlay_visible() {
+            self.hud.prepare(&self.queue, texture_size.width, texture_size.height);
+            self.hud.encode_pass(&mut encoder, &texture_view);
         }
 
         // Submit the command encoder to the queue
@@ -411,32 +1415,154 @@ impl Renderer {
 
         // Present the frame to the window
         let texture_data = self.read_texture(
-            self.st7789_render_target.as_ref().expect("st7789_render_target is None"),
-            self.st7789_render_buffer.as_ref().expect("st7789_render_buffer is None"),
+            self.offscreen_render_target.as_ref().expect("offscreen_render_target is None"),
+            self.offscreen_render_buffer.as_ref().expect("offscreen_render_buffer is None"),
         );
         let readback_ms = render_start.elapsed().as_secs_f64() * 1000.0 - render_ms;
 
         // Convert RGBA8888 to RGB565 (LE packed bytes)
-        let rgb565_bytes = rgba8888_to_rgb565_u8(&texture_data, false);
+        let rgb565_bytes = self.color_conversion.convert(&texture_data, is_bgra(self.output_format));
         let color_conversion_ms = render_start.elapsed().as_secs_f64() * 1000.0 - render_ms - readback_ms;
 
-        self.st7789_driver.as_mut().unwrap().draw(&rgb565_bytes).unwrap();
+        self.present_to_display_sinks(&rgb565_bytes, texture_size.width, texture_size.height);
         let draw_ms = render_start.elapsed().as_secs_f64() * 1000.0 - render_ms - readback_ms - color_conversion_ms;
+        self.st7789_timings = FrameTimings { render_ms, readback_ms, convert_ms: color_conversion_ms, draw_ms };
 
         if DEBUG_OVERHEADS {
             println!("Render time: {:.2}ms, GPU readback time: {:.2}ms, Color conversion time: {:.2}ms, Draw time: {:.2}ms", render_ms, readback_ms, color_conversion_ms, draw_ms);
         }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        if let Some(surface_config) = &mut self.surface_config {
-            surface_config.width = width;
-            surface_config.height = height;
-            self.surface.as_ref().unwrap().configure(&self.device, surface_config);
+    // Records the buffer passes and the shader render pass into the given encoder, drawing the given vertices into the given texture view.
+    // The output needs to be prepared for the size first.
+    fn encode_shader_pass(&self, encoder: &mut wgpu::CommandEncoder, texture_view: &wgpu::TextureView, vertex_buffer: &VertexBuffer, size: (u32, u32)) {
+        self.encode_shader_pass_with(encoder, texture_view, vertex_buffer, size, &self.render_pipeline, &self.feedback_present_pipeline);
+    }
+
+    // Like encode_shader_pass, with the pipelines for the format of the texture view
+    fn encode_shader_pass_with(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture_view: &wgpu::TextureView,
+        vertex_buffer: &VertexBuffer,
+        size: (u32, u32),
+        render_pipeline: &wgpu::RenderPipeline,
+        feedback_present_pipeline: &wgpu::RenderPipeline,
+    ) {
+        self.encode_buffer_passes(encoder, size);
+        let buffer_targets = self.multipass.targets(size);
+        match (buffer_targets.feedback(), &self.feedback_render_pipeline) {
+            // Feedback shaders render into the feedback buffer the next frame reads, which is then drawn to the output
+            (Some((feedback_view, present_bind_group)), Some(feedback_render_pipeline)) => {
+                self.encode_pass(encoder, feedback_view, &self.buffer_vertex_buffer, feedback_render_pipeline, self.load_op, buffer_targets.bind_group());
+                self.encode_pass(encoder, texture_view, vertex_buffer, feedback_present_pipeline, self.load_op, present_bind_group);
+            }
+            _ => self.encode_pass(encoder, texture_view, vertex_buffer, render_pipeline, self.load_op, buffer_targets.bind_group()),
+        }
+    }
+
+    // Records the shader pass for the offscreen target, through the sanitize pass if enabled
+    fn encode_offscreen_shader_pass(&self, encoder: &mut wgpu::CommandEncoder, texture_view: &wgpu::TextureView, vertex_buffer: &VertexBuffer) {
+        let texture_size = self.offscreen_render_target.as_ref().expect("offscreen_render_target is None").size();
+        let size = (texture_size.width, texture_size.height);
+        match &self.sanitize_pass {
+            Some(sanitize_pass) => {
+                let render_target_view = sanitize_pass.render_target.create_view(&wgpu::TextureViewDescriptor::default());
+                self.encode_shader_pass_with(encoder, &render_target_view, vertex_buffer, size, &sanitize_pass.render_pipeline, &sanitize_pass.feedback_present_pipeline);
+                sanitize_pass.blit.encode_pass(encoder, &sanitize_pass.bind_group, texture_view);
+            }
+            None => self.encode_shader_pass(encoder, texture_view, vertex_buffer, size),
+        }
+    }
+
+    // Records the buffer passes of the current shader in their configured order, buffers keep their previous contents
+    fn encode_buffer_passes(&self, encoder: &mut wgpu::CommandEncoder, size: (u32, u32)) {
+        let buffer_targets = self.multipass.targets(size);
+        for (index, buffer_pass) in self.multipass.passes().iter().enumerate() {
+            let (texture_view, channel_bind_group) = buffer_targets.pass(index);
+            self.encode_pass(encoder, texture_view, &self.buffer_vertex_buffer, &buffer_pass.render_pipeline, wgpu::LoadOp::Load, channel_bind_group);
         }
     }
 
-    // Copies data from a texture to array of bytes
+    fn encode_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture_view: &wgpu::TextureView,
+        vertex_buffer: &VertexBuffer,
+        render_pipeline: &wgpu::RenderPipeline,
+        load_op: wgpu::LoadOp<wgpu::Color>,
+        channel_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: load_op,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        // Set the render pipeline and bind groups, then draw the vertices
+        render_pass.set_pipeline(render_pipeline);
+        render_pass.set_vertex_buffer(0, vertex_buffer.buffer.slice(..));
+        render_pass.set_bind_group(0, &self.global_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.dynamic_bind_group, &[]);
+        render_pass.set_bind_group(2, channel_bind_group, &[]);
+        render_pass.draw(0..vertex_buffer.vertex_count(), 0..1);
+    }
+
+    pub fn resize(&mut self, window_id: winit::window::WindowId, width: u32, height: u32) {
+        let Some(window) = self.windows.iter_mut().find(|window| window.id == window_id) else {
+            return;
+        };
+
+        // Surfaces can not be configured with zero size (minimized window, or before the first configure event on some
+        // Wayland compositors and tiling window managers), so skip rendering to the window until it gets a real size
+        if width == 0 || height == 0 {
+            window.configured = false;
+            return;
+        }
+
+        (window.config.width, window.config.height) = clamp_texture_size((width, height), self.max_texture_size);
+        window.surface.configure(&self.device, &window.config);
+        window.configured = true;
+        window.render_target = None;
+
+        // The pixel shift depends on the window size
+        if self.antiburn.is_some() {
+            self.update_vertex_buffers();
+        }
+    }
+
+    // Size the shader renders at for a window, the window size reduced by the render scale
+    fn window_render_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let scaled = |size: u32| ((size as f32 * self.render_scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    // Creates a texture of the window's render size which is kept between frames
+    fn create_window_render_target(&self, (width, height): (u32, u32)) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Window Render Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.output_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    // Copies data from a texture to array of bytes, 4 bytes per pixel without any row padding
     fn read_texture(&self, texture: &wgpu::Texture, buffer: &wgpu::Buffer) -> Vec<u8> {
         let texture_size = texture.size();
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -445,16 +1571,16 @@ impl Renderer {
 
         encoder.copy_texture_to_buffer(
             wgpu::ImageCopyTexture {
-                texture: &texture,
+                texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
             wgpu::ImageCopyBuffer {
-                buffer: &buffer,
+                buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(4 * texture_size.width),
+                    bytes_per_row: Some(padded_bytes_per_row(texture_size.width)),
                     rows_per_image: Some(texture_size.height),
                 },
             },
@@ -481,10 +1607,9 @@ impl Renderer {
             std::thread::sleep(std::time::Duration::from_millis(1)); // Small sleep to reduce CPU usage
         }
 
-        // Retrieve the data
+        // Retrieve the data, dropping the padding at the end of each row
         let data = buffer_slice.get_mapped_range();
-        let mut image_data = vec![0; data.len()];
-        image_data.copy_from_slice(&data);
+        let image_data = remove_row_padding(&data, texture_size.width, texture_size.height);
         drop(data);
 
         // Unmap the buffer
@@ -494,26 +1619,152 @@ impl Renderer {
     }
 }
 
-// Compiles GLSL shaders to SPIR-V using glslc or glslc.exe
-fn compile_shader(shader_path: PathBuf, output_path: PathBuf) {
-    println!("Compiling shader: {}", shader_path.display());
+// Scales the mouse uniform from fractions of the window to pixels of the given resolution, keeping the signs of zw
+fn mouse_in_pixels([x, y, click_x, click_y]: [f32; 4], [width, height]: [f32; 2]) -> [f32; 4] {
+    [x * width, y * height, click_x * width, click_y * height]
+}
 
-    let compiler = if cfg!(target_os = "windows") {
-        "./glslc.exe"
-    } else {
-        "glslc"
-    };
+// Texture to buffer copies need each row to start at a multiple of COPY_BYTES_PER_ROW_ALIGNMENT (256 bytes),
+// so rows of widths which are not a multiple of 64 pixels are padded
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let bytes_per_row = 4 * width;
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    bytes_per_row.div_ceil(alignment) * alignment
+}
+
+fn remove_row_padding(padded_data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let bytes_per_row = (4 * width) as usize;
+    padded_data
+        .chunks_exact(padded_bytes_per_row(width) as usize)
+        .take(height as usize)
+        .flat_map(|row| &row[..bytes_per_row])
+        .copied()
+        .collect()
+}
+
+// Returns true if the file is a fragment shader, either GLSL (.frag) or WGSL (.wgsl)
+pub fn is_fragment_shader(file_name: &str) -> bool {
+    file_name.ends_with(".frag") || file_name.ends_with(".wgsl")
+}
+
+fn create_vertex_shader(device: &wgpu::Device, shaders_path: &Path, options: ShaderCompileOptions) -> Result<wgpu::ShaderModule, String> {
+    let output_path = shaders_path.join("compiled").join("master.vert.spv");
+    compile_shader(shaders_path.join("uncompiled").join("master.vert"), None, output_path.clone(), options)?;
+    let spirv = fs::read(&output_path).map_err(|err| format!("{}: {}", output_path.display(), err))?;
+    create_shader_module(device, "vertex_shader", wgpu::util::make_spirv(&spirv))
+}
 
-    let status = std::process::Command::new(compiler)
-        .arg(shader_path.to_str().unwrap())
-        .arg("-o")
-        .arg(output_path)
-        .status()
-        .expect("Failed to execute shader compiler");
+// Creates the fragment shader module. WGSL shaders are loaded by wgpu directly,
+// GLSL shaders are compiled to SPIR-V first. The source is read from the shader file unless given.
+fn create_fragment_shader(device: &wgpu::Device, shaders_path: &Path, shader_name: &str, source: Option<&str>, options: ShaderCompileOptions) -> Result<wgpu::ShaderModule, String> {
+    let shader_path = shaders_path.join("uncompiled").join(shader_name);
+    if shader_name.ends_with(".wgsl") {
+        let source = match source {
+            Some(source) => source.to_string(),
+            None => {
+                println!("Loading WGSL shader: {}", shader_path.display());
+                fs::read_to_string(&shader_path).map_err(|err| format!("{}: {}", shader_name, err))?
+            }
+        };
+        return create_shader_module(device, "fragment_shader", wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source)));
+    }
+
+    let output_path = shaders_path.join("compiled").join("master.frag.spv");
+    compile_shader(shader_path, source.map(str::as_bytes), output_path.clone(), options)?;
+    let spirv = fs::read(&output_path).map_err(|err| format!("{}: {}", output_path.display(), err))?;
+    create_shader_module(device, "fragment_shader", wgpu::util::make_spirv(&spirv))
+}
+
+// Compiles a GLSL shader from the shaders directory to its own SPIR-V file next to the compiled shaders, for inspecting it
+pub fn compile_to_spirv(shaders_path: &Path, shader_name: &str, options: ShaderCompileOptions) -> Result<PathBuf, String> {
+    let output_path = shaders_path.join("compiled").join(format!("{}.spv", shader_name));
+    compile_shader(shaders_path.join("uncompiled").join(shader_name), None, output_path.clone(), options)?;
+    Ok(output_path)
+}
+
+// Creates a shader module, returning validation errors instead of passing them to the device's error handler which panics
+fn create_shader_module(device: &wgpu::Device, label: &str, source: wgpu::ShaderSource) -> Result<wgpu::ShaderModule, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some(label), source });
+    match block_on(device.pop_error_scope()) {
+        Some(err) => Err(err.to_string()),
+        None => Ok(shader_module),
+    }
+}
+
+// Returns the first lines of a shader error with a heading, as shown over the output
+fn error_overlay_text(error: &str) -> String {
+    let lines: Vec<&str> = error.lines().map(str::trim).filter(|line| !line.is_empty()).take(ERROR_OVERLAY_LINES).collect();
+    format!("Shader error\n{}", lines.join("\n"))
+}
+
+// Compiles a GLSL shader to SPIR-V with naga, returns the compiler output if it fails.
+// The source is read from the shader path unless given, e.g. when piped to stdin, then the path only names the shader.
+fn compile_shader(shader_path: PathBuf, source: Option<&[u8]>, output_path: PathBuf, options: ShaderCompileOptions) -> Result<(), String> {
+    // Includes are looked up next to the original shader, also when the source is given
+    let include_directory = shader_path.parent().unwrap().to_path_buf();
+
+    let file_source;
+    let source = match source {
+        Some(source) => {
+            println!("Compiling shader {} from source", shader_path.file_name().unwrap().to_string_lossy());
+            source
+        }
+        None => {
+            // Editors may replace the file while saving, so a missing file is a compile error as well
+            file_source = fs::read(&shader_path).map_err(|err| format!("{}: {}", shader_path.display(), err))?;
+            println!("Compiling shader: {}", shader_path.display());
+            file_source.as_slice()
+        }
+    };
+    // Shaders saved on Windows may have a BOM or CRLF line endings which the compiler fails on with confusing errors
+    let source = String::from_utf8(sanitize_shader_source(source)).map_err(|_| format!("{}: not UTF-8", shader_path.display()))?;
+    let spirv = shader_compiler::compile(&shader_path, &source, &include_directory, options.debug_info)?;
+    fs::write(&output_path, cast_slice(&spirv)).map_err(|err| format!("{}: {}", output_path.display(), err))
+}
 
-    if !status.success() {
-        panic!("Shader compilation failed: {}", shader_path.display());
+// Strips a leading UTF-8 BOM and converts CRLF and lone CR line endings to LF
+pub fn sanitize_shader_source(source: &[u8]) -> Vec<u8> {
+    let source = source.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(source);
+    let mut sanitized = Vec::with_capacity(source.len());
+    let mut bytes = source.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' {
+            bytes.next_if_eq(&&b'\n');
+            sanitized.push(b'\n');
+        } else {
+            sanitized.push(byte);
+        }
     }
+    sanitized
+}
+
+// Helper to create a bind group layout and a bind group for a single uniform buffer
+fn create_uniform_bind_group(device: &wgpu::Device, uniform_buffer: &wgpu::Buffer, label: &str) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(&format!("{}_bind_group_layout", label)),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT, // or VERTEX if it's in the vertex shader
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }],
+        label: Some(&format!("{}_bind_group", label)),
+    });
+
+    (bind_group_layout, bind_group)
 }
 
 // Helper to create a render pipeline
@@ -548,38 +1799,95 @@ fn create_render_pipeline(
     })
 }
 
-fn initialize_wgpu_without_window() -> (wgpu::Device, wgpu::Queue, Option<wgpu::Surface>, Option<wgpu::SurfaceConfiguration>, wgpu::TextureFormat) {
+// Creates the offscreen texture the ST7789 frames and saved frames are rendered to, with a buffer to read it back
+fn create_offscreen_render_target(device: &wgpu::Device, output_format: wgpu::TextureFormat, (width, height): (u32, u32)) -> (wgpu::Texture, wgpu::Buffer) {
+    let output_image_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let output_image_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Render Texture"),
+        size: output_image_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: output_format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let data_size = (padded_bytes_per_row(width) * height) as u64; // Rows padded to the copy alignment
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Read Buffer"),
+        size: data_size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    (output_image_texture, buffer)
+}
+
+// Shrinks a texture size so its larger side fits the maximum texture size if one is set.
+// Both sides are scaled by the same factor, so a window surface keeps its aspect ratio and is not stretched.
+fn clamp_texture_size((width, height): (u32, u32), max_texture_size: Option<u32>) -> (u32, u32) {
+    match max_texture_size {
+        Some(max_texture_size) if width.max(height) > max_texture_size => {
+            let scale = max_texture_size as f64 / width.max(height) as f64;
+            let scaled = |size: u32| ((size as f64 * scale).round() as u32).clamp(1, max_texture_size);
+            (scaled(width), scaled(height))
+        }
+        _ => (width, height),
+    }
+}
+
+// Returns device limits to request, the adapter maximum by default or
+// downlevel limits with capped texture size to save memory on low-memory devices
+fn device_limits(adapter: &wgpu::Adapter, max_texture_size: Option<u32>) -> wgpu::Limits {
+    match max_texture_size {
+        Some(max_texture_size) => {
+            let adapter_limits = adapter.limits();
+            wgpu::Limits {
+                max_texture_dimension_1d: max_texture_size.min(adapter_limits.max_texture_dimension_1d),
+                max_texture_dimension_2d: max_texture_size.min(adapter_limits.max_texture_dimension_2d),
+                ..wgpu::Limits::downlevel_webgl2_defaults()
+            }
+        }
+        None => adapter.limits(),
+    }
+}
+
+fn initialize_wgpu_without_window(gpu_options: GpuOptions) -> (wgpu::Instance, wgpu::Adapter, wgpu::Device, wgpu::Queue, Option<WindowTarget>, wgpu::TextureFormat) {
     
     // Create a wgpu instance without a window
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
 
     // Create addapter without a surface
     let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
+        power_preference: gpu_options.power_preference,
         compatible_surface: None, 
-        force_fallback_adapter: false,
+        force_fallback_adapter: gpu_options.force_fallback_adapter,
     }))
     .expect("Failed to find a suitable adapter");
-    println!("Using backend: {:?}, device: {}", adapter.get_info().backend, adapter.get_info().name);
+    println!("Using backend: {:?}, device: {} ({:?}, power preference {:?})", adapter.get_info().backend, adapter.get_info().name, adapter.get_info().device_type, gpu_options.power_preference);
 
     // Create device and queue
     let (device, queue) = block_on(adapter.request_device(
         &wgpu::DeviceDescriptor {
             label: None,
             features: wgpu::Features::empty(),
-            limits: adapter.limits(),
+            limits: device_limits(&adapter, gpu_options.max_texture_size),
         },
         None,
     ))
     .expect("Failed to create device");
 
-    (device, queue, None, None, wgpu::TextureFormat::Rgba8Unorm)
+    (instance, adapter, device, queue, None, wgpu::TextureFormat::Rgba8Unorm)
 }
 
-fn initialize_wgpu_with_window(window: &winit::window::Window) -> (wgpu::Device, wgpu::Queue, Option<wgpu::Surface>, Option<wgpu::SurfaceConfiguration>, wgpu::TextureFormat) {
-
-    // Get the physical size of the window
-    let physical_size = window.inner_size();
+fn initialize_wgpu_with_window(window: &winit::window::Window, gpu_options: GpuOptions) -> (wgpu::Instance, wgpu::Adapter, wgpu::Device, wgpu::Queue, Option<WindowTarget>, wgpu::TextureFormat) {
 
     // Create a wgpu instance
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
@@ -589,44 +1897,55 @@ fn initialize_wgpu_with_window(window: &winit::window::Window) -> (wgpu::Device,
 
     // Create addapter with the surface
     let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
+        power_preference: gpu_options.power_preference,
+        force_fallback_adapter: gpu_options.force_fallback_adapter,
         compatible_surface: Some(&surface),
     }))
     .expect("failed to find a suitable adapter");
-    println!("Using backend: {:?}, device: {}", adapter.get_info().backend, adapter.get_info().name);
+    println!("Using backend: {:?}, device: {} ({:?}, power preference {:?})", adapter.get_info().backend, adapter.get_info().name, adapter.get_info().device_type, gpu_options.power_preference);
 
     // Create device and queue
     let (device, queue) = block_on(adapter.request_device(
         &wgpu::DeviceDescriptor {
             label: None,
             features: wgpu::Features::empty(),
-            limits: adapter.limits(),
+            limits: device_limits(&adapter, gpu_options.max_texture_size),
         },
         None,
     ))
     .expect("failed to create a device");
 
     // Configure the surface with the adapter and window size
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchain_format = wgpu::TextureFormat::Bgra8Unorm;
-
-    // Create a surface configuration with the selected format and window size
-    let surface_config: wgpu::SurfaceConfiguration = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: swapchain_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: wgpu::PresentMode::Fifo,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
+    let window = WindowTarget::new(&device, &adapter, surface, window, gpu_options, None).unwrap_or_else(|err| panic!("Failed to configure the window surface: {}", err));
+    let swapchain_format = window.config.format;
+
+    // Return the instance, adapter, device, queue, window and swapchain format
+    (instance, adapter, device, queue, Some(window), swapchain_format)
+}
+
+// Picks a swapchain format supported by the surface, preferring non-sRGB 8-bit formats
+// since shaders output display ready values, falling back to the first supported format
+fn select_swapchain_format(supported_formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    [wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::Bgra8Unorm]
+        .into_iter()
+        .find(|format| supported_formats.contains(format))
+        .unwrap_or(supported_formats[0])
+}
 
-    // Apply the surface configuration to the surface
-    surface.configure(&device, &surface_config);
+// Picks the requested present mode if the surface supports it. Mailbox and Immediate both avoid waiting for vsync,
+// so each falls back to the other before Fifo, which every surface supports.
+fn select_present_mode(requested: wgpu::PresentMode, supported_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    let fallbacks: &[wgpu::PresentMode] = match requested {
+        wgpu::PresentMode::Mailbox => &[wgpu::PresentMode::Immediate],
+        wgpu::PresentMode::Immediate => &[wgpu::PresentMode::Mailbox],
+        _ => &[],
+    };
+    std::iter::once(&requested).chain(fallbacks).copied().find(|mode| supported_modes.contains(mode)).unwrap_or(wgpu::PresentMode::Fifo)
+}
 
-    // Return the device, queue, surface, surface configuration, and swapchain format
-    (device, queue, Some(surface), Some(surface_config), swapchain_format)
+// Whether pixels of the format are stored in BGRA order, which must be swapped when reading back
+fn is_bgra(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb)
 }
 
 fn save_as_png(data: Vec<u8>, width: u32, height: u32, path: &str) -> Result<(), image::ImageError> {
@@ -635,47 +1954,519 @@ fn save_as_png(data: Vec<u8>, width: u32, height: u32, path: &str) -> Result<(),
     Ok(())
 }
 
-// Converts RGBA8888 (4 bytes per pixel) to RGB565 (2 bytes per pixel, little-endian)
-// Skips the alpha channel entirely.
-fn rgba8888_to_rgb565_u8(input: &[u8], flip_order: bool) -> Vec<u8> {
-    let mut output = Vec::with_capacity((input.len() / 4) * 2); // 2 bytes per pixel (RGB565)
-    for chunk in input.chunks_exact(4) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_renderer::{can_render, PresentedFrame, TestRenderer};
+
+    // Returns the texture coordinates which the output shows at the given screen position, interpolated like the GPU does
+    fn texture_coordinates_shown_at(vertices: &[Vertex; 3], x: f32, y: f32) -> [f32; 2] {
+        let [a, b, c] = vertices.map(|vertex| vertex.position);
+
+        // Barycentric weights of the position in the triangle
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+        let weight_b = ((x - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (y - a[1])) / area;
+        let weight_c = ((b[0] - a[0]) * (y - a[1]) - (x - a[0]) * (b[1] - a[1])) / area;
+        let weight_a = 1.0 - weight_b - weight_c;
+        assert!(weight_a >= 0.0 && weight_b >= 0.0 && weight_c >= 0.0, "Position is not covered by the triangle");
+
+        let [uv_a, uv_b, uv_c] = vertices.map(|vertex| vertex.texture_coordinates);
+        [0, 1].map(|i| weight_a * uv_a[i] + weight_b * uv_b[i] + weight_c * uv_c[i])
+    }
+
+    #[test]
+    fn fullscreen_triangle_maps_screen_to_unit_square() {
+        let vertices = screen_vertices(false, false);
+        assert_eq!(texture_coordinates_shown_at(&vertices, -1.0, -1.0), [0.0, 0.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, 1.0, -1.0), [1.0, 0.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, -1.0, 1.0), [0.0, 1.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, 1.0, 1.0), [1.0, 1.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, 0.0, 0.0), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn rotation_turns_image_clockwise() {
+        let vertices = rotate_texture_coordinates(screen_vertices(false, false), 1);
+        // The bottom-left of the image is shown at the top-left, its top-left at the top-right
+        assert_eq!(texture_coordinates_shown_at(&vertices, -1.0, 1.0), [0.0, 0.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, 1.0, 1.0), [0.0, 1.0]);
+        assert_eq!(rotate_texture_coordinates(screen_vertices(false, false), 4), screen_vertices(false, false));
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_supported_one() {
+        use wgpu::PresentMode::*;
+        assert_eq!(select_present_mode(Mailbox, &[Fifo, Mailbox, Immediate]), Mailbox);
+        assert_eq!(select_present_mode(Mailbox, &[Fifo, Immediate]), Immediate);
+        assert_eq!(select_present_mode(Immediate, &[Fifo]), Fifo);
+        assert_eq!(select_present_mode(Fifo, &[Fifo, Mailbox]), Fifo);
+    }
+
+    #[test]
+    fn global_uniforms_match_glsl_layout() {
+        assert_eq!(std::mem::offset_of!(GlobalUniforms, panel_resolution), 16);
+        assert_eq!(std::mem::offset_of!(GlobalUniforms, output_kind), 28);
+        assert_eq!(std::mem::offset_of!(GlobalUniforms, resolution), 32);
+        assert_eq!(std::mem::offset_of!(GlobalUniforms, mouse), 48);
+        assert_eq!(size_of::<GlobalUniforms>() % 16, 0);
+    }
+
+    #[test]
+    fn dynamic_uniforms_match_glsl_layout() {
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, gravity), 32);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, user_params), 48);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, angular_velocity), 112);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, orientation), 128);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, cpu_usage), 144);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, ambient_brightness), 156);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, loudness), 160);
+        assert_eq!(std::mem::offset_of!(DynamicUniforms, clients_connected), 168);
+        assert_eq!(size_of::<DynamicUniforms>() % 16, 0);
+    }
+
+    #[test]
+    fn mouse_is_scaled_to_pass_resolution() {
+        assert_eq!(mouse_in_pixels([0.5, 0.25, -0.5, -0.25], [800.0, 400.0]), [400.0, 100.0, -400.0, -100.0]);
+        assert_eq!(mouse_in_pixels([0.5, 0.25, 0.5, 0.25], [256.0, 256.0]), [128.0, 64.0, 128.0, 64.0]);
+    }
+
+    #[test]
+    fn texture_size_is_clamped_keeping_aspect_ratio() {
+        assert_eq!(clamp_texture_size((1920, 1080), Some(512)), (512, 288));
+        assert_eq!(clamp_texture_size((1080, 1920), Some(512)), (288, 512));
+        assert_eq!(clamp_texture_size((4000, 1), Some(512)), (512, 1));
+        assert_eq!(clamp_texture_size((320, 240), Some(512)), (320, 240));
+        assert_eq!(clamp_texture_size((1920, 1080), None), (1920, 1080));
+    }
+
+    #[test]
+    fn rows_are_padded_to_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(240), 1024);
+        assert_eq!(padded_bytes_per_row(256), 1024);
+        assert_eq!(padded_bytes_per_row(1), 256);
+
+        // Two rows of 3 pixels, each padded to 256 bytes
+        let mut padded_data = vec![0xAA; 512];
+        padded_data[..12].fill(1);
+        padded_data[256..268].fill(2);
+        let data = remove_row_padding(&padded_data, 3, 2);
+        assert_eq!(data, [vec![1; 12], vec![2; 12]].concat());
+    }
+
+    const BOM_CRLF_SHADER: &[u8] = b"\xEF\xBB\xBF#version 450\r\nlayout(location = 0) out vec4 out_final_color;\r\nvoid main() {\r\n    out_final_color = vec4(1.0);\r\n}\r\n";
 
-        let r = if flip_order { chunk[2] } else { chunk[0] };
-        let g = chunk[1];
-        let b = if flip_order { chunk[0] } else { chunk[2] };
+    #[test]
+    fn sanitize_shader_source_strips_bom_and_normalizes_line_endings() {
+        assert_eq!(
+            sanitize_shader_source(BOM_CRLF_SHADER),
+            b"#version 450\nlayout(location = 0) out vec4 out_final_color;\nvoid main() {\n    out_final_color = vec4(1.0);\n}\n",
+        );
+        assert_eq!(sanitize_shader_source(b"a\rb\r\n\nc"), b"a\nb\n\nc");
+        assert_eq!(sanitize_shader_source(b"void main() {}\n"), b"void main() {}\n");
+    }
+
+    #[test]
+    fn compiles_shader_with_bom_and_crlf() {
+        let directory = std::env::temp_dir().join(format!("little-shader-display-bom-test-{}", std::process::id()));
+        fs::create_dir_all(directory.join("uncompiled")).unwrap();
+        fs::create_dir_all(directory.join("compiled")).unwrap();
+        let shader_path = directory.join("uncompiled").join("bom.frag");
+        let output_path = directory.join("compiled").join("bom.frag.spv");
+        fs::write(&shader_path, BOM_CRLF_SHADER).unwrap();
+
+        compile_shader(shader_path, None, output_path.clone(), ShaderCompileOptions::default()).unwrap();
+        let spirv = fs::read(&output_path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(spirv[..4], 0x07230203u32.to_le_bytes()); // SPIR-V magic number
+    }
+
+    #[test]
+    fn flip_only_mirrors_positions() {
+        assert_eq!(screen_vertices(false, false).map(|vertex| vertex.texture_coordinates), screen_vertices(true, false).map(|vertex| vertex.texture_coordinates));
+        assert!(screen_vertices(false, false).iter().all(|vertex| (vertex.position[1] > 0.0) == (vertex.texture_coordinates[1] > 0.5)));
+    }
 
-        // Convert RGBA8888 to RGB565
-        let rgb565: u16 =
-            ((r as u16 & 0xF8) << 8) | // Red: upper 5 bits
-            ((g as u16 & 0xFC) << 3) | // Green: upper 6 bits
-            ((b as u16) >> 3);         // Blue: upper 5 bits
+    #[test]
+    fn flipped_uv_shows_top_left_origin_upright() {
+        // A shader written with (0,0) at the top-left draws the bar of its "F" at small v, so the top-left corner must show (0,0)
+        let vertices = screen_vertices(false, true);
+        assert_eq!(texture_coordinates_shown_at(&vertices, -1.0, 1.0), [0.0, 0.0]);
+        assert_eq!(texture_coordinates_shown_at(&vertices, 1.0, -1.0), [1.0, 1.0]);
+    }
 
-        // Split color value into two consecutive bytes 
-        output.push((rgb565 & 0xFF) as u8);      // Low byte
-        output.push((rgb565 >> 8) as u8);        // High byte
+    #[test]
+    fn flip_uv_only_mirrors_texture_coordinates() {
+        let vertices = screen_vertices(false, false);
+        let flipped_vertices = screen_vertices(false, true);
+        for (vertex, flipped_vertex) in vertices.iter().zip(flipped_vertices.iter()) {
+            assert_eq!(vertex.position, flipped_vertex.position);
+            assert_eq!(vertex.texture_coordinates, [flipped_vertex.texture_coordinates[0], 1.0 - flipped_vertex.texture_coordinates[1]]);
+        }
     }
 
-    output
+    const SOLID_COLOR_SHADER_NAME: &str = "solid_color.frag";
+    const SOLID_COLOR_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vec4(1.0, 0.0, 0.0, 1.0);
+}
+";
+    // Same solid color written in WGSL, loaded by wgpu without a SPIR-V step
+    const SOLID_COLOR_WGSL_SHADER_NAME: &str = "solid_color.wgsl";
+    const SOLID_COLOR_WGSL_SHADER: &str = "@fragment
+fn main(@location(0) vertex_position: vec2<f32>, @location(1) vertex_texture_coordinates: vec2<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
+";
+    const RED_RGB565: u16 = 0xF800;
+
+    // Named to sort after the shader the renderer starts with, which has to compile
+    const BROKEN_SHADER_NAME: &str = "undefined_color.wgsl";
+    const BROKEN_SHADER: &str = "@fragment fn main() -> @location(0) vec4<f32> { return undefined_color; }";
+
+    // Red on the panel and blue in the window, to check shaders can tell the outputs apart
+    const OUTPUT_KIND_SHADER_NAME: &str = "output_kind.frag";
+    const OUTPUT_KIND_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+layout(set = 0, binding = 0) uniform GlobalUniforms {
+    float time;
+    float screen_aspect_ratio;
+    uint output_srgb;
+    vec2 panel_resolution;
+    float real_time;
+    uint output_kind;
+};
+void main() {
+    out_final_color = output_kind == 1 ? vec4(1.0, 0.0, 0.0, 1.0) : vec4(0.0, 0.0, 1.0, 1.0);
+}
+";
+
+    // A white "F" on black, which looks different when mirrored or turned in any direction
+    const LETTER_F_SHADER_NAME: &str = "letter_f.frag";
+    const LETTER_F_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    vec2 uv = vertex_texture_coordinates;
+    bool stem = uv.x < 0.25;
+    bool top_bar = uv.y > 0.75;
+    bool middle_bar = uv.y > 0.4 && uv.y < 0.6 && uv.x < 0.6;
+    out_final_color = stem || top_bar || middle_bar ? vec4(1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+}
+";
+    const WHITE_RGB565: u16 = 0xFFFF;
+
+    // Red NaN, green +infinity and blue -infinity, divided by the time uniform which is 0
+    const NON_FINITE_SHADER_NAME: &str = "non_finite.frag";
+    const NON_FINITE_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+layout(set = 0, binding = 0) uniform GlobalUniforms {
+    float time;
+};
+void main() {
+    out_final_color = vec4(time / time, 1.0 / time, -1.0 / time, 1.0);
 }
+";
+    const GREEN_RGB565: u16 = 0x07E0;
+
+    // Smooth gradient over the whole output, compared against a committed golden image
+    const GRADIENT_SHADER_NAME: &str = "gradient.frag";
+    const GRADIENT_SHADER: &str = "#version 450
+layout(location = 0) in vec2 vertex_position;
+layout(location = 1) in vec2 vertex_texture_coordinates;
+layout(location = 0) out vec4 out_final_color;
+void main() {
+    out_final_color = vec4(vertex_texture_coordinates.x, vertex_texture_coordinates.y, 0.5, 1.0);
+}
+";
+    const GOLDEN_IMAGE_SIZE: u32 = 128;
+    // Software rasterizers round slightly differently between versions
+    const GOLDEN_IMAGE_TOLERANCE: u8 = 2;
+
+    fn assert_solid_red(frame: &PresentedFrame, size: u32) {
+        assert_eq!((frame.width, frame.height), (size, size));
+        assert_eq!(frame.rgb565_bytes.len(), (size * size * 2) as usize);
+        assert!(frame.pixels().iter().all(|&pixel| pixel == RED_RGB565));
+    }
+
+    #[test]
+    fn renders_widths_not_aligned_to_copy_rows() {
+        if !can_render() {
+            return;
+        }
+
+        // 240 pixels are 960 bytes per row, which is not a multiple of the 256 byte copy alignment
+        let gpu_options = GpuOptions { max_texture_size: Some(240), ..GpuOptions::default() };
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).gpu_options(gpu_options).build();
+        test.render_frames(1);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        assert_solid_red(&frames[0], 240);
+    }
+
+    #[test]
+    fn renders_wgsl_shader_to_sink() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_WGSL_SHADER_NAME, SOLID_COLOR_WGSL_SHADER).build();
+        test.render_frames(1);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        assert_solid_red(&frames[0], ST7789_OUTPUT_SIZE);
+    }
+
+    #[test]
+    fn keeps_last_good_shader_and_shows_error_until_fixed() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder()
+            .shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER)
+            .shader(BROKEN_SHADER_NAME, BROKEN_SHADER)
+            .build();
+        let renderer = &mut test.renderer;
+        renderer.recompile_shaders(BROKEN_SHADER_NAME, false, true);
+        assert!(renderer.shader_error().is_some_and(|error| error.contains("undefined_color")), "{:?}", renderer.shader_error());
+        renderer.update_uniforms(0.0, 0.0, [0.0; 3], [0.0; 4]);
+        renderer.render();
+
+        renderer.recompile_shaders(SOLID_COLOR_SHADER_NAME, false, true);
+        assert_eq!(renderer.shader_error(), None);
+        renderer.render();
+
+        // A shader which is gone, e.g. while an editor replaces it, is an error too
+        renderer.recompile_shaders("missing.frag", false, true);
+        assert!(renderer.shader_error().is_some_and(|error| error.contains("missing.frag")), "{:?}", renderer.shader_error());
+        renderer.render();
+
+        // The previous shader keeps rendering with the error drawn over it at the top-left, which is gone once fixed
+        let frames = test.take_frames(0);
+        let error_frame = frames[0].pixels();
+        assert_eq!(*error_frame.last().unwrap(), RED_RGB565);
+        assert!(error_frame.iter().any(|&pixel| pixel != RED_RGB565));
+        assert_solid_red(&frames[1], ST7789_OUTPUT_SIZE);
+    }
 
-fn rgba8888_to_rgb565(input: &[u8], flip_order: bool) -> Vec<u16> {
-    let mut output = Vec::with_capacity((input.len() / 4) * 2); // 2 bytes per pixel (RGB565)
-    for chunk in input.chunks_exact(4) {
+    #[test]
+    fn output_kind_differs_between_panel_and_window() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(OUTPUT_KIND_SHADER_NAME, OUTPUT_KIND_SHADER).build();
+        test.render_frames(1);
+
+        // Single frames saved to a file are rendered as the window output
+        let image_path = test.path("output_kind.png");
+        test.renderer.render_to_file(image_path.to_str().unwrap()).unwrap();
+        let image = image::open(&image_path).unwrap().to_rgba8();
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        assert_solid_red(&frames[0], ST7789_OUTPUT_SIZE);
+        assert!(image.pixels().all(|pixel| pixel.0 == [0, 0, 255, 255]));
+    }
+
+    // Texture coordinates the vertices give a point of the screen in clip space, interpolated like the rasterizer does
+    fn texture_coordinates_at(vertices: &[Vertex], x: f32, y: f32) -> [f32; 2] {
+        let [[x0, y0], [x1, y1], [x2, y2]] = [vertices[0].position, vertices[1].position, vertices[2].position];
+        let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+        let weight_1 = ((x - x0) * (y2 - y0) - (x2 - x0) * (y - y0)) / area;
+        let weight_2 = ((x1 - x0) * (y - y0) - (x - x0) * (y1 - y0)) / area;
+        let weight_0 = 1.0 - weight_1 - weight_2;
+        [0, 1].map(|axis| weight_0 * vertices[0].texture_coordinates[axis] + weight_1 * vertices[1].texture_coordinates[axis] + weight_2 * vertices[2].texture_coordinates[axis])
+    }
+
+    #[test]
+    fn window_and_panel_vertices_map_texture_coordinates_alike() {
+        if !can_render() {
+            return;
+        }
 
-        let r = if flip_order { chunk[2] } else { chunk[0] };
-        let g = chunk[1];
-        let b = if flip_order { chunk[0] } else { chunk[2] };
+        let test = TestRenderer::builder().shader(LETTER_F_SHADER_NAME, LETTER_F_SHADER).build();
+        // The window surface and the panel's offscreen target both show clip space y = 1 in their first row,
+        // so the same vertices show the same image on both
+        let window = &test.renderer.vertex_buffer.vertices;
+        let panel = &test.renderer.st7789_vertex_buffer.vertices;
+        assert_eq!(window, panel);
+        for ([x, y], expected) in [([-1.0, -1.0], [0.0, 0.0]), ([1.0, -1.0], [1.0, 0.0]), ([-1.0, 1.0], [0.0, 1.0]), ([1.0, 1.0], [1.0, 1.0])] {
+            let [u, v] = texture_coordinates_at(window, x, y);
+            assert!((u - expected[0]).abs() < 1e-6 && (v - expected[1]).abs() < 1e-6, "({}, {}) maps to ({}, {}) instead of {:?}", x, y, u, v, expected);
+        }
+    }
 
-        // Convert RGBA8888 to RGB565
-        let rgb565: u16 =
-            ((r as u16 & 0xF8) << 8) | // Red: upper 5 bits
-            ((g as u16 & 0xFC) << 3) | // Green: upper 6 bits
-            ((b as u16) >> 3);         // Blue: upper 5 bits
+    #[test]
+    fn saved_frames_and_panel_show_same_orientation() {
+        if !can_render() {
+            return;
+        }
 
-        // Split color value into two consecutive bytes 
-        output.push(rgb565);      // Low byte
+        let mut test = TestRenderer::builder().shader(LETTER_F_SHADER_NAME, LETTER_F_SHADER).build();
+        test.render_frames(1);
+
+        // Saved frames are rendered with the vertices of the window
+        let image_path = test.path("letter_f.png");
+        test.renderer.render_to_file(image_path.to_str().unwrap()).unwrap();
+        let image = image::open(&image_path).unwrap().to_rgba8();
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        let panel = frames[0].pixels();
+        let saved: Vec<u16> = color_conversion::rgba8888_to_rgb565_u8(image.as_raw(), false).chunks_exact(2).map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]])).collect();
+        assert_eq!((frames[0].width, frames[0].height), image.dimensions());
+        assert!(panel == saved, "The panel does not show the same image as the saved frame");
+
+        // Both show the "F" upright, the first row is the top of the image
+        let size = ST7789_OUTPUT_SIZE as usize;
+        let pixel = |x: usize, y: usize| panel[y * size + x];
+        assert_eq!(pixel(0, 0), WHITE_RGB565); // Stem and top bar
+        assert_eq!(pixel(size - 1, 0), WHITE_RGB565); // End of the top bar
+        assert_eq!(pixel(0, size - 1), WHITE_RGB565); // Foot of the stem
+        assert_eq!(pixel(size - 1, size - 1), 0); // Nothing at the bottom-right
+        assert_eq!(pixel(size / 2, size / 2), WHITE_RGB565); // Middle bar
+        assert_eq!(pixel(size - 1, size / 2), 0);
     }
 
-    output
-}
\ No newline at end of file
+    #[test]
+    fn compiles_fragment_shader_from_source() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).build();
+        let renderer = &mut test.renderer;
+        renderer.compile_fragment_source("stdin.frag", SOLID_COLOR_SHADER);
+        assert_eq!(renderer.shader_error(), None);
+        renderer.update_uniforms(0.0, 0.0, [0.0; 3], [0.0; 4]);
+        renderer.render();
+        renderer.compile_fragment_source("stdin.wgsl", SOLID_COLOR_WGSL_SHADER);
+        assert_eq!(renderer.shader_error(), None);
+        renderer.render();
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 2);
+        for frame in frames.iter() {
+            assert_solid_red(frame, ST7789_OUTPUT_SIZE);
+        }
+    }
+
+    #[test]
+    fn sanitize_replaces_non_finite_shader_output() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(NON_FINITE_SHADER_NAME, NON_FINITE_SHADER).build();
+        test.renderer.set_sanitize(true);
+        test.renderer.recompile_shaders(NON_FINITE_SHADER_NAME, false, true);
+        test.render_frames(1);
+
+        // NaN and -infinity become 0, +infinity becomes full intensity
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].pixels().iter().all(|&pixel| pixel == GREEN_RGB565));
+    }
+
+    #[test]
+    fn present_raw_sends_frame_without_shader() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).build();
+
+        // Red top row and blue bottom row
+        let mut rgba = [255, 0, 0, 255].repeat(3);
+        rgba.extend([0, 0, 255, 255].repeat(3));
+        test.renderer.present_raw(&rgba, 3, 2);
+
+        let frames = test.take_frames(0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].width, frames[0].height), (3, 2));
+        assert_eq!(frames[0].pixels(), [RED_RGB565, RED_RGB565, RED_RGB565, 0x001F, 0x001F, 0x001F]);
+    }
+
+    #[test]
+    fn fades_out_to_black_on_shutdown() {
+        if !can_render() {
+            return;
+        }
+
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).build();
+        test.renderer.set_fade_out(Some(Duration::from_millis(100)));
+        test.renderer.render();
+        test.renderer.shutdown();
+
+        // The red channel only gets darker, from the full frame to the cleared one
+        let frames = test.take_frames(0);
+        assert!(frames.len() > 2);
+        let red_levels: Vec<u16> = frames.iter().map(|frame| frame.pixels()[0] >> 11).collect();
+        assert_eq!(red_levels[0], 31);
+        assert_eq!(*red_levels.last().unwrap(), 0);
+        assert!(red_levels.windows(2).all(|pair| pair[1] <= pair[0]));
+    }
+
+    #[test]
+    fn captures_frame_as_png() {
+        if !can_render() {
+            return;
+        }
+
+        // Neither a display nor --once, so the offscreen texture is created by the capture
+        let mut test = TestRenderer::builder().shader(SOLID_COLOR_SHADER_NAME, SOLID_COLOR_SHADER).displays(0).build();
+        let png_bytes = test.renderer.capture_png().unwrap();
+
+        let image = image::load_from_memory_with_format(&png_bytes, image::ImageFormat::Png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (ST7789_OUTPUT_SIZE, ST7789_OUTPUT_SIZE));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+    }
+
+    // Renders on the software adapter so the pixels are the same on every machine, regenerate with UPDATE_GOLDEN_IMAGES=1
+    #[test]
+    fn software_render_matches_golden_image() {
+        if !can_render() {
+            return;
+        }
+        let fallback_adapter = block_on(wgpu::Instance::default().request_adapter(&wgpu::RequestAdapterOptions { force_fallback_adapter: true, ..Default::default() }));
+        if fallback_adapter.is_none() {
+            println!("Skipping: no software adapter available, install lavapipe (mesa-vulkan-drivers) to run this test");
+            return;
+        }
+
+        let gpu_options = GpuOptions { max_texture_size: Some(GOLDEN_IMAGE_SIZE), force_fallback_adapter: true, ..GpuOptions::default() };
+        let mut test = TestRenderer::builder().shader(GRADIENT_SHADER_NAME, GRADIENT_SHADER).displays(0).offscreen().gpu_options(gpu_options).build();
+        test.renderer.update_uniforms(0.0, 0.0, [0.0; 3], [0.0; 4]);
+
+        let image_path = test.path("gradient.png");
+        test.renderer.render_to_file(image_path.to_str().unwrap()).unwrap();
+        let image = image::open(&image_path).unwrap().to_rgba8();
+
+        let golden_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join("golden").join("gradient.png");
+        if std::env::var_os("UPDATE_GOLDEN_IMAGES").is_some() {
+            fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            image.save(&golden_path).unwrap();
+        }
+        let golden = image::open(&golden_path).unwrap().to_rgba8();
+
+        assert_eq!(image.dimensions(), golden.dimensions());
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let golden_pixel = golden.get_pixel(x, y);
+            let matches = pixel.0.iter().zip(golden_pixel.0.iter()).all(|(&a, &b)| a.abs_diff(b) <= GOLDEN_IMAGE_TOLERANCE);
+            assert!(matches, "pixel ({}, {}) is {:?}, expected {:?}", x, y, pixel.0, golden_pixel.0);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use rppal::gpio::{Gpio, InputPin, Trigger};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const PUSH_DEBOUNCE: Duration = Duration::from_millis(30);
+const TRANSITIONS_PER_DETENT: i32 = 4; // Common encoders go through all four states from one click to the next
+
+// Direction of a transition from the previous to the current state (a << 1 | b), indexed by previous << 2 | current.
+// Impossible transitions (both pins changed, a missed edge) count as 0.
+const TRANSITION_DIRECTIONS: [i32; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncoderEvent {
+    Turned(i32), // Clicks turned, positive clockwise
+    Pressed,
+}
+
+// Gpios of the encoder, its A and B outputs and optionally its push switch, all wired to ground when closed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderPins {
+    pub a: u8,
+    pub b: u8,
+    pub push: Option<u8>,
+}
+
+// Parses the pins given as <a>,<b> or <a>,<b>,<push>
+pub fn parse_pins(text: &str) -> Result<EncoderPins, String> {
+    let pins = text.split(',').map(|pin| pin.trim().parse::<u8>().map_err(|_| format!("{} is not a GPIO number", pin))).collect::<Result<Vec<_>, _>>()?;
+    match pins[..] {
+        [a, b] => Ok(EncoderPins { a, b, push: None }),
+        [a, b, push] => Ok(EncoderPins { a, b, push: Some(push) }),
+        _ => Err(format!("{} must be <a>,<b> or <a>,<b>,<push>", text)),
+    }
+}
+
+// Turns the edges of the A and B outputs into clicks
+#[derive(Debug)]
+struct QuadratureDecoder {
+    a: bool,
+    b: bool,
+    transitions: i32, // Counted since the last whole click
+}
+
+impl QuadratureDecoder {
+    fn new(a: bool, b: bool) -> Self {
+        Self { a, b, transitions: 0 }
+    }
+
+    // Applies the new levels, returns +1 or -1 once a whole click was turned
+    fn update(&mut self, a: bool, b: bool) -> Option<i32> {
+        let previous = (self.a as usize) << 1 | self.b as usize;
+        let current = (a as usize) << 1 | b as usize;
+        self.a = a;
+        self.b = b;
+        self.transitions += TRANSITION_DIRECTIONS[previous << 2 | current];
+        if self.transitions.abs() < TRANSITIONS_PER_DETENT {
+            return None;
+        }
+        let click = self.transitions.signum();
+        self.transitions = 0;
+        Some(click)
+    }
+}
+
+// Reads a quadrature rotary encoder with interrupts in the background, its clicks and presses arrive on a channel
+pub struct RotaryEncoder {
+    _pins: Vec<InputPin>, // Kept so their interrupts stay active
+    events: Receiver<EncoderEvent>,
+}
+
+impl RotaryEncoder {
+    // Starts watching the encoder, fails if a GPIO can not be used
+    pub fn start(pins: EncoderPins) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let get_pin = |pin_number: u8| -> Result<InputPin, Box<dyn Error>> {
+            Ok(gpio.get(pin_number).map_err(|err| format!("GPIO {}: {}", pin_number, err))?.into_input_pullup())
+        };
+        let mut a_pin = get_pin(pins.a)?;
+        let mut b_pin = get_pin(pins.b)?;
+        let decoder = Arc::new(Mutex::new(QuadratureDecoder::new(a_pin.is_high(), b_pin.is_high())));
+        let (sender, events) = mpsc::channel();
+
+        // Every edge of either output moves the decoder, the level of the other output is kept by the decoder
+        let watch = |pin: &mut InputPin, is_a: bool, decoder: Arc<Mutex<QuadratureDecoder>>, sender: Sender<EncoderEvent>| {
+            pin.set_async_interrupt(Trigger::Both, None, move |event| {
+                let high = event.trigger == Trigger::RisingEdge;
+                let mut decoder = decoder.lock().unwrap();
+                let (a, b) = if is_a { (high, decoder.b) } else { (decoder.a, high) };
+                if let Some(click) = decoder.update(a, b) {
+                    _ = sender.send(EncoderEvent::Turned(click));
+                }
+            })
+        };
+        watch(&mut a_pin, true, decoder.clone(), sender.clone())?;
+        watch(&mut b_pin, false, decoder, sender.clone())?;
+
+        let mut watched_pins = vec![a_pin, b_pin];
+        if let Some(push_pin_number) = pins.push {
+            let mut push_pin = get_pin(push_pin_number)?;
+            push_pin.set_async_interrupt(Trigger::FallingEdge, Some(PUSH_DEBOUNCE), move |_| _ = sender.send(EncoderEvent::Pressed))?;
+            watched_pins.push(push_pin);
+        }
+        Ok(Self { _pins: watched_pins, events })
+    }
+
+    // Returns the oldest event which was not taken yet
+    pub fn take_event(&self) -> Option<EncoderEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Levels of A and B through one click clockwise, starting and ending at rest (both pulled high)
+    const CLOCKWISE: [(bool, bool); 4] = [(false, true), (false, false), (true, false), (true, true)];
+
+    #[test]
+    fn decodes_whole_clicks_in_both_directions() {
+        let mut decoder = QuadratureDecoder::new(true, true);
+        let clicks: Vec<Option<i32>> = CLOCKWISE.iter().map(|&(a, b)| decoder.update(a, b)).collect();
+        assert_eq!(clicks, [None, None, None, Some(1)]);
+
+        let clicks: Vec<Option<i32>> = CLOCKWISE.iter().rev().skip(1).chain([&(true, true)]).map(|&(a, b)| decoder.update(a, b)).collect();
+        assert_eq!(clicks, [None, None, None, Some(-1)]);
+    }
+
+    #[test]
+    fn ignores_bounce_within_a_click() {
+        let mut decoder = QuadratureDecoder::new(true, true);
+        assert_eq!(decoder.update(false, true), None);
+        assert_eq!(decoder.update(true, true), None); // Bounced back
+        assert_eq!(decoder.transitions, 0);
+    }
+
+    #[test]
+    fn parses_pins() {
+        assert_eq!(parse_pins("5,6"), Ok(EncoderPins { a: 5, b: 6, push: None }));
+        assert_eq!(parse_pins("5,6,13"), Ok(EncoderPins { a: 5, b: 6, push: Some(13) }));
+        assert!(parse_pins("5").is_err());
+        assert!(parse_pins("5,x").is_err());
+    }
+}
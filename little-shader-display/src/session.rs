@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::input_message::Command;
+
+// Recorded session, e.g. for a demo loop: commands with the time they were applied at,
+// in seconds since the start of the session
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub duration: f32, // Replay restarts after this many seconds
+    pub events: Vec<SessionEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub time: f32,
+    pub command: Command,
+}
+
+// Collects the applied commands and writes them to a JSON file when the app shuts down
+pub struct SessionRecorder {
+    path: PathBuf,
+    start_time: Instant,
+    session: Session,
+}
+
+impl SessionRecorder {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), start_time: Instant::now(), session: Session::default() }
+    }
+
+    pub fn record(&mut self, command: Command) {
+        let time = self.start_time.elapsed().as_secs_f32();
+        self.session.events.push(SessionEvent { time, command });
+    }
+
+    pub fn save(&mut self) {
+        self.session.duration = self.start_time.elapsed().as_secs_f32();
+        let json = serde_json::to_string_pretty(&self.session).expect("Failed to serialize session");
+        match fs::write(&self.path, json) {
+            Ok(()) => println!("Saved session with {} events to {}", self.session.events.len(), self.path.display()),
+            Err(err) => println!("Warning: Failed to save session to {}: {}", self.path.display(), err),
+        }
+    }
+}
+
+// Replays a recorded session in a loop, handing out the commands as they become due
+pub struct SessionPlayer {
+    session: Session,
+    start_time: Instant,
+    next_event: usize,
+}
+
+impl SessionPlayer {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let session = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(Self::new(session))
+    }
+
+    fn new(session: Session) -> Self {
+        Self { session, start_time: Instant::now(), next_event: 0 }
+    }
+
+    // Returns true if the session was restarted, followed by the commands which became due since the last call
+    pub fn poll(&mut self) -> (bool, Vec<Command>) {
+        let elapsed = self.start_time.elapsed().as_secs_f32();
+        let restarted = self.session.duration > 0.0 && elapsed >= self.session.duration;
+        if restarted {
+            self.start_time = Instant::now();
+            self.next_event = 0;
+        }
+        (restarted, self.take_due_commands(if restarted { 0.0 } else { elapsed }))
+    }
+
+    fn take_due_commands(&mut self, elapsed: f32) -> Vec<Command> {
+        let due_events = self.session.events[self.next_event..].iter().take_while(|event| event.time <= elapsed);
+        let commands: Vec<Command> = due_events.map(|event| event.command.clone()).collect();
+        self.next_event += commands.len();
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader_command(name: &str) -> Command {
+        Command { shader: Some(name.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn session_round_trips_through_json() {
+        let session = Session {
+            duration: 12.5,
+            events: vec![
+                SessionEvent { time: 0.0, command: shader_command("waves.frag") },
+                SessionEvent { time: 3.0, command: Command { params: Some(vec![0.5, -1.0, 0.0]), pause: Some(true), ..Default::default() } },
+            ],
+        };
+
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(json.contains(r#"{"time":0.0,"command":{"shader":"waves.frag"}}"#), "{}", json);
+        assert_eq!(serde_json::from_str::<Session>(&json).unwrap(), session);
+    }
+
+    #[test]
+    fn hands_out_each_command_once_when_due() {
+        let mut player = SessionPlayer::new(Session {
+            duration: 10.0,
+            events: vec![
+                SessionEvent { time: 0.0, command: shader_command("waves.frag") },
+                SessionEvent { time: 1.0, command: shader_command("grid.frag") },
+                SessionEvent { time: 2.0, command: shader_command("rings.frag") },
+            ],
+        });
+
+        assert_eq!(player.take_due_commands(0.5), [shader_command("waves.frag")]);
+        assert!(player.take_due_commands(0.9).is_empty());
+        assert_eq!(player.take_due_commands(5.0), [shader_command("grid.frag"), shader_command("rings.frag")]);
+        assert!(player.take_due_commands(6.0).is_empty());
+    }
+
+    #[test]
+    fn first_poll_returns_commands_at_start() {
+        let mut player = SessionPlayer::new(Session { duration: 10.0, events: vec![SessionEvent { time: 0.0, command: shader_command("waves.frag") }] });
+        assert_eq!(player.poll(), (false, vec![shader_command("waves.frag")]));
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+
+use crate::shader_dependencies::include_directive;
+
+// Compiles GLSL shaders to SPIR-V with naga's GLSL frontend, in-process so no shader compiler has to be installed.
+// Includes are expanded before parsing, errors name the file and line they come from, also inside include files.
+
+// Expanded source of a shader with the file and line every line of it comes from
+struct ExpandedSource {
+    text: String,
+    lines: Vec<(String, usize)>, // File name and 1-based line number, per line of the text
+}
+
+// Compiles the GLSL source of the given shader (.vert or .frag) to SPIR-V words.
+// Includes are looked up next to the including file first, then in the include directory.
+pub fn compile(shader_path: &Path, source: &str, include_directory: &Path, debug_info: bool) -> Result<Vec<u32>, String> {
+    let stage = if shader_path.extension().is_some_and(|extension| extension == "vert") {
+        ShaderStage::Vertex
+    } else {
+        ShaderStage::Fragment
+    };
+    let file_name = file_name(shader_path);
+    let mut expanded = ExpandedSource { text: String::new(), lines: Vec::new() };
+    expand_includes(source, &file_name, shader_path.parent().unwrap_or(include_directory), include_directory, &mut vec![shader_path.canonicalize().unwrap_or_else(|_| shader_path.to_path_buf())], &mut expanded)?;
+
+    let module = Frontend::default().parse(&Options::from(stage), &expanded.text).map_err(|errors| {
+        errors.iter().map(|error| format!("{}: error: {}", expanded.location(error.meta.location(&expanded.text)), error.kind)).collect::<Vec<_>>().join("\n")
+    })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module).map_err(|error| {
+        let location = error.location(&expanded.text).map(|location| format!("{}: ", expanded.location(location))).unwrap_or_else(|| format!("{}: ", file_name));
+        // Validation errors name the invalid function, their sources tell what is wrong in it
+        let mut message = format!("{}error: {}", location, error.as_inner());
+        let mut source = std::error::Error::source(error.as_inner());
+        while let Some(error) = source {
+            message.push_str(&format!(": {}", error));
+            source = error.source();
+        }
+        message
+    })?;
+
+    // The source is already in Vulkan's coordinate space, so it is written without adjusting it
+    let mut options = naga::back::spv::Options::default();
+    options.flags.remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    options.flags.set(naga::back::spv::WriterFlags::DEBUG, debug_info);
+    naga::back::spv::write_vec(&module, &info, &options, None).map_err(|error| format!("{}: error: {}", file_name, error))
+}
+
+impl ExpandedSource {
+    // Names the file, line and column of a location in the expanded text
+    fn location(&self, location: naga::SourceLocation) -> String {
+        match self.lines.get(location.line_number as usize - 1) {
+            Some((file_name, line)) => format!("{}:{}:{}", file_name, line, location.line_position),
+            None => format!("{}:{}", self.lines.last().map_or("", |(file_name, _)| file_name), location.line_number),
+        }
+    }
+
+    fn push_line(&mut self, line: &str, file_name: &str, line_number: usize) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.lines.push((file_name.to_string(), line_number));
+    }
+}
+
+// Appends the source to the expanded text, replacing #include "path" and #include <path> lines with the included files.
+// #extension GL_GOOGLE_include_directive lines are dropped, naga does not know the extension. Includes of a file which is being expanded fail.
+fn expand_includes(source: &str, file_name: &str, directory: &Path, include_directory: &Path, stack: &mut Vec<PathBuf>, expanded: &mut ExpandedSource) -> Result<(), String> {
+    for (index, line) in source.lines().enumerate() {
+        let directive = line.trim_start().strip_prefix('#').map(str::trim_start);
+        if directive.is_some_and(|directive| directive.starts_with("extension") && directive.contains("GL_GOOGLE_include_directive")) {
+            expanded.push_line("", file_name, index + 1);
+            continue;
+        }
+        let Some(include_name) = include_directive(line) else {
+            expanded.push_line(line, file_name, index + 1);
+            continue;
+        };
+
+        let location = format!("{}:{}", file_name, index + 1);
+        let include_name = include_name.map_err(|_| format!("{}: error: #include expects \"path\" or <path>", location))?;
+        let include_path = [directory.join(include_name), include_directory.join(include_name)]
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("{}: error: cannot find include file {}", location, include_name))?;
+        let canonical_path = include_path.canonicalize().unwrap_or_else(|_| include_path.clone());
+        if stack.contains(&canonical_path) {
+            return Err(format!("{}: error: {} includes itself", location, include_name));
+        }
+
+        let include_source = fs::read(&include_path).map_err(|err| format!("{}: error: {}: {}", location, include_name, err))?;
+        let include_source = String::from_utf8(crate::renderer::sanitize_shader_source(&include_source)).map_err(|_| format!("{}: error: {} is not UTF-8", location, include_name))?;
+        stack.push(canonical_path);
+        expand_includes(&include_source, include_name, include_path.parent().unwrap_or(directory), include_directory, stack, expanded)?;
+        stack.pop();
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes the files into a new directory, removed when the returned guard is dropped
+    struct TestDirectory(PathBuf);
+
+    impl TestDirectory {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let path = std::env::temp_dir().join(format!("little-shader-display-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            for (file_name, source) in files {
+                fs::write(path.join(file_name), source).unwrap();
+            }
+            Self(path)
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const COMMON: &str = "vec4 red() {\n    return vec4(1.0, 0.0, 0.0, 1.0);\n}\n";
+    const SHADER: &str = "#version 450\n#extension GL_GOOGLE_include_directive : require\n#include \"common.glsl\"\nlayout(location = 0) out vec4 out_final_color;\nvoid main() {\n    out_final_color = red();\n}\n";
+
+    #[test]
+    fn compiles_shader_with_include() {
+        let directory = TestDirectory::new("compiler-include", &[("common.glsl", COMMON)]);
+        let spirv = compile(&directory.0.join("red.frag"), SHADER, &directory.0, false).unwrap();
+        assert_eq!(spirv[0], 0x07230203); // SPIR-V magic number
+    }
+
+    #[test]
+    fn errors_name_file_and_line_inside_includes() {
+        let directory = TestDirectory::new("compiler-error", &[("common.glsl", "vec4 red() {\n    return undefined_color;\n}\n")]);
+        let error = compile(&directory.0.join("red.frag"), SHADER, &directory.0, false).unwrap_err();
+        assert!(error.starts_with("common.glsl:2:"), "{}", error);
+
+        let error = compile(&directory.0.join("broken.frag"), "#version 450\nvoid main() {\n    undefined_name;\n}\n", &directory.0, false).unwrap_err();
+        assert!(error.starts_with("broken.frag:3:"), "{}", error);
+    }
+
+    #[test]
+    fn missing_and_recursive_includes_fail() {
+        let directory = TestDirectory::new("compiler-recursive", &[("loop.glsl", "#include \"loop.glsl\"\n")]);
+        let error = compile(&directory.0.join("red.frag"), SHADER, &directory.0, false).unwrap_err();
+        assert_eq!(error, "red.frag:3: error: cannot find include file common.glsl");
+
+        let error = compile(&directory.0.join("loop.frag"), "#version 450\n#include \"loop.glsl\"\n", &directory.0, false).unwrap_err();
+        assert_eq!(error, "loop.glsl:1: error: loop.glsl includes itself");
+    }
+}
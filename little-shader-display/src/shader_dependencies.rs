@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Tracks the files each shader includes with #include, so a change to a shared include file (e.g. common.glsl)
+// can be traced back to every shader using it, directly or through other include files
+#[derive(Default)]
+pub struct ShaderDependencies {
+    includes: HashMap<String, HashSet<String>>, // File name -> names of the files it includes directly
+}
+
+impl ShaderDependencies {
+    // Reads the includes of all files in the shaders directory
+    pub fn scan(directory: &Path) -> Self {
+        let mut dependencies = Self::default();
+        if let Ok(entries) = fs::read_dir(directory) {
+            for entry in entries.filter_map(Result::ok) {
+                dependencies.update(&entry.path());
+            }
+        }
+        dependencies
+    }
+
+    // Re-reads the includes of a changed file, a deleted file is forgotten
+    pub fn update(&mut self, path: &Path) {
+        let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+            return;
+        };
+        match fs::read_to_string(path) {
+            Ok(source) => self.set_source(file_name, &source),
+            Err(_) => {
+                self.includes.remove(file_name);
+            }
+        }
+    }
+
+    fn set_source(&mut self, file_name: &str, source: &str) {
+        let includes = parse_includes(source);
+        if includes.is_empty() {
+            self.includes.remove(file_name);
+        } else {
+            self.includes.insert(file_name.to_string(), includes);
+        }
+    }
+
+    // Returns the files which include the given file directly or indirectly, sorted by name
+    pub fn dependents(&self, file_name: &str) -> Vec<String> {
+        let mut dependents = HashSet::new();
+        let mut pending = vec![file_name];
+        while let Some(included) = pending.pop() {
+            for (file, includes) in &self.includes {
+                if includes.contains(included) && dependents.insert(file.clone()) {
+                    pending.push(file);
+                }
+            }
+        }
+
+        let mut dependents: Vec<String> = dependents.into_iter().filter(|dependent| dependent != file_name).collect();
+        dependents.sort();
+        dependents
+    }
+}
+
+// Returns the paths in #include "path" and #include <path> directives, relative to the shaders directory
+fn parse_includes(source: &str) -> HashSet<String> {
+    source.lines()
+        .filter_map(|line| include_directive(line)?.ok())
+        .map(str::to_string)
+        .collect()
+}
+
+// Returns the path of an #include "path" or #include <path> line, or an error for an #include without a valid path.
+// Anything after the path, e.g. a comment, is ignored. Lines which are no include directive return None.
+pub fn include_directive(line: &str) -> Option<Result<&str, ()>> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+    let closing = match rest.chars().next() {
+        Some('"') => '"',
+        Some('<') => '>',
+        _ => return Some(Err(())),
+    };
+    let path = &rest[1..];
+    Some(path.find(closing).map(|end| &path[..end]).ok_or(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_include_directive_paths() {
+        assert_eq!(include_directive("#include \"common.glsl\" // shared helpers"), Some(Ok("common.glsl")));
+        assert_eq!(include_directive("  #  include <lib/noise.glsl>"), Some(Ok("lib/noise.glsl")));
+        assert_eq!(include_directive("#include common.glsl"), Some(Err(())));
+        assert_eq!(include_directive("#include \"common.glsl"), Some(Err(())));
+        assert_eq!(include_directive("#version 450"), None);
+    }
+
+    #[test]
+    fn parses_include_directives() {
+        let source = "#version 450\n#extension GL_GOOGLE_include_directive : require\n#include \"common.glsl\"\n  # include <noise.glsl>\n// #include \"commented.glsl\"\n#include broken.glsl\n";
+        let includes = parse_includes(source);
+        assert_eq!(includes, HashSet::from(["common.glsl".to_string(), "noise.glsl".to_string()]));
+    }
+
+    #[test]
+    fn finds_direct_and_indirect_dependents() {
+        let mut dependencies = ShaderDependencies::default();
+        dependencies.set_source("waves.frag", "#include \"common.glsl\"");
+        dependencies.set_source("grid.frag", "#include \"noise.glsl\"");
+        dependencies.set_source("noise.glsl", "#include \"common.glsl\"");
+        dependencies.set_source("rings.frag", "void main() {}");
+
+        assert_eq!(dependencies.dependents("common.glsl"), ["grid.frag", "noise.glsl", "waves.frag"]);
+        assert_eq!(dependencies.dependents("noise.glsl"), ["grid.frag"]);
+        assert!(dependencies.dependents("rings.frag").is_empty());
+
+        // Dropping the include updates the graph
+        dependencies.set_source("noise.glsl", "float noise() { return 0.0; }");
+        assert_eq!(dependencies.dependents("common.glsl"), ["waves.frag"]);
+    }
+
+    #[test]
+    fn include_cycles_terminate() {
+        let mut dependencies = ShaderDependencies::default();
+        dependencies.set_source("a.glsl", "#include \"b.glsl\"");
+        dependencies.set_source("b.glsl", "#include \"a.glsl\"");
+        assert_eq!(dependencies.dependents("a.glsl"), ["b.glsl"]);
+    }
+}
@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+use crate::multipass::{self, CHANNEL_COUNT};
+use crate::user_params::{self, UserParam};
+
+// Optional per-shader settings read from a sidecar file next to the shader (e.g. trails.frag.toml):
+//   clear = false                       # Keep the previous frame instead of clearing the target (trails, accumulation)
+//   clear_color = [0.0, 0.0, 0.0, 1.0]  # Color the target is cleared to when clear = true
+//   flip_uv = true                      # Put texture coordinate (0,0) at the top-left, overrides --flip-uv
+//   target_fps = 15.0                   # Frame rate limit while the shader is shown, overrides --max-fps
+//   duration = 30.0                    # Seconds the shader is shown before the playlist advances to the next one
+//   rotation = 90                       # Degrees the shader is turned clockwise on the ST7789, e.g. for portrait designs
+//   feedback = true                     # Bind the previous frame of the shader as previous_frame (trails, simulations)
+//   channels = ["blur", "noise.png"]    # Buffers, images from res/textures or inputs (audio, keyboard, camera) bound to channel0..3 of the shader
+//   [[params]]                          # Named slots of the user_params uniform, in this order, changed at runtime
+//   name = "speed"
+//   default = 1.0
+//   min = 0.0
+//   max = 4.0
+//   [[buffers]]                         # Buffer passes rendered before the shader, in this order
+//   name = "blur"
+//   shader = "blur.buffer.frag"
+//   channels = ["scene"]                # Buffers of earlier passes or images bound to channel0..3 of this pass
+// Missing keys keep their default values, a missing file means all defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShaderMetadata {
+    pub clear: bool,
+    pub clear_color: [f64; 4],
+    pub flip_uv: Option<bool>,
+    pub target_fps: Option<f32>,
+    pub duration: Option<f32>,
+    pub rotation: u32,
+    pub feedback: bool,
+    pub channels: Vec<String>,
+    pub params: Vec<UserParam>,
+    pub buffers: Vec<BufferMetadata>,
+}
+
+impl Default for ShaderMetadata {
+    fn default() -> Self {
+        Self { clear: true, clear_color: [0.0, 0.0, 0.0, 1.0], flip_uv: None, target_fps: None, duration: None, rotation: 0, feedback: false, channels: Vec::new(), params: Vec::new(), buffers: Vec::new() }
+    }
+}
+
+// A buffer pass, rendering a shader into a texture named after the pass which later passes can sample
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BufferMetadata {
+    pub name: String,
+    pub shader: String,
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+impl ShaderMetadata {
+    // Reads the sidecar of the given shader, falling back to defaults if it is missing or invalid
+    pub fn load(shader_path: &Path) -> Self {
+        let sidecar_path = sidecar_path(shader_path);
+        let Ok(text) = fs::read_to_string(&sidecar_path) else {
+            return Self::default();
+        };
+
+        match toml::from_str::<Self>(&text) {
+            Ok(metadata) => match metadata.validate() {
+                Ok(()) => {
+                    println!("Loaded shader metadata from {:?}", sidecar_path);
+                    metadata
+                }
+                Err(err) => {
+                    println!("Warning: Ignoring invalid shader metadata {:?}: {}", sidecar_path, err);
+                    Self::default()
+                }
+            },
+            Err(err) => {
+                println!("Warning: Ignoring invalid shader metadata {:?}: {}", sidecar_path, err);
+                Self::default()
+            }
+        }
+    }
+
+    // Checks the values which can not be expressed by the types, e.g. channels naming buffers which do not exist
+    fn validate(&self) -> Result<(), String> {
+        if self.target_fps.is_some_and(|fps| fps <= 0.0) {
+            return Err("target_fps must be greater than 0".to_string());
+        }
+        if self.duration.is_some_and(|duration| duration <= 0.0) {
+            return Err("duration must be greater than 0".to_string());
+        }
+        if !matches!(self.rotation, 0 | 90 | 180 | 270) {
+            return Err("rotation must be 0, 90, 180 or 270".to_string());
+        }
+        user_params::validate(&self.params)?;
+        // The shader renders into a new buffer every frame, so there is no target contents to keep
+        if self.feedback && !self.clear {
+            return Err("feedback shaders read their previous frame from previous_frame, clear = false is not supported with feedback".to_string());
+        }
+
+        // Every pass can only read the buffers of the passes before it
+        let mut buffer_names: Vec<&str> = Vec::new();
+        for buffer in &self.buffers {
+            if !multipass::is_buffer_shader(&buffer.shader) {
+                return Err(format!("buffer shader {} must end with .buffer.frag or .buffer.wgsl", buffer.shader));
+            }
+            validate_channels(&format!("buffer {}", buffer.name), &buffer.channels, &buffer_names)?;
+            if buffer_names.contains(&buffer.name.as_str()) {
+                return Err(format!("buffer {} is defined twice", buffer.name));
+            }
+            if multipass::is_input_channel(&buffer.name) {
+                return Err(format!("buffer name {} is reserved for an input channel", buffer.name));
+            }
+            buffer_names.push(&buffer.name);
+        }
+        validate_channels("the shader", &self.channels, &buffer_names)
+    }
+
+    // Rotation on the ST7789 in clockwise quarter turns
+    pub fn quarter_turns(&self) -> u32 {
+        self.rotation / 90
+    }
+
+    // How the render target is prepared before the shader draws into it
+    pub fn load_op(&self) -> wgpu::LoadOp<wgpu::Color> {
+        if self.clear {
+            let [r, g, b, a] = self.clear_color;
+            wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a })
+        } else {
+            wgpu::LoadOp::Load
+        }
+    }
+}
+
+// Returns true if the file is a shader sidecar, e.g. waves.frag.toml or plasma.wgsl.toml
+pub fn is_sidecar(file_name: &str) -> bool {
+    file_name.ends_with(".frag.toml") || file_name.ends_with(".wgsl.toml")
+}
+
+fn validate_channels(pass: &str, channels: &[String], buffer_names: &[&str]) -> Result<(), String> {
+    if channels.len() > CHANNEL_COUNT {
+        return Err(format!("{} has {} channels, at most {} are supported", pass, channels.len(), CHANNEL_COUNT));
+    }
+    let is_valid = |channel: &String| buffer_names.contains(&channel.as_str()) || multipass::is_image_file(channel) || multipass::is_input_channel(channel);
+    match channels.iter().find(|channel| !is_valid(channel)) {
+        Some(channel) => Err(format!("{} reads channel {} which is neither a buffer of an earlier pass, an image nor an input", pass, channel)),
+        None => Ok(()),
+    }
+}
+
+fn sidecar_path(shader_path: &Path) -> PathBuf {
+    let mut path = shader_path.as_os_str().to_owned();
+    path.push(".toml");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<(), String> {
+        toml::from_str::<ShaderMetadata>(text).unwrap().validate()
+    }
+
+    #[test]
+    fn accepts_buffers_reading_earlier_buffers() {
+        let metadata = "channels = [\"b\", \"a\", \"noise.png\", \"keyboard\"]
+[[buffers]]
+name = \"a\"
+shader = \"a.buffer.frag\"
+[[buffers]]
+name = \"b\"
+shader = \"b.buffer.wgsl\"
+channels = [\"a\", \"audio\"]
+";
+        assert_eq!(parse(metadata), Ok(()));
+    }
+
+    #[test]
+    fn rejects_channels_of_missing_or_later_buffers() {
+        assert!(parse("channels = [\"a\"]").is_err());
+        assert!(parse("[[buffers]]\nname = \"a\"\nshader = \"a.buffer.frag\"\nchannels = [\"a\"]").is_err());
+        assert!(parse("[[buffers]]\nname = \"a\"\nshader = \"a.frag\"").is_err());
+        assert!(parse("target_fps = 0.0").is_err());
+        assert!(parse("[[buffers]]\nname = \"audio\"\nshader = \"a.buffer.frag\"").is_err());
+        assert!(parse("feedback = true\nclear = false").is_err());
+    }
+
+    #[test]
+    fn checks_duration_and_rotation() {
+        let metadata: ShaderMetadata = toml::from_str("duration = 30.0\nrotation = 270").unwrap();
+        assert_eq!(metadata.validate(), Ok(()));
+        assert_eq!((metadata.duration, metadata.quarter_turns()), (Some(30.0), 3));
+        assert!(parse("duration = 0.0").is_err());
+        assert!(parse("rotation = 45").is_err());
+        assert!(parse("rotation = 360").is_err());
+    }
+
+    #[test]
+    fn reads_params() {
+        let metadata: ShaderMetadata = toml::from_str("[[params]]\nname = \"speed\"\ndefault = 2.0\nmax = 4.0\nstep = 0.5").unwrap();
+        assert_eq!(metadata.validate(), Ok(()));
+        assert_eq!(metadata.params, vec![UserParam { name: "speed".to_string(), default: 2.0, min: 0.0, max: 4.0, step: Some(0.5) }]);
+        assert!(parse("[[params]]\nname = \"speed\"\ndefault = 2.0").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::OutputPin;
+
+// Default size of one SPI transfer, the default buffer size of the Linux spidev driver which rejects larger ones
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+// Display interface over SPI with a data/command pin, like SPIInterfaceNoCS from display-interface-spi,
+// but pixels are collected into transfers of chunk_size bytes instead of 128 bytes. Fewer and larger transfers
+// cost less CPU per frame and let the SPI controller move them with DMA.
+pub struct ChunkedSpiInterface<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<SPI, DC> ChunkedSpiInterface<SPI, DC>
+where
+    SPI: Write<u8>,
+    DC: OutputPin,
+{
+    pub fn new(spi: SPI, dc: DC, chunk_size: usize) -> Self {
+        assert!(chunk_size >= 2, "SPI chunk size must hold at least one pixel");
+        Self { spi, dc, buffer: Vec::with_capacity(chunk_size), chunk_size }
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), DisplayError> {
+        self.buffer.push(byte);
+        if self.buffer.len() == self.chunk_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn push_all(&mut self, bytes: impl Iterator<Item = u8>) -> Result<(), DisplayError> {
+        for byte in bytes {
+            self.push(byte)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), DisplayError> {
+        if !self.buffer.is_empty() {
+            let result = self.spi.write(&self.buffer).map_err(|_| DisplayError::BusWriteError);
+            self.buffer.clear();
+            result?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, words: DataFormat<'_>) -> Result<(), DisplayError> {
+        match words {
+            DataFormat::U8(bytes) => self.push_all(bytes.iter().copied())?,
+            DataFormat::U8Iter(bytes) => self.push_all(bytes)?,
+            DataFormat::U16BE(words) => self.push_all(words.iter().flat_map(|word| word.to_be_bytes()))?,
+            DataFormat::U16LE(words) => self.push_all(words.iter().flat_map(|word| word.to_le_bytes()))?,
+            DataFormat::U16BEIter(words) => self.push_all(words.flat_map(u16::to_be_bytes))?,
+            DataFormat::U16LEIter(words) => self.push_all(words.flat_map(u16::to_le_bytes))?,
+            _ => return Err(DisplayError::DataFormatNotImplemented),
+        }
+        self.flush()
+    }
+}
+
+impl<SPI, DC> WriteOnlyDataCommand for ChunkedSpiInterface<SPI, DC>
+where
+    SPI: Write<u8>,
+    DC: OutputPin,
+{
+    fn send_commands(&mut self, commands: DataFormat<'_>) -> Result<(), DisplayError> {
+        // DC low selects commands, high selects data
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.send(commands)
+    }
+
+    fn send_data(&mut self, data: DataFormat<'_>) -> Result<(), DisplayError> {
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.send(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::convert::Infallible;
+    use std::rc::Rc;
+    use display_interface_spi::SPIInterfaceNoCS;
+
+    // Records every SPI transfer together with the DC level it was sent with
+    #[derive(Default)]
+    struct Bus {
+        dc_high: bool,
+        transfers: Vec<(bool, Vec<u8>)>,
+    }
+
+    struct MockSpi(Rc<RefCell<Bus>>);
+    struct MockDc(Rc<RefCell<Bus>>);
+
+    impl Write<u8> for MockSpi {
+        type Error = Infallible;
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            let mut bus = self.0.borrow_mut();
+            let dc_high = bus.dc_high;
+            bus.transfers.push((dc_high, words.to_vec()));
+            Ok(())
+        }
+    }
+
+    impl OutputPin for MockDc {
+        type Error = Infallible;
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().dc_high = false;
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().dc_high = true;
+            Ok(())
+        }
+    }
+
+    // Sends a command and a frame of pixels the way the st7789 crate does
+    fn send_frame(interface: &mut impl WriteOnlyDataCommand, pixels: &[u16]) {
+        interface.send_commands(DataFormat::U8Iter(&mut [0x2C].into_iter())).unwrap();
+        interface.send_data(DataFormat::U16BEIter(&mut pixels.iter().copied())).unwrap();
+    }
+
+    // Joins the transfers into the bytes seen by the display, split where DC changes
+    fn stream(transfers: &[(bool, Vec<u8>)]) -> Vec<(bool, Vec<u8>)> {
+        let mut stream: Vec<(bool, Vec<u8>)> = Vec::new();
+        for (dc_high, bytes) in transfers {
+            match stream.last_mut() {
+                Some((last_dc_high, last_bytes)) if last_dc_high == dc_high => last_bytes.extend(bytes),
+                _ => stream.push((*dc_high, bytes.clone())),
+            }
+        }
+        stream
+    }
+
+    #[test]
+    fn sends_the_same_bytes_in_fewer_transfers() {
+        let pixels: Vec<u16> = (0..1000).map(|index| index * 37).collect();
+
+        let reference_bus = Rc::new(RefCell::new(Bus::default()));
+        send_frame(&mut SPIInterfaceNoCS::new(MockSpi(reference_bus.clone()), MockDc(reference_bus.clone())), &pixels);
+
+        let chunked_bus = Rc::new(RefCell::new(Bus::default()));
+        send_frame(&mut ChunkedSpiInterface::new(MockSpi(chunked_bus.clone()), MockDc(chunked_bus.clone()), 512), &pixels);
+
+        let reference_transfers = &reference_bus.borrow().transfers;
+        let chunked_transfers = &chunked_bus.borrow().transfers;
+        assert_eq!(stream(chunked_transfers), stream(reference_transfers));
+
+        // One command transfer, then 2000 pixel bytes in chunks of 512
+        let transfer_sizes: Vec<usize> = chunked_transfers.iter().map(|(_, bytes)| bytes.len()).collect();
+        assert_eq!(transfer_sizes, [1, 512, 512, 512, 464]);
+        assert!(reference_transfers.len() > chunked_transfers.len());
+    }
+}
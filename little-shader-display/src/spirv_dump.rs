@@ -0,0 +1,185 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use naga::proc::Layouter;
+use naga::{AddressSpace, Binding, Handle, Module, ScalarKind, Type, TypeInner};
+
+use crate::renderer::{self, ShaderCompileOptions};
+use crate::SHADERS_PATH;
+
+// Compiles the shader and prints its disassembly and layout. WGSL shaders are not compiled to SPIR-V,
+// only the layout naga reads from their source is printed.
+pub fn dump(shader_name: &str, options: ShaderCompileOptions) -> Result<(), String> {
+    let module = if shader_name.ends_with(".wgsl") {
+        let source = fs::read_to_string(SHADERS_PATH.join("uncompiled").join(shader_name)).map_err(|err| format!("{}: {}", shader_name, err))?;
+        naga::front::wgsl::parse_str(&source).map_err(|err| err.emit_to_string(&source))?
+    } else {
+        let spirv_path = renderer::compile_to_spirv(&SHADERS_PATH, shader_name, options)?;
+        let spirv = fs::read(&spirv_path).map_err(|err| format!("{}: {}", spirv_path.display(), err))?;
+        let module = naga::front::spv::parse_u8_slice(&spirv, &naga::front::spv::Options::default()).map_err(|err| format!("Failed to read SPIR-V: {:?}", err))?;
+        println!("SPIR-V written to {} ({} bytes)\n", spirv_path.display(), spirv.len());
+        println!("{}", disassemble(&spirv_path, &module)?);
+        module
+    };
+    print!("{}", describe(&module)?);
+    Ok(())
+}
+
+// Describes the entry points and the resource bindings of a shader module, with the offset and size naga derives
+// for every member of the uniform blocks. Used by --dump-spirv to track down misaligned uniforms and wrong entry points.
+pub fn describe(module: &Module) -> Result<String, String> {
+    let mut layouter = Layouter::default();
+    layouter.update(&module.types, &module.constants).map_err(|err| format!("Failed to compute type layouts: {}", err))?;
+
+    let mut text = String::new();
+    writeln!(text, "Entry points:").unwrap();
+    for entry_point in module.entry_points.iter() {
+        writeln!(text, "  {:?} {}", entry_point.stage, entry_point.name).unwrap();
+        for argument in entry_point.function.arguments.iter() {
+            let line = format!("    in  {}: {} {}", binding_name(argument.binding.as_ref()), type_name(module, argument.ty), argument.name.as_deref().unwrap_or(""));
+            writeln!(text, "{}", line.trim_end()).unwrap();
+        }
+        if let Some(result) = &entry_point.function.result {
+            writeln!(text, "    out {}: {}", binding_name(result.binding.as_ref()), type_name(module, result.ty)).unwrap();
+        }
+    }
+
+    writeln!(text, "Resources:").unwrap();
+    let mut globals: Vec<_> = module.global_variables.iter().filter_map(|(_, global)| global.binding.as_ref().map(|binding| (binding, global))).collect();
+    globals.sort_by_key(|(binding, _)| (binding.group, binding.binding));
+    for (binding, global) in globals {
+        let space = match global.space {
+            AddressSpace::Uniform => "uniform",
+            AddressSpace::Storage { .. } => "storage",
+            AddressSpace::Handle => "handle",
+            _ => "other",
+        };
+        writeln!(
+            text,
+            "  group {} binding {}: {} {} {} ({} bytes)",
+            binding.group,
+            binding.binding,
+            space,
+            type_name(module, global.ty),
+            global.name.as_deref().unwrap_or("-"),
+            layouter[global.ty].size,
+        ).unwrap();
+
+        write_members(&mut text, module, &layouter, global.ty, 0, 2);
+    }
+    Ok(text)
+}
+
+// Lists the members of a struct type with their offsets from the start of the binding, nested structs are expanded
+fn write_members(text: &mut String, module: &Module, layouter: &Layouter, ty: Handle<Type>, base_offset: u32, depth: usize) {
+    if let TypeInner::Struct { members, .. } = &module.types[ty].inner {
+        for member in members {
+            let offset = base_offset + member.offset;
+            writeln!(
+                text,
+                "{:indent$}offset {:>3} size {:>3}  {}: {}",
+                "",
+                offset,
+                layouter[member.ty].size,
+                member.name.as_deref().unwrap_or("-"),
+                type_name(module, member.ty),
+                indent = depth * 2,
+            ).unwrap();
+            write_members(text, module, layouter, member.ty, offset, depth + 1);
+        }
+    }
+}
+
+// Disassembles the SPIR-V file with spirv-dis if it is installed, otherwise translates the module to WGSL with naga
+pub fn disassemble(spirv_path: &Path, module: &Module) -> Result<String, String> {
+    if let Ok(output) = Command::new("spirv-dis").arg(spirv_path).output() {
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        println!("Warning: spirv-dis failed, showing the shader as WGSL instead: {}", String::from_utf8_lossy(&output.stderr));
+    } else {
+        println!("spirv-dis is not installed, showing the shader as WGSL translated by naga instead");
+    }
+    to_wgsl(module)
+}
+
+pub fn to_wgsl(module: &Module) -> Result<String, String> {
+    let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(module)
+        .map_err(|err| format!("Validation failed: {:?}", err))?;
+    naga::back::wgsl::write_string(module, &info, naga::back::wgsl::WriterFlags::empty()).map_err(|err| err.to_string())
+}
+
+fn binding_name(binding: Option<&Binding>) -> String {
+    match binding {
+        Some(Binding::Location { location, .. }) => format!("location {}", location),
+        Some(Binding::BuiltIn(built_in)) => format!("builtin {:?}", built_in),
+        None => "struct".to_string(),
+    }
+}
+
+// Name of the type as in the shader, or its WGSL spelling for unnamed vectors and matrices
+fn type_name(module: &Module, ty: Handle<Type>) -> String {
+    let ty = &module.types[ty];
+    if let Some(name) = &ty.name {
+        return name.clone();
+    }
+    match ty.inner {
+        TypeInner::Scalar { kind, width } => scalar_name(kind, width),
+        TypeInner::Vector { size, kind, width } => format!("vec{}<{}>", size as u8, scalar_name(kind, width)),
+        TypeInner::Matrix { columns, rows, width } => format!("mat{}x{}<{}>", columns as u8, rows as u8, scalar_name(ScalarKind::Float, width)),
+        TypeInner::Array { base, size: naga::ArraySize::Constant(size), .. } => match &module.constants[size].inner {
+            naga::ConstantInner::Scalar { value: naga::ScalarValue::Uint(count), .. } => format!("array<{}, {}>", type_name(module, base), count),
+            naga::ConstantInner::Scalar { value: naga::ScalarValue::Sint(count), .. } => format!("array<{}, {}>", type_name(module, base), count),
+            _ => format!("array<{}>", type_name(module, base)),
+        },
+        TypeInner::Array { base, .. } => format!("array<{}>", type_name(module, base)),
+        TypeInner::Struct { .. } => "struct".to_string(),
+        ref inner => format!("{:?}", inner),
+    }
+}
+
+fn scalar_name(kind: ScalarKind, width: u8) -> String {
+    match kind {
+        ScalarKind::Sint => format!("i{}", width * 8),
+        ScalarKind::Uint => format!("u{}", width * 8),
+        ScalarKind::Float => format!("f{}", width * 8),
+        ScalarKind::Bool => "bool".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+struct GlobalUniforms { time: f32, screen_aspect_ratio: f32, output_srgb: u32, panel_resolution: vec2<f32> }
+@group(0) @binding(0) var<uniform> globals: GlobalUniforms;
+
+@fragment
+fn main(@location(1) vertex_texture_coordinates: vec2<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(vertex_texture_coordinates, globals.time, 1.0);
+}
+";
+
+    #[test]
+    fn describes_entry_points_and_uniform_offsets() {
+        let module = naga::front::wgsl::parse_str(SHADER).unwrap();
+        let description = describe(&module).unwrap();
+
+        assert!(description.contains("Fragment main"), "{}", description);
+        assert!(description.contains("in  location 1: vec2<f32> vertex_texture_coordinates"), "{}", description);
+        assert!(description.contains("out location 0: vec4<f32>"), "{}", description);
+        assert!(description.contains("group 0 binding 0: uniform GlobalUniforms globals (24 bytes)"), "{}", description);
+        assert!(description.contains("offset   8 size   4  output_srgb: u32"), "{}", description);
+        assert!(description.contains("offset  16 size   8  panel_resolution: vec2<f32>"), "{}", description);
+    }
+
+    #[test]
+    fn translates_module_to_wgsl() {
+        let module = naga::front::wgsl::parse_str(SHADER).unwrap();
+        assert!(to_wgsl(&module).unwrap().contains("fn main("));
+    }
+}
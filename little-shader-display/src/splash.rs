@@ -0,0 +1,45 @@
+use std::path::Path;
+
+// Image pushed to the display right after it is initialized, so it shows a loading screen instead of black or garbage
+// while the first shaders compile and the render pipeline is created
+const FALLBACK_COLOR: [u8; 4] = [16, 16, 48, 255];
+
+// Loads the splash image scaled to a square of the given size as RGBA8888 pixels, first row at the top.
+// Falls back to a solid color if the image is missing or can not be decoded.
+pub fn load(path: &Path, size: u32) -> Vec<u8> {
+    if !path.exists() {
+        return FALLBACK_COLOR.repeat((size * size) as usize);
+    }
+
+    match image::open(path) {
+        Ok(image) => image.resize_exact(size, size, image::imageops::FilterType::Triangle).to_rgba8().into_raw(),
+        Err(err) => {
+            println!("Warning: Failed to load splash image {:?}: {}, showing a solid color instead", path, err);
+            FALLBACK_COLOR.repeat((size * size) as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_splash_falls_back_to_solid_color() {
+        let pixels = load(Path::new("missing-splash.png"), 4);
+        assert_eq!(pixels.len(), 4 * 4 * 4);
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel == FALLBACK_COLOR));
+    }
+
+    #[test]
+    fn splash_is_scaled_to_frame_size() {
+        let path = std::env::temp_dir().join(format!("little-shader-display-splash-{}.png", std::process::id()));
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255])).save(&path).unwrap();
+
+        let pixels = load(&path, 8);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(pixels.len(), 8 * 8 * 4);
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]));
+    }
+}
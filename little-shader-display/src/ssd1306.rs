@@ -0,0 +1,212 @@
+use rppal::i2c::I2c;
+use std::error::Error;
+
+use crate::display_sink::{DisplaySink, Dithering};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 64;
+const ADDRESSES: [u16; 2] = [0x3C, 0x3D]; // Selected by the SA0 pin or a jumper on the module
+const DATA_CHUNK_SIZE: usize = 32; // Bytes of pixels per I2C write, some I2C adapters limit the transfer size
+
+// Thresholds of a 4x4 Bayer matrix, in sixteenths
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// 128x64 monochrome OLED on I2C bus 1, the frame is dithered to 1 bit
+pub struct Ssd1306 {
+    i2c: I2c,
+    dithering: Dithering,
+}
+
+impl Ssd1306 {
+    // Finds the display at one of its addresses and initializes it, fails if there is none
+    pub fn new(dithering: Dithering) -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::new()?;
+        let address = ADDRESSES
+            .into_iter()
+            .find(|&address| i2c.set_slave_address(address).is_ok() && i2c.write(&[0x00, 0xAE]).is_ok()) // Display off
+            .ok_or("No SSD1306 found on I2C bus 1")?;
+        println!("Found SSD1306 at I2C address {:#04x}", address);
+
+        let mut display = Self { i2c, dithering };
+        display.command(&[
+            0xAE, // Display off
+            0xD5, 0x80, // Clock divider
+            0xA8, 0x3F, // Multiplex ratio, 64 rows
+            0xD3, 0x00, // No display offset
+            0x40, // Start line 0
+            0x8D, 0x14, // Charge pump on
+            0x20, 0x00, // Horizontal addressing, pages are written left to right then top to bottom
+            0xA1, // Column 127 is SEG0, together with the reversed COM scan (0,0) is the top-left
+            0xC8, // COM scan from the last row
+            0xDA, 0x12, // COM pins of the 128x64 modules
+            0x81, 0xCF, // Contrast
+            0xD9, 0xF1, // Pre-charge period
+            0xDB, 0x40, // VCOMH level
+            0xA4, // Show the RAM contents
+            0xA6, // Not inverted
+            0xAF, // Display on
+        ])?;
+        Ok(display)
+    }
+
+    // Control byte 0x00 marks the following bytes as commands
+    fn command(&mut self, commands: &[u8]) -> rppal::i2c::Result<()> {
+        let bytes: Vec<u8> = [0x00].iter().chain(commands).copied().collect();
+        self.i2c.write(&bytes).map(|_| ())
+    }
+
+    // Writes the whole screen, 8 pages of 8 rows with one byte per column each
+    fn draw(&mut self, pages: &[u8]) -> rppal::i2c::Result<()> {
+        self.command(&[0x21, 0, (WIDTH - 1) as u8, 0x22, 0, (HEIGHT / 8 - 1) as u8])?;
+        for chunk in pages.chunks(DATA_CHUNK_SIZE) {
+            // Control byte 0x40 marks the following bytes as pixels
+            let bytes: Vec<u8> = [0x40].iter().chain(chunk).copied().collect();
+            self.i2c.write(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl DisplaySink for Ssd1306 {
+    fn panel_size(&self) -> (u32, u32) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        let luminance = downsample_luminance(rgb565_bytes, width, height);
+        let pixels = dither(&luminance, self.dithering);
+        Ok(self.draw(&pack_pages(&pixels))?)
+    }
+
+    // An OLED has no backlight, it is switched off instead
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        Ok(self.command(&[if on { 0xAF } else { 0xAE }])?)
+    }
+
+    fn set_backlight_level(&mut self, level: f32) -> Result<(), Box<dyn Error>> {
+        Ok(self.command(&[0x81, (level.clamp(0.0, 1.0) * 255.0) as u8])?)
+    }
+}
+
+// Luminance from 0 to 1 of the frame cropped to the panel's 2:1 aspect ratio around its center
+// and averaged down to the panel size, one value per panel pixel row by row
+fn downsample_luminance(rgb565_bytes: &[u8], width: u32, height: u32) -> Vec<f32> {
+    let (crop_width, crop_height) = if width * HEIGHT > height * WIDTH { (height * WIDTH / HEIGHT, height) } else { (width, width * HEIGHT / WIDTH) };
+    let (crop_x, crop_y) = ((width - crop_width) / 2, (height - crop_height) / 2);
+    let luminance_at = |x: u32, y: u32| {
+        let index = ((y * width + x) * 2) as usize;
+        let rgb565 = u16::from_le_bytes([rgb565_bytes[index], rgb565_bytes[index + 1]]);
+        let r = (rgb565 >> 11) as f32 / 31.0;
+        let g = ((rgb565 >> 5) & 0x3F) as f32 / 63.0;
+        let b = (rgb565 & 0x1F) as f32 / 31.0;
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    // Source pixels covered by the panel pixel at the given position, at least one
+    let span = |position: u32, panel_size: u32, crop_start: u32, crop_size: u32| {
+        let start = crop_start + position * crop_size / panel_size;
+        let end = (crop_start + (position + 1) * crop_size / panel_size).max(start + 1);
+        start..end
+    };
+
+    (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (columns, rows) = (span(x, WIDTH, crop_x, crop_width), span(y, HEIGHT, crop_y, crop_height));
+            let count = (columns.len() * rows.len()) as f32;
+            rows.flat_map(|y| columns.clone().map(move |x| (x, y))).map(|(x, y)| luminance_at(x, y)).sum::<f32>() / count
+        })
+        .collect()
+}
+
+// Turns panel sized luminance into lit pixels
+fn dither(luminance: &[f32], dithering: Dithering) -> Vec<bool> {
+    match dithering {
+        Dithering::Ordered => luminance
+            .iter()
+            .enumerate()
+            .map(|(index, &value)| {
+                let (x, y) = (index % WIDTH as usize, index / WIDTH as usize);
+                value > (BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0
+            })
+            .collect(),
+        Dithering::FloydSteinberg => {
+            let (width, height) = (WIDTH as usize, HEIGHT as usize);
+            let mut values = luminance.to_vec();
+            let mut pixels = vec![false; values.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let index = y * width + x;
+                    pixels[index] = values[index] > 0.5;
+                    let error = values[index] - if pixels[index] { 1.0 } else { 0.0 };
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx >= 0 && (nx as usize) < width && y + dy < height {
+                            values[(y + dy) * width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+            pixels
+        }
+    }
+}
+
+// Packs pixels into the SSD1306 memory layout: pages of 8 rows, one byte per column with the top row in bit 0
+fn pack_pages(pixels: &[bool]) -> Vec<u8> {
+    let mut pages = vec![0u8; (WIDTH * HEIGHT / 8) as usize];
+    for (index, _) in pixels.iter().enumerate().filter(|(_, &lit)| lit) {
+        let (x, y) = (index % WIDTH as usize, index / WIDTH as usize);
+        pages[(y / 8) * WIDTH as usize + x] |= 1 << (y % 8);
+    }
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_frame(width: u32, height: u32, rgb565: u16) -> Vec<u8> {
+        (0..width * height).flat_map(|_| rgb565.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn downsamples_center_of_square_frame() {
+        // Top and bottom quarters are white, they are cropped away
+        let mut frame = solid_frame(256, 256, 0x0000);
+        for y in (0..64).chain(192..256) {
+            frame[(y * 256 * 2) as usize..((y + 1) * 256 * 2) as usize].fill(0xFF);
+        }
+        let luminance = downsample_luminance(&frame, 256, 256);
+        assert_eq!(luminance.len(), (WIDTH * HEIGHT) as usize);
+        assert!(luminance.iter().all(|&value| value == 0.0));
+
+        let luminance = downsample_luminance(&solid_frame(256, 256, 0xFFFF), 256, 256);
+        assert!(luminance.iter().all(|&value| (value - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn dithering_keeps_average_brightness() {
+        for dithering in [Dithering::Ordered, Dithering::FloydSteinberg] {
+            assert!(dither(&vec![0.0; (WIDTH * HEIGHT) as usize], dithering).iter().all(|&lit| !lit));
+            assert!(dither(&vec![1.0; (WIDTH * HEIGHT) as usize], dithering).iter().all(|&lit| lit));
+            let lit_count = dither(&vec![0.25; (WIDTH * HEIGHT) as usize], dithering).iter().filter(|&&lit| lit).count();
+            let lit_share = lit_count as f32 / (WIDTH * HEIGHT) as f32;
+            assert!((lit_share - 0.25).abs() < 0.02, "{:?}: {}", dithering, lit_share);
+        }
+    }
+
+    #[test]
+    fn packs_rows_into_page_bits() {
+        let mut pixels = vec![false; (WIDTH * HEIGHT) as usize];
+        pixels[0] = true; // (0, 0)
+        pixels[(9 * WIDTH + 3) as usize] = true; // (3, 9)
+        let pages = pack_pages(&pixels);
+        assert_eq!(pages[0], 0b1);
+        assert_eq!(pages[WIDTH as usize + 3], 0b10);
+        assert_eq!(pages.iter().filter(|&&byte| byte != 0).count(), 2);
+    }
+}
@@ -1,14 +1,16 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use display_interface_spi::SPIInterfaceNoCS;
 use embedded_graphics::image::{Image, ImageRawLE};
 use embedded_graphics::pixelcolor::Rgb565;
 use embedded_graphics::prelude::{Point, RgbColor};
-use rppal::gpio::Gpio;
-use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
-use st7789::{Orientation, ST7789};
+use rppal::gpio::{Gpio, InputPin, Level, Trigger};
+use rppal::spi::{Bus, Mode, Segment, SlaveSelect, Spi};
+use st7789::{Orientation, TearingEffect, ST7789};
 use embedded_hal::digital::v2::OutputPin as EmbeddedOutputPin;
 use rppal::gpio::OutputPin as RppalOutputPin;
 use rppal::gpio::Error as RppalError;
@@ -18,18 +20,28 @@ use embedded_hal::blocking::delay::DelayUs;
 use embedded_graphics::Drawable;
 use embedded_graphics::draw_target::DrawTarget;
 
-const DC_PIN_NUMBER: u8 = 25;
-const RST_PIN_NUMBER: u8 = 27;
-const CS_PIN_NUMBER: u8 = 8;
-const BL_PIN_NUMBER: u8 = 18;
+use crate::display_sink::DisplaySink;
+use crate::spi_interface::{self, ChunkedSpiInterface};
+
+pub const DEFAULT_SPI_CLOCK_SPEED: u32 = 64_000_000; // Hz, lower it for long wires or breadboards
+const BACKLIGHT_PWM_FREQUENCY: f64 = 500.0; // Hz, fast enough not to flicker with the software PWM
+
+const RDDID_COMMAND: u8 = 0x04; // Read display ID
+const EXPECTED_DISPLAY_ID: u32 = 0x858552; // ID1, ID2, ID3 reported by ST7789
+const READ_CLOCK_SPEED: u32 = 1_000_000; // Reads are much slower than writes on ST7789
+const SPI_RETRY_DELAY: Duration = Duration::from_millis(2);
+const TE_TIMEOUT: Duration = Duration::from_millis(100); // Several refresh periods, TE pulses come at the panel refresh rate
+
+// A GPIO output which can be wired to several panels, e.g. DC of two panels on one SPI bus
+type SharedOutputPin = Arc<Mutex<RppalOutputPin>>;
 
 pub struct RaspberryDelayOutputPin {
-    pin: RppalOutputPin,
+    pin: Option<SharedOutputPin>, // None for a pin another panel drives, e.g. a shared reset which only the first panel pulses
 }
 
 // Implement the OutputPin trait for the wrapper
 impl RaspberryDelayOutputPin {
-    pub fn new(pin: RppalOutputPin) -> Self {
+    pub fn new(pin: Option<SharedOutputPin>) -> Self {
         Self { pin }
     }
 }
@@ -38,11 +50,17 @@ impl EmbeddedOutputPin for RaspberryDelayOutputPin {
     type Error = RppalError; // Using rppal's error type
 
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        Ok(self.pin.set_low())
+        if let Some(pin) = &self.pin {
+            pin.lock().unwrap().set_low();
+        }
+        Ok(())
     }
 
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        Ok(self.pin.set_high())
+        if let Some(pin) = &self.pin {
+            pin.lock().unwrap().set_high();
+        }
+        Ok(())
     }
 
     fn set_state(&mut self, state: embedded_hal::digital::v2::PinState) -> Result<(), Self::Error> {
@@ -69,63 +87,320 @@ impl DelayUs<u32> for RaspberryDelay {
 
 pub struct RaspberrySpi {
     spi: Spi,
+    retries: u32,
 }
 
 impl RaspberrySpi {
-    pub fn new(spi: Spi) -> Self {
-        Self { spi }
+    pub fn new(spi: Spi, retries: u32) -> Self {
+        Self { spi, retries }
     }
 }
 
 impl Write<u8> for RaspberrySpi {
     type Error = SpiError; 
+    // Retries failed writes after a short delay, so transient glitches (long cables, noise) do not drop the frame
     fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-        self.spi.write(words)?;
-        Ok(())
+        let mut attempt = 0;
+        loop {
+            match self.spi.write(words) {
+                Ok(_) => return Ok(()),
+                Err(err) if attempt < self.retries => {
+                    attempt += 1;
+                    println!("Warning: SPI write failed: {}, retrying ({}/{})", err, attempt, self.retries);
+                    thread::sleep(SPI_RETRY_DELAY);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// Reasons a frame could not be drawn, the display keeps showing the previous frame
+#[derive(Debug)]
+pub enum DrawError {
+    SizeMismatch { byte_count: usize, width: u32, height: u32 },
+    Transfer(String), // SPI write kept failing after all retries
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawError::SizeMismatch { byte_count, width, height } => write!(f, "Image of {} bytes does not match its size {}x{}", byte_count, width, height),
+            DrawError::Transfer(message) => write!(f, "Failed to draw image: {}", message),
+        }
+    }
+}
+
+impl Error for DrawError {}
+
+// Settings of the connected panel
+#[derive(Debug, Clone, Copy)]
+pub struct ST7789Config {
+    pub width: u16,
+    pub height: u16,
+    pub verify_spi: bool, // Read back the display ID before initializing to catch disabled SPI or bad wiring
+    pub te_pin: Option<u8>, // BCM number of the GPIO wired to the panel's TE pin, frames are pushed on its rising edge (vertical blank)
+    pub spi_retries: u32, // How many times a failed SPI write is retried before the frame is dropped
+    pub spi_chunk_size: usize, // Bytes per SPI transfer, frames are sent in transfers of this size
+    pub spi: SpiDevice, // Where the panel is connected, panels sharing a bus need different chip selects
+    pub spi_clock_speed: u32, // Hz
+    pub pins: ST7789Pins,
+}
+
+impl Default for ST7789Config {
+    fn default() -> Self {
+        Self {
+            width: 240,
+            height: 280,
+            verify_spi: false,
+            te_pin: None,
+            spi_retries: 3,
+            spi_chunk_size: spi_interface::DEFAULT_CHUNK_SIZE,
+            spi: SpiDevice::default(),
+            spi_clock_speed: DEFAULT_SPI_CLOCK_SPEED,
+            pins: ST7789Pins::default(),
+        }
+    }
+}
+
+// BCM numbers of the GPIOs wired to the panel besides SPI, the chip select line is driven by the SPI controller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ST7789Pins {
+    pub dc: u8,
+    pub rst: u8,
+    pub bl: u8,
+}
+
+impl Default for ST7789Pins {
+    fn default() -> Self {
+        Self { dc: 25, rst: 27, bl: 18 }
+    }
+}
+
+// SPI bus and chip select line of a panel, e.g. bus 0 with CE0 (GPIO 8) or CE1 (GPIO 7)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SpiDevice {
+    pub bus: u8, // 0 to 6, buses other than 0 have to be enabled with a dtoverlay
+    pub chip_select: u8, // 0 to 2
+}
+
+impl SpiDevice {
+    // Parses <bus>.<chip select>, e.g. 0.1 for CE1 on SPI0 (/dev/spidev0.1)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("Invalid SPI device {}, expected <bus>.<chip select> like 0.1", text);
+        let (bus, chip_select) = text.split_once('.').ok_or_else(error)?;
+        let device = Self { bus: bus.parse().map_err(|_| error())?, chip_select: chip_select.parse().map_err(|_| error())? };
+        device.bus().ok_or_else(error)?;
+        device.slave_select().ok_or_else(error)?;
+        Ok(device)
+    }
+
+    pub fn bus(self) -> Option<Bus> {
+        [Bus::Spi0, Bus::Spi1, Bus::Spi2, Bus::Spi3, Bus::Spi4, Bus::Spi5, Bus::Spi6].get(self.bus as usize).copied()
+    }
+
+    pub fn slave_select(self) -> Option<SlaveSelect> {
+        [SlaveSelect::Ss0, SlaveSelect::Ss1, SlaveSelect::Ss2].get(self.chip_select as usize).copied()
+    }
+}
+
+impl fmt::Display for SpiDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.bus, self.chip_select)
+    }
+}
+
+// Hands out the GPIO outputs of the panels, a pin wired to several panels is only taken once and then shared
+struct OutputPins {
+    gpio: Gpio,
+    pins: HashMap<u8, SharedOutputPin>,
+}
+
+impl OutputPins {
+    fn new() -> Result<Self, RppalError> {
+        Ok(Self { gpio: Gpio::new()?, pins: HashMap::new() })
+    }
+
+    // Returns the pin and whether it was taken by an earlier panel already
+    fn get(&mut self, pin_number: u8, initially_high: bool) -> Result<(SharedOutputPin, bool), RppalError> {
+        if let Some(pin) = self.pins.get(&pin_number) {
+            return Ok((pin.clone(), true));
+        }
+        let pin = self.gpio.get(pin_number)?;
+        let pin = Arc::new(Mutex::new(if initially_high { pin.into_output_high() } else { pin.into_output() }));
+        self.pins.insert(pin_number, pin.clone());
+        Ok((pin, false))
     }
 }
 
 pub struct RaspberryST7789Driver {
+    config: ST7789Config,
     delay: RaspberryDelay,
-    display: ST7789<SPIInterfaceNoCS<RaspberrySpi, RaspberryDelayOutputPin>, RaspberryDelayOutputPin>,
+    te_pin: Option<InputPin>,
+    bl_pin: SharedOutputPin,
+    display: ST7789<ChunkedSpiInterface<RaspberrySpi, RaspberryDelayOutputPin>, RaspberryDelayOutputPin>,
 }
 
 impl RaspberryST7789Driver {
-    pub fn new() -> Result<Self, Box<dyn Error>>  {
-        let gpio = Gpio::new()?; 
-        let dc_pin = RaspberryDelayOutputPin::new(gpio.get(DC_PIN_NUMBER)?.into_output());
-        let rst_pin = RaspberryDelayOutputPin::new(gpio.get(RST_PIN_NUMBER)?.into_output());
-        let cs_pin = RaspberryDelayOutputPin::new(gpio.get(CS_PIN_NUMBER)?.into_output());
-        let bl_pin = RaspberryDelayOutputPin::new(gpio.get(BL_PIN_NUMBER)?.into_output());
-        
-        let spi = RaspberrySpi::new(Spi::new(Bus::Spi0, SlaveSelect::Ss0, 64_000_000, Mode::Mode0)?);
-        let display_interface = SPIInterfaceNoCS::new(spi, dc_pin);
-        let display = ST7789::new(display_interface, rst_pin, 240, 280);
+    pub fn new(config: ST7789Config) -> Result<Self, Box<dyn Error>>  {
+        Ok(Self::new_panels(&[config])?.remove(0))
+    }
+
+    // Creates a driver for each panel, panels wired to the same DC, reset or backlight pin share it.
+    // A shared reset is only pulsed by the first of its panels, so initializing a panel does not reset the ones before it.
+    // Panels should be initialized in the given order.
+    pub fn new_panels(configs: &[ST7789Config]) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut output_pins = OutputPins::new()?;
+        configs.iter().map(|config| Self::new_panel(*config, &mut output_pins)).collect()
+    }
+
+    fn new_panel(config: ST7789Config, output_pins: &mut OutputPins) -> Result<Self, Box<dyn Error>> {
+        let (dc_pin, _) = output_pins.get(config.pins.dc, false)?;
+        let (rst_pin, rst_pin_shared) = output_pins.get(config.pins.rst, false)?;
+        let (bl_pin, _) = output_pins.get(config.pins.bl, true)?;
+
+        let bus = config.spi.bus().ok_or_else(|| format!("Invalid SPI bus {}", config.spi.bus))?;
+        let slave_select = config.spi.slave_select().ok_or_else(|| format!("Invalid SPI chip select {}", config.spi.chip_select))?;
+        let spi = Spi::new(bus, slave_select, config.spi_clock_speed, Mode::Mode0).map_err(|err| format!("Failed to open SPI device {}: {}", config.spi, err))?;
+
+        let te_pin = match config.te_pin {
+            Some(te_pin_number) => {
+                let mut te_pin = output_pins.gpio.get(te_pin_number)?.into_input();
+                te_pin.set_interrupt(Trigger::RisingEdge, None)?;
+                Some(te_pin)
+            }
+            None => None,
+        };
+
+        if config.verify_spi {
+            verify_display_id(&spi, &mut dc_pin.lock().unwrap(), &mut rst_pin.lock().unwrap())?;
+        }
+
+        let dc_pin = RaspberryDelayOutputPin::new(Some(dc_pin));
+        let rst_pin = RaspberryDelayOutputPin::new((!rst_pin_shared).then_some(rst_pin));
+        let spi = RaspberrySpi::new(spi, config.spi_retries);
+        let display_interface = ChunkedSpiInterface::new(spi, dc_pin, config.spi_chunk_size);
+        let display = ST7789::new(display_interface, rst_pin, config.width, config.height);
     
         Ok(Self {
+            config,
             delay: RaspberryDelay::new(),
+            te_pin,
+            bl_pin,
             display,
         })    
     }
 
     pub fn initialize(&mut self) -> Result<(), Box<dyn Error>> {
-        self.display.init(&mut self.delay);
-        self.display.set_orientation(Orientation::Portrait);
-        self.display.clear(Rgb565::BLACK);
+        self.display.init(&mut self.delay).map_err(|e| format!("Failed to initialize display: {:?}", e))?;
+        self.display.set_orientation(Orientation::Portrait).map_err(|e| format!("Failed to set orientation: {:?}", e))?;
+        self.display.clear(Rgb565::BLACK).map_err(|e| format!("Failed to clear display: {:?}", e))?;
+        if self.te_pin.is_some() {
+            self.display.set_tearing_effect(TearingEffect::Vertical).map_err(|e| format!("Failed to enable tearing effect output: {:?}", e))?;
+        }
+        Ok(())
+    }
+
+    // Blocks until the panel starts its vertical blank, so the following frame write does not tear.
+    // If no pulse arrives TE is assumed to be not wired and frames are pushed unsynchronized from then on.
+    pub fn wait_for_te(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(te_pin) = &mut self.te_pin else {
+            return Ok(());
+        };
+
+        // Reset so an edge which happened while the previous frame was rendered is not taken as the current one
+        if te_pin.poll_interrupt(true, Some(TE_TIMEOUT))?.is_none() {
+            println!("Warning: No TE pulse within {}ms, check the TE wiring. Continuing without TE synchronization", TE_TIMEOUT.as_millis());
+            self.te_pin = None;
+        }
         Ok(())
     }
     
-    pub fn draw(&mut self, rgb565_bytes: &[u8]) -> Result<(), Box<dyn Error>> {
-        // Compute square side size from byte count
-        let dim = (rgb565_bytes.len() / 2) as u32;
-        let side = (dim as f32).sqrt() as u32;
-    
-        let raw_image: ImageRawLE<Rgb565> = ImageRawLE::new(&rgb565_bytes, side);
+    // Draws an image of RGB565 pixels packed as little-endian bytes, first row at the top.
+    // Conversion from the rendered RGBA8888 frame happens in the renderer, this only pushes the pixels.
+    pub fn draw(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), DrawError> {
+        if rgb565_bytes.len() != (width * height * 2) as usize {
+            return Err(DrawError::SizeMismatch { byte_count: rgb565_bytes.len(), width, height });
+        }
+
+        let raw_image: ImageRawLE<Rgb565> = ImageRawLE::new(rgb565_bytes, width);
         let image = Image::new(&raw_image, Point::new(-16, 40));
         
-        image.draw(&mut self.display);
+        image.draw(&mut self.display).map_err(|e| DrawError::Transfer(format!("{:?}", e)))?;
         Ok(())
     }
 }
 
+impl DisplaySink for RaspberryST7789Driver {
+    fn panel_size(&self) -> (u32, u32) {
+        (self.config.width as u32, self.config.height as u32)
+    }
+
+    fn wait_for_te(&mut self) -> Result<(), Box<dyn Error>> {
+        RaspberryST7789Driver::wait_for_te(self)
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        Ok(self.draw(rgb565_bytes, width, height)?)
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        let mut bl_pin = self.bl_pin.lock().unwrap();
+        bl_pin.clear_pwm()?;
+        bl_pin.write(if on { Level::High } else { Level::Low });
+        // The pin would go back to an input when the driver is dropped and the panel's pull-up would light it again
+        bl_pin.set_reset_on_drop(on);
+        Ok(())
+    }
+
+    // Software PWM on the backlight pin, fully on without PWM at level 1
+    fn set_backlight_level(&mut self, level: f32) -> Result<(), Box<dyn Error>> {
+        if level >= 1.0 {
+            return self.set_backlight(true);
+        }
+        Ok(self.bl_pin.lock().unwrap().set_pwm_frequency(BACKLIGHT_PWM_FREQUENCY, level.max(0.0) as f64)?)
+    }
+}
+
+// Reads the display ID with the RDDID command and checks it is an ST7789.
+// Best-effort since some modules do not wire the display's data output back to MISO.
+fn verify_display_id(spi: &Spi, dc_pin: &mut RppalOutputPin, rst_pin: &mut RppalOutputPin) -> Result<(), Box<dyn Error>> {
+    // Take the display out of reset so it can respond
+    rst_pin.set_high();
+    thread::sleep(Duration::from_millis(120));
+
+    // Command byte is sent with DC low, the ID follows after one dummy clock cycle
+    let mut response = [0u8; 4];
+    let mut command_segment = Segment::with_write(&[RDDID_COMMAND]);
+    command_segment.set_clock_speed(READ_CLOCK_SPEED);
+    let mut read_segment = Segment::with_read(&mut response);
+    read_segment.set_clock_speed(READ_CLOCK_SPEED);
+    dc_pin.set_low();
+    spi.transfer_segments(&[command_segment, read_segment])?;
+    dc_pin.set_high();
 
+    let display_id = (u32::from_be_bytes(response) >> 7) & 0xFFFFFF;
+    println!("ST7789 display ID: {:06X}", display_id);
+
+    if display_id == 0 || display_id == 0xFFFFFF {
+        return Err("No response from display, check that SPI is enabled and the display is wired correctly".into());
+    }
+    if display_id != EXPECTED_DISPLAY_ID {
+        return Err(format!("Unexpected display ID {:06X} (expected {:06X}), check the wiring and that the display is an ST7789", display_id, EXPECTED_DISPLAY_ID).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_spi_devices() {
+        assert_eq!(SpiDevice::parse("0.1"), Ok(SpiDevice { bus: 0, chip_select: 1 }));
+        assert_eq!(SpiDevice::parse("1.2"), Ok(SpiDevice { bus: 1, chip_select: 2 }));
+        assert!(SpiDevice::parse("0").is_err());
+        assert!(SpiDevice::parse("7.0").is_err());
+        assert!(SpiDevice::parse("0.3").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Line separating successive shaders piped to stdin
+pub const DELIMITER: &str = "---";
+
+// Fragment shaders piped to stdin, for live coding from an external editor or tool (e.g. `tool | little-shader-display --stdin-shader`).
+// Each shader ends with a line containing only the delimiter, the end of the stream also ends the last shader.
+pub struct StdinShader {
+    receiver: Receiver<String>,
+}
+
+impl StdinShader {
+    // Reads stdin on a background thread, so a blocking read does not hold back rendering
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = ShaderStream::default();
+            for line in std::io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if let Some(source) = stream.push_line(&line) {
+                            if sender.send(source).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        println!("Warning: Failed to read shader from stdin: {}", err);
+                        break;
+                    }
+                }
+            }
+            if let Some(source) = stream.finish() {
+                let _ = sender.send(source);
+            }
+            println!("Shader stream on stdin closed");
+        });
+        Self { receiver }
+    }
+
+    // Latest complete shader, older ones still queued are skipped since they would be replaced right away
+    pub fn take_latest(&self) -> Option<String> {
+        self.receiver.try_iter().last()
+    }
+}
+
+// Name the piped shader is compiled as, WGSL is told apart by its entry point attribute
+pub fn shader_name(source: &str) -> &'static str {
+    if source.contains("@fragment") { "stdin.wgsl" } else { "stdin.frag" }
+}
+
+// Collects lines until a shader is complete
+#[derive(Default)]
+struct ShaderStream {
+    source: String,
+}
+
+impl ShaderStream {
+    // Returns the shader once its delimiter line is read
+    fn push_line(&mut self, line: &str) -> Option<String> {
+        if line.trim_end() == DELIMITER {
+            return self.finish();
+        }
+        self.source.push_str(line);
+        self.source.push('\n');
+        None
+    }
+
+    // Returns the collected shader, or None if there is only whitespace (e.g. two delimiters in a row)
+    fn finish(&mut self) -> Option<String> {
+        let source = std::mem::take(&mut self.source);
+        (!source.trim().is_empty()).then_some(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_shaders_on_delimiter_and_end_of_stream() {
+        let mut stream = ShaderStream::default();
+        let mut shaders = Vec::new();
+        for line in ["void main() {", "}", "---", "", "---\r", "@fragment fn main() {}"] {
+            shaders.extend(stream.push_line(line));
+        }
+        shaders.extend(stream.finish());
+
+        assert_eq!(shaders, ["void main() {\n}\n", "@fragment fn main() {}\n"]);
+    }
+
+    #[test]
+    fn names_shader_by_language() {
+        assert_eq!(shader_name("#version 450\nvoid main() {}"), "stdin.frag");
+        assert_eq!(shader_name("@fragment\nfn main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }"), "stdin.wgsl");
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use sysinfo::System;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const THERMAL_ZONE_PATH: &str = "/sys/class/thermal/thermal_zone0/temp"; // The SoC on a Raspberry Pi
+
+// System load for "system monitor" shaders, each value is -1 while it is unknown (e.g. no thermal zone outside Linux)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SystemStats {
+    pub cpu_usage: f32, // 0..1, share of CPU time not idle since the previous sample, over all cores
+    pub memory_usage: f32, // 0..1, share of the memory in use
+    pub soc_temperature: f32, // °C
+}
+
+impl Default for SystemStats {
+    fn default() -> Self {
+        Self { cpu_usage: -1.0, memory_usage: -1.0, soc_temperature: -1.0 }
+    }
+}
+
+// Samples the system stats once per second in the background
+pub struct Telemetry {
+    stats: Arc<Mutex<SystemStats>>,
+}
+
+impl Telemetry {
+    pub fn start() -> Self {
+        let stats = Arc::new(Mutex::new(SystemStats::default()));
+        let thread_stats = stats.clone();
+        thread::spawn(move || {
+            let mut system = System::new();
+            // CPU usage is measured between two refreshes, the first one only sets the starting point
+            system.refresh_cpu_usage();
+            loop {
+                thread::sleep(SAMPLE_INTERVAL);
+                system.refresh_cpu_usage();
+                system.refresh_memory();
+                let sample = SystemStats {
+                    cpu_usage: system.global_cpu_usage() / 100.0,
+                    memory_usage: memory_usage(system.used_memory(), system.total_memory()).unwrap_or(-1.0),
+                    soc_temperature: fs::read_to_string(THERMAL_ZONE_PATH).ok().and_then(|temp| parse_temperature(&temp)).unwrap_or(-1.0),
+                };
+                *thread_stats.lock().unwrap() = sample;
+            }
+        });
+        Self { stats }
+    }
+
+    pub fn stats(&self) -> SystemStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+fn memory_usage(used: u64, total: u64) -> Option<f32> {
+    (total > 0).then(|| used as f32 / total as f32)
+}
+
+// Thermal zones report millidegrees Celsius
+fn parse_temperature(temp: &str) -> Option<f32> {
+    temp.trim().parse::<f32>().ok().map(|millidegrees| millidegrees / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_memory_usage_and_parses_temperature() {
+        assert_eq!(memory_usage(1000, 4000), Some(0.25));
+        assert_eq!(memory_usage(0, 0), None);
+
+        assert_eq!(parse_temperature("48312\n"), Some(48.312));
+        assert_eq!(parse_temperature(""), None);
+    }
+}
@@ -0,0 +1,69 @@
+// Test patterns generated on the CPU, used to verify display wiring, color order and orientation without any shader
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestPattern {
+    #[value(name = "bars")]
+    #[serde(rename = "bars")]
+    ColorBars,     // Vertical bars: white, yellow, cyan, green, magenta, red, blue, black (left to right)
+    Gradient,      // Red increases to the right, green increases to the bottom, blue decreases to the right
+    Checkerboard,  // Black and white squares, top-left square is white
+    #[value(name = "corners")]
+    #[serde(rename = "corners")]
+    CornerMarkers, // Black background with colored squares: red top-left, green top-right, blue bottom-left, white bottom-right
+}
+
+const COLOR_BARS: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 0, 0],
+];
+
+const CHECKERBOARD_SQUARE_SIZE: u32 = 16;
+const CORNER_MARKER_SIZE: u32 = 32;
+
+impl TestPattern {
+    // Generates RGBA8888 pixels of the pattern, first row is the top of the image
+    pub fn generate(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = self.pixel(x, y, width, height);
+                pixels.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        pixels
+    }
+
+    fn pixel(&self, x: u32, y: u32, width: u32, height: u32) -> [u8; 3] {
+        match self {
+            TestPattern::ColorBars => COLOR_BARS[(x * COLOR_BARS.len() as u32 / width) as usize],
+            TestPattern::Gradient => {
+                let r = (x * 255 / (width - 1).max(1)) as u8;
+                let g = (y * 255 / (height - 1).max(1)) as u8;
+                [r, g, 255 - r]
+            }
+            TestPattern::Checkerboard => {
+                if (x / CHECKERBOARD_SQUARE_SIZE + y / CHECKERBOARD_SQUARE_SIZE).is_multiple_of(2) { [255, 255, 255] } else { [0, 0, 0] }
+            }
+            TestPattern::CornerMarkers => {
+                let left = x < CORNER_MARKER_SIZE;
+                let right = x >= width.saturating_sub(CORNER_MARKER_SIZE);
+                let top = y < CORNER_MARKER_SIZE;
+                let bottom = y >= height.saturating_sub(CORNER_MARKER_SIZE);
+                match (left, right, top, bottom) {
+                    (true, _, true, _) => [255, 0, 0],
+                    (_, true, true, _) => [0, 255, 0],
+                    (true, _, _, true) => [0, 0, 255],
+                    (_, true, _, true) => [255, 255, 255],
+                    _ => [0, 0, 0],
+                }
+            }
+        }
+    }
+}
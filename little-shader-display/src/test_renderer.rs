@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use futures::executor::block_on;
+
+use crate::display_sink::DisplaySink;
+use crate::playlist::Playlist;
+use crate::renderer::{GpuOptions, Renderer, RendererOptions};
+use crate::SHADERS_PATH;
+
+// Tests share the shaders directory and compiled shader paths, so they render one at a time
+static SHADERS_LOCK: Mutex<()> = Mutex::new(());
+
+pub struct PresentedFrame {
+    pub rgb565_bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PresentedFrame {
+    pub fn pixels(&self) -> Vec<u16> {
+        self.rgb565_bytes.chunks_exact(2).map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]])).collect()
+    }
+}
+
+// Display which records every presented frame
+struct MockSink {
+    frames: Arc<Mutex<Vec<PresentedFrame>>>,
+}
+
+impl DisplaySink for MockSink {
+    fn panel_size(&self) -> (u32, u32) {
+        (240, 280)
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        self.frames.lock().unwrap().push(PresentedFrame { rgb565_bytes: rgb565_bytes.to_vec(), width, height });
+        Ok(())
+    }
+}
+
+// Shaders are rendered on any adapter including software ones, skip where there is none
+pub fn can_render() -> bool {
+    if block_on(wgpu::Instance::default().request_adapter(&wgpu::RequestAdapterOptions::default())).is_none() {
+        println!("Skipping: no graphics adapter available");
+        return false;
+    }
+    true
+}
+
+// Renderer of a test with its own shaders directory and mock displays.
+// The renderer starts with the first of the given shaders by name, the directory is removed when it is dropped.
+pub struct TestRenderer {
+    pub renderer: Renderer,
+    displays: Vec<Arc<Mutex<Vec<PresentedFrame>>>>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+pub struct TestRendererBuilder {
+    files: Vec<(PathBuf, Vec<u8>)>, // Relative to the directory of the shaders directory
+    display_count: usize,
+    offscreen: bool,
+    gpu_options: GpuOptions,
+}
+
+impl TestRenderer {
+    pub fn builder() -> TestRendererBuilder {
+        TestRendererBuilder { files: Vec::new(), display_count: 1, offscreen: false, gpu_options: GpuOptions::default() }
+    }
+
+    // Renders frames 0.1 seconds apart, starting at time 0
+    pub fn render_frames(&mut self, frame_count: usize) {
+        for frame in 0..frame_count {
+            let time = frame as f32 * 0.1;
+            self.renderer.update_uniforms(time, time, [0.0; 3], [0.0; 4]);
+            self.renderer.render();
+        }
+    }
+
+    // Frames presented to the display with the given index since the last call
+    pub fn take_frames(&self, display_index: usize) -> Vec<PresentedFrame> {
+        std::mem::take(&mut *self.displays[display_index].lock().unwrap())
+    }
+
+    // Path of a file next to the shaders directory, e.g. for a frame saved with render_to_file
+    pub fn path(&self, name: &str) -> PathBuf {
+        SHADERS_PATH.parent().unwrap().join(name)
+    }
+}
+
+impl Drop for TestRenderer {
+    fn drop(&mut self) {
+        fs::remove_dir_all(SHADERS_PATH.parent().unwrap()).ok();
+    }
+}
+
+impl TestRendererBuilder {
+    pub fn shader(self, name: &str, source: &str) -> Self {
+        self.file(Path::new("shaders").join("uncompiled").join(name), source.as_bytes().to_vec())
+    }
+
+    // Sidecar metadata of the shader with the given name
+    pub fn metadata(self, shader_name: &str, metadata: &str) -> Self {
+        self.shader(&format!("{}.toml", shader_name), metadata)
+    }
+
+    // Image in the textures directory, e.g. for a shader channel
+    pub fn texture(self, name: &str, image: &image::RgbaImage) -> Self {
+        let mut png_bytes = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut png_bytes), image::ImageFormat::Png).unwrap();
+        self.file(Path::new("textures").join(name), png_bytes)
+    }
+
+    pub fn displays(mut self, display_count: usize) -> Self {
+        self.display_count = display_count;
+        self
+    }
+
+    pub fn offscreen(mut self) -> Self {
+        self.offscreen = true;
+        self
+    }
+
+    pub fn gpu_options(mut self, gpu_options: GpuOptions) -> Self {
+        self.gpu_options = gpu_options;
+        self
+    }
+
+    fn file(mut self, path: PathBuf, contents: Vec<u8>) -> Self {
+        self.files.push((path, contents));
+        self
+    }
+
+    pub fn build(self) -> TestRenderer {
+        let guard = SHADERS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // The bundled vertex shader and the files of the test, nothing left over from a previous test
+        let directory = SHADERS_PATH.parent().unwrap();
+        fs::remove_dir_all(directory).ok();
+        fs::create_dir_all(SHADERS_PATH.join("uncompiled")).unwrap();
+        fs::create_dir_all(SHADERS_PATH.join("compiled")).unwrap();
+        let bundled_shaders_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join("shaders").join("uncompiled");
+        fs::copy(bundled_shaders_path.join("master.vert"), SHADERS_PATH.join("uncompiled").join("master.vert")).unwrap();
+        for (path, contents) in &self.files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let displays: Vec<_> = (0..self.display_count).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let display_sinks = displays.iter().map(|frames| Box::new(MockSink { frames: frames.clone() }) as Box<dyn DisplaySink>).collect();
+        let shader_name = Playlist::scan(&SHADERS_PATH.join("uncompiled")).into_iter().next().expect("No fragment shader given");
+        let renderer = Renderer::new(RendererOptions { offscreen: self.offscreen, display_sinks, gpu_options: self.gpu_options, shader_name: &shader_name, ..RendererOptions::default() });
+        TestRenderer { renderer, displays, _guard: guard }
+    }
+}
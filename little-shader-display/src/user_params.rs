@@ -0,0 +1,160 @@
+use serde::Deserialize;
+
+// Slots of the user_params uniform, four vec4s in the shader
+pub const USER_PARAM_COUNT: usize = 16;
+
+// A named slot of the user_params uniform declared in the shader metadata, e.g.
+//   [[params]]
+//   name = "speed"
+//   default = 1.0
+//   min = 0.0
+//   max = 4.0
+//   step = 0.25
+// Slots are assigned in the order the params are declared.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserParam {
+    pub name: String,
+    #[serde(default)]
+    pub default: f32,
+    #[serde(default)]
+    pub min: f32,
+    #[serde(default = "default_max")]
+    pub max: f32,
+    pub step: Option<f32>, // Change per key press, a twentieth of the range by default
+}
+
+fn default_max() -> f32 {
+    1.0
+}
+
+impl UserParam {
+    fn step(&self) -> f32 {
+        self.step.unwrap_or((self.max - self.min) / 20.0)
+    }
+}
+
+// Checks the declared params of a shader, e.g. defaults outside of their range
+pub fn validate(params: &[UserParam]) -> Result<(), String> {
+    if params.len() > USER_PARAM_COUNT {
+        return Err(format!("{} params are declared, at most {} are supported", params.len(), USER_PARAM_COUNT));
+    }
+    for (index, param) in params.iter().enumerate() {
+        if params[..index].iter().any(|other| other.name == param.name) {
+            return Err(format!("param {} is declared twice", param.name));
+        }
+        if param.min > param.max || !(param.min..=param.max).contains(&param.default) {
+            return Err(format!("param {} needs min <= default <= max", param.name));
+        }
+        if param.step.is_some_and(|step| step <= 0.0) {
+            return Err(format!("step of param {} must be greater than 0", param.name));
+        }
+    }
+    Ok(())
+}
+
+// Values of the params declared by the current shader and the one changed by keys
+#[derive(Debug, Default)]
+pub struct UserParams {
+    params: Vec<UserParam>,
+    values: [f32; USER_PARAM_COUNT],
+    selected: usize,
+}
+
+impl UserParams {
+    // Replaces the declared params, all of them start at their defaults.
+    // Unchanged declarations keep their values, e.g. when the shader is edited.
+    pub fn declare(&mut self, params: &[UserParam]) {
+        if self.params == params {
+            return;
+        }
+        self.params = params.to_vec();
+        self.values = [0.0; USER_PARAM_COUNT];
+        for (value, param) in self.values.iter_mut().zip(params) {
+            *value = param.default;
+        }
+        self.selected = 0;
+    }
+
+    pub fn values(&self) -> [f32; USER_PARAM_COUNT] {
+        self.values
+    }
+
+    // Sets the named param, clamped to its range. Fails if the current shader does not declare it.
+    pub fn set(&mut self, name: &str, value: f32) -> Result<f32, String> {
+        let index = self.params.iter().position(|param| param.name == name).ok_or_else(|| format!("the shader has no param {}", name))?;
+        let param = &self.params[index];
+        self.values[index] = value.clamp(param.min, param.max);
+        Ok(self.values[index])
+    }
+
+    // Selects the next param to be changed by step_selected, returns its name
+    pub fn select_next(&mut self) -> Option<&str> {
+        if self.params.is_empty() {
+            return None;
+        }
+        self.selected = (self.selected + 1) % self.params.len();
+        Some(&self.params[self.selected].name)
+    }
+
+    // Changes the selected param by the given number of steps, returns its name and new value
+    pub fn step_selected(&mut self, steps: f32) -> Option<(&str, f32)> {
+        let value = self.step(self.selected, steps)?;
+        Some((&self.params[self.selected].name, value))
+    }
+
+    // Changes the named param by the given number of steps, returns its new value.
+    // Fails if the current shader does not declare it.
+    pub fn step_named(&mut self, name: &str, steps: f32) -> Result<f32, String> {
+        let index = self.params.iter().position(|param| param.name == name).ok_or_else(|| format!("the shader has no param {}", name))?;
+        Ok(self.step(index, steps).expect("param index is valid"))
+    }
+
+    fn step(&mut self, index: usize, steps: f32) -> Option<f32> {
+        let param = self.params.get(index)?;
+        let value = (self.values[index] + steps * param.step()).clamp(param.min, param.max);
+        self.values[index] = value;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, default: f32, min: f32, max: f32) -> UserParam {
+        UserParam { name: name.to_string(), default, min, max, step: None }
+    }
+
+    #[test]
+    fn params_start_at_defaults_and_stay_in_range() {
+        let mut user_params = UserParams::default();
+        user_params.declare(&[param("speed", 1.0, 0.0, 4.0), param("hue", 0.5, 0.0, 1.0)]);
+        assert_eq!(user_params.values()[..3], [1.0, 0.5, 0.0]);
+
+        assert_eq!(user_params.set("hue", 2.0), Ok(1.0));
+        assert!(user_params.set("missing", 1.0).is_err());
+        assert_eq!(user_params.step_selected(1.0), Some(("speed", 1.2)));
+        assert_eq!(user_params.select_next(), Some("hue"));
+        assert_eq!(user_params.step_selected(-1.0), Some(("hue", 0.95)));
+        assert_eq!(user_params.select_next(), Some("speed"));
+        assert_eq!(user_params.step_named("hue", 1.0), Ok(1.0));
+        assert_eq!(user_params.step_named("hue", -1.0), Ok(0.95));
+        assert!(user_params.step_named("missing", 1.0).is_err());
+        user_params.declare(&[param("speed", 1.0, 0.0, 4.0), param("hue", 0.5, 0.0, 1.0)]);
+        assert_eq!(user_params.values()[..2], [1.2, 0.95]);
+
+        user_params.declare(&[]);
+        assert_eq!(user_params.values(), [0.0; USER_PARAM_COUNT]);
+        assert_eq!(user_params.step_selected(1.0), None);
+    }
+
+    #[test]
+    fn rejects_invalid_declarations() {
+        assert_eq!(validate(&[param("speed", 1.0, 0.0, 4.0)]), Ok(()));
+        assert!(validate(&[param("speed", 5.0, 0.0, 4.0)]).is_err());
+        assert!(validate(&[param("speed", 1.0, 0.0, 4.0), param("speed", 1.0, 0.0, 4.0)]).is_err());
+        assert!(validate(&vec![param("speed", 0.0, 0.0, 1.0); USER_PARAM_COUNT + 1]).is_err());
+        assert!(validate(&[UserParam { step: Some(0.0), ..param("speed", 1.0, 0.0, 4.0) }]).is_err());
+    }
+}
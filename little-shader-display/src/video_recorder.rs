@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+pub const DEFAULT_FPS: f32 = 30.0;
+
+// Encodes rendered frames to a video file (e.g. an .mp4 with H.264) by piping them to ffmpeg as raw RGBA frames.
+// Animation time steps exactly one frame interval per written frame, so the video is smooth however slow rendering is.
+pub struct VideoRecorder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    path: PathBuf,
+    size: (u32, u32),
+    fps: f32,
+    frame_count: u32,
+    time: f32, // Animation time of the next frame
+}
+
+impl VideoRecorder {
+    // Starts ffmpeg writing a video of the given frame size and rate, the first frame is rendered at start_time.
+    // Fails if ffmpeg can not be started.
+    pub fn start(path: &Path, size: (u32, u32), fps: f32, start_time: f32) -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new("ffmpeg")
+            .args(ffmpeg_args(path, size, fps))
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+        let stdin = child.stdin.take().ok_or("ffmpeg has no input")?;
+        Ok(Self { child, stdin: Some(stdin), path: path.to_path_buf(), size, fps, frame_count: 0, time: start_time })
+    }
+
+    // Animation time of the next frame
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    // Length of the video written so far in seconds
+    pub fn duration(&self) -> f32 {
+        self.frame_count as f32 / self.fps
+    }
+
+    // Appends an RGBA8888 frame (first row at the top) and advances the animation time by one frame unless paused
+    pub fn write_frame(&mut self, rgba: &[u8], size: (u32, u32), paused: bool) -> Result<(), Box<dyn Error>> {
+        if size != self.size {
+            return Err(format!("frame is {}x{}, the video is {}x{}", size.0, size.1, self.size.0, self.size.1).into());
+        }
+        let stdin = self.stdin.as_mut().ok_or("ffmpeg input is closed")?;
+        stdin.write_all(rgba).map_err(|err| format!("Failed to write to ffmpeg: {}", err))?;
+        self.frame_count += 1;
+        if !paused {
+            self.time += 1.0 / self.fps;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VideoRecorder {
+    // Closes ffmpeg's input so it finishes the file
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        match self.child.wait() {
+            Ok(status) if status.success() => println!("Saved video {} ({} frames, {:.1} seconds)", self.path.display(), self.frame_count, self.duration()),
+            Ok(status) => println!("Warning: ffmpeg failed to write {} ({})", self.path.display(), status),
+            Err(err) => println!("Warning: ffmpeg failed to write {} ({})", self.path.display(), err),
+        }
+    }
+}
+
+// Raw RGBA frames on stdin encoded to H.264 in yuv420p, which most players support. yuv420p needs even sizes,
+// so odd ones are padded by a pixel.
+fn ffmpeg_args(path: &Path, size: (u32, u32), fps: f32) -> Vec<String> {
+    let mut args: Vec<String> = ["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgba"].map(String::from).to_vec();
+    args.extend(["-s".to_string(), format!("{}x{}", size.0, size.1), "-r".to_string(), fps.to_string(), "-i".to_string(), "-".to_string()]);
+    args.extend(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-c:v", "libx264", "-pix_fmt", "yuv420p"].map(String::from));
+    args.push(path.display().to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffmpeg_reads_raw_frames_of_the_given_size_and_rate() {
+        let args = ffmpeg_args(Path::new("out.mp4"), (256, 128), 30.0).join(" ");
+        assert!(args.contains("-f rawvideo -pix_fmt rgba -s 256x128 -r 30 -i -"), "{}", args);
+        assert!(args.ends_with(" out.mp4"), "{}", args);
+    }
+}
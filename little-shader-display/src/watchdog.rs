@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const WATCHDOG_EXIT_CODE: i32 = 3;
+
+// Exits the process when the main loop stops making progress for the given time (e.g. a GPU hang blocking a frame),
+// so a supervisor like systemd can restart it instead of leaving a frozen display.
+// Runs on its own thread since a stalled main loop can not notice that it is stalled.
+pub struct Watchdog {
+    heartbeat: Arc<AtomicU64>,
+}
+
+impl Watchdog {
+    pub fn start(timeout: Duration) -> Self {
+        let heartbeat = Arc::new(AtomicU64::new(0));
+        let thread_heartbeat = heartbeat.clone();
+
+        thread::spawn(move || {
+            let check_interval = Duration::from_secs(1);
+            let mut last_heartbeat = thread_heartbeat.load(Ordering::Relaxed);
+            let mut stalled_for = Duration::ZERO;
+
+            loop {
+                thread::sleep(check_interval);
+                let heartbeat = thread_heartbeat.load(Ordering::Relaxed);
+                if heartbeat != last_heartbeat {
+                    last_heartbeat = heartbeat;
+                    stalled_for = Duration::ZERO;
+                    continue;
+                }
+
+                stalled_for += check_interval;
+                if stalled_for >= timeout {
+                    println!("Error: Main loop made no progress for {} seconds, exiting", stalled_for.as_secs());
+                    std::process::exit(WATCHDOG_EXIT_CODE);
+                }
+            }
+        });
+
+        Self { heartbeat }
+    }
+
+    // Signals that the main loop is alive, must be called at least once per timeout
+    pub fn feed(&self) {
+        self.heartbeat.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::bluetooth_server::{self, SnapshotRequest};
+
+const SNAPSHOT_COMMAND: &str = "snapshot";
+const MAX_MESSAGE_LENGTH: usize = 65536; // Commands are short, longer messages close the connection
+
+// Received commands, taken by the main loop in the order they arrived
+pub type CommandQueue = Arc<Mutex<VecDeque<String>>>;
+
+// WebSocket endpoint for remote control, e.g. from a web page. Every text message is a command as accepted over MQTT
+// (next, previous, uniforms, loaddir <path> or a JSON command), the message snapshot is answered with the current frame
+// as PNG in a binary message. Any number of clients can be connected at once.
+pub struct WebSocketServer {
+    listener: TcpListener,
+    received_commands: CommandQueue,
+    clients_connected: Arc<AtomicU32>,
+    snapshot_request: SnapshotRequest,
+}
+
+impl WebSocketServer {
+    // Only local clients can connect unless public is set, since any client can load shaders from any directory
+    pub async fn bind(port: u16, public: bool) -> std::io::Result<Self> {
+        let address = if public { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST };
+        Ok(WebSocketServer {
+            listener: TcpListener::bind((address, port)).await?,
+            received_commands: Arc::new(Mutex::new(VecDeque::new())),
+            clients_connected: Arc::new(AtomicU32::new(0)),
+            snapshot_request: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|address| address.port()).unwrap_or_default()
+    }
+
+    pub fn received_commands(&self) -> CommandQueue {
+        self.received_commands.clone()
+    }
+
+    pub fn clients_connected(&self) -> Arc<AtomicU32> {
+        self.clients_connected.clone()
+    }
+
+    pub fn snapshot_request(&self) -> SnapshotRequest {
+        self.snapshot_request.clone()
+    }
+
+    pub async fn run(self) {
+        let server = Arc::new(self);
+        loop {
+            // Accept errors (e.g. out of file descriptors) only affect that connection, keep serving
+            let (stream, address) = match server.listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    println!("Warning: Failed to accept WebSocket connection: {}", err);
+                    continue;
+                }
+            };
+
+            let server = server.clone();
+            tokio::spawn(async move {
+                server.clients_connected.fetch_add(1, Ordering::Relaxed);
+                match server.serve(stream).await {
+                    Ok(()) => println!("WebSocket client {} disconnected", address),
+                    Err(err) => println!("Warning: WebSocket connection to {} failed: {}", address, err),
+                }
+                server.clients_connected.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    }
+
+    // Upgrades the connection to a WebSocket and handles its messages until it is closed.
+    // Pings, close frames, unmasked client frames and the size of fragmented messages are handled by tungstenite.
+    async fn serve(&self, stream: impl AsyncRead + AsyncWrite + Unpin) -> Result<(), WsError> {
+        let config = WebSocketConfig {
+            max_message_size: Some(MAX_MESSAGE_LENGTH),
+            max_frame_size: Some(MAX_MESSAGE_LENGTH),
+            ..Default::default()
+        };
+        let mut websocket = tokio_tungstenite::accept_async_with_config(stream, Some(config)).await?;
+
+        while let Some(message) = websocket.next().await {
+            match message? {
+                Message::Text(command) => self.handle_command(&mut websocket, command.trim()).await?,
+                Message::Binary(bytes) => match String::from_utf8(bytes) {
+                    Ok(command) => self.handle_command(&mut websocket, command.trim()).await?,
+                    Err(_) => println!("Warning: Ignoring WebSocket message which is not UTF-8"),
+                },
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_command<S>(&self, websocket: &mut WebSocketStream<S>, command: &str) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if command == SNAPSHOT_COMMAND {
+            let png_bytes = bluetooth_server::request_snapshot(&self.snapshot_request).await;
+            println!("Sending snapshot over WebSocket ({} bytes)", png_bytes.len());
+            return websocket.send(Message::Binary(png_bytes)).await;
+        }
+        self.received_commands.lock().await.push_back(command.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+    #[tokio::test]
+    async fn queues_text_messages_and_answers_pings() {
+        let server = WebSocketServer::bind(0, false).await.unwrap();
+        let port = server.port();
+        let received_commands = server.received_commands();
+        tokio::spawn(server.run());
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (mut websocket, response) = tokio_tungstenite::client_async(format!("ws://127.0.0.1:{}/", port), stream).await.unwrap();
+        assert_eq!(response.status(), 101);
+
+        websocket.send(Message::Text(r#"{"shader":"waves.frag"}"#.to_string())).await.unwrap();
+        websocket.send(Message::Ping(b"hi".to_vec())).await.unwrap();
+        assert_eq!(websocket.next().await.unwrap().unwrap(), Message::Pong(b"hi".to_vec()));
+        assert_eq!(received_commands.lock().await.pop_front().as_deref(), Some(r#"{"shader":"waves.frag"}"#));
+
+        websocket.close(Some(CloseFrame { code: CloseCode::Normal, reason: "".into() })).await.unwrap();
+        match websocket.next().await.unwrap().unwrap() {
+            Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Normal),
+            message => panic!("expected a close frame, got {:?}", message),
+        }
+    }
+}
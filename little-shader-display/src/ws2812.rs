@@ -0,0 +1,196 @@
+use rppal::spi::{Mode, Spi};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+
+use crate::display_sink::{ColorOrder, DisplaySink};
+use crate::st7789_driver::SpiDevice;
+
+const SPI_CLOCK_SPEED: u32 = 2_400_000; // Three SPI bits per LED bit give the 1.25 us WS2812 bit time
+const RESET_BYTES: usize = 100; // 333 us low after the data latches the colors, WS2812B needs at least 280 us
+const DEFAULT_SPIDEV_BUFFER_SIZE: usize = 4096;
+
+// Positions of the LEDs in strip order, in a grid of width x height cells laid over the frame
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LedMap {
+    #[serde(default)]
+    pub width: u32, // Of the grid, the largest x + 1 if 0
+    #[serde(default)]
+    pub height: u32, // Of the grid, the largest y + 1 if 0
+    pub leds: Vec<[u32; 2]>,
+    #[serde(skip)]
+    is_grid: bool, // Frames are rendered at the grid size
+}
+
+impl LedMap {
+    // A strip across the middle of the frame, e.g. strip:60
+    fn strip(count: u32) -> Self {
+        Self { width: count, height: 1, leds: (0..count).map(|x| [x, 0]).collect(), is_grid: false }
+    }
+
+    // A matrix wired row by row, every other row runs backwards, e.g. grid:16x16
+    fn grid(width: u32, height: u32) -> Self {
+        let leds = (0..height).flat_map(|y| (0..width).map(move |x| [if y % 2 == 0 { x } else { width - 1 - x }, y])).collect();
+        Self { width, height, leds, is_grid: true }
+    }
+
+    // Parses strip:<count>, grid:<width>x<height> or the path to a JSON mapping file like {"leds": [[0, 0], [1, 0]]}
+    pub fn parse(layout: &str) -> Result<Self, String> {
+        let error = || format!("Invalid LED layout {}, expected strip:<count>, grid:<width>x<height> or a mapping file", layout);
+        let map = if let Some(count) = layout.strip_prefix("strip:") {
+            Self::strip(count.parse().map_err(|_| error())?)
+        } else if let Some(size) = layout.strip_prefix("grid:") {
+            let (width, height) = size.split_once('x').ok_or_else(error)?;
+            Self::grid(width.parse().map_err(|_| error())?, height.parse().map_err(|_| error())?)
+        } else {
+            let path = Path::new(layout);
+            let text = std::fs::read_to_string(path).map_err(|err| format!("Failed to read LED mapping {}: {}", path.display(), err))?;
+            Self::from_json(&text).map_err(|err| format!("Invalid LED mapping {}: {}", path.display(), err))?
+        };
+        if map.leds.is_empty() {
+            return Err(error());
+        }
+        Ok(map)
+    }
+
+    fn from_json(text: &str) -> Result<Self, String> {
+        let mut map: Self = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        map.width = map.width.max(map.leds.iter().map(|[x, _]| x + 1).max().unwrap_or(0));
+        map.height = map.height.max(map.leds.iter().map(|[_, y]| y + 1).max().unwrap_or(0));
+        Ok(map)
+    }
+
+    // Frame pixel at the center of each LED's cell
+    fn sample(&self, rgb565_bytes: &[u8], width: u32, height: u32) -> Vec<u16> {
+        self.leds
+            .iter()
+            .map(|&[x, y]| {
+                let source_x = ((2 * x + 1) * width / (2 * self.width)).min(width - 1);
+                let source_y = ((2 * y + 1) * height / (2 * self.height)).min(height - 1);
+                let index = ((source_y * width + source_x) * 2) as usize;
+                u16::from_le_bytes([rgb565_bytes[index], rgb565_bytes[index + 1]])
+            })
+            .collect()
+    }
+}
+
+// WS2812/NeoPixel LED strips and matrices on the MOSI pin of an SPI bus, the SPI clock times the LED bits
+pub struct Ws2812 {
+    spi: Spi,
+    map: LedMap,
+    color_order: ColorOrder,
+    max_brightness: f32,
+    brightness: f32,
+    lit: bool,
+}
+
+impl Ws2812 {
+    pub fn new(spi_device: SpiDevice, map: LedMap, color_order: ColorOrder, max_brightness: f32) -> Result<Self, Box<dyn Error>> {
+        // A frame has to be sent in one transfer, a pause between transfers would latch the LEDs
+        let buffer_size = std::fs::read_to_string("/sys/module/spidev/parameters/bufsiz").ok().and_then(|text| text.trim().parse().ok()).unwrap_or(DEFAULT_SPIDEV_BUFFER_SIZE);
+        let frame_size = encoded_size(map.leds.len());
+        if frame_size > buffer_size {
+            return Err(format!("{} LEDs need a {} byte SPI transfer, add spidev.bufsiz={} to /boot/cmdline.txt", map.leds.len(), frame_size, frame_size).into());
+        }
+
+        let (bus, slave_select) = (spi_device.bus().unwrap(), spi_device.slave_select().unwrap());
+        let spi = Spi::new(bus, slave_select, SPI_CLOCK_SPEED, Mode::Mode0).map_err(|err| format!("Failed to open SPI device {}: {}", spi_device, err))?;
+        println!("Using {} WS2812 LEDs on SPI {}", map.leds.len(), spi_device);
+        Ok(Self { spi, map, color_order, max_brightness, brightness: 1.0, lit: true })
+    }
+}
+
+impl DisplaySink for Ws2812 {
+    fn panel_size(&self) -> (u32, u32) {
+        (self.map.width, self.map.height)
+    }
+
+    fn render_size(&self) -> Option<(u32, u32)> {
+        self.map.is_grid.then_some((self.map.width, self.map.height))
+    }
+
+    fn present(&mut self, rgb565_bytes: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+        let brightness = if self.lit { self.max_brightness * self.brightness } else { 0.0 };
+        let colors: Vec<[u8; 3]> = self.map.sample(rgb565_bytes, width, height).into_iter().map(|rgb565| led_color(rgb565, self.color_order, brightness)).collect();
+        self.spi.write(&encode(&colors))?;
+        Ok(())
+    }
+
+    // There is no backlight, the LEDs are dimmed or switched off from the next frame on
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.lit = on;
+        if !on {
+            self.spi.write(&encode(&vec![[0; 3]; self.map.leds.len()]))?;
+        }
+        Ok(())
+    }
+
+    fn set_backlight_level(&mut self, level: f32) -> Result<(), Box<dyn Error>> {
+        self.brightness = level.clamp(0.0, 1.0);
+        Ok(())
+    }
+}
+
+// Channel bytes of a pixel in the order the LED expects
+fn led_color(rgb565: u16, color_order: ColorOrder, brightness: f32) -> [u8; 3] {
+    let scale = |value: u32, max: u32| (value as f32 * 255.0 / max as f32 * brightness).round() as u8;
+    let (r, g, b) = (scale((rgb565 >> 11) as u32, 31), scale(((rgb565 >> 5) & 0x3F) as u32, 63), scale((rgb565 & 0x1F) as u32, 31));
+    match color_order {
+        ColorOrder::Grb => [g, r, b],
+        ColorOrder::Rgb => [r, g, b],
+    }
+}
+
+fn encoded_size(led_count: usize) -> usize {
+    1 + led_count * 9 + RESET_BYTES
+}
+
+// SPI bytes sending the LED colors, each bit becomes 110 for a one or 100 for a zero, most significant bit first.
+// A leading zero byte keeps MOSI low before the first bit, the trailing zero bytes latch the colors.
+fn encode(colors: &[[u8; 3]]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(encoded_size(colors.len()));
+    bytes.push(0);
+    for &channel in colors.iter().flatten() {
+        let bits = (0..8).rev().fold(0u32, |bits, bit| (bits << 3) | if channel & (1 << bit) != 0 { 0b110 } else { 0b100 });
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..]);
+    }
+    bytes.resize(bytes.len() + RESET_BYTES, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_bits_as_spi_pulses() {
+        let bytes = encode(&[[0x80, 0x00, 0xFF]]);
+        assert_eq!(bytes.len(), encoded_size(1));
+        assert_eq!(bytes[0], 0);
+        assert_eq!(&bytes[1..10], &[0xD2, 0x49, 0x24, 0x92, 0x49, 0x24, 0xDB, 0x6D, 0xB6]);
+        assert!(bytes[10..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn parses_layouts() {
+        let strip = LedMap::parse("strip:3").unwrap();
+        assert_eq!((strip.width, strip.height, strip.leds.len()), (3, 1, 3));
+        // Serpentine rows
+        assert_eq!(LedMap::parse("grid:3x2").unwrap().leds, vec![[0, 0], [1, 0], [2, 0], [2, 1], [1, 1], [0, 1]]);
+        assert!(LedMap::parse("grid:3").is_err());
+        assert!(LedMap::parse("strip:0").is_err());
+
+        let map = LedMap::from_json(r#"{"leds": [[0, 0], [4, 1]]}"#).unwrap();
+        assert_eq!((map.width, map.height), (5, 2));
+        assert_eq!(LedMap::from_json(r#"{"width": 10, "leds": [[0, 0]]}"#).unwrap().width, 10);
+    }
+
+    #[test]
+    fn samples_strip_across_middle_row() {
+        // 2x3 frame with a red middle row
+        let frame: Vec<u8> = [0u16, 0, 0xF800, 0xF800, 0, 0].iter().flat_map(|pixel| pixel.to_le_bytes()).collect();
+        assert_eq!(LedMap::strip(2).sample(&frame, 2, 3), vec![0xF800, 0xF800]);
+        assert_eq!(led_color(0xF800, ColorOrder::Grb, 1.0), [0, 255, 0]);
+        assert_eq!(led_color(0xF800, ColorOrder::Rgb, 0.5), [128, 0, 0]);
+    }
+}